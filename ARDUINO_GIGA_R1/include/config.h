@@ -49,6 +49,7 @@ const byte tabla7seg[16] = {
 #define RFSH_PIN 40
 #define WAIT_PIN 44
 #define PBAS_PIN 3
+#define M1_PIN 4     // Necesario para CMD_GET_REGISTERS: distingue la búsqueda de códigos de operación
 
 
 uint8_t dato = 0; // Variable donde se guarda el valor leído del bus de datos
@@ -64,19 +65,64 @@ const uint8_t CMD_RUN_Z80 = 0x05;          // Iniciar la ejecución del Z80
 const uint8_t CMD_READ_Z80_MEMORY = 0x06;  // Leer un bloque de la memoria Z80
 const uint8_t CMD_RESET_Z80 = 0x07;        // Resetear el Z80
 const uint8_t CMD_RESET_SIN_MODIFICAR_Z80 = 0x08;
-const uint8_t CMD_GET_REGISTERS = 0x09; // (Futuro) Obtener el estado de registros
+const uint8_t CMD_GET_REGISTERS = 0x09; // Obtener el estado de registros
 const uint8_t CMD_START_CAPTURE = 0x0A;
 const uint8_t CMD_STOP_CAPTURE = 0x0B;
 const uint8_t CMD_QUITAR_WAIT = 0x0C;
 const uint8_t CMD_PONER_WAIT = 0x0D;
 
-const uint8_t ACK_SUCCESS = 0xDD; // Confirmación no lo uso por ahora
-const uint8_t ACK_ERROR = 0x00;   // Indicador de error no lo uso por ahora
+// Protocolo de tramas (ver src/protocolo.rs en el lado Rust)
+//   Petición:  SYNC VERSION CMD LEN_H LEN_L DATOS[LEN] CRC_H CRC_L
+//   Respuesta: SYNC VERSION CMD ESTADO LEN_H LEN_L DATOS[LEN] CRC_H CRC_L
+// CRC-16/CCITT-FALSE de todo lo que hay entre SYNC y el CRC
+const uint8_t TRAMA_SYNC = 0xA5;
+const uint8_t TRAMA_VERSION = 0x01;
+const uint16_t TRAMA_MAX_DATOS = 260;
+const uint16_t BLOQUE_MEMORIA = 256;      // Máximo de bytes de memoria por trama
+const uint32_t TRAMA_TIMEOUT_MS = 100;    // Tiempo máximo entre bytes de una petición
+
+// Códigos de estado de las respuestas
+const uint8_t ACK_SUCCESS = 0xDD;  // Petición ejecutada
+const uint8_t ACK_ERROR = 0x00;    // Error genérico
+const uint8_t NAK_CRC = 0x01;      // CRC incorrecto, el PC repite la petición
+const uint8_t NAK_LONGITUD = 0x02; // Longitud o rango de direcciones no válido
+const uint8_t NAK_COMANDO = 0x03;  // Comando desconocido o no implementado
+const uint8_t NAK_VERSION = 0x04;  // Versión de trama distinta
 
 // Constantes de velocidad de comunicación serie
 const uint32_t SERIAL_BAUD_RATE_RUST = 115200;
 const uint32_t SERIAL_BAUD_RATE_DEBUG = 1000000;
 
+// Volcado de registros (CMD_GET_REGISTERS)
+// Rutina z80/volcado_registros.asm ensamblada en ZONA_VOLCADO. Se inyecta con un CALL en
+// la siguiente búsqueda del primer código de operación de una instrucción y deja los
+// registros en BUFFER_VOLCADO.
+const uint16_t ZONA_VOLCADO = 0xFF00;
+const uint16_t FIN_VOLCADO = 0xFF50;        // RET final de la rutina
+const uint16_t BUFFER_VOLCADO = 0xFF80;
+const uint16_t TAMANO_ZONA_VOLCADO = 0x9B;  // Rutina y buffer, se restaura al terminar
+const uint8_t CORRECCION_R = 30;            // Ciclos M1 desde el CALL inyectado hasta LD A,R
+const uint32_t TIMEOUT_VOLCADO_MS = 500;    // Espera máxima sin que el volcado avance
+const uint16_t LONGITUD_REGISTROS = 29;
+const uint8_t IM_DESCONOCIDO = 0xFF;        // Aún no se ha visto ningún IM ni reset
+const uint8_t rutina_volcado[] = {
+    0x22, 0x86, 0xFF, 0xED, 0x53, 0x84, 0xFF, 0xED, 0x43, 0x82, 0xFF, 0xDD, 0x22, 0x88, 0xFF, 0xFD,
+    0x22, 0x8A, 0xFF, 0xED, 0x73, 0x8C, 0xFF, 0xE1, 0x22, 0x8E, 0xFF, 0x2B, 0x2B, 0x2B, 0xE5, 0xF5,
+    0xE1, 0x22, 0x80, 0xFF, 0xED, 0x57, 0x32, 0x98, 0xFF, 0xF5, 0xE1, 0x7D, 0x32, 0x9A, 0xFF, 0xED,
+    0x5F, 0x32, 0x99, 0xFF, 0x08, 0xF5, 0xE1, 0x22, 0x90, 0xFF, 0x08, 0xD9, 0x22, 0x96, 0xFF, 0xED,
+    0x53, 0x94, 0xFF, 0xED, 0x43, 0x92, 0xFF, 0xD9, 0x2A, 0x80, 0xFF, 0xE5, 0xF1, 0x2A, 0x86, 0xFF,
+    0xC9};
+
+typedef enum
+{
+  VOLCADO_INACTIVO,
+  VOLCADO_ESPERA_M1,   // Rutina copiada, esperando la siguiente búsqueda de instrucción
+  VOLCADO_OPERANDO_L,  // Servido el CALL, falta el byte bajo de la dirección
+  VOLCADO_OPERANDO_H,  // Falta el byte alto de la dirección
+  VOLCADO_EN_RUTINA,   // El Z80 está ejecutando la rutina
+  VOLCADO_RESTAURAR    // Registros enviados, se restaura la zona al salir de ella
+} EstadoVolcado;
+
 // Estados del sistema
 typedef enum
 {
@@ -90,6 +136,12 @@ volatile SystemState currentState = STATE_IDLE;       // Estado actual del siste
 uint16_t execution_start_address = 0x00; // Dirección de memoria donde se iniciará la ejecución del Z80
 bool capturar = false;                   // Flag para indicar si se deben capturar los ciclos del Z80 (no utilizado completamente)
 volatile uint16_t ciclo = 0;              // Contador de ciclos del Z80
+EstadoVolcado estado_volcado = VOLCADO_INACTIVO;
+uint32_t avance_volcado = 0;              // millis() al pedir el volcado o al avanzar
+uint8_t zona_guardada[TAMANO_ZONA_VOLCADO]; // Contenido original de ZONA_VOLCADO
+uint8_t modo_interrupcion = IM_DESCONOCIDO; // Último IM servido en un M1 (0 tras reset)
+bool m1_anterior_ed = false;               // El último M1 servido fue un prefijo ED
+bool m1_anterior_prefijo = false;          // El último M1 servido fue CB, DD, ED o FD
 
 // Memoria RAM del Z80 (64KB) inicializada a cero
 uint8_t z80_memoria[65536] = {0};
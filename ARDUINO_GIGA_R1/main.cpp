@@ -19,6 +19,9 @@ void TrigguerReset()
 {
   direccion = 0;
   ciclo = 0; // Reiniciar el contador de ciclos
+  modo_interrupcion = 0; // /RESET deja el Z80 en IM 0
+  m1_anterior_ed = false;
+  m1_anterior_prefijo = false;
 }
 
 void inicializaSieteSeg()
@@ -66,6 +69,37 @@ void presentaSieteSeg(uint16_t direccionZ80, uint8_t datosZ80)
   desplaza(0x01, tabla7seg[datosZ80 & 0x0F]);
 }
 
+// CRC-16/CCITT-FALSE, el mismo que calcula el PC
+uint16_t crc16(uint16_t crc, const uint8_t *datos, uint16_t longitud)
+{
+  for (uint16_t i = 0; i < longitud; i++)
+  {
+    crc ^= (uint16_t)datos[i] << 8;
+    for (int b = 0; b < 8; b++)
+    {
+      crc = (crc & 0x8000) ? (crc << 1) ^ 0x1021 : crc << 1;
+    }
+  }
+  return crc;
+}
+
+// Envía una trama de respuesta al PC
+void enviar_respuesta(uint8_t comando, uint8_t estado, const uint8_t *datos, uint16_t longitud)
+{
+  uint8_t cabecera[6] = {TRAMA_SYNC, TRAMA_VERSION, comando, estado,
+                         (uint8_t)(longitud >> 8), (uint8_t)(longitud & 0xFF)};
+  uint16_t crc = crc16(0xFFFF, cabecera + 1, sizeof(cabecera) - 1);
+  crc = crc16(crc, datos, longitud);
+  uint8_t cola[2] = {(uint8_t)(crc >> 8), (uint8_t)(crc & 0xFF)};
+
+  Serial4.write(cabecera, sizeof(cabecera));
+  if (longitud > 0)
+  {
+    Serial4.write(datos, longitud);
+  }
+  Serial4.write(cola, sizeof(cola));
+}
+
 void enviar_datos_ciclo()
 {
   uint8_t datos[11] = {0}; // Ajustar el tamaño según la cantidad de datos a enviar
@@ -95,7 +129,7 @@ void enviar_datos_ciclo()
   delay(15);
   if ((currentState == STATE_RUNNING) && (digitalRead(RFSH_PIN) == HIGH))
   {
-    Serial4.write(datos, sizeof(datos));
+    enviar_respuesta(CMD_START_CAPTURE, ACK_SUCCESS, datos, sizeof(datos));
   }
 }
 
@@ -172,148 +206,409 @@ void escribeBusDatos(uint8_t dat)
   Serial.println(dato_str);
 }
 
-// Obtiene todos los datos necesarios con la subida del reloj
-void TrigguerClock()
+// Copia la rutina de volcado en memoria; la inyección empieza en la siguiente búsqueda
+// de instrucción fuera de HALT (ver servir_lectura)
+void iniciar_volcado()
 {
-  statePba = !statePba;
+  memcpy(zona_guardada, &z80_memoria[ZONA_VOLCADO], TAMANO_ZONA_VOLCADO);
+  memcpy(&z80_memoria[ZONA_VOLCADO], rutina_volcado, sizeof(rutina_volcado));
+  avance_volcado = millis();
+  estado_volcado = VOLCADO_ESPERA_M1;
 }
 
-void manejar_comando()
+void restaurar_zona_volcado()
 {
-  // Recibimos los parámetros en Little Endian
-  if (Serial4.available() >= 5)
-  { // Esperar 5 bytes (comando + 4 de datos)
-    uint8_t comando = Serial4.read();
-    uint8_t data_bytes[4];
-    for (int i = 0; i < 4; i++)
-    {
-      data_bytes[i] = Serial4.read();
-    }
+  memcpy(&z80_memoria[ZONA_VOLCADO], zona_guardada, TAMANO_ZONA_VOLCADO);
+  estado_volcado = VOLCADO_INACTIVO;
+}
+
+// Convierte el buffer que deja la rutina en la respuesta a CMD_GET_REGISTERS
+void enviar_volcado()
+{
+  const uint8_t *b = &z80_memoria[BUFFER_VOLCADO];
+  uint16_t valores[12];
+  for (int i = 0; i < 12; i++)
+  {
+    valores[i] = b[2 * i] | (b[2 * i + 1] << 8);
+  }
+  valores[6] += 2; // SP antes del CALL inyectado
+  valores[7] -= 3; // PC de la instrucción interrumpida
 
-    char buff0[150];
-    snprintf(buff0, sizeof(buff0), "data_bytes[0] = 0x%02X  data_bytes[1] = 0x%02X data_bytes[2] = 0x%02X  data_bytes[3] = 0x%02X",
-             data_bytes[0], data_bytes[1], data_bytes[2], data_bytes[3]);
-    Serial.println(buff0);
+  uint8_t datos[LONGITUD_REGISTROS];
+  for (int i = 0; i < 12; i++)
+  {
+    datos[2 * i] = valores[i] >> 8;
+    datos[2 * i + 1] = valores[i] & 0xFF;
+  }
+  uint8_t r = b[0x19];
+  bool iff2 = (b[0x1A] & 0x04) != 0; // P/V tras LD A,I
+  datos[24] = b[0x18];
+  datos[25] = (r & 0x80) | ((r - CORRECCION_R) & 0x7F);
+  datos[26] = iff2; // Fuera de una NMI IFF1 == IFF2
+  datos[27] = iff2;
+  datos[28] = modo_interrupcion;
+  enviar_respuesta(CMD_GET_REGISTERS, ACK_SUCCESS, datos, sizeof(datos));
+}
 
-    switch (comando)
-    {
-    case CMD_ECHO:
+// Si el volcado no avanza a tiempo (Z80 parado en HALT, /WAIT o /BUSREQ activas...) se
+// abandona y se restaura la zona. Si los registros ya se enviaron no se responde otra vez.
+void comprobar_volcado()
+{
+  if (estado_volcado == VOLCADO_INACTIVO || millis() - avance_volcado <= TIMEOUT_VOLCADO_MS)
+  {
+    return;
+  }
+  bool respondido = estado_volcado == VOLCADO_RESTAURAR;
+  restaurar_zona_volcado();
+  if (!respondido)
+  {
+    enviar_respuesta(CMD_GET_REGISTERS, ACK_ERROR, NULL, 0);
+  }
+}
+
+// El modo de interrupción no se puede leer del Z80: se sigue con las instrucciones IM
+// que se le sirven en ciclos M1. Las variantes no documentadas (ED 4E, 66, 6E, 76 y 7E)
+// cuentan igual. Hasta ver una o un reset se informa IM_DESCONOCIDO.
+void anotar_modo_interrupcion(uint8_t opcode)
+{
+  if (m1_anterior_ed)
+  {
+    switch (opcode)
     {
-      Serial4.write(data_bytes[3]); // El último byte es el dato a hacer eco
-      // Serial.write(data_bytes[3]);
-      Serial.println("ECHOOOOOOOOOOOOOOOOOOOOOOOOO");
+    case 0x46:
+    case 0x4E:
+    case 0x66:
+    case 0x6E:
+      modo_interrupcion = 0;
       break;
-    }
-    case CMD_SEND_BYTE:
-    {
-      // Recibido byte: data_bytes[3]
+    case 0x56:
+    case 0x76:
+      modo_interrupcion = 1;
+      break;
+    case 0x5E:
+    case 0x7E:
+      modo_interrupcion = 2;
+      break;
+    default:
       break;
     }
-    case CMD_GET_STATUS:
+  }
+  m1_anterior_ed = opcode == 0xED;
+}
+
+// Sigue los códigos servidos en ciclos M1. Tras un prefijo CB, DD, ED o FD el siguiente M1
+// es de la misma instrucción y no se puede inyectar en él el CALL del volcado. Tras DD CB
+// el aviso sobra y el CALL se retrasa una instrucción.
+void anotar_m1(uint8_t opcode)
+{
+  anotar_modo_interrupcion(opcode);
+  m1_anterior_prefijo = opcode == 0xCB || opcode == 0xDD || opcode == 0xED || opcode == 0xFD;
+}
+
+// Byte que se pone en el bus de datos en una lectura de memoria del Z80. Normalmente es
+// el contenido de la memoria, salvo mientras se inyecta la rutina de volcado.
+uint8_t servir_lectura(uint16_t dir)
+{
+  bool m1 = digitalRead(M1_PIN) == LOW;
+  // Parado en HALT el Z80 sigue haciendo búsquedas M1 pero no ejecuta lo que lee
+  bool parado = digitalRead(HALT_PIN) == LOW;
+  bool en_zona = dir >= ZONA_VOLCADO && dir < ZONA_VOLCADO + TAMANO_ZONA_VOLCADO;
+
+  if (estado_volcado != VOLCADO_INACTIVO && estado_volcado != VOLCADO_ESPERA_M1 && !parado)
+  {
+    avance_volcado = millis();
+  }
+  switch (estado_volcado)
+  {
+  case VOLCADO_ESPERA_M1:
+    if (m1 && !en_zona && !m1_anterior_prefijo && !parado)
     {
-      Serial4.write(currentState);
-      break;
+      avance_volcado = millis();
+      estado_volcado = VOLCADO_OPERANDO_L;
+      return 0xCD; // CALL ZONA_VOLCADO
     }
-    case CMD_READ_Z80_MEMORY:
+    break;
+  case VOLCADO_OPERANDO_L:
+    estado_volcado = VOLCADO_OPERANDO_H;
+    return ZONA_VOLCADO & 0xFF;
+  case VOLCADO_OPERANDO_H:
+    estado_volcado = VOLCADO_EN_RUTINA;
+    return ZONA_VOLCADO >> 8;
+  case VOLCADO_EN_RUTINA:
+    if (m1 && dir == FIN_VOLCADO)
     {
-      uint16_t start_address = (data_bytes[0] << 8) | data_bytes[1];
-      uint16_t length_to_read = (data_bytes[2] << 8) | data_bytes[3];
-      // Serial.printf("CMD_READ_Z80_MEMORY: Dirección Inicio = 0x%04X, Longitud = %u\n", start_address, length_to_read);
-
-      if (start_address + length_to_read <= 65536)
-      {
-        for (uint16_t i = 0; i < length_to_read; i++)
-        {
-          uint8_t byte_leido = z80_memoria[start_address + i];
-          Serial4.write(byte_leido);
-        }
-      }
-      break;
+      // Al buscar el RET final todos los registros están ya en el buffer
+      enviar_volcado();
+      estado_volcado = VOLCADO_RESTAURAR;
     }
-    case CMD_WRITE_Z80_MEMORY:
+    break;
+  case VOLCADO_RESTAURAR:
+    if (m1 && !en_zona)
     {
-      // AHH ALL DHH DLL  AHH direccion alta, ALL Direccion baja,  DHH num bytes alto,  DLL num bytes bajo
-      uint16_t start_address = (data_bytes[0] << 8) | data_bytes[1];
-      uint16_t length_to_write = (data_bytes[2] << 8) | data_bytes[3];
-
-      if (start_address + length_to_write <= 65536 && length_to_write > 0)
-      {
-        // Espera activa hasta que lleguen todos los bytes
-        while (Serial4.available() < length_to_write)
-        {
-          // Hacemos un pequeño delay para evitar bloquear el sistema completamente
-          delay(1);
-        }
+      restaurar_zona_volcado();
+    }
+    break;
+  default:
+    break;
+  }
+  return z80_memoria[dir];
+}
 
-        for (uint16_t i = 0; i < length_to_write; i++)
-        {
-          z80_memoria[start_address + i] = Serial4.read();
-        }
+// Obtiene todos los datos necesarios con la subida del reloj
+void TrigguerClock()
+{
+  statePba = !statePba;
+}
 
-        Serial.println("CMD_WRITE_Z80_MEMORY completado");
-      }
-      else
-      {
-        Serial.println("CMD_WRITE_Z80_MEMORY error: rango inválido");
-      }
+// Estado del receptor de tramas
+enum EstadoTrama
+{
+  TRAMA_ESPERA_SYNC,
+  TRAMA_CABECERA,
+  TRAMA_DATOS,
+  TRAMA_CRC
+};
+
+EstadoTrama estado_trama = TRAMA_ESPERA_SYNC;
+uint8_t trama_cabecera[4];                // VERSION CMD LEN_H LEN_L
+uint8_t trama_datos[TRAMA_MAX_DATOS];
+uint8_t trama_crc[2];
+uint16_t trama_longitud = 0;
+uint16_t trama_recibidos = 0;
+uint32_t trama_ultimo_byte = 0;
+
+// Ejecuta una petición ya validada y envía su respuesta
+void ejecutar_comando(uint8_t comando, const uint8_t *datos, uint16_t longitud)
+{
+  char buff0[80];
+  snprintf(buff0, sizeof(buff0), "Comando 0x%02X con %u bytes de datos", comando, longitud);
+  Serial.println(buff0);
 
+  switch (comando)
+  {
+  case CMD_ECHO:
+  {
+    enviar_respuesta(comando, ACK_SUCCESS, datos, longitud); // Se devuelven los mismos datos
+    Serial.println("ECHOOOOOOOOOOOOOOOOOOOOOOOOO");
+    break;
+  }
+  case CMD_SEND_BYTE:
+  {
+    // Recibido byte: datos[0]
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    break;
+  }
+  case CMD_GET_STATUS:
+  {
+    uint8_t estado = currentState;
+    enviar_respuesta(comando, ACK_SUCCESS, &estado, 1);
+    break;
+  }
+  case CMD_READ_Z80_MEMORY:
+  {
+    // AHH ALL LHH LLL  dirección y número de bytes a leer
+    if (longitud != 4)
+    {
+      enviar_respuesta(comando, NAK_LONGITUD, NULL, 0);
       break;
     }
+    uint16_t start_address = (datos[0] << 8) | datos[1];
+    uint16_t length_to_read = (datos[2] << 8) | datos[3];
 
-    case CMD_RUN_Z80:
+    if ((uint32_t)start_address + length_to_read <= 65536 && length_to_read <= BLOQUE_MEMORIA)
     {
-      // Poner WAIT en alto para que se pueda ya ejecutar
-      // digitalWrite(WAIT_PIN, HIGH);
-
-      uint16_t start_address_run = (data_bytes[0] << 8) | data_bytes[1];
-      execution_start_address = start_address_run;
-      currentState = STATE_RUNNING;
-      break;
+      enviar_respuesta(comando, ACK_SUCCESS, &z80_memoria[start_address], length_to_read);
     }
-
-    case CMD_RESET_Z80:
+    else
     {
-      TrigguerReset();
-      ClearRAM();
-      currentState = STATE_IDLE;
-      break;
+      enviar_respuesta(comando, NAK_LONGITUD, NULL, 0);
     }
-    case CMD_RESET_SIN_MODIFICAR_Z80:
+    break;
+  }
+  case CMD_WRITE_Z80_MEMORY:
+  {
+    // AHH ALL seguido de los bytes a escribir
+    if (longitud < 3)
     {
-
-      // Se ejecutará el código que habia en memoria apartir de 0x0000
-      TrigguerReset();
-      currentState = STATE_RUNNING;
+      enviar_respuesta(comando, NAK_LONGITUD, NULL, 0);
       break;
     }
-    case CMD_START_CAPTURE:
-    {
-      // capturar = true;
-      enviar_datos_ciclo();
+    uint16_t start_address = (datos[0] << 8) | datos[1];
+    uint16_t length_to_write = longitud - 2;
 
-      break;
+    if ((uint32_t)start_address + length_to_write <= 65536)
+    {
+      memcpy(&z80_memoria[start_address], datos + 2, length_to_write);
+      Serial.println("CMD_WRITE_Z80_MEMORY completado");
+      enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
     }
-    case CMD_STOP_CAPTURE:
+    else
     {
-      capturar = false;
-      break;
+      Serial.println("CMD_WRITE_Z80_MEMORY error: rango inválido");
+      enviar_respuesta(comando, NAK_LONGITUD, NULL, 0);
     }
-    case CMD_QUITAR_WAIT:
+    break;
+  }
+
+  case CMD_RUN_Z80:
+  {
+    // Poner WAIT en alto para que se pueda ya ejecutar
+    // digitalWrite(WAIT_PIN, HIGH);
+    if (longitud != 2)
     {
-      digitalWrite(WAIT_PIN, HIGH);
-      Serial.println("CMD_QUITAR_WAIT");
+      enviar_respuesta(comando, NAK_LONGITUD, NULL, 0);
       break;
     }
-    case CMD_PONER_WAIT:
+    uint16_t start_address_run = (datos[0] << 8) | datos[1];
+    execution_start_address = start_address_run;
+    currentState = STATE_RUNNING;
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    break;
+  }
+
+  case CMD_RESET_Z80:
+  {
+    TrigguerReset();
+    ClearRAM();
+    currentState = STATE_IDLE;
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    break;
+  }
+  case CMD_RESET_SIN_MODIFICAR_Z80:
+  {
+    // Se ejecutará el código que habia en memoria apartir de 0x0000
+    TrigguerReset();
+    currentState = STATE_RUNNING;
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    break;
+  }
+  case CMD_GET_REGISTERS:
+  {
+    // La respuesta se envía cuando el Z80 termina la rutina de volcado
+    if (currentState != STATE_RUNNING || estado_volcado != VOLCADO_INACTIVO)
     {
-      digitalWrite(WAIT_PIN, LOW);
-      Serial.println("CMD_PONER_WAIT");
+      enviar_respuesta(comando, ACK_ERROR, NULL, 0);
       break;
     }
-    default:
+    iniciar_volcado();
+    break;
+  }
+  case CMD_START_CAPTURE:
+  {
+    // A partir de aquí loop() envía una trama por ciclo
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    capturar = true;
+    break;
+  }
+  case CMD_STOP_CAPTURE:
+  {
+    capturar = false;
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    break;
+  }
+  case CMD_QUITAR_WAIT:
+  {
+    digitalWrite(WAIT_PIN, HIGH);
+    Serial.println("CMD_QUITAR_WAIT");
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    break;
+  }
+  case CMD_PONER_WAIT:
+  {
+    digitalWrite(WAIT_PIN, LOW);
+    Serial.println("CMD_PONER_WAIT");
+    enviar_respuesta(comando, ACK_SUCCESS, NULL, 0);
+    break;
+  }
+  default:
+  {
+    enviar_respuesta(comando, NAK_COMANDO, NULL, 0);
+    break;
+  }
+  }
+}
+
+// Valida la trama recibida y la ejecuta o contesta con el NAK correspondiente
+void procesar_trama()
+{
+  uint8_t comando = trama_cabecera[1];
+  uint16_t crc = crc16(0xFFFF, trama_cabecera, sizeof(trama_cabecera));
+  crc = crc16(crc, trama_datos, trama_longitud);
+
+  if (crc != ((trama_crc[0] << 8) | trama_crc[1]))
+  {
+    Serial.println("Trama con CRC incorrecto");
+    enviar_respuesta(comando, NAK_CRC, NULL, 0);
+  }
+  else if (trama_cabecera[0] != TRAMA_VERSION)
+  {
+    enviar_respuesta(comando, NAK_VERSION, NULL, 0);
+  }
+  else
+  {
+    ejecutar_comando(comando, trama_datos, trama_longitud);
+  }
+}
+
+// Lee los bytes disponibles sin bloquear y ejecuta la petición cuando está completa.
+// Si una petición se queda a medias más de TRAMA_TIMEOUT_MS se descarta y el PC,
+// al no recibir respuesta, la repite.
+void manejar_comando()
+{
+  if (estado_trama != TRAMA_ESPERA_SYNC && millis() - trama_ultimo_byte > TRAMA_TIMEOUT_MS)
+  {
+    Serial.println("Trama incompleta descartada");
+    estado_trama = TRAMA_ESPERA_SYNC;
+  }
+
+  while (Serial4.available() > 0)
+  {
+    uint8_t byte = Serial4.read();
+    trama_ultimo_byte = millis();
+
+    switch (estado_trama)
     {
+    case TRAMA_ESPERA_SYNC:
+      if (byte == TRAMA_SYNC)
+      {
+        trama_recibidos = 0;
+        estado_trama = TRAMA_CABECERA;
+      }
+      break;
+    case TRAMA_CABECERA:
+      trama_cabecera[trama_recibidos++] = byte;
+      if (trama_recibidos == sizeof(trama_cabecera))
+      {
+        trama_longitud = (trama_cabecera[2] << 8) | trama_cabecera[3];
+        trama_recibidos = 0;
+        if (trama_longitud > TRAMA_MAX_DATOS)
+        {
+          // No cabe: se descarta y se vuelve a buscar el sincronismo
+          enviar_respuesta(trama_cabecera[1], NAK_LONGITUD, NULL, 0);
+          estado_trama = TRAMA_ESPERA_SYNC;
+        }
+        else
+        {
+          estado_trama = trama_longitud > 0 ? TRAMA_DATOS : TRAMA_CRC;
+        }
+      }
+      break;
+    case TRAMA_DATOS:
+      trama_datos[trama_recibidos++] = byte;
+      if (trama_recibidos == trama_longitud)
+      {
+        trama_recibidos = 0;
+        estado_trama = TRAMA_CRC;
+      }
+      break;
+    case TRAMA_CRC:
+      trama_crc[trama_recibidos++] = byte;
+      if (trama_recibidos == sizeof(trama_crc))
+      {
+        estado_trama = TRAMA_ESPERA_SYNC;
+        procesar_trama();
+      }
       break;
-    }
     }
   }
 }
@@ -331,6 +626,7 @@ void inicializar_hardware()
   pinMode(RFSH_PIN, INPUT);
   // pinMode(IOREQ_PIN, INPUT);
   pinMode(HALT_PIN, INPUT);
+  pinMode(M1_PIN, INPUT);
   pinMode(PBAS_PIN, OUTPUT);
 
   // Bus de direcciones siempre será de entrada
@@ -367,7 +663,11 @@ void ejecutar_z80()
           pinMode(DataBus8[i], OUTPUT); // Asegurar salida para lectura
 
         Serial.println("Z80 LEYENDO EN MEMORIA");
-        leerDatoEnDireccion();
+        dato = servir_lectura(leerBusDireccion());
+        if (digitalRead(M1_PIN) == LOW)
+        {
+          anotar_m1(dato);
+        }
         escribeBusDatos(dato);
         delayMicroseconds(10);
         //vTaskDelay();
@@ -479,6 +779,11 @@ void loop()
 {
   //probarLecturaDirectaPuertos();
   manejar_comando();
+  comprobar_volcado();
+  if (capturar)
+  {
+    enviar_datos_ciclo();
+  }
 
   presentaDebug();
 
@@ -0,0 +1,56 @@
+; Rutina de volcado de registros para CMD_GET_REGISTERS.
+;
+; El firmware la copia en ZONA_VOLCADO y, en la siguiente búsqueda de código de
+; operación (/M1), sirve al Z80 un CALL ZONA_VOLCADO en lugar de la instrucción de
+; memoria. La rutina guarda los registros en BUFFER y vuelve a la instrucción
+; interrumpida sin modificar ningún registro (salvo R).
+;
+; BUFFER (palabras en little endian):
+;   +00 AF   +02 BC   +04 DE   +06 HL   +08 IX   +0A IY
+;   +0C SP   (el del CALL: hay que sumarle 2)
+;   +0E PC   (dirección de retorno del CALL: hay que restarle 3)
+;   +10 AF'  +12 BC'  +14 DE'  +16 HL'
+;   +18 I    +19 R    +1A F tras LD A,I (P/V = IFF2)
+
+BUFFER  EQU $FF80
+
+        ORG $FF00
+VOLCADO:
+        LD (BUFFER+$06),HL
+        LD (BUFFER+$04),DE
+        LD (BUFFER+$02),BC
+        LD (BUFFER+$08),IX
+        LD (BUFFER+$0A),IY
+        LD (BUFFER+$0C),SP
+        POP HL                  ; Dirección de retorno = PC + 3
+        LD (BUFFER+$0E),HL
+        DEC HL
+        DEC HL
+        DEC HL
+        PUSH HL                 ; Se vuelve a la instrucción que no llegó a ejecutarse
+        PUSH AF
+        POP HL
+        LD (BUFFER+$00),HL
+        LD A,I
+        LD (BUFFER+$18),A
+        PUSH AF
+        POP HL
+        LD A,L
+        LD (BUFFER+$1A),A
+        LD A,R
+        LD (BUFFER+$19),A
+        EX AF,AF'
+        PUSH AF
+        POP HL
+        LD (BUFFER+$10),HL
+        EX AF,AF'
+        EXX
+        LD (BUFFER+$16),HL
+        LD (BUFFER+$14),DE
+        LD (BUFFER+$12),BC
+        EXX
+        LD HL,(BUFFER+$00)      ; Se recuperan AF y HL
+        PUSH HL
+        POP AF
+        LD HL,(BUFFER+$06)
+FIN:    RET
@@ -1,5 +1,5 @@
     LD  A,0x00
     LD  IX,0x4161
     ADC A,A
-    ADC A,IXL
     ADC A,IXH
+    ADC A,IXL
@@ -1,5 +1,5 @@
     LD  A,0x00
     LD  IY,0x4161
     ADC A,A
-    ADC A,IYL
     ADC A,IYH
+    ADC A,IYL
@@ -1,10 +1,10 @@
     LD  A,0x04
     LD  IX,0x01F8
     SUB A,A
-    SBC A,IXL
     SBC A,IXH
+    SBC A,IXL
     LD  A,0x04
     LD  IY,0x01F8
     SUB A,A
-    SBC A,IYL
     SBC A,IYH
+    SBC A,IYL
@@ -1,5 +1,5 @@
     LD  A,0x04
     LD  IX,0x01F8
     SUB A,A
-    SUB A,IXL
     SUB A,IXH
+    SUB A,IXL
@@ -1,5 +1,5 @@
     LD  A,0x04
     LD  IY,0x01F8
     SUB A,A
-    SUB A,IYL
     SUB A,IYH
+    SUB A,IYL
@@ -0,0 +1,387 @@
+// Fichero binario de captura de ciclos del bus.
+//
+//   Cabecera:
+//     "Z80CAP"                 6 bytes
+//     versión del formato      1 byte  (FORMATO)
+//     versión del firmware     1 byte  (versión de trama con la que se capturó)
+//     marca de tiempo          8 bytes (segundos UNIX, little endian)
+//     longitud del registro    1 byte
+//     número de campos         1 byte
+//     por cada campo: longitud del nombre, nombre, posición y ancho en bytes
+//   Registros:
+//     los 11 bytes de datos de cada trama CMD_START_CAPTURE, tal cual llegan
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::protocolo;
+
+const MAGICO: &[u8; 6] = b"Z80CAP";
+
+/// Versión del formato del fichero
+pub const FORMATO: u8 = 1;
+
+/// Bytes de cada registro de captura
+pub const LONGITUD_REGISTRO: usize = 11;
+
+/// Señal de control del bus. Todas son activas a nivel bajo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Senal {
+    Rd,
+    Wr,
+    Mreq,
+    Rfsh,
+}
+
+impl Senal {
+    pub fn desde_nombre(nombre: &str) -> Option<Senal> {
+        match nombre.to_ascii_lowercase().as_str() {
+            "rd" => Some(Senal::Rd),
+            "wr" => Some(Senal::Wr),
+            "mreq" => Some(Senal::Mreq),
+            "rfsh" => Some(Senal::Rfsh),
+            _ => None,
+        }
+    }
+}
+
+/// Campo de un registro: nombre, posición y ancho en bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Campo {
+    pub nombre: String,
+    pub posicion: u8,
+    pub ancho: u8,
+}
+
+/// Disposición de las señales en los registros que envía el firmware
+pub fn campos_firmware() -> Vec<Campo> {
+    [
+        ("ciclo", 0, 2),
+        ("reloj", 2, 1),
+        ("direccion", 3, 2),
+        ("dato", 5, 1),
+        ("rd", 6, 1),
+        ("wr", 7, 1),
+        ("mreq", 8, 1),
+        ("rfsh", 9, 1),
+        ("reset", 10, 1),
+    ]
+    .iter()
+    .map(|&(nombre, posicion, ancho)| Campo {
+        nombre: String::from(nombre),
+        posicion,
+        ancho,
+    })
+    .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cabecera {
+    pub version_firmware: u8,
+    /// Segundos UNIX del comienzo de la captura
+    pub marca_tiempo: u64,
+    pub longitud_registro: u8,
+    pub campos: Vec<Campo>,
+}
+
+impl Cabecera {
+    /// Cabecera de una captura que empieza ahora con el formato del firmware actual
+    pub fn nueva() -> Cabecera {
+        let marca_tiempo = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Cabecera {
+            version_firmware: protocolo::VERSION,
+            marca_tiempo,
+            longitud_registro: LONGITUD_REGISTRO as u8,
+            campos: campos_firmware(),
+        }
+    }
+
+    pub fn escribir(&self, salida: &mut dyn Write) -> io::Result<()> {
+        let mut bytes = MAGICO.to_vec();
+        bytes.push(FORMATO);
+        bytes.push(self.version_firmware);
+        bytes.extend_from_slice(&self.marca_tiempo.to_le_bytes());
+        bytes.push(self.longitud_registro);
+        bytes.push(self.campos.len() as u8);
+        for campo in &self.campos {
+            bytes.push(campo.nombre.len() as u8);
+            bytes.extend_from_slice(campo.nombre.as_bytes());
+            bytes.push(campo.posicion);
+            bytes.push(campo.ancho);
+        }
+        salida.write_all(&bytes)
+    }
+
+    pub fn leer(entrada: &mut dyn Read) -> io::Result<Cabecera> {
+        let no_valido = |mensaje: &str| io::Error::new(io::ErrorKind::InvalidData, mensaje.to_string());
+        let mut byte = || -> io::Result<u8> {
+            let mut b = [0u8; 1];
+            entrada.read_exact(&mut b)?;
+            Ok(b[0])
+        };
+
+        let mut magico = [0u8; 6];
+        for b in magico.iter_mut() {
+            *b = byte()?;
+        }
+        if &magico != MAGICO {
+            return Err(no_valido("no es un fichero de captura"));
+        }
+        let formato = byte()?;
+        if formato != FORMATO {
+            return Err(no_valido(&format!("formato de captura {} no soportado", formato)));
+        }
+        let version_firmware = byte()?;
+        let mut marca = [0u8; 8];
+        for b in marca.iter_mut() {
+            *b = byte()?;
+        }
+        let longitud_registro = byte()?;
+        if (longitud_registro as usize) < LONGITUD_REGISTRO {
+            return Err(no_valido("registros de captura demasiado cortos"));
+        }
+        let num_campos = byte()?;
+        let mut campos = Vec::new();
+        for _ in 0..num_campos {
+            let longitud = byte()?;
+            let mut nombre = Vec::new();
+            for _ in 0..longitud {
+                nombre.push(byte()?);
+            }
+            campos.push(Campo {
+                nombre: String::from_utf8_lossy(&nombre).into_owned(),
+                posicion: byte()?,
+                ancho: byte()?,
+            });
+        }
+        Ok(Cabecera {
+            version_firmware,
+            marca_tiempo: u64::from_le_bytes(marca),
+            longitud_registro,
+            campos,
+        })
+    }
+}
+
+/// Ciclo de bus capturado. Las señales guardan el nivel del pin (0 = activa).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegistroCiclo {
+    pub ciclo: u16,
+    pub reloj: u8,
+    pub direccion: u16,
+    pub dato: u8,
+    pub rd: u8,
+    pub wr: u8,
+    pub mreq: u8,
+    pub rfsh: u8,
+    pub reset: u8,
+}
+
+impl RegistroCiclo {
+    /// Decodifica los datos de una trama de captura. El firmware envía el ciclo en big
+    /// endian y la dirección en little endian.
+    pub fn decodificar(datos: &[u8]) -> Option<RegistroCiclo> {
+        if datos.len() < LONGITUD_REGISTRO {
+            return None;
+        }
+        Some(RegistroCiclo {
+            ciclo: u16::from_be_bytes([datos[0], datos[1]]),
+            reloj: datos[2],
+            direccion: u16::from_le_bytes([datos[3], datos[4]]),
+            dato: datos[5],
+            rd: datos[6],
+            wr: datos[7],
+            mreq: datos[8],
+            rfsh: datos[9],
+            reset: datos[10],
+        })
+    }
+
+    /// Indica si la señal está activa (pin a nivel bajo)
+    pub fn activa(&self, senal: Senal) -> bool {
+        let nivel = match senal {
+            Senal::Rd => self.rd,
+            Senal::Wr => self.wr,
+            Senal::Mreq => self.mreq,
+            Senal::Rfsh => self.rfsh,
+        };
+        nivel == 0
+    }
+}
+
+impl fmt::Display for RegistroCiclo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CICLO: {:04X} - RELOJ: {:02X} - DIRECCION: {:04X} - DATOS: {:02X} - RD_PIN: {:02X} - \
+             WR_PIN: {:02X} - MREQ_PIN:{:02X} - RFSH_PIN:{:02X} - RESET_PIN:{:02X}",
+            self.ciclo, self.reloj, self.direccion, self.dato, self.rd, self.wr, self.mreq, self.rfsh, self.reset
+        )
+    }
+}
+
+/// Fichero donde se van guardando los registros de una captura en curso
+pub trait DestinoCaptura {
+    /// Guarda los datos de una trama de captura
+    fn registrar_trama(&mut self, datos: &[u8]) -> io::Result<()>;
+
+    /// Registros guardados hasta ahora
+    fn registros(&self) -> usize;
+
+    /// Vacía lo pendiente y cierra el fichero
+    fn cerrar(self: Box<Self>) -> io::Result<()>;
+}
+
+/// Escribe la cabecera al crearse y luego cada registro sin formatear
+pub struct EscritorCaptura<W: Write> {
+    salida: W,
+    pub registros: usize,
+}
+
+impl<W: Write> EscritorCaptura<W> {
+    pub fn new(mut salida: W, cabecera: &Cabecera) -> io::Result<EscritorCaptura<W>> {
+        cabecera.escribir(&mut salida)?;
+        Ok(EscritorCaptura { salida, registros: 0 })
+    }
+
+    pub fn registrar(&mut self, datos: &[u8]) -> io::Result<()> {
+        self.salida.write_all(&datos[..LONGITUD_REGISTRO])?;
+        self.registros += 1;
+        Ok(())
+    }
+
+    pub fn terminar(mut self) -> io::Result<W> {
+        self.salida.flush()?;
+        Ok(self.salida)
+    }
+}
+
+impl<W: Write> DestinoCaptura for EscritorCaptura<W> {
+    fn registrar_trama(&mut self, datos: &[u8]) -> io::Result<()> {
+        self.registrar(datos)
+    }
+
+    fn registros(&self) -> usize {
+        self.registros
+    }
+
+    fn cerrar(self: Box<Self>) -> io::Result<()> {
+        self.terminar().map(|_| ())
+    }
+}
+
+/// Lee un fichero de captura completo. Un registro final incompleto se ignora.
+pub fn leer_captura(entrada: &mut dyn Read) -> io::Result<(Cabecera, Vec<RegistroCiclo>)> {
+    let cabecera = Cabecera::leer(entrada)?;
+    let mut resto = Vec::new();
+    entrada.read_to_end(&mut resto)?;
+    let registros = resto
+        .chunks_exact(cabecera.longitud_registro as usize)
+        .filter_map(RegistroCiclo::decodificar)
+        .collect();
+    Ok((cabecera, registros))
+}
+
+/// Condiciones que debe cumplir un registro para mostrarse. Los límites son inclusivos.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filtro {
+    pub direcciones: Option<(u16, u16)>,
+    pub ciclos: Option<(u16, u16)>,
+    /// Señales que tienen que estar activas a la vez
+    pub senales: Vec<Senal>,
+}
+
+impl Filtro {
+    pub fn acepta(&self, r: &RegistroCiclo) -> bool {
+        let dentro = |rango: Option<(u16, u16)>, valor: u16| match rango {
+            Some((desde, hasta)) => desde <= valor && valor <= hasta,
+            None => true,
+        };
+        dentro(self.direcciones, r.direccion)
+            && dentro(self.ciclos, r.ciclo)
+            && self.senales.iter().all(|&s| r.activa(s))
+    }
+}
+
+/// Exporta los registros en CSV con una fila de títulos
+pub fn escribir_csv<'a>(salida: &mut dyn Write, registros: impl IntoIterator<Item = &'a RegistroCiclo>) -> io::Result<()> {
+    writeln!(salida, "ciclo,reloj,direccion,dato,rd,wr,mreq,rfsh,reset")?;
+    for r in registros {
+        writeln!(
+            salida,
+            "{},{},{:04X},{:02X},{},{},{},{},{}",
+            r.ciclo, r.reloj, r.direccion, r.dato, r.rd, r.wr, r.mreq, r.rfsh, r.reset
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registro(ciclo: u16, direccion: u16, rd: u8, wr: u8, rfsh: u8) -> [u8; 11] {
+        let [c_h, c_l] = ciclo.to_be_bytes();
+        let [d_l, d_h] = direccion.to_le_bytes();
+        [c_h, c_l, 1, d_l, d_h, 0x3E, rd, wr, 0, rfsh, 1]
+    }
+
+    #[test]
+    fn fichero_ida_y_vuelta() {
+        let cabecera = Cabecera::nueva();
+        let mut escritor = EscritorCaptura::new(Vec::new(), &cabecera).unwrap();
+        escritor.registrar(&registro(0x0102, 0x8000, 0, 1, 1)).unwrap();
+        escritor.registrar(&registro(0x0103, 0x8001, 1, 0, 1)).unwrap();
+        assert_eq!(escritor.registros, 2);
+        let mut bytes = escritor.terminar().unwrap();
+        // Registro cortado al parar la captura
+        bytes.extend_from_slice(&[0x01, 0x04]);
+
+        let (leida, registros) = leer_captura(&mut bytes.as_slice()).unwrap();
+        assert_eq!(leida, cabecera);
+        assert_eq!(leida.campos[2].nombre, "direccion");
+        assert_eq!(registros.len(), 2);
+        assert_eq!(registros[0].ciclo, 0x0102);
+        assert_eq!(registros[0].direccion, 0x8000);
+        assert!(registros[0].activa(Senal::Rd));
+        assert!(registros[1].activa(Senal::Wr));
+
+        assert!(leer_captura(&mut &b"z80_log.txt"[..]).is_err());
+    }
+
+    #[test]
+    fn filtros_y_csv() {
+        let registros: Vec<RegistroCiclo> = [
+            registro(1, 0x0000, 0, 1, 1),
+            registro(2, 0x0001, 1, 1, 0),
+            registro(3, 0x8000, 1, 0, 1),
+            registro(4, 0x8001, 0, 1, 1),
+        ]
+        .iter()
+        .filter_map(|r| RegistroCiclo::decodificar(r))
+        .collect();
+        let ciclos = |filtro: &Filtro| -> Vec<u16> {
+            registros.iter().filter(|r| filtro.acepta(r)).map(|r| r.ciclo).collect()
+        };
+
+        assert_eq!(ciclos(&Filtro::default()), vec![1, 2, 3, 4]);
+        assert_eq!(ciclos(&Filtro { direcciones: Some((0x8000, 0xFFFF)), ..Filtro::default() }), vec![3, 4]);
+        assert_eq!(ciclos(&Filtro { ciclos: Some((2, 3)), ..Filtro::default() }), vec![2, 3]);
+        assert_eq!(ciclos(&Filtro { senales: vec![Senal::Rfsh], ..Filtro::default() }), vec![2]);
+        assert_eq!(
+            ciclos(&Filtro { senales: vec![Senal::Rd, Senal::Mreq], direcciones: Some((0x8000, 0x8001)), ..Filtro::default() }),
+            vec![4]
+        );
+
+        let mut csv = Vec::new();
+        escribir_csv(&mut csv, &registros[2..3]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "ciclo,reloj,direccion,dato,rd,wr,mreq,rfsh,reset\n3,1,8000,3E,1,0,0,1,1\n"
+        );
+    }
+}
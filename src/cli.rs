@@ -0,0 +1,483 @@
+// Línea de comandos no interactiva.
+//
+//   main [--port PUERTO] [--baud BPS] <subcomando> [argumentos]
+//
+// Sin subcomando se abre el menú interactivo de siempre.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+
+use crate::captura::{escribir_csv, leer_captura, Filtro, RegistroCiclo, Senal};
+use crate::comparacion::comparar;
+use crate::cpu::bus::{Bus, Memoria};
+use crate::cpu::cpu::{EstadoEncendido, CPU};
+use crate::cpu::hex::escribir_hex;
+use crate::cpu::traza::BusTraza;
+use crate::placa_virtual::servir_en_pty;
+use crate::traza_captura::{ciclos_de_bus, decodificar_instrucciones};
+use crate::vcd::escribir_vcd;
+use crate::serial::{mostrar_hex, nombre_status, Serial, CAPTURA_POR_DEFECTO};
+use crate::transporte::Transporte;
+
+pub const AYUDA: &str = "\
+Uso: main [--port PUERTO] [--baud BPS] [SUBCOMANDO]
+
+Sin subcomando se abre el menú interactivo.
+
+Subcomandos:
+  upload <fichero> [--at DIR]   Escribe un .bin en DIR (0x0000 por defecto) o un .hex
+  run [--at DIR]                Ejecuta desde DIR (0x0000 por defecto)
+  reset [--keep-memory]         Resetea el Z80, opcionalmente sin borrar la memoria
+  dump <dir> <len> [-o fich]    Lee memoria; con -o la guarda (.hex en Intel HEX, si no en binario)
+  status                        Muestra el estado de la placa
+  regs                          Muestra los registros del Z80
+  capture [-o fich]             Captura ciclos hasta pulsar una tecla (z80_captura.cap por defecto,
+                                o VCD si fich acaba en .vcd)
+  view <fich> [filtros] [-o f]  Muestra una captura; con -o la exporta (.vcd en VCD, si no en CSV)
+      --from DIR --to DIR       Solo direcciones en el rango
+      --cycles N[-M]            Solo esos ciclos
+      --signal rd|wr|mreq|rfsh  Solo ciclos con la señal activa (se puede repetir)
+  trace <fich>                  Muestra las instrucciones ejecutadas en una captura
+  lockstep <prog> [--at DIR]    Ejecuta el programa en la placa y en el emulador y para en la
+           [--capture fich]     primera diferencia; con --capture usa una captura ya hecha
+  wait on|off                   Activa o quita la señal WAIT
+  echo                          Comprueba la comunicación con CMD_ECHO
+  virtual                       Sirve una placa virtual (el emulador) en un pseudo-terminal
+  help                          Muestra esta ayuda
+
+Los números admiten 0x1234, $1234 o decimal.";
+
+/// Subcomando pedido en la línea de comandos
+#[derive(Debug, Clone, PartialEq)]
+pub enum Comando {
+    Upload { fichero: String, direccion: u16 },
+    Run { direccion: u16 },
+    Reset { conservar_memoria: bool },
+    Dump { direccion: u16, longitud: u32, salida: Option<String> },
+    Status,
+    Regs,
+    Capture { salida: String },
+    View { fichero: String, filtro: Filtro, csv: Option<String> },
+    Trace { fichero: String },
+    Lockstep { programa: String, direccion: u16, captura: Option<String> },
+    Wait(bool),
+    Echo,
+    Virtual,
+    Ayuda,
+}
+
+/// Opciones de la línea de comandos. `comando` es None para el menú interactivo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opciones {
+    pub puerto: String,
+    pub baudios: u32,
+    pub comando: Option<Comando>,
+}
+
+/// Analiza los argumentos (sin el nombre del programa)
+pub fn analizar(args: &[String]) -> Result<Opciones, String> {
+    let mut puerto = String::from(crate::SERIAL_PORT_NAME);
+    let mut baudios = crate::BAUD_RATE;
+    let mut direccion = None;
+    let mut salida = None;
+    let mut conservar_memoria = false;
+    let mut desde = None;
+    let mut hasta = None;
+    let mut filtro = Filtro::default();
+    let mut captura = None;
+    let mut posicionales = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let mut valor = || {
+            i += 1;
+            args.get(i)
+                .cloned()
+                .ok_or_else(|| format!("falta el valor de {}", arg))
+        };
+        match arg {
+            "--port" => puerto = valor()?,
+            "--baud" => {
+                let v = valor()?;
+                baudios = v.parse().map_err(|_| format!("velocidad no válida: {}", v))?
+            }
+            "--at" => direccion = Some(numero(&valor()?)?),
+            "-o" | "--output" => salida = Some(valor()?),
+            "--keep-memory" => conservar_memoria = true,
+            "--capture" => captura = Some(valor()?),
+            "--from" => desde = Some(numero(&valor()?)?),
+            "--to" => hasta = Some(numero(&valor()?)?),
+            "--cycles" => {
+                let v = valor()?;
+                filtro.ciclos = Some(match v.split_once('-') {
+                    Some((a, b)) => (numero(a)?, numero(b)?),
+                    None => (numero(&v)?, numero(&v)?),
+                })
+            }
+            "--signal" => {
+                let v = valor()?;
+                filtro.senales.push(Senal::desde_nombre(&v).ok_or_else(|| format!("señal desconocida: {}", v))?)
+            }
+            "-h" | "--help" => posicionales.push(String::from("help")),
+            _ if arg.starts_with("--") => return Err(format!("opción desconocida: {}", arg)),
+            _ => posicionales.push(args[i].clone()),
+        }
+        i += 1;
+    }
+
+    let mut posicionales = posicionales.into_iter();
+    let subcomando = posicionales.next();
+    let argumentos: Vec<String> = posicionales.collect();
+    let esperar = |n: usize| {
+        if argumentos.len() == n {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} espera {} argumentos y ha recibido {}",
+                subcomando.as_deref().unwrap_or(""),
+                n,
+                argumentos.len()
+            ))
+        }
+    };
+
+    let comando = match subcomando.as_deref() {
+        None => None,
+        Some("upload") => {
+            esperar(1)?;
+            Some(Comando::Upload {
+                fichero: argumentos[0].clone(),
+                direccion: direccion.unwrap_or(0x0000),
+            })
+        }
+        Some("run") => {
+            esperar(0)?;
+            Some(Comando::Run {
+                direccion: direccion.unwrap_or(0x0000),
+            })
+        }
+        Some("reset") => {
+            esperar(0)?;
+            Some(Comando::Reset { conservar_memoria })
+        }
+        Some("dump") => {
+            esperar(2)?;
+            let direccion = numero(&argumentos[0])?;
+            let longitud = numero_u32(&argumentos[1])?;
+            if direccion as u32 + longitud > 0x10000 {
+                return Err(format!("{} bytes en 0x{:04X} se salen de la memoria del Z80", longitud, direccion));
+            }
+            Some(Comando::Dump {
+                direccion,
+                longitud,
+                salida,
+            })
+        }
+        Some("status") => {
+            esperar(0)?;
+            Some(Comando::Status)
+        }
+        Some("regs") => {
+            esperar(0)?;
+            Some(Comando::Regs)
+        }
+        Some("capture") => {
+            esperar(0)?;
+            Some(Comando::Capture {
+                salida: salida.unwrap_or_else(|| String::from(CAPTURA_POR_DEFECTO)),
+            })
+        }
+        Some("view") => {
+            esperar(1)?;
+            if desde.is_some() || hasta.is_some() {
+                filtro.direcciones = Some((desde.unwrap_or(0x0000), hasta.unwrap_or(0xFFFF)));
+            }
+            Some(Comando::View {
+                fichero: argumentos[0].clone(),
+                filtro,
+                csv: salida,
+            })
+        }
+        Some("trace") => {
+            esperar(1)?;
+            Some(Comando::Trace {
+                fichero: argumentos[0].clone(),
+            })
+        }
+        Some("lockstep") => {
+            esperar(1)?;
+            Some(Comando::Lockstep {
+                programa: argumentos[0].clone(),
+                direccion: direccion.unwrap_or(0x0000),
+                captura,
+            })
+        }
+        Some("wait") => {
+            esperar(1)?;
+            match argumentos[0].as_str() {
+                "on" => Some(Comando::Wait(true)),
+                "off" => Some(Comando::Wait(false)),
+                otro => return Err(format!("wait espera on u off, no {}", otro)),
+            }
+        }
+        Some("echo") => {
+            esperar(0)?;
+            Some(Comando::Echo)
+        }
+        Some("virtual") => {
+            esperar(0)?;
+            Some(Comando::Virtual)
+        }
+        Some("help") => Some(Comando::Ayuda),
+        Some(otro) => return Err(format!("subcomando desconocido: {}", otro)),
+    };
+
+    Ok(Opciones {
+        puerto,
+        baudios,
+        comando,
+    })
+}
+
+/// Convierte 0x1234, $1234 o 1234 (decimal) en un número de 16 bits
+fn numero(texto: &str) -> Result<u16, String> {
+    numero_u32(texto)?
+        .try_into()
+        .map_err(|_| format!("número no válido: {}", texto))
+}
+
+/// Como `numero` pero admite valores mayores que 0xFFFF, p.e. la longitud de un volcado
+/// de los 64 KiB
+fn numero_u32(texto: &str) -> Result<u32, String> {
+    let resultado = if let Some(hex) = texto
+        .strip_prefix("0x")
+        .or_else(|| texto.strip_prefix("0X"))
+        .or_else(|| texto.strip_prefix('$'))
+    {
+        u32::from_str_radix(hex, 16)
+    } else {
+        texto.parse()
+    };
+    resultado.map_err(|_| format!("número no válido: {}", texto))
+}
+
+/// Muestra los ciclos de un fichero de captura que pasan `filtro`, o los exporta a VCD o
+/// CSV según la extensión de `csv`
+pub fn ver_captura(fichero: &str, filtro: &Filtro, csv: Option<&str>) -> io::Result<()> {
+    let (cabecera, registros) = leer_captura(&mut BufReader::new(File::open(fichero)?))?;
+    let seleccion = registros.iter().filter(|r| filtro.acepta(r));
+    match csv {
+        Some(f) => {
+            let mut salida = BufWriter::new(File::create(f)?);
+            if f.ends_with(".vcd") {
+                escribir_vcd(&mut salida, &cabecera, seleccion)?;
+            } else {
+                escribir_csv(&mut salida, seleccion)?;
+            }
+            salida.flush()
+        }
+        None => {
+            println!(
+                "Captura de {} ciclos, firmware v{}, marca de tiempo {}",
+                registros.len(),
+                cabecera.version_firmware,
+                cabecera.marca_tiempo
+            );
+            for r in seleccion {
+                println!("{}", r);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Muestra las instrucciones reconstruidas de un fichero de captura con sus accesos
+pub fn trazar_captura(fichero: &str) -> io::Result<()> {
+    let (_, registros) = leer_captura(&mut BufReader::new(File::open(fichero)?))?;
+    for instruccion in decodificar_instrucciones(&ciclos_de_bus(&registros)) {
+        println!("{}", instruccion);
+    }
+    Ok(())
+}
+
+pub fn leer_registros_captura(fichero: &str) -> io::Result<Vec<RegistroCiclo>> {
+    leer_captura(&mut BufReader::new(File::open(fichero)?)).map(|(_, registros)| registros)
+}
+
+/// Máximo de registros que se capturan en una comparación con la placa
+const LIMITE_LOCKSTEP: usize = 100_000;
+
+/// Ejecuta `programa` (.bin en `direccion` o .hex) en el emulador y lo compara con lo que
+/// hizo la placa en `registros`. Una divergencia es un error.
+pub fn comparar_con_emulador(programa: &str, direccion: u16, registros: &[RegistroCiclo]) -> io::Result<()> {
+    // Mismos registros que el Z80 NMOS de la placa al encenderse
+    let mut cpu = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+    cpu.encender(EstadoEncendido::NMOS);
+    if programa.ends_with(".hex") {
+        cpu.bus.load_hex(programa)?;
+    } else {
+        cpu.bus.load_bin(programa, direccion)?;
+    }
+    cpu.reg.pc = direccion;
+
+    let hardware = decodificar_instrucciones(&ciclos_de_bus(registros));
+    match comparar(&mut cpu, &hardware) {
+        Ok(n) => {
+            println!("{} instrucciones iguales en la placa y en el emulador.", n);
+            Ok(())
+        }
+        Err(divergencia) => {
+            println!("{}", divergencia);
+            Err(io::Error::other("la placa y el emulador no coinciden"))
+        }
+    }
+}
+
+/// Ejecuta un subcomando con los métodos de `Serial`. Cualquier fallo se devuelve como
+/// error para que el proceso termine con código distinto de 0.
+pub fn ejecutar(serial: &mut Serial, port: &mut dyn Transporte, comando: &Comando) -> io::Result<()> {
+    match comando {
+        Comando::Upload { fichero, direccion } => serial.subir_fichero(port, fichero, *direccion),
+        Comando::Run { direccion } => serial.ejecutar_z80(port, *direccion),
+        Comando::Reset { conservar_memoria: false } => serial.reset_z80(port),
+        Comando::Reset { conservar_memoria: true } => serial.reset_z80_sin_modificar_memoria(port),
+        Comando::Dump { direccion, longitud, salida } => {
+            let datos = serial.leer_memoria(port, *direccion, *longitud as usize)?;
+            match salida {
+                Some(f) if f.ends_with(".hex") => fs::write(f, escribir_hex(*direccion, &datos)),
+                Some(f) => fs::write(f, &datos),
+                None => {
+                    mostrar_hex(&datos);
+                    Ok(())
+                }
+            }
+        }
+        Comando::Status => {
+            let status = serial.leer_status(port)?;
+            println!("{}", nombre_status(status));
+            Ok(())
+        }
+        Comando::Regs => {
+            let registros = serial.leer_registros(port)?;
+            println!("{}", registros);
+            Ok(())
+        }
+        Comando::Capture { salida } => serial.capturar(port, salida),
+        Comando::View { fichero, filtro, csv } => ver_captura(fichero, filtro, csv.as_deref()),
+        Comando::Trace { fichero } => trazar_captura(fichero),
+        Comando::Lockstep { programa, direccion, captura } => {
+            let registros = match captura {
+                Some(f) => leer_registros_captura(f)?,
+                None => {
+                    serial.subir_fichero(port, programa, *direccion)?;
+                    serial.capturar_ejecucion(port, *direccion, LIMITE_LOCKSTEP)?
+                }
+            };
+            comparar_con_emulador(programa, *direccion, &registros)
+        }
+        Comando::Wait(true) => serial.poner_wait(port),
+        Comando::Wait(false) => serial.quitar_wait(port),
+        Comando::Echo => serial.comprobar_echo(port),
+        // La placa virtual abre su propio pseudo-terminal, no usa el puerto del cliente
+        Comando::Virtual => servir_en_pty(),
+        Comando::Ayuda => {
+            println!("{}", AYUDA);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(texto: &str) -> Vec<String> {
+        texto.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn sin_subcomando_es_el_menu() {
+        let opciones = analizar(&[]).unwrap();
+        assert_eq!(opciones.puerto, crate::SERIAL_PORT_NAME);
+        assert_eq!(opciones.baudios, crate::BAUD_RATE);
+        assert_eq!(opciones.comando, None);
+    }
+
+    #[test]
+    fn subcomandos() {
+        let opciones = analizar(&args("--port /dev/ttyACM0 upload prog.bin --at 0x8000 --baud 9600")).unwrap();
+        assert_eq!(opciones.puerto, "/dev/ttyACM0");
+        assert_eq!(opciones.baudios, 9600);
+        assert_eq!(
+            opciones.comando,
+            Some(Comando::Upload {
+                fichero: String::from("prog.bin"),
+                direccion: 0x8000
+            })
+        );
+        assert_eq!(
+            analizar(&args("dump $0100 256 -o mem.hex")).unwrap().comando,
+            Some(Comando::Dump {
+                direccion: 0x0100,
+                longitud: 256,
+                salida: Some(String::from("mem.hex"))
+            })
+        );
+        assert_eq!(
+            analizar(&args("reset --keep-memory")).unwrap().comando,
+            Some(Comando::Reset {
+                conservar_memoria: true
+            })
+        );
+        assert_eq!(analizar(&args("run")).unwrap().comando, Some(Comando::Run { direccion: 0 }));
+        assert_eq!(analizar(&args("wait off")).unwrap().comando, Some(Comando::Wait(false)));
+        assert_eq!(
+            analizar(&args("dump 0 0x10000")).unwrap().comando,
+            Some(Comando::Dump {
+                direccion: 0x0000,
+                longitud: 0x10000,
+                salida: None
+            })
+        );
+        assert_eq!(analizar(&args("virtual")).unwrap().comando, Some(Comando::Virtual));
+        assert_eq!(analizar(&args("regs")).unwrap().comando, Some(Comando::Regs));
+        assert_eq!(
+            analizar(&args("trace cap.cap")).unwrap().comando,
+            Some(Comando::Trace { fichero: String::from("cap.cap") })
+        );
+        assert_eq!(
+            analizar(&args("lockstep prog.bin --at 0x100 --capture cap.cap")).unwrap().comando,
+            Some(Comando::Lockstep {
+                programa: String::from("prog.bin"),
+                direccion: 0x0100,
+                captura: Some(String::from("cap.cap"))
+            })
+        );
+        assert_eq!(
+            analizar(&args("view cap.cap --from 0x8000 --cycles 10-20 --signal rd --signal MREQ -o cap.csv")).unwrap().comando,
+            Some(Comando::View {
+                fichero: String::from("cap.cap"),
+                filtro: Filtro {
+                    direcciones: Some((0x8000, 0xFFFF)),
+                    ciclos: Some((10, 20)),
+                    senales: vec![Senal::Rd, Senal::Mreq],
+                },
+                csv: Some(String::from("cap.csv"))
+            })
+        );
+    }
+
+    #[test]
+    fn errores() {
+        assert!(analizar(&args("upload")).is_err());
+        assert!(analizar(&args("dump 0x10")).is_err());
+        assert!(analizar(&args("dump 0x10 0x10000")).is_err());
+        assert!(analizar(&args("run --at 0x10000")).is_err());
+        assert!(analizar(&args("wait quizas")).is_err());
+        assert!(analizar(&args("status --port")).is_err());
+        assert!(analizar(&args("volar")).is_err());
+        assert!(analizar(&args("view cap.cap --signal halt")).is_err());
+        assert!(analizar(&args("status --rapido")).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+// Comparación paso a paso entre el Z80 de la placa y el emulador.
+//
+// El lado del hardware son las instrucciones reconstruidas de una captura del bus
+// (`traza_captura`); el del emulador, los accesos que anota `BusTraza` al ejecutar cada
+// instrucción. La comparación para en la primera instrucción en la que no coinciden la
+// dirección, las lecturas o las escrituras.
+
+use std::fmt;
+
+use crate::cpu::bus::Memoria;
+use crate::cpu::cpu::CPU;
+use crate::cpu::traza::{Acceso, BusTraza, TipoAcceso};
+use crate::traza_captura::{InstruccionEjecutada, TipoCiclo};
+
+/// Primera diferencia entre la placa y el emulador
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergencia {
+    /// Número de instrucción, empezando en 0
+    pub indice: usize,
+    /// Contador del firmware al empezar la instrucción
+    pub ciclo: u16,
+    pub motivo: String,
+    /// Lo que hizo la placa
+    pub hardware: InstruccionEjecutada,
+    /// PC del emulador antes de ejecutar la instrucción
+    pub pc_emulador: u16,
+    /// Accesos del emulador (vacío si la diferencia está en el PC)
+    pub emulador: Vec<Acceso>,
+}
+
+impl fmt::Display for Divergencia {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Divergencia en la instrucción {} (ciclo {:04X}): {}",
+            self.indice, self.ciclo, self.motivo
+        )?;
+        writeln!(f, "Placa:")?;
+        writeln!(f, "{}", self.hardware)?;
+        write!(f, "Emulador: PC {:04X}", self.pc_emulador)?;
+        for a in &self.emulador {
+            let tipo = match a.tipo {
+                TipoAcceso::Fetch => "M1",
+                TipoAcceso::Lectura => "RD",
+                TipoAcceso::Escritura => "WR",
+            };
+            write!(f, "\n              {} {:04X} {:02X}", tipo, a.direccion, a.dato)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lecturas y escrituras, como pares (dirección, dato)
+type Patron = (Vec<(u16, u8)>, Vec<(u16, u8)>);
+
+/// Lecturas y escrituras de una instrucción en una forma comparable entre los dos lados.
+///
+/// El emulador no siempre lee en el orden del Z80 (los operandos de las instrucciones con
+/// prefijo se leen de golpe), así que las lecturas se comparan ordenadas por dirección y
+/// sin distinguir búsquedas de lecturas. Las escrituras se comparan en orden.
+fn patron(accesos: impl Iterator<Item = (bool, u16, u8)>) -> Patron {
+    let mut lecturas = Vec::new();
+    let mut escrituras = Vec::new();
+    for (escritura, direccion, dato) in accesos {
+        if escritura {
+            escrituras.push((direccion, dato));
+        } else {
+            lecturas.push((direccion, dato));
+        }
+    }
+    lecturas.sort();
+    (lecturas, escrituras)
+}
+
+/// Ejecuta en `cpu` las instrucciones que hizo la placa y compara cada una. Termina al
+/// acabarse la captura o al ejecutar un HALT y devuelve las instrucciones comparadas.
+///
+/// `cpu` debe tener cargado el mismo programa y el PC en la dirección de arranque. Los
+/// registros que no inicialice el programa (SP, por ejemplo) tienen que valer lo mismo en
+/// los dos lados para que coincidan los accesos.
+pub fn comparar<B: Memoria>(cpu: &mut CPU<BusTraza<B>>, hardware: &[InstruccionEjecutada]) -> Result<usize, Box<Divergencia>> {
+    cpu.bus.tomar_accesos();
+    for (indice, hw) in hardware.iter().enumerate() {
+        let divergencia = |motivo: String, pc_emulador: u16, emulador: Vec<Acceso>| {
+            Box::new(Divergencia {
+                indice,
+                ciclo: hw.accesos.first().map(|a| a.ciclo).unwrap_or(0),
+                motivo,
+                hardware: hw.clone(),
+                pc_emulador,
+                emulador,
+            })
+        };
+
+        let pc = cpu.reg.pc;
+        if pc != hw.instruccion.direccion {
+            return Err(divergencia(
+                format!("la placa ejecuta en {:04X} y el emulador en {:04X}", hw.instruccion.direccion, pc),
+                pc,
+                Vec::new(),
+            ));
+        }
+        cpu.execute();
+        let accesos = cpu.bus.tomar_accesos();
+
+        let (lecturas_hw, escrituras_hw) = patron(
+            hw.accesos
+                .iter()
+                .filter(|c| c.tipo != TipoCiclo::Refresco)
+                .map(|c| (c.tipo == TipoCiclo::Escritura, c.direccion, c.dato)),
+        );
+        let (lecturas_emu, escrituras_emu) =
+            patron(accesos.iter().map(|a| (a.tipo == TipoAcceso::Escritura, a.direccion, a.dato)));
+        if lecturas_hw != lecturas_emu {
+            return Err(divergencia(String::from("lecturas distintas"), pc, accesos));
+        }
+        if escrituras_hw != escrituras_emu {
+            return Err(divergencia(String::from("escrituras distintas"), pc, accesos));
+        }
+
+        if cpu.halt {
+            return Ok(indice + 1);
+        }
+    }
+    Ok(hardware.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captura::RegistroCiclo;
+    use crate::cpu::bus::Bus;
+    use crate::placa_simulada::PlacaSimulada;
+    use crate::serial::Serial;
+    use crate::traza_captura::{ciclos_de_bus, decodificar_instrucciones};
+    use std::time::Duration;
+
+    // LD SP,0xF000 ; LD HL,0x9000 ; LD (HL),0x12 ; INC (HL) ; PUSH HL ; LD A,(0x9000) ; HALT
+    const PROGRAMA: [u8; 15] = [
+        0x31, 0x00, 0xF0, 0x21, 0x00, 0x90, 0x36, 0x12, 0x34, 0xE5, 0x3A, 0x00, 0x90, 0x76, 0x00,
+    ];
+
+    // LD IX,0x9000 ; LD (IX+1),0x12 ; SET 0,(IX+1) ; LD DE,(0x9001) ; ADD A,IXL ; HALT
+    const PROGRAMA_PREFIJOS: [u8; 19] = [
+        0xDD, 0x21, 0x00, 0x90, 0xDD, 0x36, 0x01, 0x12, 0xDD, 0xCB, 0x01, 0xC6, 0xED, 0x5B, 0x01,
+        0x90, 0xDD, 0x85, 0x76,
+    ];
+
+    // LD SP,0xF000 ; LD HL,0x1234 ; XOR A ; JR NZ,$+4 ; PUSH HL ; CALL 0x0110 ... 0x0110: HALT
+    const PROGRAMA_SILICIO: [u8; 17] = [
+        0x31, 0x00, 0xF0, 0x21, 0x34, 0x12, 0xAF, 0x20, 0x02, 0xE5, 0xCD, 0x10, 0x01, 0x00, 0x00,
+        0x00, 0x76,
+    ];
+
+    /// Captura de `PROGRAMA_SILICIO` escrita a mano estado T a estado T con los ciclos de
+    /// máquina en el orden de los diagramas de tiempos del Z80, sin pasar por el emulador
+    fn captura_silicio() -> Vec<RegistroCiclo> {
+        enum M {
+            Fetch(u16, u8),
+            Lectura(u16, u8),
+            Escritura(u16, u8),
+            Internos(usize),
+        }
+        use M::*;
+        let ciclos = [
+            // LD SP,nn: 4, 3, 3
+            Fetch(0x0100, 0x31), Lectura(0x0101, 0x00), Lectura(0x0102, 0xF0),
+            // LD HL,nn: 4, 3, 3
+            Fetch(0x0103, 0x21), Lectura(0x0104, 0x34), Lectura(0x0105, 0x12),
+            // XOR A: 4
+            Fetch(0x0106, 0xAF),
+            // JR NZ,e sin saltar: 4, 3. El desplazamiento se lee igualmente.
+            Fetch(0x0107, 0x20), Lectura(0x0108, 0x02),
+            // PUSH HL: 5, 3, 3, con el byte alto primero
+            Fetch(0x0109, 0xE5), Internos(1), Escritura(0xEFFF, 0x12), Escritura(0xEFFE, 0x34),
+            // CALL nn: 4, 3, 4, 3, 3
+            Fetch(0x010A, 0xCD), Lectura(0x010B, 0x10), Lectura(0x010C, 0x01), Internos(1),
+            Escritura(0xEFFD, 0x01), Escritura(0xEFFC, 0x0D),
+            // HALT
+            Fetch(0x0110, 0x76),
+        ];
+
+        let mut registros = Vec::new();
+        let mut refresco = 0u16;
+        let mut anotar = |direccion: u16, dato: u8, senales: &str| {
+            let nivel = |s: &str| if senales.contains(s) { 0 } else { 1 };
+            let ciclo = registros.len() as u16;
+            registros.push(RegistroCiclo {
+                ciclo,
+                reloj: 1,
+                direccion,
+                dato,
+                rd: nivel("rd"),
+                wr: nivel("wr"),
+                mreq: nivel("mreq"),
+                rfsh: nivel("rfsh"),
+                reset: 1,
+            });
+        };
+        for ciclo in ciclos {
+            match ciclo {
+                Fetch(direccion, dato) => {
+                    anotar(direccion, dato, "mreq rd");
+                    anotar(direccion, dato, "mreq rd");
+                    anotar(refresco, 0xFF, "mreq rfsh");
+                    anotar(refresco, 0xFF, "mreq rfsh");
+                    refresco += 1;
+                }
+                Lectura(direccion, dato) => {
+                    for _ in 0..3 {
+                        anotar(direccion, dato, "mreq rd");
+                    }
+                }
+                Escritura(direccion, dato) => {
+                    anotar(direccion, dato, "mreq");
+                    anotar(direccion, dato, "mreq wr");
+                    anotar(direccion, dato, "mreq wr");
+                }
+                Internos(n) => {
+                    for _ in 0..n {
+                        anotar(0xFFFF, 0xFF, "");
+                    }
+                }
+            }
+        }
+        registros
+    }
+
+    /// Ejecuta el programa en una placa simulada capturando el bus
+    fn capturar_placa(programa: &[u8]) -> Vec<RegistroCiclo> {
+        let mut placa = PlacaSimulada::new();
+        let mut serial = Serial::new();
+        serial.escribir_memoria_z80(&mut placa, 0x0100, programa).unwrap();
+        serial.transaccion(&mut placa, crate::CMD_START_CAPTURE, &[], Duration::from_secs(1)).unwrap();
+        serial.ejecutar_z80(&mut placa, 0x0100).unwrap();
+        serial.quitar_wait(&mut placa).unwrap();
+        while placa.paso() > 0 {}
+        let mut registros = Vec::new();
+        while let Ok(r) = serial.leer_respuesta(&mut placa, Duration::from_millis(10)) {
+            registros.extend(RegistroCiclo::decodificar(&r.datos));
+        }
+        registros
+    }
+
+    fn emulador(programa: &[u8]) -> CPU<BusTraza> {
+        let mut cpu = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+        cpu.bus.cargar(0x0100, programa);
+        cpu.reg.pc = 0x0100;
+        cpu
+    }
+
+    #[test]
+    fn placa_y_emulador_coinciden() {
+        let hardware = decodificar_instrucciones(&ciclos_de_bus(&capturar_placa(&PROGRAMA)));
+        assert_eq!(hardware.len(), 7);
+        assert_eq!(comparar(&mut emulador(&PROGRAMA), &hardware), Ok(7));
+    }
+
+    #[test]
+    fn instrucciones_con_prefijo() {
+        // Cada byte de la instrucción aparece una sola vez en el bus, como en el Z80
+        let hardware = decodificar_instrucciones(&ciclos_de_bus(&capturar_placa(&PROGRAMA_PREFIJOS)));
+        assert_eq!(hardware.len(), 6);
+        for hw in &hardware {
+            let mut direcciones: Vec<u16> = hw
+                .accesos
+                .iter()
+                .filter(|c| c.tipo != TipoCiclo::Refresco && c.tipo != TipoCiclo::Escritura)
+                .map(|c| c.direccion)
+                .collect();
+            let total = direcciones.len();
+            direcciones.sort();
+            direcciones.dedup();
+            assert_eq!(direcciones.len(), total, "{}", hw);
+        }
+        assert_eq!(comparar(&mut emulador(&PROGRAMA_PREFIJOS), &hardware), Ok(6));
+    }
+
+    #[test]
+    fn captura_con_el_orden_del_z80() {
+        let hardware = decodificar_instrucciones(&ciclos_de_bus(&captura_silicio()));
+        let textos: Vec<String> = hardware.iter().map(|i| i.instruccion.texto()).collect();
+        assert_eq!(
+            textos,
+            vec!["LD SP,$F000", "LD HL,$1234", "XOR A", "JR NZ,$010B", "PUSH HL", "CALL $0110", "HALT"]
+        );
+        assert_eq!(comparar(&mut emulador(&PROGRAMA_SILICIO), &hardware), Ok(7));
+    }
+
+    #[test]
+    fn primera_divergencia() {
+        let mut registros = capturar_placa(&PROGRAMA);
+        // La placa escribe 0x14 en lugar de 0x13 al ejecutar INC (HL)
+        let escritura = registros.iter().position(|r| r.wr == 0 && r.dato == 0x13).unwrap();
+        registros[escritura].dato = 0x14;
+        let hardware = decodificar_instrucciones(&ciclos_de_bus(&registros));
+
+        let divergencia = comparar(&mut emulador(&PROGRAMA), &hardware).unwrap_err();
+        assert_eq!(divergencia.indice, 3);
+        assert_eq!(divergencia.motivo, "escrituras distintas");
+        assert_eq!(divergencia.hardware.instruccion.texto(), "INC (HL)");
+        assert_eq!(divergencia.pc_emulador, 0x0108);
+        let texto = divergencia.to_string();
+        assert!(texto.starts_with("Divergencia en la instrucción 3 (ciclo "));
+        assert!(texto.contains("WR 9000 13"));
+        assert!(texto.contains("WR 9000 14"));
+    }
+}
@@ -0,0 +1,618 @@
+use crate::cpu::dasm::{plantilla_indice_cb, DASM_BASE, DASM_CB, DASM_DD, DASM_ED};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Registros y condiciones: un operando con uno de estos nombres nunca es una expresión
+const RESERVADAS: [&str; 27] = [
+    "A", "B", "C", "D", "E", "H", "L", "I", "R", "AF", "AF'", "BC", "DE", "HL", "SP", "IX", "IY",
+    "IXH", "IXL", "IYH", "IYL", "NZ", "Z", "NC", "PO", "PE", "P",
+];
+
+/// Resultado de ensamblar un fichero fuente
+#[derive(Debug, Clone, PartialEq)]
+pub struct Programa {
+    /// Dirección del primer byte del binario
+    pub origen: u16,
+    /// Binario plano a partir de origen. Los huecos entre bloques .ORG se rellenan con 0
+    pub binario: Vec<u8>,
+    /// Etiquetas y constantes EQU, en mayúsculas
+    pub simbolos: BTreeMap<String, u16>,
+}
+
+/// Error de ensamblado con la línea (empezando en 1) en la que se ha producido
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorEnsamblado {
+    pub linea: usize,
+    pub mensaje: String,
+}
+
+impl fmt::Display for ErrorEnsamblado {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "línea {}: {}", self.linea, self.mensaje)
+    }
+}
+
+impl std::error::Error for ErrorEnsamblado {}
+
+/// Forma de una instrucción sacada de las tablas del desensamblador
+struct Patron {
+    /// Prefijos y opcode
+    bytes: Vec<u8>,
+    mnemonico: String,
+    operandos: Vec<String>,
+    /// DDCB/FDCB: el desplazamiento va antes del opcode
+    indice_cb: bool,
+}
+
+/// Ensambla un fichero fuente con la sintaxis de PROGRAMAS/asm:
+/// - Directivas .ORG/ORG, .END/END, DB/DEFB, DW/DEFW, DS/DEFS y EQU
+/// - Etiquetas terminadas en ':' (sin distinguir mayúsculas)
+/// - Números 0xFF00, $FF00, 0FF00H, %1010, decimales y caracteres 'a'; $ solo es la dirección actual
+/// - Los mnemónicos que genera el desensamblador, y también "SUB A,r", "AND A,r", etc.
+pub fn ensamblar(fuente: &str) -> Result<Programa, ErrorEnsamblado> {
+    let mut e = Ensamblador::new();
+    e.pasada(fuente, false)?;
+    e.pasada(fuente, true)?;
+    Ok(e.programa())
+}
+
+struct Ensamblador {
+    patrones: Vec<Patron>,
+    simbolos: BTreeMap<String, u16>,
+    pc: u16,
+    // Dirección de la línea actual, el valor de $
+    pc_linea: u16,
+    memoria: Vec<u8>,
+    // Rango de direcciones escritas: [inicio, fin)
+    inicio: Option<usize>,
+    fin: usize,
+    // En la primera pasada las etiquetas aún no definidas valen 0
+    definitivo: bool,
+}
+
+impl Ensamblador {
+    fn new() -> Ensamblador {
+        Ensamblador {
+            patrones: patrones(),
+            simbolos: BTreeMap::new(),
+            pc: 0,
+            pc_linea: 0,
+            memoria: vec![0; 0x10000],
+            inicio: None,
+            fin: 0,
+            definitivo: false,
+        }
+    }
+
+    fn programa(&self) -> Programa {
+        let inicio = self.inicio.unwrap_or(0);
+        Programa {
+            origen: inicio as u16,
+            binario: self.memoria[inicio..self.fin.max(inicio)].to_vec(),
+            simbolos: self.simbolos.clone(),
+        }
+    }
+
+    fn pasada(&mut self, fuente: &str, definitivo: bool) -> Result<(), ErrorEnsamblado> {
+        self.definitivo = definitivo;
+        self.pc = 0;
+        self.memoria.fill(0);
+        self.inicio = None;
+        self.fin = 0;
+        for (n, linea) in fuente.lines().enumerate() {
+            let seguir = self.linea(linea).map_err(|mensaje| ErrorEnsamblado {
+                linea: n + 1,
+                mensaje,
+            })?;
+            if !seguir {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Procesa una línea. Devuelve false al llegar a .END
+    fn linea(&mut self, linea: &str) -> Result<bool, String> {
+        let mut resto = quitar_comentario(linea).trim();
+        if resto.is_empty() {
+            return Ok(true);
+        }
+        self.pc_linea = self.pc;
+
+        // Etiqueta "nombre:" o constante "nombre EQU valor"
+        let mut etiqueta = None;
+        let (primero, tras_primero) = separar(resto);
+        if let Some(nombre) = primero.strip_suffix(':') {
+            etiqueta = Some(nombre.to_uppercase());
+            resto = tras_primero;
+        } else if separar(tras_primero).0.eq_ignore_ascii_case("EQU") {
+            etiqueta = Some(primero.to_uppercase());
+            resto = tras_primero;
+        }
+
+        let (mnemonico, operandos) = separar(resto);
+        let mnemonico = mnemonico.to_uppercase();
+        if mnemonico == "EQU" {
+            let nombre = etiqueta.ok_or("EQU sin nombre")?;
+            let valor = self.evaluar(operandos)?;
+            self.definir(nombre, valor as u16)?;
+            return Ok(true);
+        }
+        if let Some(nombre) = etiqueta {
+            self.definir(nombre, self.pc)?;
+        }
+
+        match mnemonico.as_str() {
+            "" => {}
+            ".ORG" | "ORG" => self.pc = self.evaluar(operandos)? as u16,
+            ".END" | "END" => return Ok(false),
+            "DB" | "DEFB" | ".DB" | ".BYTE" => {
+                for elemento in dividir(operandos) {
+                    if let Some(texto) = cadena(&elemento) {
+                        self.emitir(texto.as_bytes());
+                    } else {
+                        let valor = self.evaluar(&elemento)?;
+                        self.emitir(&[self.byte(valor)?]);
+                    }
+                }
+            }
+            "DW" | "DEFW" | ".DW" | ".WORD" => {
+                for elemento in dividir(operandos) {
+                    let valor = self.evaluar(&elemento)?;
+                    self.emitir(&self.palabra(valor)?.to_le_bytes());
+                }
+            }
+            "DS" | "DEFS" | ".DS" => {
+                let n = self.evaluar(operandos)?;
+                self.emitir(&vec![0; n.max(0) as usize]);
+            }
+            _ => {
+                let operandos: Vec<String> = dividir(operandos)
+                    .iter()
+                    .map(|o| normalizar(o))
+                    .collect();
+                let bytes = self.instruccion(&mnemonico, operandos)?;
+                self.emitir(&bytes);
+            }
+        }
+        Ok(true)
+    }
+
+    fn definir(&mut self, nombre: String, valor: u16) -> Result<(), String> {
+        if !self.definitivo && self.simbolos.insert(nombre.clone(), valor).is_some() {
+            return Err(format!("etiqueta duplicada: {}", nombre));
+        }
+        Ok(())
+    }
+
+    fn emitir(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            let dir = self.pc as usize;
+            self.memoria[dir] = b;
+            self.inicio = Some(self.inicio.map_or(dir, |i| i.min(dir)));
+            self.fin = self.fin.max(dir + 1);
+            self.pc = self.pc.wrapping_add(1);
+        }
+    }
+
+    /// Codifica una instrucción buscando el primer patrón de las tablas que encaja con los
+    /// operandos. Las tablas se recorren de la forma más corta a la más larga, así que
+    /// LD (nn),HL se ensambla como 22 nn y no como ED 63 nn.
+    fn instruccion(&self, mnemonico: &str, mut operandos: Vec<String>) -> Result<Vec<u8>, String> {
+        // Forma alternativa con el acumulador explícito: SUB A,B
+        if matches!(mnemonico, "SUB" | "AND" | "XOR" | "OR" | "CP")
+            && operandos.len() == 2
+            && operandos[0] == "A"
+        {
+            operandos.remove(0);
+        }
+
+        if mnemonico == "RST" && operandos.len() == 1 {
+            let destino = self.evaluar(&operandos[0])?;
+            if destino & !0x38 != 0 {
+                return Err(format!("RST a una dirección no válida: {}", operandos[0]));
+            }
+            return Ok(vec![0xC7 | destino as u8]);
+        }
+
+        let patron = self
+            .patrones
+            .iter()
+            .find(|p| {
+                p.mnemonico == mnemonico
+                    && p.operandos.len() == operandos.len()
+                    && p.operandos.iter().zip(&operandos).all(|(p, o)| encaja(p, o))
+            })
+            .ok_or_else(|| format!("instrucción no válida: {} {}", mnemonico, operandos.join(",")))?;
+
+        let mut d = None;
+        let mut inmediato = Vec::new();
+        let tamano = patron.bytes.len()
+            + patron.operandos.iter().map(|p| bytes_operando(p)).sum::<usize>();
+        for (p, o) in patron.operandos.iter().zip(&operandos) {
+            if p.contains("{d}") {
+                let (_, desplazamiento) = operando_indice(o).unwrap();
+                let valor = if desplazamiento.is_empty() {
+                    0
+                } else {
+                    self.evaluar(desplazamiento)?
+                };
+                if self.definitivo && !(-128..=127).contains(&valor) {
+                    return Err(format!("desplazamiento fuera de rango: {}", o));
+                }
+                d = Some(valor as u8);
+            } else if p.contains("{nn}") {
+                let valor = self.evaluar(o.trim_start_matches('(').trim_end_matches(')'))?;
+                inmediato.extend_from_slice(&self.palabra(valor)?.to_le_bytes());
+            } else if p.contains("{n}") {
+                let valor = self.evaluar(o.trim_start_matches('(').trim_end_matches(')'))?;
+                inmediato.push(self.byte(valor)?);
+            } else if p == "{e}" {
+                let destino = self.evaluar(o)?;
+                let salto = destino - (self.pc as i32 + tamano as i32);
+                if self.definitivo && !(-128..=127).contains(&salto) {
+                    return Err(format!("salto relativo fuera de rango: {}", o));
+                }
+                inmediato.push(salto as u8);
+            }
+        }
+
+        let mut bytes = patron.bytes.clone();
+        if let Some(d) = d {
+            if patron.indice_cb {
+                bytes.insert(2, d);
+            } else {
+                bytes.push(d);
+            }
+        }
+        bytes.extend(inmediato);
+        Ok(bytes)
+    }
+
+    fn byte(&self, valor: i32) -> Result<u8, String> {
+        if self.definitivo && !(-128..=255).contains(&valor) {
+            return Err(format!("valor fuera de rango para un byte: {}", valor));
+        }
+        Ok(valor as u8)
+    }
+
+    fn palabra(&self, valor: i32) -> Result<u16, String> {
+        if self.definitivo && !(-32768..=65535).contains(&valor) {
+            return Err(format!("valor fuera de rango para una palabra: {}", valor));
+        }
+        Ok(valor as u16)
+    }
+
+    /// Evalúa una expresión de sumas y restas de números, caracteres y etiquetas
+    fn evaluar(&self, expresion: &str) -> Result<i32, String> {
+        let expresion = expresion.trim();
+        if expresion.is_empty() {
+            return Err(String::from("falta un operando"));
+        }
+        let mut total = 0;
+        let mut signo = 1;
+        let mut termino = String::new();
+        let mut caracteres = expresion.chars().peekable();
+        while let Some(c) = caracteres.next() {
+            match c {
+                '\'' => {
+                    termino.push(c);
+                    for c in caracteres.by_ref() {
+                        termino.push(c);
+                        if c == '\'' {
+                            break;
+                        }
+                    }
+                }
+                '+' | '-' => {
+                    if !termino.trim().is_empty() {
+                        total += signo * self.termino(termino.trim())?;
+                        termino.clear();
+                        signo = 1;
+                    }
+                    if c == '-' {
+                        signo = -signo;
+                    }
+                }
+                _ => termino.push(c),
+            }
+        }
+        if termino.trim().is_empty() {
+            return Err(format!("expresión incompleta: {}", expresion));
+        }
+        Ok(total + signo * self.termino(termino.trim())?)
+    }
+
+    fn termino(&self, termino: &str) -> Result<i32, String> {
+        let numero = |digitos: &str, base: u32| {
+            i32::from_str_radix(digitos, base).map_err(|_| format!("número no válido: {}", termino))
+        };
+        let mayusculas = termino.to_uppercase();
+        if termino == "$" {
+            Ok(self.pc_linea as i32)
+        } else if let Some(c) = cadena(termino) {
+            let mut c = c.chars();
+            match (c.next(), c.next()) {
+                (Some(c), None) => Ok(c as i32),
+                _ => Err(format!("carácter no válido: {}", termino)),
+            }
+        } else if let Some(hex) = mayusculas.strip_prefix("0X").or(mayusculas.strip_prefix('$')) {
+            numero(hex, 16)
+        } else if let Some(bin) = mayusculas.strip_prefix('%') {
+            numero(bin, 2)
+        } else if mayusculas.starts_with(|c: char| c.is_ascii_digit()) {
+            match mayusculas.strip_suffix('H') {
+                Some(hex) => numero(hex, 16),
+                None => numero(&mayusculas, 10),
+            }
+        } else {
+            match self.simbolos.get(&mayusculas) {
+                Some(&valor) => Ok(valor as i32),
+                None if !self.definitivo => Ok(0),
+                None => Err(format!("etiqueta no definida: {}", termino)),
+            }
+        }
+    }
+}
+
+/// Construye los patrones de todas las instrucciones a partir de las tablas del desensamblador
+fn patrones() -> Vec<Patron> {
+    let mut patrones = Vec::new();
+    for op in 0..=255u8 {
+        agregar(&mut patrones, vec![op], DASM_BASE[op as usize], false);
+    }
+    for op in 0..=255u8 {
+        agregar(&mut patrones, vec![0xCB, op], DASM_CB[op as usize], false);
+    }
+    for op in 0..=255u8 {
+        agregar(&mut patrones, vec![0xED, op], DASM_ED[op as usize], false);
+    }
+    for (prefijo, indice) in [(0xDD, "IX"), (0xFD, "IY")] {
+        for op in 0..=255u8 {
+            let plantilla = DASM_DD[op as usize].replace("IX", indice);
+            agregar(&mut patrones, vec![prefijo, op], &plantilla, false);
+        }
+        for op in 0..=255u8 {
+            let plantilla = plantilla_indice_cb(op).replace("IX", indice);
+            agregar(&mut patrones, vec![prefijo, 0xCB, op], &plantilla, true);
+        }
+    }
+    patrones
+}
+
+fn agregar(patrones: &mut Vec<Patron>, bytes: Vec<u8>, plantilla: &str, indice_cb: bool) {
+    if plantilla.is_empty() {
+        return;
+    }
+    let (mnemonico, operandos) = separar(plantilla);
+    patrones.push(Patron {
+        bytes,
+        mnemonico: mnemonico.to_string(),
+        operandos: dividir(operandos),
+        indice_cb,
+    });
+}
+
+/// Bytes que añade un operando del patrón además del opcode
+fn bytes_operando(patron: &str) -> usize {
+    if patron.contains("{nn}") {
+        2
+    } else if patron.contains("{n}") || patron.contains("{e}") || patron.contains("{d}") {
+        1
+    } else {
+        0
+    }
+}
+
+/// Comprueba si un operando del fuente (ya normalizado) encaja con un operando del patrón
+fn encaja(patron: &str, operando: &str) -> bool {
+    match patron {
+        "{n}" | "{nn}" | "{e}" => es_expresion(operando),
+        "({n})" | "({nn})" => {
+            operando_indice(operando).is_none()
+                && operando
+                    .strip_prefix('(')
+                    .and_then(|o| o.strip_suffix(')'))
+                    .is_some_and(es_expresion)
+        }
+        _ if patron.contains("{d}") => {
+            operando_indice(operando).is_some_and(|(registro, _)| patron[1..3] == *registro)
+        }
+        _ => patron == operando,
+    }
+}
+
+fn es_expresion(operando: &str) -> bool {
+    !operando.is_empty() && !operando.starts_with('(') && !RESERVADAS.contains(&operando)
+}
+
+/// Separa "(IX+expr)" en ("IX", "+expr"). "(IX)" equivale a "(IX+0)"
+fn operando_indice(operando: &str) -> Option<(&str, &str)> {
+    let interior = operando.strip_prefix('(')?.strip_suffix(')')?;
+    let registro = interior.get(..2)?;
+    let resto = &interior[2..];
+    if (registro == "IX" || registro == "IY")
+        && (resto.is_empty() || resto.starts_with('+') || resto.starts_with('-'))
+    {
+        Some((registro, resto))
+    } else {
+        None
+    }
+}
+
+/// Separa la primera palabra del resto de la línea
+fn separar(texto: &str) -> (&str, &str) {
+    let texto = texto.trim();
+    match texto.find(char::is_whitespace) {
+        Some(i) => (&texto[..i], texto[i..].trim()),
+        None => (texto, ""),
+    }
+}
+
+/// Divide los operandos por comas, respetando las cadenas entre comillas
+fn dividir(operandos: &str) -> Vec<String> {
+    let mut resultado = Vec::new();
+    if operandos.trim().is_empty() {
+        return resultado;
+    }
+    let mut actual = String::new();
+    let mut comillas = None;
+    for c in operandos.chars() {
+        match (c, comillas) {
+            ('"', None) => comillas = Some('"'),
+            // AF' no abre una cadena
+            ('\'', None) if !actual.trim().eq_ignore_ascii_case("AF") => comillas = Some('\''),
+            (c, Some(q)) if c == q => comillas = None,
+            (',', None) => {
+                resultado.push(actual.trim().to_string());
+                actual.clear();
+                continue;
+            }
+            _ => {}
+        }
+        actual.push(c);
+    }
+    resultado.push(actual.trim().to_string());
+    resultado
+}
+
+/// Contenido de una cadena "texto" o 'texto'
+fn cadena(elemento: &str) -> Option<&str> {
+    let elemento = elemento.trim();
+    if elemento.len() >= 2 {
+        for q in ['"', '\''] {
+            if let Some(interior) = elemento.strip_prefix(q).and_then(|e| e.strip_suffix(q)) {
+                return Some(interior);
+            }
+        }
+    }
+    None
+}
+
+/// Quita espacios y pasa a mayúsculas un operando, salvo los caracteres entre comillas
+fn normalizar(operando: &str) -> String {
+    let mut resultado = String::new();
+    let mut comillas = false;
+    for c in operando.chars() {
+        if c == '\'' && !resultado.ends_with("AF") {
+            comillas = !comillas;
+        }
+        if comillas || c == '\'' {
+            resultado.push(c);
+        } else if !c.is_whitespace() {
+            resultado.push(c.to_ascii_uppercase());
+        }
+    }
+    resultado
+}
+
+fn quitar_comentario(linea: &str) -> &str {
+    let mut comillas = None;
+    for (i, c) in linea.char_indices() {
+        match (c, comillas) {
+            (';', None) => return &linea[..i],
+            ('"', None) => comillas = Some('"'),
+            (c, Some(q)) if c == q => comillas = None,
+            _ => {}
+        }
+    }
+    linea
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::dasm::{desensamblar_bloque, Instruccion};
+    use std::fs;
+
+    #[test]
+    fn programas_asm_igual_que_bin() {
+        // Todos los fuentes de PROGRAMAS/asm deben generar el mismo binario que el ensamblador externo
+        let mut comprobados = 0;
+        for entrada in fs::read_dir("PROGRAMAS/asm").unwrap() {
+            let ruta = entrada.unwrap().path();
+            let bin = ruta
+                .with_extension("bin")
+                .to_string_lossy()
+                .replace("PROGRAMAS/asm", "PROGRAMAS/bin");
+            let Ok(esperado) = fs::read(&bin) else {
+                continue;
+            };
+            let fuente = fs::read_to_string(&ruta).unwrap();
+            let programa = ensamblar(&fuente).unwrap_or_else(|e| panic!("{:?}: {}", ruta, e));
+            assert_eq!(programa.binario, esperado, "{:?}", ruta);
+            comprobados += 1;
+        }
+        assert!(comprobados > 50);
+    }
+
+    #[test]
+    fn etiquetas_y_directivas() {
+        let fuente = "
+            .ORG 0x8000
+    inicio: LD HL,texto     ; comentario
+            LD B,fin-texto
+    bucle:  DJNZ bucle
+            JR inicio
+    texto:  DB \"Hola;\",0x0D,'!'
+    fin:    DW inicio,$
+    CUENTA  EQU 3
+            DS CUENTA
+            .END
+            NOP";
+        let programa = ensamblar(fuente).unwrap();
+        assert_eq!(programa.origen, 0x8000);
+        assert_eq!(programa.simbolos["INICIO"], 0x8000);
+        assert_eq!(programa.simbolos["TEXTO"], 0x8009);
+        assert_eq!(programa.simbolos["FIN"], 0x8010);
+        assert_eq!(programa.simbolos["CUENTA"], 3);
+        assert_eq!(
+            programa.binario,
+            vec![
+                0x21, 0x09, 0x80, 0x06, 0x07, 0x10, 0xFE, 0x18, 0xF7, b'H', b'o', b'l', b'a', b';',
+                0x0D, b'!', 0x00, 0x80, 0x10, 0x80, 0, 0, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn ida_y_vuelta_con_el_desensamblador() {
+        // Lo que emite el desensamblador se vuelve a ensamblar a los mismos bytes
+        let mut datos = Vec::new();
+        for op in 0..=255u8 {
+            datos.extend_from_slice(&[0xDD, 0xCB, 0x85, op]);
+            datos.extend_from_slice(&[0xED, op, 0x34, 0x12]);
+        }
+        for op in 0..=255u8 {
+            datos.extend_from_slice(&[op, 0x01, 0x02, 0x03]);
+            datos.extend_from_slice(&[0xFD, op, 0xFE, 0x12, 0x34]);
+        }
+        let instrucciones = desensamblar_bloque(&datos, 0x4000);
+        let mut fuente = String::from(".ORG 0x4000\n");
+        for i in &instrucciones {
+            fuente.push_str(&i.texto());
+            fuente.push('\n');
+        }
+        let programa = ensamblar(&fuente).unwrap();
+        // Las formas duplicadas (NEG, IM, RETN, ED 63...) se ensamblan con su primer opcode,
+        // así que se compara el texto al volver a desensamblar
+        let textos = |instrucciones: Vec<Instruccion>| -> Vec<String> {
+            instrucciones.iter().map(|i| i.texto()).collect()
+        };
+        assert_eq!(
+            textos(desensamblar_bloque(&programa.binario, 0x4000)),
+            textos(instrucciones)
+        );
+    }
+
+    #[test]
+    fn errores() {
+        let error = ensamblar("NOP\nLD A,(DE),B").unwrap_err();
+        assert_eq!(error.linea, 2);
+        assert!(ensamblar("JP nada").is_err());
+        assert!(ensamblar("x: NOP\nx: NOP").is_err());
+        assert!(ensamblar("JR $+200").is_err());
+        assert!(ensamblar("LD A,256").is_err());
+        assert!(ensamblar("RST 0x39").is_err());
+    }
+}
@@ -1,4 +1,132 @@
-use std::{fs::File, io::{prelude::*, self}};
+use std::{fs::{self, File}, io::{prelude::*, self}};
+
+use crate::cpu::hex::{escribir_hex, leer_hex};
+
+/// Mapa de memoria visto por la CPU.
+///
+/// `CPU` es genérica sobre este trait, de modo que cada máquina puede aportar su propio
+/// mapa de memoria: regiones espejo, dispositivos mapeados en memoria, bancos de RAM...
+/// Solo `leer_byte` y `escribir_byte` son obligatorios; el resto de operaciones se
+/// construyen sobre ellos. La estructura `Bus` es la implementación por defecto.
+pub trait Memoria {
+    /// Lee un byte de la memoria sin efectos secundarios (desensamblador, volcados...)
+    fn leer_byte(&self, direccion: u16) -> u8;
+
+    /// Número de bytes direccionables. Por defecto los 64 KiB del Z80.
+    fn tamano(&self) -> usize {
+        0x10000
+    }
+
+    /// Escribe un byte en la memoria
+    fn escribir_byte(&mut self, direccion: u16, data: u8);
+
+    /// Lectura de datos hecha por la CPU. Por defecto es `leer_byte`; un dispositivo
+    /// mapeado en memoria puede sobreescribirla para reaccionar al acceso (registros de
+    /// estado que se borran al leerse, cambio de banco...).
+    fn leer(&mut self, direccion: u16) -> u8 {
+        self.leer_byte(direccion)
+    }
+
+    /// Lee el código de operación de un ciclo M1. Por defecto es una lectura normal;
+    /// una máquina puede sobreescribirlo para detectar búsquedas de instrucciones
+    /// (paginación automática de ROM, trazas, contención...).
+    fn fetch(&mut self, direccion: u16) -> u8 {
+        self.leer(direccion)
+    }
+
+    /// Como `read_word`, pero con dos lecturas de la CPU (`leer`)
+    fn leer_word(&mut self, direccion: u16) -> u16 {
+        u16::from(self.leer(direccion)) | (u16::from(self.leer(direccion.wrapping_add(1))) << 8)
+    }
+
+    /// Lee una palabra almacenada en la memoria en orden de bytes little endian y
+    /// devuelve esta palabra en orden de bytes big endian
+    fn read_word(&self, direccion: u16) -> u16 {
+        u16::from(self.leer_byte(direccion))
+            | (u16::from(self.leer_byte(direccion.wrapping_add(1))) << 8)
+    }
+
+    /// Lee una palabra (16 bits) almacenada en memoria en orden de bytes Little Endian y
+    /// devuelve esta palabra en orden de bytes Big Endian.
+    fn read_le_word(&self, direccion: u16) -> u16 {
+        u16::from(self.leer_byte(direccion)) << 8
+            | u16::from(self.leer_byte(direccion.wrapping_add(1)))
+    }
+
+    /// Lee una doble palabra (32 bits) almacenada en memoria en orden de bytes Little Endian
+    /// y devuelve esta doble palabra en orden de bytes Little Endian.
+    fn read_le_dword(&self, direccion: u16) -> u32 {
+        u32::from(self.leer_byte(direccion)) << 24
+            | u32::from(self.leer_byte(direccion.wrapping_add(1))) << 16
+            | u32::from(self.leer_byte(direccion.wrapping_add(2))) << 8
+            | u32::from(self.leer_byte(direccion.wrapping_add(3)))
+    }
+
+    /// Escribe una palabra (16 bits) en memoria en orden de bytes Little Endian.
+    fn write_word(&mut self, direccion: u16, data: u16) {
+        self.escribir_byte(direccion, (data & 0xFF) as u8);
+        self.escribir_byte(direccion.wrapping_add(1), (data >> 8) as u8);
+    }
+
+    /// Copia un bloque de bytes a partir de 'direccion'. Por defecto usa escribir_byte;
+    /// las implementaciones con ROM lo sobreescriben para poder cargar su contenido.
+    fn cargar(&mut self, direccion: u16, datos: &[u8]) {
+        for (i, dato) in datos.iter().enumerate() {
+            self.escribir_byte(direccion.wrapping_add(i as u16), *dato);
+        }
+    }
+
+    /// Carga datos binarios desde el disco a la memoria en la dirección $0000 + offset.
+    /// Devuelve el tamaño del archivo cargado.
+    fn load_bin(&mut self, file: &str, org: u16) -> io::Result<usize> {
+        let mut f = File::open(file)?;
+        let mut buf = Vec::new();
+        let s = f.read_to_end(&mut buf)?;
+        comprobar_espacio(self.tamano(), org, s)?;
+        self.cargar(org, &buf);
+        Ok(s)
+    }
+
+    /// Carga un fichero Intel HEX, cada registro en su dirección.
+    /// Devuelve el número de bytes cargados.
+    fn load_hex(&mut self, file: &str) -> io::Result<usize> {
+        let texto = fs::read_to_string(file)?;
+        let segmentos =
+            leer_hex(&texto).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for s in &segmentos {
+            comprobar_espacio(self.tamano(), s.direccion, s.datos.len())?;
+        }
+        let mut total = 0;
+        for s in &segmentos {
+            self.cargar(s.direccion, &s.datos);
+            total += s.datos.len();
+        }
+        Ok(total)
+    }
+
+    /// Devuelve 'longitud' bytes de memoria a partir de 'direccion' en formato Intel HEX.
+    fn dump_hex(&self, direccion: u16, longitud: usize) -> String {
+        let datos: Vec<u8> = (0..longitud)
+            .map(|i| self.leer_byte(direccion.wrapping_add(i as u16)))
+            .collect();
+        escribir_hex(direccion, &datos)
+    }
+}
+
+/// Error si 'longitud' bytes a partir de 'direccion' no caben en una memoria de 'tamano'
+/// bytes
+fn comprobar_espacio(tamano: usize, direccion: u16, longitud: usize) -> io::Result<()> {
+    if direccion as usize + longitud > tamano {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} bytes en ${:04X} no caben en una memoria de {} bytes",
+                longitud, direccion, tamano
+            ),
+        ));
+    }
+    Ok(())
+}
 
 /// La estructura Bus aloja el mapa de memoria Z80.
 pub struct Bus {
@@ -46,90 +174,41 @@ impl Bus {
             self.espacio_direcc[m] = 0;
         }
     }
+}
 
-    /// Lee un byte de la memoria
-    pub fn leer_byte(&self, direccion: u16) -> u8 {
+impl Memoria for Bus {
+    fn leer_byte(&self, direccion: u16) -> u8 {
         if direccion as usize >= self.espacio_direcc.len() {
             return 0;
         }
         self.espacio_direcc[usize::from(direccion)]
     }
 
-    /// Escribe un byte en la memoria
-    pub fn escribir_byte(&mut self, direccion: u16, data: u8) {
-        if direccion as usize >= self.espacio_direcc.len() {
-            return;
-        }
-        // Si se declara espacio rom y se solicita una operación de escritura en el área rom: salimos
-        if self.espacio_rom.is_some()
-            && direccion >= self.espacio_rom.as_ref().unwrap().inicio
-            && direccion <= self.espacio_rom.as_ref().unwrap().fin
-        {
-            return;
-        };
-        self.espacio_direcc[usize::from(direccion)] = data;
-    }
-
-    /// Lee una palabra almacenada en la memoria en orden de bytes little endian y
-    /// devuelve esta palabra en orden de bytes big endian
-    pub fn read_word(&self, direccion: u16) -> u16 {
-        if direccion as usize >= self.espacio_direcc.len() {
-            return 0;
-        }
-        u16::from(self.espacio_direcc[usize::from(direccion)])
-            | (u16::from(self.espacio_direcc[usize::from(direccion + 1)]) << 8)
-    }
-
-    /// Lee una palabra (16 bits) almacenada en memoria en orden de bytes Little Endian y
-    /// devuelve esta palabra en orden de bytes Big Endian.
-    pub fn read_le_word(&self, direccion: u16) -> u16 {
-        if direccion as usize >= self.espacio_direcc.len() {
-            return 0;
-        }
-        u16::from(self.espacio_direcc[usize::from(direccion)]) << 8
-            | (u16::from(self.espacio_direcc[usize::from(direccion + 1)]))
-    }
-
-    /// Lee una doble palabra (32 bits) almacenada en memoria en orden de bytes Little Endian
-    /// y devuelve esta doble palabra en orden de bytes Little Endian.
-    pub fn read_le_dword(&self, direccion: u16) -> u32 {
-        if direccion as usize >= self.espacio_direcc.len() {
-            return 0;
-        }
-        u32::from(self.espacio_direcc[usize::from(direccion)]) << 24
-            | u32::from(self.espacio_direcc[usize::from(direccion + 1)]) << 16
-            | u32::from(self.espacio_direcc[usize::from(direccion + 2)]) << 8
-            | u32::from(self.espacio_direcc[usize::from(direccion + 3)])
+    fn tamano(&self) -> usize {
+        self.espacio_direcc.len()
     }
 
-    /// Escribe una palabra (16 bits) en memoria en orden de bytes Little Endian.
-    pub fn write_word(&mut self, direccion: u16, data: u16) {
+    fn escribir_byte(&mut self, direccion: u16, data: u8) {
         if direccion as usize >= self.espacio_direcc.len() {
             return;
         }
-        // Si el espacio de ROM está declarado y se solicita una operación
-        // de escritura en el área de ROM: salimos.
+        // Si se declara espacio rom y se solicita una operación de escritura en el área rom: salimos
         if self.espacio_rom.is_some()
             && direccion >= self.espacio_rom.as_ref().unwrap().inicio
             && direccion <= self.espacio_rom.as_ref().unwrap().fin
         {
             return;
         };
-        self.espacio_direcc[usize::from(direccion)] = (data & 0xFF) as u8;
-        self.espacio_direcc[usize::from(direccion + 1)] = (data >> 8) as u8;
+        self.espacio_direcc[usize::from(direccion)] = data;
     }
 
-    /// Carga datos binarios desde el disco a la memoria en la dirección $0000 + offset.
-    /// Devuelve el tamaño del archivo cargado.
-    pub fn load_bin(&mut self, file: &str, org: u16) -> io::Result<usize> {
-        if org as usize >= self.espacio_direcc.len() {
+    // La carga ignora el espacio de ROM: es la forma de poner su contenido en memoria.
+    fn cargar(&mut self, direccion: u16, datos: &[u8]) {
+        if direccion as usize + datos.len() > self.espacio_direcc.len() {
             panic!("Write operation after the end of address space !")
         }
-        let mut f = File::open(file)?;
-        let mut buf = Vec::new();
-        let s = f.read_to_end(&mut buf)?;
-        self.espacio_direcc[org as usize..(buf.len() + org as usize)].clone_from_slice(&buf[..]);
-        Ok(s)
+        self.espacio_direcc[direccion as usize..(datos.len() + direccion as usize)]
+            .clone_from_slice(datos);
     }
 }
 
@@ -162,6 +241,16 @@ mod tests {
         assert_eq!(b.leer_byte(0x0000), 0xFF);
     }
 
+    #[test]
+    fn load_romspace() {
+        let mut b = Bus::new(0x00FF);
+        b.set_espacio_rom(0x0000, 0x000F);
+        b.cargar(0x000E, &[0x11, 0x22, 0x33]);
+        assert_eq!(b.read_mem_slice(0x000E, 0x0010), vec![0x11, 0x22, 0x33]);
+        b.write_word(0x000F, 0xAAAA);
+        assert_eq!(b.read_word(0x000F), 0xAA22);
+    }
+
     #[test]
     fn clear_slice() {
         let mut b = Bus::new(0x000F);
@@ -172,4 +261,105 @@ mod tests {
         b.clear_mem_slice(0x0000, 0x000F);
         assert_eq!(b.leer_byte(0x000F), 0x00);
     }
+
+    // Memoria de 1 KiB repetida en todo el espacio de direcciones, con un
+    // registro mapeado en 0xFFFF que cuenta las escrituras que recibe.
+    struct Espejo {
+        ram: Vec<u8>,
+        contador: u8,
+        fetches: Vec<u16>,
+    }
+
+    impl Memoria for Espejo {
+        fn leer_byte(&self, direccion: u16) -> u8 {
+            match direccion {
+                0xFFFF => self.contador,
+                _ => self.ram[(direccion & 0x03FF) as usize],
+            }
+        }
+
+        fn escribir_byte(&mut self, direccion: u16, data: u8) {
+            match direccion {
+                0xFFFF => self.contador = self.contador.wrapping_add(1),
+                _ => self.ram[(direccion & 0x03FF) as usize] = data,
+            }
+        }
+
+        fn fetch(&mut self, direccion: u16) -> u8 {
+            self.fetches.push(direccion);
+            self.leer_byte(direccion)
+        }
+    }
+
+    #[test]
+    fn cpu_con_memoria_propia() {
+        use crate::cpu::cpu::CPU;
+        let mut c = CPU::con_bus(Espejo {
+            ram: vec![0; 0x0400],
+            contador: 0,
+            fetches: Vec::new(),
+        });
+        // LD A,$42 / LD ($0400),A / LD ($FFFF),A
+        c.bus.cargar(0x0000, &[0x3E, 0x42, 0x32, 0x00, 0x04, 0x32, 0xFF, 0xFF]);
+        c.execute();
+        c.execute();
+        c.execute();
+        assert_eq!(c.bus.leer_byte(0x0000), 0x42);
+        assert_eq!(c.bus.leer_byte(0x0C00), 0x42);
+        assert_eq!(c.bus.leer_byte(0xFFFF), 0x01);
+        assert_eq!(c.bus.fetches, vec![0x0000, 0x0002, 0x0005]);
+    }
+
+    // Registro de estado en 0x8000 que se borra cuando la CPU lo lee
+    struct EstadoBorrable {
+        ram: Bus,
+        estado: u8,
+    }
+
+    impl Memoria for EstadoBorrable {
+        fn leer_byte(&self, direccion: u16) -> u8 {
+            match direccion {
+                0x8000 => self.estado,
+                _ => self.ram.leer_byte(direccion),
+            }
+        }
+
+        fn escribir_byte(&mut self, direccion: u16, data: u8) {
+            self.ram.escribir_byte(direccion, data);
+        }
+
+        fn leer(&mut self, direccion: u16) -> u8 {
+            let dato = self.leer_byte(direccion);
+            if direccion == 0x8000 {
+                self.estado = 0;
+            }
+            dato
+        }
+    }
+
+    #[test]
+    fn lectura_con_efectos() {
+        use crate::cpu::cpu::CPU;
+        let mut c = CPU::con_bus(EstadoBorrable {
+            ram: Bus::new(0xFFFF),
+            estado: 0x81,
+        });
+        // LD A,($8000) / LD B,A / LD A,($8000)
+        c.bus.cargar(0x0000, &[0x3A, 0x00, 0x80, 0x47, 0x3A, 0x00, 0x80]);
+        // Consultar sin efectos no borra el registro
+        assert_eq!(c.bus.leer_byte(0x8000), 0x81);
+        c.execute();
+        c.execute();
+        c.execute();
+        assert_eq!(c.reg.b, 0x81);
+        assert_eq!(c.reg.a, 0x00);
+
+        // También en las lecturas indexadas: LD IX,$8000 / LD C,(IX+$00)
+        c.bus.estado = 0x42;
+        c.bus.cargar(0x0007, &[0xDD, 0x21, 0x00, 0x80, 0xDD, 0x4E, 0x00]);
+        c.execute();
+        c.execute();
+        assert_eq!(c.reg.c, 0x42);
+        assert_eq!(c.bus.estado, 0x00);
+    }
 }
@@ -1,5 +1,5 @@
 pub const CICLOS: [u8; 256] = [
-    0, 10, 7, 6, 4, 4, 7, 4, 4, 11, 7, 6, 4, 4, 7, 4, 0, 10, 7, 6, 4, 4, 7, 4, 12, 11, 7, 6, 4, 4,
+    4, 10, 7, 6, 4, 4, 7, 4, 4, 11, 7, 6, 4, 4, 7, 4, 0, 10, 7, 6, 4, 4, 7, 4, 12, 11, 7, 6, 4, 4,
     7, 4, 0, 10, 16, 6, 4, 4, 7, 4, 0, 11, 16, 6, 4, 4, 7, 4, 0, 10, 13, 6, 11, 11, 10, 4, 0, 11,
     13, 6, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, 4,
     4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, 7, 7, 7, 7, 7, 7, 4, 7, 4,
@@ -25,13 +25,13 @@ pub const CICLOS_DD_FD: [u8; 256] = [
 pub const CICLOS_ED: [u8; 256] = [
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 15, 20, 8, 0, 8, 9, 0, 0, 15, 20, 0, 0, 0, 9, 0, 0, 15, 20, 0, 0, 8, 9, 0, 0, 15, 20, 0,
-    0, 8, 9, 0, 0, 15, 20, 0, 0, 0, 18, 0, 0, 15, 20, 0, 0, 0, 18, 0, 0, 15, 20, 0, 0, 0, 0, 0, 0,
-    15, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 16, 16, 16, 16, 0, 0, 0, 0, 16, 16, 16, 16, 0, 0, 0, 0, 21, 21, 21, 21, 0,
-    0, 0, 0, 21, 21, 21, 21, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    12, 12, 15, 20, 8, 0, 8, 9, 12, 12, 15, 20, 0, 0, 0, 9, 12, 12, 15, 20, 0, 0, 8, 9, 12, 12, 15,
+    20, 0, 0, 8, 9, 12, 12, 15, 20, 0, 0, 0, 18, 12, 12, 15, 20, 0, 0, 0, 18, 12, 12, 15, 20, 0, 0,
+    0, 0, 12, 12, 15, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 16, 16, 16, 16, 0, 0, 0, 0, 16, 16, 16, 16, 0, 0, 0, 0, 21, 21,
+    21, 21, 0, 0, 0, 0, 21, 21, 21, 21, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
 pub const CICLOS_CB: [u8; 256] = [
@@ -1,17 +1,56 @@
 use std::time::SystemTime;
 use crate::cpu::bit;
-use crate::cpu::bus::Bus;
+use crate::cpu::bus::{Bus, Memoria};
 use crate::cpu::ciclos::{CICLOS, CICLOS_CB, CICLOS_DD_FD, CICLOS_ED};
+use crate::cpu::dasm::DASM_DD;
+use crate::cpu::interrupciones::CadenaInterrupciones;
+use crate::cpu::io::Puertos;
 use crate::cpu::registers::Registros;
 
-pub struct CPU {
+/// Valor de los registros al dar tensión al Z80. /RESET no los inicializa; en los NMOS AF
+/// y SP aparecen a 0xFFFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EstadoEncendido {
+    pub af: u16,
+    pub sp: u16,
+    /// BC, DE, HL, IX, IY y los alternativos
+    pub resto: u16,
+}
+
+impl EstadoEncendido {
+    /// Z80 NMOS: AF y SP a 0xFFFF
+    pub const NMOS: EstadoEncendido = EstadoEncendido { af: 0xFFFF, sp: 0xFFFF, resto: 0x0000 };
+}
+
+pub struct CPU<B: Memoria = Bus> {
     pub reg: Registros,
     pub alt: Registros,
-    pub bus: Bus,
+    pub bus: B,
+    pub io: Puertos,
+    /// Periféricos conectados en cadena a /INT
+    pub interrupciones: CadenaInterrupciones,
     pub(crate) halt: bool,
+    /// Registro interno MEMPTR (WZ). No se ve desde los programas, pero se nota en los flags
+    /// X e Y de BIT n,(HL).
+    pub memptr: u16,
     pub debug: Debug,
-    int: Option<u8>,
+    // Petición de interrupción enmascarable: /INT sigue activa hasta que se reconoce. Guarda
+    // lo que el dispositivo pone en el bus de datos durante el reconocimiento.
+    int_pendiente: Option<Vec<u8>>,
+    // Instrucción de la interrupción que se está ejecutando en esta llamada a `execute`
+    int: Option<Vec<u8>>,
+    // Bytes de la instrucción en curso ya leídos de memoria: el Z80 lee cada uno una sola vez
+    leidos: [Option<u8>; 4],
     nmi: bool,
+    // Tras EI no se acepta /INT hasta terminar la instrucción siguiente; tras un prefijo
+    // DD/FD sin efecto no se acepta ninguna interrupción
+    tras_ei: bool,
+    tras_prefijo: bool,
+    // Pines de entrada /WAIT, /BUSREQ y /RESET (true = activa) y salida /BUSACK
+    wait: bool,
+    busreq: bool,
+    busack: bool,
+    reset: bool,
     im: u8,
     iff1: bool,
     iff2: bool,
@@ -26,14 +65,32 @@ pub struct CPU {
 impl CPU {
     /// Crea una nueva instancia de CPU. 'size' será su dirección superior.
     pub fn new(size: u16) -> CPU {
+        CPU::con_bus(Bus::new(size))
+    }
+}
+
+impl<B: Memoria> CPU<B> {
+    /// Crea una nueva instancia de CPU sobre un mapa de memoria propio.
+    pub fn con_bus(bus: B) -> CPU<B> {
         CPU {
             reg: Registros::new(),
             alt: Registros::new(),
-            bus: Bus::new(size),
+            bus,
+            io: Puertos::new(),
+            interrupciones: CadenaInterrupciones::new(),
             halt: false,
+            memptr: 0,
             debug: Debug::new(),
+            int_pendiente: None,
             int: None,
+            leidos: [None; 4],
             nmi: false,
+            tras_ei: false,
+            tras_prefijo: false,
+            wait: false,
+            busreq: false,
+            busack: false,
+            reset: false,
             im: 0,
             iff1: false,
             iff2: false,
@@ -44,9 +101,17 @@ impl CPU {
         }
     }
 
-    /// Crea un requerimiento de interrupcion enmascarable
+    /// Crea un requerimiento de interrupcion enmascarable. Como /INT, queda pendiente
+    /// hasta que la CPU la reconoce. Los dispositivos de `interrupciones` tienen preferencia.
     pub fn int_requerimiento(&mut self, byte: u8) {
-        self.int = Some(byte);
+        self.int_requerimiento_instruccion(&[byte]);
+    }
+
+    /// Crea un requerimiento de interrupcion enmascarable en el que el dispositivo pone
+    /// una instrucción completa en el bus de datos (CALL nn, por ejemplo). En modo 0 la CPU
+    /// la ejecuta sin avanzar PC; en los modos 1 y 2 solo cuenta el primer byte.
+    pub fn int_requerimiento_instruccion(&mut self, instruccion: &[u8]) {
+        self.int_pendiente = Some(instruccion.to_vec());
     }
 
     /// Crea un requerimiento de interrupcion no enmascarable
@@ -54,63 +119,299 @@ impl CPU {
         self.nmi = true;
     }
 
+    /// Pone o quita /WAIT. Mientras está activa el ciclo en curso se alarga y `execute`
+    /// no avanza.
+    pub fn set_wait(&mut self, activa: bool) {
+        self.wait = activa;
+    }
+
+    /// Indica si /WAIT está activa
+    pub fn wait(&self) -> bool {
+        self.wait
+    }
+
+    /// Pone o quita /BUSREQ. El Z80 suelta el bus al terminar el ciclo en curso y lo
+    /// indica con /BUSACK; mientras tanto no ejecuta nada.
+    pub fn set_busreq(&mut self, activa: bool) {
+        self.busreq = activa;
+    }
+
+    /// Indica si /BUSACK está activa (bus cedido)
+    pub fn busack(&self) -> bool {
+        self.busack
+    }
+
+    /// Indica si /HALT está activa: la CPU está parada en un HALT hasta que se acepte una
+    /// interrupción o llegue /RESET
+    pub fn halt(&self) -> bool {
+        self.halt
+    }
+
+    /// Pone o quita /RESET. Al activarla se hace lo mismo que en `reiniciar`. Mientras sigue
+    /// activa el Z80 no ejecuta nada y al quitarla empieza en 0x0000.
+    pub fn set_reset(&mut self, activa: bool) {
+        if activa {
+            self.reiniciar();
+        }
+        self.reset = activa;
+    }
+
+    /// Da tensión al Z80: los registros toman los valores de `estado` y después se hace
+    /// un reset. La memoria no cambia.
+    /// Ejemplo:
+    ///    let mut c = CPU::new(0xFFFF);
+    ///    c.encender(EstadoEncendido::NMOS);
+    pub fn encender(&mut self, estado: EstadoEncendido) {
+        for reg in [&mut self.reg, &mut self.alt] {
+            reg.set_af(estado.resto);
+            reg.set_bc(estado.resto);
+            reg.set_de(estado.resto);
+            reg.set_hl(estado.resto);
+        }
+        self.reg.set_af(estado.af);
+        self.reg.set_ix(estado.resto);
+        self.reg.set_iy(estado.resto);
+        self.reg.sp = estado.sp;
+        self.reiniciar();
+    }
+
+    /// Indica si /RESET está activa
+    pub fn en_reset(&self) -> bool {
+        self.reset
+    }
+
+    /// Reset del Z80, el efecto de un pulso en /RESET: PC, I y R a cero, interrupciones
+    /// desactivadas con IM 0 y fuera de HALT. El resto de registros y la memoria no cambian.
+    pub fn reiniciar(&mut self) {
+        self.reg.pc = 0;
+        self.reg.i = 0;
+        self.reg.r = 0;
+        self.iff1 = false;
+        self.iff2 = false;
+        self.im = 0;
+        self.halt = false;
+        self.int_pendiente = None;
+        self.int = None;
+        self.interrupciones.reiniciar();
+        self.nmi = false;
+        self.tras_ei = false;
+        self.tras_prefijo = false;
+        self.busack = false;
+    }
+
+    /// Muestrea /BUSREQ al final de un ciclo de máquina y actualiza /BUSACK. Devuelve true
+    /// si el bus queda cedido.
+    pub(crate) fn ceder_bus(&mut self) -> bool {
+        self.busack = self.busreq;
+        self.busack
+    }
+
+    /// Bytes de la interrupción enmascarable que se atenderá en la próxima llamada a
+    /// `execute`, si la hay
+    pub(crate) fn interrupcion_aceptada(&self) -> Option<Vec<u8>> {
+        let bloqueada = self.tras_ei || self.tras_prefijo || self.nmi_pendiente();
+        if !self.iff1 || bloqueada {
+            return None;
+        }
+        match self.interrupciones.peticion() {
+            Some(vector) => Some(vec![vector]),
+            None => self.int_pendiente.clone(),
+        }
+    }
+
+    /// Indica si hay una interrupción no enmascarable pendiente
+    pub(crate) fn nmi_pendiente(&self) -> bool {
+        self.nmi && !self.tras_prefijo
+    }
+
     /// Convierte los flags a un byte
     pub fn flags(&self) -> u8 {
         self.reg.flags.to_byte()
     }
 
+    /// Biestable de habilitación de interrupciones IFF1
+    pub fn iff1(&self) -> bool {
+        self.iff1
+    }
+
+    /// Biestable IFF2, copia de IFF1 durante una NMI
+    pub fn iff2(&self) -> bool {
+        self.iff2
+    }
+
+    /// Modo de interrupción (0, 1 o 2)
+    pub fn im(&self) -> u8 {
+        self.im
+    }
+
     fn ldi(&mut self) {
         let bc = self.reg.get_bc();
         let de = self.reg.get_de();
         let hl = self.reg.get_hl();
-        self.bus.escribir_byte(de, self.bus.leer_byte(hl));
+        let dato = self.bus.leer(hl);
+        self.bus.escribir_byte(de, dato);
         self.reg.set_de(de.wrapping_add(1));
         self.reg.set_hl(hl.wrapping_add(1));
         self.reg.set_bc(bc.wrapping_sub(1));
+        self.flags_xy_ldi(dato);
     }
 
     fn ldd(&mut self) {
         let bc = self.reg.get_bc();
         let de = self.reg.get_de();
         let hl = self.reg.get_hl();
-        self.bus.escribir_byte(de, self.bus.leer_byte(hl));
+        let dato = self.bus.leer(hl);
+        self.bus.escribir_byte(de, dato);
         self.reg.set_de(de.wrapping_sub(1));
         self.reg.set_hl(hl.wrapping_sub(1));
         self.reg.set_bc(bc.wrapping_sub(1));
+        self.flags_xy_ldi(dato);
+    }
+
+    // En LDI/LDD los flags no documentados salen de A + (HL): X es su bit 3 e Y su bit 1
+    fn flags_xy_ldi(&mut self, dato: u8) {
+        let n = self.reg.a.wrapping_add(dato);
+        self.reg.flags.b3 = bit::get(n, 3);
+        self.reg.flags.b5 = bit::get(n, 1);
     }
 
     // Returns A - (HL)
     fn cpi(&mut self) {
         let bc = self.reg.get_bc();
         let hl = self.reg.get_hl();
-        let h = self.bus.leer_byte(hl);
+        let h = self.bus.leer(hl);
         let r = self.reg.a.wrapping_sub(h);
 
         self.reg.set_hl(hl.wrapping_add(1));
         self.reg.set_bc(bc.wrapping_sub(1));
+        self.memptr = self.memptr.wrapping_add(1);
 
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.z = self.reg.a == h;
         self.reg.flags.h = (self.reg.a as i8 & 0x0F) < (h as i8 & 0x0F);
         self.reg.flags.p = self.reg.get_bc() != 0;
         self.reg.flags.n = true;
+        self.flags_xy_cpi(r);
     }
 
     // Returns A - (HL)
     fn cpd(&mut self) {
         let bc = self.reg.get_bc();
         let hl = self.reg.get_hl();
-        let h = self.bus.leer_byte(hl);
+        let h = self.bus.leer(hl);
         let r = self.reg.a.wrapping_sub(h);
 
         self.reg.set_hl(hl.wrapping_sub(1));
         self.reg.set_bc(bc.wrapping_sub(1));
+        self.memptr = self.memptr.wrapping_sub(1);
 
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.z = self.reg.a == h;
         self.reg.flags.h = (self.reg.a as i8 & 0x0F) < (h as i8 & 0x0F);
         self.reg.flags.p = self.reg.get_bc() != 0;
         self.reg.flags.n = true;
+        self.flags_xy_cpi(r);
+    }
+
+    // En CPI/CPD X es el bit 3 y Y el bit 1 de A - (HL) - H, con el H ya calculado
+    fn flags_xy_cpi(&mut self, r: u8) {
+        let n = r.wrapping_sub(u8::from(self.reg.flags.h));
+        self.reg.flags.b3 = bit::get(n, 3);
+        self.reg.flags.b5 = bit::get(n, 1);
+    }
+
+    // Lee un puerto de E/S
+    fn leer_puerto(&mut self, puerto: u16) -> u8 {
+        let dato = self.io.leer(puerto);
+        if self.debug.io {
+            println!("IN  ({:#06X}) -> {:#04X}", puerto, dato);
+        }
+        dato
+    }
+
+    // Escribe en un puerto de E/S
+    fn escribir_puerto(&mut self, puerto: u16, dato: u8) {
+        if self.debug.io {
+            println!("OUT ({:#06X}) <- {:#04X}", puerto, dato);
+        }
+        self.io.escribir(puerto, dato);
+    }
+
+    // IN r,(C): lee el puerto BC y actualiza los flags
+    fn in_c(&mut self) -> u8 {
+        self.memptr = self.reg.get_bc().wrapping_add(1);
+        let r = self.leer_puerto(self.reg.get_bc());
+        self.reg.flags.s = (r as i8) < 0;
+        self.reg.flags.z = r == 0x00;
+        self.reg.flags.h = false;
+        self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
+        self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
+        r
+    }
+
+    // OUT (C),r: escribe en el puerto BC
+    fn out_c(&mut self, dato: u8) {
+        self.memptr = self.reg.get_bc().wrapping_add(1);
+        self.escribir_puerto(self.reg.get_bc(), dato);
+    }
+
+    // Flags comunes de INI, IND, OUTI y OUTD. 'k' es la suma del dato transferido
+    // con C+1 (INI), C-1 (IND) o L (OUTI/OUTD), según "The Undocumented Z80 Documented".
+    fn flags_bloque_io(&mut self, dato: u8, k: u16) {
+        let b = self.reg.b;
+        self.reg.flags.s = (b as i8) < 0;
+        self.reg.flags.z = b == 0x00;
+        self.reg.flags.h = k > 0xFF;
+        self.reg.flags.c = k > 0xFF;
+        self.reg.flags.p = (((k & 0x07) as u8) ^ b).count_ones() & 0x01 == 0x00;
+        self.reg.flags.n = bit::get(dato, 7);
+        self.reg.flags.set_xy(b);
+    }
+
+    fn ini(&mut self) {
+        self.memptr = self.reg.get_bc().wrapping_add(1);
+        let hl = self.reg.get_hl();
+        let dato = self.leer_puerto(self.reg.get_bc());
+        self.bus.escribir_byte(hl, dato);
+        self.reg.set_hl(hl.wrapping_add(1));
+        self.reg.b = self.reg.b.wrapping_sub(1);
+        let k = dato as u16 + self.reg.c.wrapping_add(1) as u16;
+        self.flags_bloque_io(dato, k);
+    }
+
+    fn ind(&mut self) {
+        self.memptr = self.reg.get_bc().wrapping_sub(1);
+        let hl = self.reg.get_hl();
+        let dato = self.leer_puerto(self.reg.get_bc());
+        self.bus.escribir_byte(hl, dato);
+        self.reg.set_hl(hl.wrapping_sub(1));
+        self.reg.b = self.reg.b.wrapping_sub(1);
+        let k = dato as u16 + self.reg.c.wrapping_sub(1) as u16;
+        self.flags_bloque_io(dato, k);
+    }
+
+    // En OUTI/OUTD el Z80 decrementa B antes de poner BC en el bus de direcciones
+    fn outi(&mut self) {
+        let hl = self.reg.get_hl();
+        let dato = self.bus.leer(hl);
+        self.reg.b = self.reg.b.wrapping_sub(1);
+        self.escribir_puerto(self.reg.get_bc(), dato);
+        self.memptr = self.reg.get_bc().wrapping_add(1);
+        self.reg.set_hl(hl.wrapping_add(1));
+        let k = dato as u16 + self.reg.l as u16;
+        self.flags_bloque_io(dato, k);
+    }
+
+    fn outd(&mut self) {
+        let hl = self.reg.get_hl();
+        let dato = self.bus.leer(hl);
+        self.reg.b = self.reg.b.wrapping_sub(1);
+        self.escribir_puerto(self.reg.get_bc(), dato);
+        self.memptr = self.reg.get_bc().wrapping_sub(1);
+        self.reg.set_hl(hl.wrapping_sub(1));
+        let k = dato as u16 + self.reg.l as u16;
+        self.flags_bloque_io(dato, k);
     }
 
     // ADD A,r
@@ -123,6 +424,7 @@ impl CPU {
         self.reg.flags.h = (a & 0x0f) + (n & 0x0f) > 0x0f;
         self.reg.flags.c = u16::from(a) + u16::from(n) > 0xff;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -140,6 +442,7 @@ impl CPU {
         self.reg.flags.h = (a & 0x0f) + (n & 0x0f) + c > 0x0f;
         self.reg.flags.c = u16::from(a) + u16::from(n) + u16::from(c) > 0xff;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -153,6 +456,7 @@ impl CPU {
         self.reg.flags.h = (a as i8 & 0x0f) < (n as i8 & 0x0f);
         self.reg.flags.c = u16::from(a) < u16::from(n);
         self.reg.flags.n = true;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -170,6 +474,7 @@ impl CPU {
         self.reg.flags.h = (a as i8 & 0x0f) < (n as i8 & 0x0f).wrapping_add(c as i8);
         self.reg.flags.c = u16::from(a) < (u16::from(n) + u16::from(c));
         self.reg.flags.n = true;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -182,6 +487,7 @@ impl CPU {
         self.reg.flags.h = true;
         self.reg.flags.c = false;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -194,6 +500,7 @@ impl CPU {
         self.reg.flags.h = false;
         self.reg.flags.c = false;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -207,6 +514,7 @@ impl CPU {
         self.reg.flags.h = false;
         self.reg.flags.c = false;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -215,6 +523,8 @@ impl CPU {
         let r = self.reg.a;
         self.sub(n);
         self.reg.a = r;
+        // En CP los flags no documentados salen del operando, no del resultado
+        self.reg.flags.set_xy(n);
     }
 
     // Incremento
@@ -225,6 +535,7 @@ impl CPU {
         self.reg.flags.p = n == 0x7F;
         self.reg.flags.h = (n & 0x0f) + 0x01 > 0x0f;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -236,6 +547,7 @@ impl CPU {
         self.reg.flags.p = n == 0x80;
         self.reg.flags.h = ((n & 0x0f) as i8) < 1;
         self.reg.flags.n = true;
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -294,6 +606,7 @@ impl CPU {
         self.reg.flags.z = self.reg.a == 0x00;
         self.reg.flags.s = bit::get(self.reg.a, 7);
         self.reg.flags.p = self.reg.a.count_ones() & 0x01 == 0x00;
+        self.reg.flags.set_xy(self.reg.a);
     }
 
     // NEG
@@ -306,15 +619,19 @@ impl CPU {
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.h = 0 < (self.reg.a & 0x0F);
         self.reg.flags.n = true;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
     // 16 bits add
     fn add_16(&mut self, n1: u16, n2: u16) -> u16 {
         let r = n1.wrapping_add(n2);
+        self.memptr = n1.wrapping_add(1);
         self.reg.flags.c = u32::from(n1) + u32::from(n2) > 0xffff;
         self.reg.flags.h = (n1 & 0x0FFF) + (n2 & 0x0FFF) > 0x0FFF;
         self.reg.flags.n = false;
+        // En las operaciones de 16 bits X e Y salen del byte alto
+        self.reg.flags.set_xy((r >> 8) as u8);
         r
     }
 
@@ -326,6 +643,7 @@ impl CPU {
         };
         let h = self.reg.get_hl();
         let r = h.wrapping_add(n).wrapping_add(c);
+        self.memptr = h.wrapping_add(1);
         self.reg.set_hl(r);
         self.reg.flags.s = (r as i16) < 0;
         self.reg.flags.z = r == 0x00;
@@ -335,7 +653,8 @@ impl CPU {
         self.reg.flags.p = {
             let r = (h as i16).overflowing_add((n + c) as i16);
             r.1
-        }
+        };
+        self.reg.flags.set_xy((r >> 8) as u8);
     }
 
     // Resta de pareja de registros con acarreo
@@ -346,6 +665,7 @@ impl CPU {
         };
         let h = self.reg.get_hl();
         let r = h.wrapping_sub(n).wrapping_sub(c);
+        self.memptr = h.wrapping_add(1);
         self.reg.set_hl(r);
         self.reg.flags.z = r == 0x00;
         self.reg.flags.s = (r as i16) < 0;
@@ -355,7 +675,8 @@ impl CPU {
         self.reg.flags.p = {
             let r = (h as i16).overflowing_sub((n + c) as i16);
             r.1
-        }
+        };
+        self.reg.flags.set_xy((r >> 8) as u8);
     }
 
     // Rota Acumulador a la izquierda
@@ -365,6 +686,7 @@ impl CPU {
         self.reg.flags.c = bit::get(self.reg.a, 7);
         self.reg.flags.h = false;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -377,6 +699,7 @@ impl CPU {
         self.reg.flags.h = false;
         self.reg.flags.n = false;
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -390,6 +713,7 @@ impl CPU {
         };
         self.reg.flags.h = false;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -406,6 +730,7 @@ impl CPU {
         self.reg.flags.h = false;
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -419,6 +744,7 @@ impl CPU {
             true => (self.reg.a << 1) | 0x01,
             false => self.reg.a << 1,
         };
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -435,6 +761,7 @@ impl CPU {
         self.reg.flags.z = r == 0x00;
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -448,6 +775,7 @@ impl CPU {
             true => (self.reg.a >> 1) | 0x80,
             false => self.reg.a >> 1,
         };
+        self.reg.flags.set_xy(r);
         self.reg.a = r;
     }
 
@@ -464,6 +792,7 @@ impl CPU {
         self.reg.flags.z = r == 0x00;
         self.reg.flags.s = (r as i8) < 0;
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -476,6 +805,7 @@ impl CPU {
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
         self.reg.flags.n = false;
         self.reg.flags.c = bit::get(n, 7);
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -488,6 +818,7 @@ impl CPU {
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
         self.reg.flags.n = false;
         self.reg.flags.c = bit::get(n, 7);
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -502,6 +833,7 @@ impl CPU {
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
         self.reg.flags.n = false;
         self.reg.flags.c = bit::get(n, 0);
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -516,6 +848,7 @@ impl CPU {
         self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
         self.reg.flags.n = false;
         self.reg.flags.c = bit::get(n, 0);
+        self.reg.flags.set_xy(r);
         r
     }
 
@@ -523,20 +856,31 @@ impl CPU {
     fn bit(&mut self, operand: u8) {
         let bit = ((operand & 0x38) >> 3) as usize;
         let register = operand & 0x07;
-        let r = match register {
-            0 => bit::get(self.reg.b, bit),
-            1 => bit::get(self.reg.c, bit),
-            2 => bit::get(self.reg.d, bit),
-            3 => bit::get(self.reg.e, bit),
-            4 => bit::get(self.reg.h, bit),
-            5 => bit::get(self.reg.l, bit),
-            6 => bit::get(self.bus.leer_byte(self.reg.get_hl()), bit),
-            7 => bit::get(self.reg.a, bit),
-            _ => false,
+        let n = match register {
+            0 => self.reg.b,
+            1 => self.reg.c,
+            2 => self.reg.d,
+            3 => self.reg.e,
+            4 => self.reg.h,
+            5 => self.reg.l,
+            6 => self.bus.leer(self.reg.get_hl()),
+            7 => self.reg.a,
+            _ => 0,
         };
+        // Con (HL) X e Y salen del byte alto de MEMPTR
+        let xy = if register == 6 { (self.memptr >> 8) as u8 } else { n };
+        self.flags_bit(bit::get(n, bit), bit, xy);
+    }
+
+    // Flags de BIT: P/V copia a Z y S solo se activa al probar el bit 7 a uno. X e Y salen
+    // de `xy`, que depende del modo de direccionamiento.
+    fn flags_bit(&mut self, r: bool, bit: usize, xy: u8) {
         self.reg.flags.z = !r;
+        self.reg.flags.p = !r;
+        self.reg.flags.s = r && bit == 7;
         self.reg.flags.h = true;
         self.reg.flags.n = false;
+        self.reg.flags.set_xy(xy);
     }
 
     // Bit set
@@ -550,10 +894,11 @@ impl CPU {
             3 => self.reg.e = bit::set(self.reg.e, bit),
             4 => self.reg.h = bit::set(self.reg.h, bit),
             5 => self.reg.l = bit::set(self.reg.l, bit),
-            6 => self.bus.escribir_byte(
-                self.reg.get_hl(),
-                bit::set(self.bus.leer_byte(self.reg.get_hl()), bit),
-            ),
+            6 => {
+                let hl = self.reg.get_hl();
+                let dato = self.bus.leer(hl);
+                self.bus.escribir_byte(hl, bit::set(dato, bit));
+            }
             7 => self.reg.a = bit::set(self.reg.a, bit),
             _ => {}
         };
@@ -570,10 +915,11 @@ impl CPU {
             3 => self.reg.e = bit::reset(self.reg.e, bit),
             4 => self.reg.h = bit::reset(self.reg.h, bit),
             5 => self.reg.l = bit::reset(self.reg.l, bit),
-            6 => self.bus.escribir_byte(
-                self.reg.get_hl(),
-                bit::reset(self.bus.leer_byte(self.reg.get_hl()), bit),
-            ),
+            6 => {
+                let hl = self.reg.get_hl();
+                let dato = self.bus.leer(hl);
+                self.bus.escribir_byte(hl, bit::reset(dato, bit));
+            }
             7 => self.reg.a = bit::reset(self.reg.a, bit),
             _ => {}
         };
@@ -581,62 +927,192 @@ impl CPU {
 
     // call stack push
     fn call_stack_push(&mut self) {
-        self.reg.sp = self.reg.sp.wrapping_sub(2);
-        self.bus
-            .write_word(self.reg.sp, self.reg.pc.wrapping_add(3));
+        self.apilar(self.reg.pc.wrapping_add(3));
+    }
+
+    // Apila una palabra en el orden del Z80: primero el byte alto en SP - 1 y luego el
+    // bajo en SP - 2
+    fn apilar(&mut self, dato: u16) {
+        let [bajo, alto] = dato.to_le_bytes();
+        self.reg.sp = self.reg.sp.wrapping_sub(1);
+        self.bus.escribir_byte(self.reg.sp, alto);
+        self.reg.sp = self.reg.sp.wrapping_sub(1);
+        self.bus.escribir_byte(self.reg.sp, bajo);
+    }
+
+    // EX (SP),rr: lee la cima de la pila y escribe `dato` en su lugar, como el Z80, byte
+    // alto primero. Devuelve la palabra leída.
+    fn intercambiar_cima_pila(&mut self, dato: u16) -> u16 {
+        let leido = self.bus.leer_word(self.reg.sp);
+        let [bajo, alto] = dato.to_le_bytes();
+        self.bus.escribir_byte(self.reg.sp.wrapping_add(1), alto);
+        self.bus.escribir_byte(self.reg.sp, bajo);
+        leido
     }
 
     // call stack pop
     fn call_stack_pop(&mut self) {
-        self.reg.pc = self.bus.read_word(self.reg.sp);
+        self.reg.pc = self.bus.leer_word(self.reg.sp);
         self.reg.sp = self.reg.sp.wrapping_add(2);
+        self.memptr = self.reg.pc;
+    }
+
+    // Dirección (IX+d) o (IY+d) según el prefijo
+    fn direccion_indexada(&self, prefijo: u8, desplazamiento: u8) -> u16 {
+        let indice = if prefijo == 0xDD { self.reg.get_ix() } else { self.reg.get_iy() };
+        indice.wrapping_add(desplazamiento as i8 as u16)
     }
 
     // interrupt stack push
     fn interrupt_stack_push(&mut self) {
-        self.reg.sp = self.reg.sp.wrapping_sub(2);
-        self.bus.write_word(self.reg.sp, self.reg.pc);
+        self.apilar(self.reg.pc);
+    }
+
+    /// Byte `n` de la instrucción en curso. Al atender una interrupción en modo 0 los da el
+    /// dispositivo por el bus de datos en lugar de la memoria.
+    /// Los que ya se leyeron en esta instrucción no se vuelven a pedir al bus.
+    fn leer_instruccion(&mut self, n: u16) -> u8 {
+        if let Some(instruccion) = &self.int {
+            return instruccion.get(n as usize).copied().unwrap_or(0xFF);
+        }
+        if let Some(dato) = self.leidos[n as usize] {
+            return dato;
+        }
+        let dato = self.bus.leer(self.reg.pc.wrapping_add(n));
+        self.leidos[n as usize] = Some(dato);
+        dato
+    }
+
+    /// Byte `n` de la instrucción en curso sin hacer ningún acceso al bus
+    fn consultar_instruccion(&self, n: u16) -> u8 {
+        if let Some(instruccion) = &self.int {
+            return instruccion.get(n as usize).copied().unwrap_or(0xFF);
+        }
+        self.leidos[n as usize].unwrap_or_else(|| self.bus.leer_byte(self.reg.pc.wrapping_add(n)))
+    }
+
+    /// Busca el código de operación `n` de la instrucción en curso en un ciclo M1
+    fn fetch_instruccion(&mut self, n: u16) -> u8 {
+        if self.int.is_some() {
+            return self.leer_instruccion(n);
+        }
+        let dato = self.bus.fetch(self.reg.pc.wrapping_add(n));
+        self.leidos[n as usize] = Some(dato);
+        dato
+    }
+
+    /// Palabra en los bytes `n` y `n + 1` de la instrucción en curso
+    fn leer_instruccion_word(&mut self, n: u16) -> u16 {
+        u16::from_le_bytes([self.leer_instruccion(n), self.leer_instruccion(n + 1)])
+    }
+
+    /// Final de una repetición de LDIR, CPIR, INIR... Si el bucle sigue, PC vuelve a la
+    /// instrucción para que la siguiente llamada a `execute` haga otra repetición: cada una
+    /// busca de nuevo los dos códigos (R sube 2) y entre ellas se aceptan interrupciones.
+    /// Devuelve los ciclos de la repetición.
+    fn repetir_bloque(&mut self, sigue: bool) -> u32 {
+        if !sigue {
+            return 16;
+        }
+        // Al sumar la longitud de la instrucción PC se queda donde estaba
+        self.memptr = self.reg.pc.wrapping_add(1);
+        self.reg.pc = self.reg.pc.wrapping_sub(2);
+        21
+    }
+
+    /// Al aceptar una interrupción parado en HALT se sale de él y se apila la dirección
+    /// siguiente, ya que PC se queda sobre el HALT mientras dura
+    fn salir_de_halt(&mut self) {
+        if self.halt {
+            self.halt = false;
+            self.reg.pc = self.reg.pc.wrapping_add(1);
+        }
+    }
+
+    /// Cada ciclo M1 incrementa los 7 bits bajos de R; el bit 7 solo cambia con LD R,A
+    fn incrementar_r(&mut self) {
+        self.reg.r = (self.reg.r & 0x80) | (self.reg.r.wrapping_add(1) & 0x7F);
     }
 
     ///  Obtiene y ejecuta una instrucción desde (pc). Devuelve los ciclos de reloj consumidos.
+    /// Con /RESET, /BUSREQ o /WAIT activas no se ejecuta nada y se devuelve un estado T.
     pub fn execute(&mut self) -> u32 {
-        if self.halt {
-            return 4;
-        };
+        if self.reset || self.ceder_bus() || self.wait {
+            return 1;
+        }
+
+        // Las interrupciones se muestrean al final de la instrucción anterior
+        let tras_ei = std::mem::take(&mut self.tras_ei);
+        let tras_prefijo = std::mem::take(&mut self.tras_prefijo);
+
+        // Estados T de los ciclos de reconocimiento
+        let mut reconocimiento = 0;
 
         // ¿Se solicitó una interrupción no enmascarable?
-        if self.nmi {
+        if self.nmi && !tras_prefijo {
+            self.salir_de_halt();
             self.iff2 = self.iff1;
             self.iff1 = false;
+            // El M1 que se descarta también refresca
+            self.incrementar_r();
             self.interrupt_stack_push();
             self.reg.pc = 0x0066;
+            self.memptr = 0x0066;
             self.nmi = false;
+            reconocimiento = 11;
+        }
+
+        // Reconocimiento de /INT: el dispositivo retira la petición y se desactivan las
+        // interrupciones
+        if self.iff1 && !tras_ei && !tras_prefijo {
+            self.int = match self.interrupciones.reconocer() {
+                Some(vector) => Some(vec![vector]),
+                None => self.int_pendiente.take(),
+            };
+            if self.int.is_some() {
+                self.salir_de_halt();
+                self.iff1 = false;
+                self.iff2 = false;
+            }
+        }
+
+        // Parada en HALT la CPU sigue haciendo ciclos M1 que ejecuta como NOP
+        if self.halt {
+            self.incrementar_r();
+            return 4;
         }
 
         // ¿Se solicitó una interrupción en el modo de interrupción 1? Reiniciar en la dirección 0038h (opcode 0xFF)
-        if self.iff1 && self.int.is_some() && self.im == 1 {
-            self.int = Some(0xFF)
+        if self.int.is_some() && self.im == 1 {
+            self.int = Some(vec![0xFF])
         };
 
         // ¿Interrupción solicitada en modo de interrupción 2? Insertar PC en el stack,
         // construir la dirección de salto y saltar a ella.
-        if self.iff1 && self.int.is_some() && self.im == 2 {
+        if self.int.is_some() && self.im == 2 {
+            // Ciclo de reconocimiento
+            self.incrementar_r();
             self.interrupt_stack_push();
-            let addr = ((self.reg.i as u16) << 8) | (self.int.unwrap() as u16);
-            self.reg.pc = self.bus.read_word(addr);
-            self.int = None;
+            let vector = self.int.take().and_then(|i| i.first().copied()).unwrap_or(0xFF);
+            let addr = ((self.reg.i as u16) << 8) | (vector as u16);
+            self.reg.pc = self.bus.leer_word(addr);
+            self.memptr = self.reg.pc;
+            reconocimiento = 19;
         };
 
+        // En modo 0 el M1 de reconocimiento dura dos estados T más y PC no avanza mientras se
+        // leen los bytes de la instrucción. Se retrocede PC para que al sumar su longitud
+        // quede donde estaba (y CALL o RST apilen la dirección interrumpida).
+        if let Some(instruccion) = &self.int {
+            self.reg.pc = self.reg.pc.wrapping_sub(instruccion.len() as u16);
+            reconocimiento = 2;
+        }
+
         // Recuperamos el código de operación, ya sea que provenga de una solicitud de interrupción
         // o de una búsqueda normal.
-        let opcode = match self.iff1 {
-            false => self.bus.leer_byte(self.reg.pc),
-            // Interrupciones habilitadas: ¿hay una interrupción pendiente?
-            true => match self.int {
-                None => self.bus.leer_byte(self.reg.pc),
-                Some(o) => o,
-            },
-        };
+        self.leidos = [None; 4];
+        let opcode = self.fetch_instruccion(0);
+        self.incrementar_r();
 
         let cycles = match opcode {
             0xDD | 0xFD | 0xED | 0xCB => self.execute_2bytes(),
@@ -644,7 +1120,7 @@ impl CPU {
         };
 
         self.int = None;
-        cycles
+        reconocimiento + cycles
     }
 
     /// Obtiene y ejecuta una instrucción de pc.
@@ -681,21 +1157,22 @@ impl CPU {
 
     // DDCB FDCB
     fn execute_4bytes(&mut self) -> u32 {
-        let opcode = self.bus.read_le_dword(self.reg.pc);
+        let opcode = u32::from_be_bytes([0, 1, 2, 3].map(|n| self.leer_instruccion(n)));
         let cycles;
+        self.memptr = self.direccion_indexada((opcode >> 24) as u8, (opcode >> 8) as u8);
 
         match opcode & 0xFFFF00FF {
             0xDDCB0006 => {
                 // RLC (IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rlc(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rlc(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -704,15 +1181,15 @@ impl CPU {
 
             0xFDCB0006 => {
                 // RLC (IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rlc(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rlc(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -721,15 +1198,15 @@ impl CPU {
 
             0xDDCB0016 => {
                 // RL (IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rl(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rl(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -738,15 +1215,15 @@ impl CPU {
 
             0xFDCB0016 => {
                 // RL (IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rl(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rl(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -755,15 +1232,15 @@ impl CPU {
 
             0xDDCB000E => {
                 // RRC (IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rrc(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rrc(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -772,15 +1249,15 @@ impl CPU {
 
             0xFDCB000E => {
                 // RRC (IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rrc(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rrc(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -789,15 +1266,15 @@ impl CPU {
 
             0xDDCB001E => {
                 // RR (IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rr(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rr(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -806,15 +1283,15 @@ impl CPU {
 
             0xFDCB001E => {
                 // RR (IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rr(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.rr(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -823,15 +1300,15 @@ impl CPU {
 
             0xDDCB0026 => {
                 // SLA (IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sla(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sla(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -840,15 +1317,15 @@ impl CPU {
 
             0xFDCB0026 => {
                 // SLA (IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sla(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sla(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -857,15 +1334,15 @@ impl CPU {
 
             0xDDCB002E => {
                 // SRA (IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sra(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sra(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -874,15 +1351,15 @@ impl CPU {
 
             0xFDCB002E => {
                 // SRA (IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sra(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sra(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -891,15 +1368,15 @@ impl CPU {
 
             0xDDCB003E => {
                 // SRL (IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.srl(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.srl(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -908,15 +1385,15 @@ impl CPU {
 
             0xFDCB003E => {
                 // SRL (IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.srl(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.srl(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -926,23 +1403,17 @@ impl CPU {
             0xDDCB0046 | 0xDDCB004E | 0xDDCB0056 | 0xDDCB005E | 0xDDCB0066 | 0xDDCB006E
             | 0xDDCB0076 | 0xDDCB007E => {
                 // BIT b,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let operand = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                let operand = self.leer_instruccion(3);
                 let bit = ((operand & 0x38) >> 3) as usize;
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
-                    let r = bit::get(d, bit);
-                    self.reg.flags.z = !r;
-                    self.reg.flags.h = true;
-                    self.reg.flags.n = false;
+                    let d = self.bus.leer(m);
+                    self.flags_bit(bit::get(d, bit), bit, (m >> 8) as u8);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
-                    let r = bit::get(d, bit);
-                    self.reg.flags.z = !r;
-                    self.reg.flags.h = true;
-                    self.reg.flags.n = false;
+                    let d = self.bus.leer(m);
+                    self.flags_bit(bit::get(d, bit), bit, (m >> 8) as u8);
                 }
                 cycles = 20;
             }
@@ -950,23 +1421,17 @@ impl CPU {
             0xFDCB0046 | 0xFDCB004E | 0xFDCB0056 | 0xFDCB005E | 0xFDCB0066 | 0xFDCB006E
             | 0xFDCB0076 | 0xFDCB007E => {
                 // BIT b,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let operand = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                let operand = self.leer_instruccion(3);
                 let bit = ((operand & 0x38) >> 3) as usize;
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
-                    let r = bit::get(d, bit);
-                    self.reg.flags.z = !r;
-                    self.reg.flags.h = true;
-                    self.reg.flags.n = false;
+                    let d = self.bus.leer(m);
+                    self.flags_bit(bit::get(d, bit), bit, (m >> 8) as u8);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
-                    let r = bit::get(d, bit);
-                    self.reg.flags.z = !r;
-                    self.reg.flags.h = true;
-                    self.reg.flags.n = false;
+                    let d = self.bus.leer(m);
+                    self.flags_bit(bit::get(d, bit), bit, (m >> 8) as u8);
                 }
                 cycles = 20;
             }
@@ -974,17 +1439,17 @@ impl CPU {
             0xDDCB00C6 | 0xDDCB00CE | 0xDDCB00D6 | 0xDDCB00DE | 0xDDCB00E6 | 0xDDCB00EE
             | 0xDDCB00F6 | 0xDDCB00FE => {
                 // SET b,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let operand = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                let operand = self.leer_instruccion(3);
                 let bit = ((operand & 0x38) >> 3) as usize;
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::set(d, bit);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::set(d, bit);
                     self.bus.escribir_byte(m, r);
                 }
@@ -994,17 +1459,17 @@ impl CPU {
             0xFDCB00C6 | 0xFDCB00CE | 0xFDCB00D6 | 0xFDCB00DE | 0xFDCB00E6 | 0xFDCB00EE
             | 0xFDCB00F6 | 0xFDCB00FE => {
                 // SET b,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let operand = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                let operand = self.leer_instruccion(3);
                 let bit = ((operand & 0x38) >> 3) as usize;
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::set(d, bit);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::set(d, bit);
                     self.bus.escribir_byte(m, r);
                 }
@@ -1014,17 +1479,17 @@ impl CPU {
             0xDDCB0086 | 0xDDCB008E | 0xDDCB0096 | 0xDDCB009E | 0xDDCB00A6 | 0xDDCB00AE
             | 0xDDCB00B6 | 0xDDCB00BE => {
                 // RES b,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let operand = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                let operand = self.leer_instruccion(3);
                 let bit = ((operand & 0x38) >> 3) as usize;
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::reset(d, bit);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::reset(d, bit);
                     self.bus.escribir_byte(m, r);
                 }
@@ -1034,17 +1499,17 @@ impl CPU {
             0xFDCB0086 | 0xFDCB008E | 0xFDCB0096 | 0xFDCB009E | 0xFDCB00A6 | 0xFDCB00AE
             | 0xFDCB00B6 | 0xFDCB00BE => {
                 // RES b,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let operand = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                let operand = self.leer_instruccion(3);
                 let bit = ((operand & 0x38) >> 3) as usize;
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::reset(d, bit);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = bit::reset(d, bit);
                     self.bus.escribir_byte(m, r);
                 }
@@ -1054,15 +1519,15 @@ impl CPU {
             // Instrucciones no documentadas
             // SLL (IX+d)
             0xDDCB0036 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sll(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sll(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -1071,15 +1536,15 @@ impl CPU {
 
             // SLL (IY+d)
             0xFDCB0036 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sll(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.sll(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -1093,7 +1558,7 @@ impl CPU {
                 cycles = 0xFF;
             }
         }
-        self.reg.pc += 4;
+        self.reg.pc = self.reg.pc.wrapping_add(4);
         if self.debug.opcode {
             self.debug.string = format!("{:#10X}", opcode)
         }
@@ -1101,7 +1566,24 @@ impl CPU {
     }
 
     fn execute_2bytes(&mut self) -> u32 {
-        let opcode = self.bus.read_le_word(self.reg.pc);
+        let prefijo = self.leer_instruccion(0);
+
+        // Un prefijo DD/FD seguido de otro prefijo o de una instrucción que no usa HL no
+        // tiene efecto: cuenta como un NOP de 4 estados T y el código siguiente se busca en
+        // su propio M1 en la siguiente llamada, sin aceptar interrupciones entre medias
+        if matches!(prefijo, 0xDD | 0xFD) {
+            let siguiente = self.consultar_instruccion(1);
+            if siguiente != 0xCB && DASM_DD[siguiente as usize].is_empty() {
+                self.tras_prefijo = true;
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                return 4;
+            }
+        }
+
+        // El byte que sigue al prefijo también se lee en un ciclo M1
+        let segundo = self.fetch_instruccion(1);
+        let opcode = u16::from(prefijo) << 8 | u16::from(segundo);
+        self.incrementar_r();
         let mut cycles = match opcode & 0xFF00 {
             0xDD00 | 0xFD00 => CICLOS_DD_FD[(opcode & 0x00FF) as usize].into(),
             0xED00 => CICLOS_ED[(opcode & 0x00FF) as usize].into(),
@@ -1117,193 +1599,208 @@ impl CPU {
             // LD r,(IX+d)
             0xDD46 => {
                 // LD B,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.b = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.b = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16))
+                        .leer(self.reg.get_ix() + (displacement as u16))
                 }
             }
             0xDD4E => {
                 // LD C,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.c = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.c = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16))
+                        .leer(self.reg.get_ix() + (displacement as u16))
                 }
             }
             0xDD56 => {
                 // LD D,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16))
+                        .leer(self.reg.get_ix() + (displacement as u16))
                 }
             }
             0xDD5E => {
                 // LD E,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.e = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.e = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16))
+                        .leer(self.reg.get_ix() + (displacement as u16))
                 }
             }
             0xDD66 => {
                 // LD H,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.h = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.h = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16))
+                        .leer(self.reg.get_ix() + (displacement as u16))
                 }
             }
             0xDD6E => {
                 // LD L,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.l = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.l = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16))
+                        .leer(self.reg.get_ix() + (displacement as u16))
                 }
             }
             0xDD7E => {
                 // LD A,(IX+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.a = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.a = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16))
+                        .leer(self.reg.get_ix() + (displacement as u16))
                 }
             }
 
             // LD r,(IY+d)
             0xFD46 => {
                 // LD B,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.b = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.b = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16))
+                        .leer(self.reg.get_iy() + (displacement as u16))
                 }
             }
             0xFD4E => {
                 // LD C,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.c = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.c = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16))
+                        .leer(self.reg.get_iy() + (displacement as u16))
                 }
             }
             0xFD56 => {
                 // LD D,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16))
+                        .leer(self.reg.get_iy() + (displacement as u16))
                 }
             }
             0xFD5E => {
                 // LD E,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.e = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.e = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16))
+                        .leer(self.reg.get_iy() + (displacement as u16))
                 }
             }
             0xFD66 => {
                 // LD H,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.h = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.h = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16))
+                        .leer(self.reg.get_iy() + (displacement as u16))
                 }
             }
             0xFD6E => {
                 // LD L,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.l = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.l = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16))
+                        .leer(self.reg.get_iy() + (displacement as u16))
                 }
             }
             0xFD7E => {
                 // LD A,(IY+d)
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.reg.a = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16))
                 } else {
                     self.reg.a = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16))
+                        .leer(self.reg.get_iy() + (displacement as u16))
                 }
             }
 
             // LD (IX+d),r
             0xDD70 => {
                 // LD (IX+d),B
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1316,7 +1813,8 @@ impl CPU {
             }
             0xDD71 => {
                 // LD (IX+d),C
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1329,7 +1827,8 @@ impl CPU {
             }
             0xDD72 => {
                 // LD (IX+d),D
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1342,7 +1841,8 @@ impl CPU {
             }
             0xDD73 => {
                 // LD (IX+d),E
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1355,7 +1855,8 @@ impl CPU {
             }
             0xDD74 => {
                 // LD (IX+d),H
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1368,7 +1869,8 @@ impl CPU {
             }
             0xDD75 => {
                 // LD (IX+d),L
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1381,7 +1883,8 @@ impl CPU {
             }
             0xDD77 => {
                 // LD (IX+d),A
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1396,7 +1899,8 @@ impl CPU {
             // LD (IY+d),r
             0xFD70 => {
                 // LD (IY+d),B
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1409,7 +1913,8 @@ impl CPU {
             }
             0xFD71 => {
                 // LD (IY+d),C
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1422,7 +1927,8 @@ impl CPU {
             }
             0xFD72 => {
                 // LD (IY+d),D
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1435,7 +1941,8 @@ impl CPU {
             }
             0xFD73 => {
                 // LD (IY+d),E
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1448,7 +1955,8 @@ impl CPU {
             }
             0xFD74 => {
                 // LD (IY+d),H
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1461,7 +1969,8 @@ impl CPU {
             }
             0xFD75 => {
                 // LD (IY+d),L
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1474,7 +1983,8 @@ impl CPU {
             }
             0xFD77 => {
                 // LD (IY+d),A
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1488,8 +1998,9 @@ impl CPU {
 
             // LD (IX+d),n
             0xDD36 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let data = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
+                let data = self.leer_instruccion(3);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_ix() - (signed_to_abs(displacement) as u16),
@@ -1503,18 +2014,21 @@ impl CPU {
 
             // LD IX,nn
             0xDD21 => {
-                self.reg.set_ix(self.bus.read_word(self.reg.pc + 2));
+                let nn = self.leer_instruccion_word(2);
+                self.reg.set_ix(nn);
             }
 
             // LD IY,nn
             0xFD21 => {
-                self.reg.set_iy(self.bus.read_word(self.reg.pc + 2));
+                let nn = self.leer_instruccion_word(2);
+                self.reg.set_iy(nn);
             }
 
             // LD (IY+d),n
             0xFD36 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
-                let data = self.bus.leer_byte(self.reg.pc + 3);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
+                let data = self.leer_instruccion(3);
                 if bit::get(displacement, 7) {
                     self.bus.escribir_byte(
                         self.reg.get_iy() - (signed_to_abs(displacement) as u16),
@@ -1534,6 +2048,7 @@ impl CPU {
                 self.reg.flags.h = false;
                 self.reg.flags.p = self.iff2;
                 self.reg.flags.n = false;
+                self.reg.flags.set_xy(self.reg.a);
                 // TODO :
                 // If an interrupt occurs during execution of this instruction, the Parity flag contains a 0.
             }
@@ -1546,6 +2061,7 @@ impl CPU {
                 self.reg.flags.h = false;
                 self.reg.flags.p = self.iff2;
                 self.reg.flags.n = false;
+                self.reg.flags.set_xy(self.reg.a);
                 // TODO :
                 // Si ocurre una interrupción durante la ejecución de esta instrucción, el flag de Paridad contiene un 0.
             }
@@ -1560,80 +2076,92 @@ impl CPU {
             // LD dd,(nn)
             0xED4B => {
                 // LD BC,(nn)
-                let addr = self.bus.read_word(self.reg.pc + 2);
-                let d = self.bus.read_word(addr);
+                let addr = self.leer_instruccion_word(2);
+                let d = self.bus.leer_word(addr);
+                self.memptr = addr.wrapping_add(1);
                 self.reg.set_bc(d);
             }
 
             0xED5B => {
                 // LD DE,(nn)
-                let addr = self.bus.read_word(self.reg.pc + 2);
-                let d = self.bus.read_word(addr);
+                let addr = self.leer_instruccion_word(2);
+                let d = self.bus.leer_word(addr);
+                self.memptr = addr.wrapping_add(1);
                 self.reg.set_de(d);
             }
 
             0xED6B => {
                 // LD HL,(nn)
-                let addr = self.bus.read_word(self.reg.pc + 2);
-                let d = self.bus.read_word(addr);
+                let addr = self.leer_instruccion_word(2);
+                let d = self.bus.leer_word(addr);
+                self.memptr = addr.wrapping_add(1);
                 self.reg.set_hl(d);
             }
 
             0xED7B => {
                 // LD SP,(nn)
-                let addr = self.bus.read_word(self.reg.pc + 2);
-                let d = self.bus.read_word(addr);
+                let addr = self.leer_instruccion_word(2);
+                let d = self.bus.leer_word(addr);
+                self.memptr = addr.wrapping_add(1);
                 self.reg.sp = d;
             }
 
             // LD IX,(nn)
             0xDD2A => {
-                let addr = self.bus.read_word(self.reg.pc + 2);
-                let d = self.bus.read_word(addr);
+                let addr = self.leer_instruccion_word(2);
+                let d = self.bus.leer_word(addr);
+                self.memptr = addr.wrapping_add(1);
                 self.reg.set_ix(d);
             }
 
             // LD IY,(nn)
             0xFD2A => {
-                let addr = self.bus.read_word(self.reg.pc + 2);
-                let d = self.bus.read_word(addr);
+                let addr = self.leer_instruccion_word(2);
+                let d = self.bus.leer_word(addr);
+                self.memptr = addr.wrapping_add(1);
                 self.reg.set_iy(d);
             }
 
             // LD (nn),dd
             0xED43 => {
                 // LD (nn),BC
-                let addr = self.bus.read_word(self.reg.pc + 2);
+                let addr = self.leer_instruccion_word(2);
+                self.memptr = addr.wrapping_add(1);
                 self.bus.write_word(addr, self.reg.get_bc());
             }
 
             0xED53 => {
                 // LD (nn),DE
-                let addr = self.bus.read_word(self.reg.pc + 2);
+                let addr = self.leer_instruccion_word(2);
+                self.memptr = addr.wrapping_add(1);
                 self.bus.write_word(addr, self.reg.get_de());
             }
 
             0xED63 => {
                 // LD (nn),HL
-                let addr = self.bus.read_word(self.reg.pc + 2);
+                let addr = self.leer_instruccion_word(2);
+                self.memptr = addr.wrapping_add(1);
                 self.bus.write_word(addr, self.reg.get_hl());
             }
 
             0xED73 => {
                 // LD (nn),SP
-                let addr = self.bus.read_word(self.reg.pc + 2);
+                let addr = self.leer_instruccion_word(2);
+                self.memptr = addr.wrapping_add(1);
                 self.bus.write_word(addr, self.reg.sp);
             }
 
             // LD (nn),IX
             0xDD22 => {
-                let addr = self.bus.read_word(self.reg.pc + 2);
+                let addr = self.leer_instruccion_word(2);
+                self.memptr = addr.wrapping_add(1);
                 self.bus.write_word(addr, self.reg.get_ix());
             }
 
             // LD (nn),IY
             0xFD22 => {
-                let addr = self.bus.read_word(self.reg.pc + 2);
+                let addr = self.leer_instruccion_word(2);
+                self.memptr = addr.wrapping_add(1);
                 self.bus.write_word(addr, self.reg.get_iy());
             }
 
@@ -1645,41 +2173,39 @@ impl CPU {
 
             // PUSH IX
             0xDDE5 => {
-                self.reg.sp = self.reg.sp.wrapping_sub(2);
-                self.bus.write_word(self.reg.sp, self.reg.get_ix());
+                self.apilar(self.reg.get_ix());
             }
 
             // PUSH IY
             0xFDE5 => {
-                self.reg.sp = self.reg.sp.wrapping_sub(2);
-                self.bus.write_word(self.reg.sp, self.reg.get_iy());
+                self.apilar(self.reg.get_iy());
             }
 
             // POP IX
             0xDDE1 => {
-                self.reg.set_ix(self.bus.read_word(self.reg.sp));
+                self.reg.set_ix(self.bus.leer_word(self.reg.sp));
                 self.reg.sp = self.reg.sp.wrapping_add(2);
             }
 
             // POP IY
             0xFDE1 => {
-                self.reg.set_iy(self.bus.read_word(self.reg.sp));
+                self.reg.set_iy(self.bus.leer_word(self.reg.sp));
                 self.reg.sp = self.reg.sp.wrapping_add(2);
             }
 
             // Grupo de Intercambio, Transferencia de Bloque y Búsqueda
             // EX (SP),IX
             0xDDE3 => {
-                let pointed_by_sp = self.bus.read_word(self.reg.sp);
-                self.bus.write_word(self.reg.sp, self.reg.get_ix());
+                let pointed_by_sp = self.intercambiar_cima_pila(self.reg.get_ix());
                 self.reg.set_ix(pointed_by_sp);
+                self.memptr = pointed_by_sp;
             }
 
             // EX (SP),IY
             0xFDE3 => {
-                let pointed_by_sp = self.bus.read_word(self.reg.sp);
-                self.bus.write_word(self.reg.sp, self.reg.get_iy());
+                let pointed_by_sp = self.intercambiar_cima_pila(self.reg.get_iy());
                 self.reg.set_iy(pointed_by_sp);
+                self.memptr = pointed_by_sp;
             }
 
             // LDI
@@ -1693,16 +2219,13 @@ impl CPU {
 
             // LDIR
             0xEDB0 => {
-                // TODO : Cuando BC se establece en 0 antes de la ejecución de la instrucción,
-                // TODO : la instrucción entra en un bucle a través de 64 KB.
-                while self.reg.get_bc() != 0 {
-                    self.ldi();
-                    let bc = self.reg.get_bc();
-                    self.reg.flags.h = false;
-                    self.reg.flags.p = bc != 0;
-                    self.reg.flags.n = false;
-                    // TODO : return cycles * number of executions
-                }
+                // Con BC = 0 la instrucción recorre los 64 KB
+                self.ldi();
+                let bc = self.reg.get_bc();
+                self.reg.flags.h = false;
+                self.reg.flags.p = bc != 0;
+                self.reg.flags.n = false;
+                cycles = self.repetir_bloque(bc != 0);
             }
 
             // LDD
@@ -1716,16 +2239,12 @@ impl CPU {
 
             // LDDR
             0xEDB8 => {
-                // TODO : Cuando BC se establece en 0 antes de la ejecución de la instrucción,
-                // TODO : la instrucción entra en un bucle a través de 64 KB.
-                while self.reg.get_bc() != 0 {
-                    self.ldd();
-                    let bc = self.reg.get_bc();
-                    self.reg.flags.h = false;
-                    self.reg.flags.p = bc != 0;
-                    self.reg.flags.n = false;
-                    // TODO : devolver ciclos * número de ejecuciones
-                }
+                self.ldd();
+                let bc = self.reg.get_bc();
+                self.reg.flags.h = false;
+                self.reg.flags.p = bc != 0;
+                self.reg.flags.n = false;
+                cycles = self.repetir_bloque(bc != 0);
             }
 
             // CPI
@@ -1733,15 +2252,9 @@ impl CPU {
 
             // CPIR
             0xEDB1 => {
-                // TODO : Cuando BC se establece en 0 antes de la ejecución de la instrucción,
-                // TODO : la instrucción entra en un bucle a través de 64 KB.
-                while self.reg.get_bc() != 0 {
-                    self.cpi();
-                    if self.reg.flags.z {
-                        break;
-                    }
-                    // TODO : devolver ciclos * número de ejecuciones
-                }
+                self.cpi();
+                let sigue = self.reg.get_bc() != 0 && !self.reg.flags.z;
+                cycles = self.repetir_bloque(sigue);
             }
 
             // CPD
@@ -1749,283 +2262,296 @@ impl CPU {
 
             // CPDR
             0xEDB9 => {
-                while self.reg.get_bc() != 0 {
-                    self.cpd();
-                    if self.reg.flags.z {
-                        break;
-                    }
-                    // TODO : devolver ciclos * número de ejecuciones
-                }
+                self.cpd();
+                let sigue = self.reg.get_bc() != 0 && !self.reg.flags.z;
+                cycles = self.repetir_bloque(sigue);
             }
 
             // 8-Bit Arithmetic Group
             // ADD A,(IX+d)
             0xDD86 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.add(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.add(d);
                 }
             }
 
             // ADD A,(IY+d)
             0xFD86 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.add(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.add(d);
                 }
             }
 
             // ADC A,(IX+d)
             0xDD8E => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.adc(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.adc(d);
                 }
             }
 
             // ADC A,(IY+d)
             0xFD8E => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.adc(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.adc(d);
                 }
             }
 
             // SUB (IX+d)
             0xDD96 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.sub(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.sub(d);
                 }
             }
 
             // SUB (IY+d)
             0xFD96 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.sub(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.sub(d);
                 }
             }
 
             // SBC (IX+d)
             0xDD9E => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.sbc(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.sbc(d);
                 }
             }
 
             // SBC (IY+d)
             0xFD9E => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.sbc(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.sbc(d);
                 }
             }
 
             // AND (IX+d)
             0xDDA6 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.and(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.and(d);
                 }
             }
 
             // AND (IY+d)
             0xFDA6 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.and(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.and(d);
                 }
             }
 
             // OR (IX+d)
             0xDDB6 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.or(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.or(d);
                 }
             }
 
             // OR (IY+d)
             0xFDB6 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.or(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.or(d);
                 }
             }
 
             // XOR (IX+d)
             0xDDAE => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.xor(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.xor(d);
                 }
             }
 
             // XOR (IY+d)
             0xFDAE => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.xor(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.xor(d);
                 }
             }
 
             // CP (IX+d)
             0xDDBE => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_ix() - (signed_to_abs(displacement) as u16));
                     self.cp(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_ix() + (displacement as u16));
+                        .leer(self.reg.get_ix() + (displacement as u16));
                     self.cp(d);
                 }
             }
 
             // CP (IY+d)
             0xFDBE => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
+                        .leer(self.reg.get_iy() - (signed_to_abs(displacement) as u16));
                     self.cp(d);
                 } else {
                     let d = self
                         .bus
-                        .leer_byte(self.reg.get_iy() + (displacement as u16));
+                        .leer(self.reg.get_iy() + (displacement as u16));
                     self.cp(d);
                 }
             }
 
             // INC (IX+d)
             0xDD34 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.inc(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.inc(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -2033,15 +2559,16 @@ impl CPU {
 
             // INC (IY+d)
             0xFD34 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.inc(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.inc(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -2049,15 +2576,16 @@ impl CPU {
 
             // DEC (IX+d)
             0xDD35 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_ix() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.dec(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_ix() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.dec(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -2065,15 +2593,16 @@ impl CPU {
 
             // DEC (IY+d)
             0xFD35 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 2);
+                let displacement = self.leer_instruccion(2);
+                self.memptr = self.direccion_indexada((opcode >> 8) as u8, displacement);
                 if bit::get(displacement, 7) {
                     let m = self.reg.get_iy() - (signed_to_abs(displacement) as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.dec(d);
                     self.bus.escribir_byte(m, r);
                 } else {
                     let m = self.reg.get_iy() + (displacement as u16);
-                    let d = self.bus.leer_byte(m);
+                    let d = self.bus.leer(m);
                     let r = self.dec(d);
                     self.bus.escribir_byte(m, r);
                 }
@@ -2085,8 +2614,12 @@ impl CPU {
                 self.neg();
             }
 
-            // RETI
-            0xED4D => self.call_stack_pop(),
+            // RETI: los periféricos de la cadena lo ven en el bus y terminan el servicio
+            0xED4D => {
+                self.iff1 = self.iff2;
+                self.call_stack_pop();
+                self.interrupciones.reti();
+            }
 
             // RETN
             0xED45 => {
@@ -2264,7 +2797,8 @@ impl CPU {
             0xCB06 => {
                 // RLC (HL)
                 let addr = self.reg.get_hl();
-                let r = self.rlc(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.rlc(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -2314,7 +2848,8 @@ impl CPU {
             0xCB16 => {
                 // RL (HL)
                 let addr = self.reg.get_hl();
-                let r = self.rl(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.rl(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -2364,7 +2899,8 @@ impl CPU {
             0xCB0E => {
                 // RR (HL)
                 let addr = self.reg.get_hl();
-                let r = self.rrc(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.rrc(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -2414,7 +2950,8 @@ impl CPU {
             0xCB1E => {
                 // RR (HL)
                 let addr = self.reg.get_hl();
-                let r = self.rr(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.rr(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -2464,7 +3001,8 @@ impl CPU {
             0xCB26 => {
                 // SLA (HL)
                 let addr = self.reg.get_hl();
-                let r = self.sla(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.sla(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -2514,7 +3052,8 @@ impl CPU {
             0xCB2E => {
                 // SRA (HL)
                 let addr = self.reg.get_hl();
-                let r = self.sra(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.sra(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -2564,7 +3103,8 @@ impl CPU {
             0xCB3E => {
                 // SRL (HL)
                 let addr = self.reg.get_hl();
-                let r = self.srl(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.srl(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -2576,7 +3116,8 @@ impl CPU {
 
             // RLD
             0xED6F => {
-                let hl_contents = self.bus.leer_byte(self.reg.get_hl());
+                let hl_contents = self.bus.leer(self.reg.get_hl());
+                self.memptr = self.reg.get_hl().wrapping_add(1);
                 let a_contents = self.reg.a;
 
                 let r = (self.reg.a & 0xF0) | ((((hl_contents & 0xF0) as i8) >> 4) as u8);
@@ -2588,11 +3129,13 @@ impl CPU {
                 self.reg.flags.h = false;
                 self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
                 self.reg.flags.n = false;
+                self.reg.flags.set_xy(r);
             }
 
             // RRD
             0xED67 => {
-                let hl_contents = self.bus.leer_byte(self.reg.get_hl());
+                let hl_contents = self.bus.leer(self.reg.get_hl());
+                self.memptr = self.reg.get_hl().wrapping_add(1);
                 let a_contents = self.reg.a;
 
                 let r = (self.reg.a & 0xF0) | (hl_contents & 0x0F);
@@ -2606,17 +3149,94 @@ impl CPU {
                 self.reg.flags.h = false;
                 self.reg.flags.p = r.count_ones() & 0x01 == 0x00;
                 self.reg.flags.n = false;
+                self.reg.flags.set_xy(r);
+            }
+
+            // Grupo de Entrada y Salida
+            // IN r,(C)
+            0xED40 => self.reg.b = self.in_c(), // IN B,(C)
+            0xED48 => self.reg.c = self.in_c(), // IN C,(C)
+            0xED50 => self.reg.d = self.in_c(), // IN D,(C)
+            0xED58 => self.reg.e = self.in_c(), // IN E,(C)
+            0xED60 => self.reg.h = self.in_c(), // IN H,(C)
+            0xED68 => self.reg.l = self.in_c(), // IN L,(C)
+            0xED78 => self.reg.a = self.in_c(), // IN A,(C)
+
+            // IN (C) (no documentada): solo afecta a los flags
+            0xED70 => {
+                self.in_c();
+            }
+
+            // OUT (C),r
+            0xED41 => self.out_c(self.reg.b), // OUT (C),B
+            0xED49 => self.out_c(self.reg.c), // OUT (C),C
+            0xED51 => self.out_c(self.reg.d), // OUT (C),D
+            0xED59 => self.out_c(self.reg.e), // OUT (C),E
+            0xED61 => self.out_c(self.reg.h), // OUT (C),H
+            0xED69 => self.out_c(self.reg.l), // OUT (C),L
+            0xED79 => self.out_c(self.reg.a), // OUT (C),A
+
+            // OUT (C),0 (no documentada, 0xFF en los Z80 CMOS)
+            0xED71 => self.out_c(0x00),
+
+            // INI
+            0xEDA2 => self.ini(),
+
+            // INIR
+            0xEDB2 => {
+                self.ini();
+                let sigue = self.reg.b != 0;
+                cycles = self.repetir_bloque(sigue);
+            }
+
+            // IND
+            0xEDAA => self.ind(),
+
+            // INDR
+            0xEDBA => {
+                self.ind();
+                let sigue = self.reg.b != 0;
+                cycles = self.repetir_bloque(sigue);
+            }
+
+            // OUTI
+            0xEDA3 => self.outi(),
+
+            // OTIR
+            0xEDB3 => {
+                self.outi();
+                let sigue = self.reg.b != 0;
+                cycles = self.repetir_bloque(sigue);
+            }
+
+            // OUTD
+            0xEDAB => self.outd(),
+
+            // OTDR
+            0xEDBB => {
+                self.outd();
+                let sigue = self.reg.b != 0;
+                cycles = self.repetir_bloque(sigue);
             }
 
             // Grupo de Bit Set, Reset y Test
             // BIT b,r
-            0xCB40..=0xCB7F => self.bit(self.bus.leer_byte(self.reg.pc + 1)),
+            0xCB40..=0xCB7F => {
+                let operando = self.leer_instruccion(1);
+                self.bit(operando)
+            }
 
             // SET b,r
-            0xCBC0..=0xCBFF => self.set(self.bus.leer_byte(self.reg.pc + 1)),
+            0xCBC0..=0xCBFF => {
+                let operando = self.leer_instruccion(1);
+                self.set(operando)
+            }
 
             // RES b,r
-            0xCB80..=0xCBBF => self.reset(self.bus.leer_byte(self.reg.pc + 1)),
+            0xCB80..=0xCBBF => {
+                let operando = self.leer_instruccion(1);
+                self.reset(operando)
+            }
 
             // Grupo de Saltos
             // JP (IX)
@@ -2852,40 +3472,28 @@ impl CPU {
 
             // LD IXH,n
             0xDD26 => {
-                let n = self.bus.leer_byte(self.reg.pc + 3);
+                let n = self.leer_instruccion(3);
                 self.reg.ixh = n;
             }
 
             // LD IYH,n
             0xFD26 => {
-                let n = self.bus.leer_byte(self.reg.pc + 3);
+                let n = self.leer_instruccion(3);
                 self.reg.iyh = n;
             }
 
             // LD IXL,n
             0xDD2E => {
-                let n = self.bus.leer_byte(self.reg.pc + 3);
+                let n = self.leer_instruccion(3);
                 self.reg.ixl = n;
             }
 
             // LD IYL,n
             0xFD2E => {
-                let n = self.bus.leer_byte(self.reg.pc + 3);
+                let n = self.leer_instruccion(3);
                 self.reg.iyl = n;
             }
 
-            // LD B,B
-            0xDD40 | 0xFD40 => {}
-
-            // LD B,C
-            0xDD41 | 0xFD41 => self.reg.b = self.reg.c,
-
-            // LD B,D
-            0xDD42 | 0xFD42 => self.reg.b = self.reg.d,
-
-            // LD B,E
-            0xDD43 | 0xFD43 => self.reg.b = self.reg.e,
-
             // LD B,IXH
             0xDD44 => self.reg.b = self.reg.ixh,
 
@@ -2898,21 +3506,6 @@ impl CPU {
             // LD B,IYL
             0xFD45 => self.reg.b = self.reg.iyl,
 
-            // LD B,A
-            0xDD47 | 0xFD47 => self.reg.b = self.reg.a,
-
-            // LD C,B
-            0xDD48 | 0xFD48 => self.reg.c = self.reg.b,
-
-            // LD C,C
-            0xDD49 | 0xFD49 => {}
-
-            // LD C,D
-            0xDD4A | 0xFD4A => self.reg.c = self.reg.d,
-
-            // LD C,E
-            0xDD4B | 0xFD4B => self.reg.c = self.reg.e,
-
             // LD C,IXH
             0xDD4C => self.reg.c = self.reg.ixh,
 
@@ -2925,21 +3518,6 @@ impl CPU {
             // LD C,IYL
             0xFD4D => self.reg.c = self.reg.iyl,
 
-            // LD C,A
-            0xDD4F | 0xFD4F => self.reg.c = self.reg.a,
-
-            // LD D,B
-            0xDD50 | 0xFD50 => self.reg.d = self.reg.b,
-
-            // LD D,C
-            0xDD51 | 0xFD51 => self.reg.d = self.reg.c,
-
-            // LD D,D
-            0xDD52 | 0xFD52 => {}
-
-            // LD D,E
-            0xDD53 | 0xFD53 => self.reg.d = self.reg.e,
-
             // LD D,IXH
             0xDD54 => self.reg.d = self.reg.ixh,
 
@@ -2952,21 +3530,6 @@ impl CPU {
             // LD D,IYL
             0xFD55 => self.reg.d = self.reg.iyl,
 
-            // LD D,A
-            0xDD57 | 0xFD57 => self.reg.d = self.reg.a,
-
-            // LD E,B
-            0xDD58 | 0xFD58 => self.reg.e = self.reg.b,
-
-            // LD E,C
-            0xDD59 | 0xFD59 => self.reg.e = self.reg.c,
-
-            // LD E,D
-            0xDD5A | 0xFD5A => self.reg.e = self.reg.d,
-
-            // LD E,E
-            0xDD5B | 0xFD5B => {}
-
             // LD E,IXH
             0xDD5C => self.reg.e = self.reg.ixh,
 
@@ -2979,9 +3542,6 @@ impl CPU {
             // LD E,IYL
             0xFD5D => self.reg.e = self.reg.iyl,
 
-            // LD E,A
-            0xDD5F | 0xFD5F => self.reg.e = self.reg.a,
-
             // LD IXH,B
             0xDD60 => self.reg.ixh = self.reg.b,
 
@@ -3066,18 +3626,6 @@ impl CPU {
             // LD IYL,A
             0xFD6F => self.reg.iyl = self.reg.a,
 
-            // LD A,B
-            0xDD78 | 0xFD78 => self.reg.a = self.reg.b,
-
-            // LD A,C
-            0xDD79 | 0xFD79 => self.reg.a = self.reg.c,
-
-            // LD A,D
-            0xDD7A | 0xFD7A => self.reg.a = self.reg.d,
-
-            // LD A,E
-            0xDD7B | 0xFD7B => self.reg.a = self.reg.e,
-
             // LD A,IXH
             0xDD7C => self.reg.a = self.reg.ixh,
 
@@ -3090,9 +3638,6 @@ impl CPU {
             // LD A,IYL
             0xFD7D => self.reg.a = self.reg.iyl,
 
-            // LD A,A
-            0xDD7F | 0xFD7F => {}
-
             // SLL B
             0xCB30 => {
                 let r = self.sll(self.reg.b);
@@ -3132,7 +3677,8 @@ impl CPU {
             // SLL (HL)
             0xCB36 => {
                 let addr = self.reg.get_hl();
-                let r = self.sll(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.sll(dato);
                 self.bus.escribir_byte(addr, r);
             }
 
@@ -3157,12 +3703,12 @@ impl CPU {
             | 0xDD74 | 0xDD75 | 0xDD77 | 0xFD70 | 0xFD71 | 0xFD72 | 0xFD73 | 0xFD74 | 0xFD75
             | 0xFD77 | 0xDD86 | 0xFD86 | 0xDD8E | 0xFD8E | 0xDD96 | 0xFD96 | 0xDD9E | 0xFD9E
             | 0xDDA6 | 0xFDA6 | 0xDDB6 | 0xFDB6 | 0xDDAE | 0xFDAE | 0xDDBE | 0xFDBE | 0xDD34
-            | 0xFD34 | 0xDD35 | 0xFD35 => self.reg.pc += 3,
+            | 0xFD34 | 0xDD35 | 0xFD35 => self.reg.pc = self.reg.pc.wrapping_add(3),
             0xDD36 | 0xFD36 | 0xDD21 | 0xFD21 | 0xED4B | 0xED5B | 0xED6B | 0xED7B | 0xDD2A
             | 0xFD2A | 0xED43 | 0xED53 | 0xED63 | 0xED73 | 0xDD22 | 0xFD22 | 0xDDCB | 0xFDCB => {
-                self.reg.pc += 4
+                self.reg.pc = self.reg.pc.wrapping_add(4)
             }
-            _ => self.reg.pc += 2,
+            _ => self.reg.pc = self.reg.pc.wrapping_add(2),
         }
 
         if self.debug.opcode {
@@ -3190,7 +3736,7 @@ impl CPU {
             0x46 => {
                 // LD B,(HL)
                 let addr = self.reg.get_hl();
-                self.reg.b = self.bus.leer_byte(addr)
+                self.reg.b = self.bus.leer(addr)
             }
             0x47 => self.reg.b = self.reg.a, // LD B,A
 
@@ -3203,7 +3749,7 @@ impl CPU {
             0x4E => {
                 // LD C,(HL)
                 let addr = self.reg.get_hl();
-                self.reg.c = self.bus.leer_byte(addr)
+                self.reg.c = self.bus.leer(addr)
             }
             0x4F => self.reg.c = self.reg.a, // LD C,A
 
@@ -3216,7 +3762,7 @@ impl CPU {
             0x56 => {
                 // LD D,(HL)
                 let addr = self.reg.get_hl();
-                self.reg.d = self.bus.leer_byte(addr)
+                self.reg.d = self.bus.leer(addr)
             }
             0x57 => self.reg.d = self.reg.a, // LD D,A
 
@@ -3229,7 +3775,7 @@ impl CPU {
             0x5E => {
                 // LD E,(HL)
                 let addr = self.reg.get_hl();
-                self.reg.e = self.bus.leer_byte(addr)
+                self.reg.e = self.bus.leer(addr)
             }
             0x5F => self.reg.e = self.reg.a, // LD E,A
 
@@ -3242,7 +3788,7 @@ impl CPU {
             0x66 => {
                 // LD H,(HL)
                 let addr = self.reg.get_hl();
-                self.reg.h = self.bus.leer_byte(addr)
+                self.reg.h = self.bus.leer(addr)
             }
             0x67 => self.reg.h = self.reg.a, // LD H,A
 
@@ -3255,7 +3801,7 @@ impl CPU {
             0x6E => {
                 // LD L,(HL)
                 let addr = self.reg.get_hl();
-                self.reg.l = self.bus.leer_byte(addr)
+                self.reg.l = self.bus.leer(addr)
             }
             0x6F => self.reg.l = self.reg.a, // LD L,A
 
@@ -3268,7 +3814,7 @@ impl CPU {
             0x7E => {
                 // LD A,(HL)
                 let addr = self.reg.get_hl();
-                self.reg.a = self.bus.leer_byte(addr)
+                self.reg.a = self.bus.leer(addr)
             }
             0x7F => {} // LD A,A
 
@@ -3313,117 +3859,126 @@ impl CPU {
             // LD r,n
             0x06 => {
                 // LD B,n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 self.reg.b = data;
             }
             0x0E => {
                 // LD C,n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 self.reg.c = data;
             }
             0x16 => {
                 // LD D,n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 self.reg.d = data;
             }
             0x1E => {
                 // LD E,n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 self.reg.e = data;
             }
             0x26 => {
                 // LD H,n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 self.reg.h = data;
             }
             0x2E => {
                 // LD L,n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 self.reg.l = data;
             }
             0x36 => {
                 // LD (HL),n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 let addr = self.reg.get_hl();
                 self.bus.escribir_byte(addr, data);
             }
             0x3E => {
                 // LD A,n
-                let data = self.bus.leer_byte(self.reg.pc + 1);
+                let data = self.leer_instruccion(1);
                 self.reg.a = data;
             }
 
             // LD A,(BC)
             0x0A => {
                 let addr = self.reg.get_bc();
-                self.reg.a = self.bus.leer_byte(addr);
+                self.reg.a = self.bus.leer(addr);
+                self.memptr = addr.wrapping_add(1);
             }
 
             // LD A,(DE)
             0x1A => {
                 let addr = self.reg.get_de();
-                self.reg.a = self.bus.leer_byte(addr);
+                self.reg.a = self.bus.leer(addr);
+                self.memptr = addr.wrapping_add(1);
             }
 
             // LD A,(nn)
             0x3A => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
-                self.reg.a = self.bus.leer_byte(addr);
+                let addr = self.leer_instruccion_word(1);
+                self.reg.a = self.bus.leer(addr);
+                self.memptr = addr.wrapping_add(1);
             }
 
             // LD (BC),A
             0x02 => {
                 let addr = self.reg.get_bc();
                 self.bus.escribir_byte(addr, self.reg.a);
+                self.memptr = (self.reg.a as u16) << 8 | (addr.wrapping_add(1) & 0x00FF);
             }
 
             // LD (DE),A
             0x12 => {
                 let addr = self.reg.get_de();
                 self.bus.escribir_byte(addr, self.reg.a);
+                self.memptr = (self.reg.a as u16) << 8 | (addr.wrapping_add(1) & 0x00FF);
             }
 
             // LD (nn),A
             0x32 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
                 self.bus.escribir_byte(addr, self.reg.a);
+                // En los NMOS el byte alto es A
+                self.memptr = (self.reg.a as u16) << 8 | (addr.wrapping_add(1) & 0x00FF);
             }
 
             // 16-Bit Load Group
             // LD dd,nn
             0x01 => {
                 // LD BC,nn
-                let d16 = self.bus.read_word(self.reg.pc + 1);
+                let d16 = self.leer_instruccion_word(1);
                 self.reg.set_bc(d16);
             }
             0x11 => {
                 // LD DE,nn
-                let d16 = self.bus.read_word(self.reg.pc + 1);
+                let d16 = self.leer_instruccion_word(1);
                 self.reg.set_de(d16);
             }
             0x21 => {
                 // LD HL,nn
-                let d16 = self.bus.read_word(self.reg.pc + 1);
+                let d16 = self.leer_instruccion_word(1);
                 self.reg.set_hl(d16);
             }
             0x31 => {
                 // LD SP,nn
-                let d16 = self.bus.read_word(self.reg.pc + 1);
+                let d16 = self.leer_instruccion_word(1);
                 self.reg.sp = d16;
             }
 
             // LD HL,(nn)
             0x2A => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
-                let d = self.bus.read_word(addr);
+                let addr = self.leer_instruccion_word(1);
+                let d = self.bus.leer_word(addr);
                 self.reg.set_hl(d);
+                self.memptr = addr.wrapping_add(1);
             }
 
             // LD (nn),HL
             0x22 => {
                 let d = self.reg.get_hl();
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
                 self.bus.write_word(addr, d);
+                self.memptr = addr.wrapping_add(1);
             }
 
             // LD SP,HL
@@ -3432,49 +3987,44 @@ impl CPU {
             // PUSH qq
             0xC5 => {
                 // PUSH BC
-                self.reg.sp = self.reg.sp.wrapping_sub(2);
-                self.bus.write_word(self.reg.sp, self.reg.get_bc());
+                self.apilar(self.reg.get_bc());
             }
             0xD5 => {
                 // PUSH DE
-                self.reg.sp = self.reg.sp.wrapping_sub(2);
-                self.bus.write_word(self.reg.sp, self.reg.get_de());
+                self.apilar(self.reg.get_de());
             }
             0xE5 => {
                 // PUSH HL
-                self.reg.sp = self.reg.sp.wrapping_sub(2);
-                self.bus.write_word(self.reg.sp, self.reg.get_hl());
+                self.apilar(self.reg.get_hl());
             }
             0xF5 => {
                 // PUSH AF
-                self.reg.sp = self.reg.sp.wrapping_sub(2);
-                self.bus.escribir_byte(self.reg.sp, self.reg.flags.to_byte());
-                self.bus.escribir_byte(self.reg.sp + 1, self.reg.a);
+                self.apilar(self.reg.get_af());
             }
 
             // POP qq
             0xC1 => {
                 // POP BC
-                self.reg.set_bc(self.bus.read_word(self.reg.sp));
+                self.reg.set_bc(self.bus.leer_word(self.reg.sp));
                 self.reg.sp = self.reg.sp.wrapping_add(2);
             }
 
             0xD1 => {
                 // POP DE
-                self.reg.set_de(self.bus.read_word(self.reg.sp));
+                self.reg.set_de(self.bus.leer_word(self.reg.sp));
                 self.reg.sp = self.reg.sp.wrapping_add(2);
             }
 
             0xE1 => {
                 // POP HL
-                self.reg.set_hl(self.bus.read_word(self.reg.sp));
+                self.reg.set_hl(self.bus.leer_word(self.reg.sp));
                 self.reg.sp = self.reg.sp.wrapping_add(2);
             }
 
             0xF1 => {
                 // POP AF
-                self.reg.a = self.bus.leer_byte((self.reg.sp) + 1);
-                let bflags = self.bus.leer_byte(self.reg.sp);
+                self.reg.a = self.bus.leer((self.reg.sp) + 1);
+                let bflags = self.bus.leer(self.reg.sp);
                 self.reg.flags.set_from_byte(bflags);
                 self.reg.sp = self.reg.sp.wrapping_add(2);
             }
@@ -3514,10 +4064,9 @@ impl CPU {
 
             // EX (SP),HL
             0xE3 => {
-                let pointed_by_sp = self.bus.read_word(self.reg.sp);
-                let hl = self.reg.get_hl();
-                self.bus.write_word(self.reg.sp, hl);
+                let pointed_by_sp = self.intercambiar_cima_pila(self.reg.get_hl());
                 self.reg.set_hl(pointed_by_sp);
+                self.memptr = pointed_by_sp;
             }
 
             // 8-Bit Arithmetic Group
@@ -3531,14 +4080,14 @@ impl CPU {
             0x86 => {
                 // ADD (HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.add(n)
             }
             0x87 => self.add(self.reg.a), // ADD A,A
 
             // ADD A,n
             0xC6 => {
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.add(n);
             }
 
@@ -3552,7 +4101,7 @@ impl CPU {
             0x8E => {
                 // ADC A,(HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.adc(n)
             }
             0x8F => self.adc(self.reg.a), // ADC A,A
@@ -3560,7 +4109,7 @@ impl CPU {
             // ADC a,n
             0xCE => {
                 // ADC A,(HL)
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.adc(n)
             }
 
@@ -3574,14 +4123,14 @@ impl CPU {
             0x96 => {
                 // SUB A,(HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.sub(n)
             }
             0x97 => self.sub(self.reg.a), // SUB A,A
 
             0xD6 => {
                 // SUB A,n
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.sub(n);
             }
 
@@ -3595,14 +4144,14 @@ impl CPU {
             0x9E => {
                 // SBC A,(HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.sbc(n)
             }
             0x9F => self.sbc(self.reg.a), // SBC A,A
 
             0xDE => {
                 // SBC A,n
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.sbc(n);
             }
 
@@ -3616,14 +4165,14 @@ impl CPU {
             0xA6 => {
                 // AND (HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.and(n)
             }
             0xA7 => self.and(self.reg.a), // AND A
 
             0xE6 => {
                 // AND n
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.and(n);
             }
 
@@ -3637,14 +4186,14 @@ impl CPU {
             0xB6 => {
                 // OR (HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.or(n)
             }
             0xB7 => self.or(self.reg.a), // OR A
 
             0xF6 => {
                 // OR n
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.or(n);
             }
 
@@ -3658,14 +4207,14 @@ impl CPU {
             0xAE => {
                 // XOR (HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.xor(n)
             }
             0xAF => self.xor(self.reg.a), // XOR A
 
             0xEE => {
                 // XOR n
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.xor(n);
             }
 
@@ -3679,14 +4228,14 @@ impl CPU {
             0xBE => {
                 // CP (HL)
                 let addr = self.reg.get_hl();
-                let n = self.bus.leer_byte(addr);
+                let n = self.bus.leer(addr);
                 self.cp(n)
             }
             0xBF => self.cp(self.reg.a), // CP A
 
             0xFE => {
                 // CP n
-                let n = self.bus.leer_byte(self.reg.pc + 1);
+                let n = self.leer_instruccion(1);
                 self.cp(n);
             }
 
@@ -3700,7 +4249,8 @@ impl CPU {
             0x34 => {
                 // INC (HL)
                 let addr = self.reg.get_hl();
-                let r = self.inc(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.inc(dato);
                 self.bus.escribir_byte(addr, r);
             }
             0x3C => self.reg.a = self.inc(self.reg.a), // INC A
@@ -3715,7 +4265,8 @@ impl CPU {
             0x35 => {
                 // DEC (HL)
                 let addr = self.reg.get_hl();
-                let r = self.dec(self.bus.leer_byte(addr));
+                let dato = self.bus.leer(addr);
+                let r = self.dec(dato);
                 self.bus.escribir_byte(addr, r);
             }
             0x3D => self.reg.a = self.dec(self.reg.a), // DEC A
@@ -3729,6 +4280,7 @@ impl CPU {
                 self.reg.a = !self.reg.a;
                 self.reg.flags.h = true;
                 self.reg.flags.n = true;
+                self.reg.flags.set_xy(self.reg.a);
             }
 
             // CCF
@@ -3736,6 +4288,7 @@ impl CPU {
                 self.reg.flags.h = self.reg.flags.c;
                 self.reg.flags.c = !self.reg.flags.c;
                 self.reg.flags.n = false;
+                self.reg.flags.set_xy(self.reg.a);
             }
 
             // SCF
@@ -3743,6 +4296,7 @@ impl CPU {
                 self.reg.flags.c = true;
                 self.reg.flags.h = false;
                 self.reg.flags.n = false;
+                self.reg.flags.set_xy(self.reg.a);
             }
 
             // NOP
@@ -3761,6 +4315,7 @@ impl CPU {
             0xFB => {
                 self.iff1 = true;
                 self.iff2 = true;
+                self.tras_ei = true;
             }
 
             // 16-Bit Arithmetic Group
@@ -3856,160 +4411,178 @@ impl CPU {
             // Jump group
             // JP nn
             0xC3 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 self.reg.pc = addr;
             }
 
             // JP C,nn
             0xDA => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.c {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JP NC,nn
             0xD2 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.c {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JP Z,nn
             0xCA => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.z {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JP NZ,nn
             0xC2 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.z {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JP M,nn
             0xFA => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.s {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JP P,nn
             0xF2 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.s {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JP PE,nn
             0xEA => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.p {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JP PO,nn
             0xE2 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.p {
                     self.reg.pc = addr;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // JR e
             0x18 => {
-                let displacement = self.bus.leer_byte(self.reg.pc + 1);
+                let displacement = self.leer_instruccion(1);
                 if bit::get(displacement, 7) {
-                    self.reg.pc = self.reg.pc + 2 - (signed_to_abs(displacement) as u16)
+                    self.reg.pc = self.reg.pc.wrapping_add(2).wrapping_sub(signed_to_abs(displacement) as u16)
                 } else {
-                    self.reg.pc = self.reg.pc + (displacement as u16) + 2
+                    self.reg.pc = self.reg.pc.wrapping_add(displacement as u16).wrapping_add(2)
                 }
+                self.memptr = self.reg.pc;
             }
 
             // JR C,e
             0x38 => {
+                // El desplazamiento se lee aunque no se salte
+                let displacement = self.leer_instruccion(1);
                 if self.reg.flags.c {
-                    let displacement = self.bus.leer_byte(self.reg.pc + 1);
                     if bit::get(displacement, 7) {
-                        self.reg.pc = self.reg.pc + 2 - (signed_to_abs(displacement) as u16)
+                        self.reg.pc = self.reg.pc.wrapping_add(2).wrapping_sub(signed_to_abs(displacement) as u16)
                     } else {
-                        self.reg.pc = self.reg.pc + (displacement as u16) + 2
+                        self.reg.pc = self.reg.pc.wrapping_add(displacement as u16).wrapping_add(2)
                     }
+                    self.memptr = self.reg.pc;
                     cycles += 5;
                 } else {
-                    self.reg.pc += 2
+                    self.reg.pc = self.reg.pc.wrapping_add(2)
                 }
                 cycles += 7;
             }
 
             // JR NC,e
             0x30 => {
+                // El desplazamiento se lee aunque no se salte
+                let displacement = self.leer_instruccion(1);
                 if !self.reg.flags.c {
-                    let displacement = self.bus.leer_byte(self.reg.pc + 1);
                     if bit::get(displacement, 7) {
-                        self.reg.pc = self.reg.pc + 2 - (signed_to_abs(displacement) as u16)
+                        self.reg.pc = self.reg.pc.wrapping_add(2).wrapping_sub(signed_to_abs(displacement) as u16)
                     } else {
-                        self.reg.pc = self.reg.pc + (displacement as u16) + 2
+                        self.reg.pc = self.reg.pc.wrapping_add(displacement as u16).wrapping_add(2)
                     }
+                    self.memptr = self.reg.pc;
                     cycles += 5;
                 } else {
-                    self.reg.pc += 2
+                    self.reg.pc = self.reg.pc.wrapping_add(2)
                 }
                 cycles += 7;
             }
 
             // JR Z,e
             0x28 => {
+                // El desplazamiento se lee aunque no se salte
+                let displacement = self.leer_instruccion(1);
                 if self.reg.flags.z {
-                    let displacement = self.bus.leer_byte(self.reg.pc + 1);
                     if bit::get(displacement, 7) {
-                        self.reg.pc = self.reg.pc + 2 - (signed_to_abs(displacement) as u16)
+                        self.reg.pc = self.reg.pc.wrapping_add(2).wrapping_sub(signed_to_abs(displacement) as u16)
                     } else {
-                        self.reg.pc = self.reg.pc + (displacement as u16) + 2
+                        self.reg.pc = self.reg.pc.wrapping_add(displacement as u16).wrapping_add(2)
                     }
+                    self.memptr = self.reg.pc;
                     cycles += 5;
                 } else {
-                    self.reg.pc += 2
+                    self.reg.pc = self.reg.pc.wrapping_add(2)
                 }
                 cycles += 7;
             }
 
             // JR NZ,e
             0x20 => {
+                // El desplazamiento se lee aunque no se salte
+                let displacement = self.leer_instruccion(1);
                 if !self.reg.flags.z {
-                    let displacement = self.bus.leer_byte(self.reg.pc + 1);
                     if bit::get(displacement, 7) {
-                        self.reg.pc = self.reg.pc + 2 - (signed_to_abs(displacement) as u16)
+                        self.reg.pc = self.reg.pc.wrapping_add(2).wrapping_sub(signed_to_abs(displacement) as u16)
                     } else {
-                        self.reg.pc = self.reg.pc + (displacement as u16) + 2
+                        self.reg.pc = self.reg.pc.wrapping_add(displacement as u16).wrapping_add(2)
                     }
+                    self.memptr = self.reg.pc;
                     cycles += 5;
                 } else {
-                    self.reg.pc += 2
+                    self.reg.pc = self.reg.pc.wrapping_add(2)
                 }
                 cycles += 7;
             }
@@ -4022,16 +4595,18 @@ impl CPU {
             // DJNZ, e
             0x10 => {
                 self.reg.b = (self.reg.b).wrapping_sub(1);
+                // El desplazamiento se lee aunque no se salte
+                let displacement = self.leer_instruccion(1);
                 if self.reg.b != 0 {
-                    let displacement = self.bus.leer_byte(self.reg.pc + 1);
                     if bit::get(displacement, 7) {
-                        self.reg.pc = self.reg.pc + 2 - (signed_to_abs(displacement) as u16)
+                        self.reg.pc = self.reg.pc.wrapping_add(2).wrapping_sub(signed_to_abs(displacement) as u16)
                     } else {
-                        self.reg.pc = self.reg.pc + (displacement as u16) + 2
+                        self.reg.pc = self.reg.pc.wrapping_add(displacement as u16).wrapping_add(2)
                     }
+                    self.memptr = self.reg.pc;
                     cycles += 5;
                 } else {
-                    self.reg.pc += 2
+                    self.reg.pc = self.reg.pc.wrapping_add(2)
                 }
                 cycles += 8;
             }
@@ -4039,104 +4614,113 @@ impl CPU {
             // Call and Return Group
             // CALL nn
             0xCD => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 self.call_stack_push();
                 self.reg.pc = addr;
             }
 
             // CALL C,nn
             0xDC => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.c {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // CALL NC,nn
             0xD4 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.c {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // CALL Z,nn
             0xCC => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.z {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // CALL NZ,nn
             0xC4 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.z {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // CALL M,nn
             0xFC => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.s {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // CALL P,nn
             0xF4 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.s {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // CALL PE,nn
             0xEC => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if self.reg.flags.p {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
             // CALL PO,nn
             0xE4 => {
-                let addr = self.bus.read_word(self.reg.pc + 1);
+                let addr = self.leer_instruccion_word(1);
+                self.memptr = addr;
                 if !self.reg.flags.p {
                     self.call_stack_push();
                     self.reg.pc = addr;
                     cycles += 7;
                 } else {
-                    self.reg.pc += 3
+                    self.reg.pc = self.reg.pc.wrapping_add(3)
                 }
             }
 
@@ -4149,7 +4733,7 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
@@ -4159,7 +4743,7 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
@@ -4169,7 +4753,7 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
@@ -4179,7 +4763,7 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
@@ -4189,7 +4773,7 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
@@ -4199,7 +4783,7 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
@@ -4209,7 +4793,7 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
@@ -4219,104 +4803,89 @@ impl CPU {
                     self.call_stack_pop();
                     cycles += 6;
                 } else {
-                    self.reg.pc += 1;
+                    self.reg.pc = self.reg.pc.wrapping_add(1);
                 }
             }
 
             // RST 0
             0xC7 => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0000;
+                self.memptr = 0x0000;
             }
 
             // RST 08
             0xCF => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0008;
+                self.memptr = 0x0008;
             }
 
             // RST 10
             0xD7 => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0010;
+                self.memptr = 0x0010;
             }
 
             // RST 18
             0xDF => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0018;
+                self.memptr = 0x0018;
             }
 
             // RST 20
             0xE7 => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0020;
+                self.memptr = 0x0020;
             }
 
             // RST 28
             0xEF => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0028;
+                self.memptr = 0x0028;
             }
 
             // RST 30
             0xF7 => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0030;
+                self.memptr = 0x0030;
             }
 
             // RST 38
             0xFF => {
-                match self.int {
-                    Some(_) => self.interrupt_stack_push(),
-                    None => {
-                        self.reg.pc += 1;
-                        self.interrupt_stack_push();
-                    }
-                }
+                self.reg.pc = self.reg.pc.wrapping_add(1);
+                self.interrupt_stack_push();
                 self.reg.pc = 0x0038;
+                self.memptr = 0x0038;
+            }
+
+            // Grupo de Entrada y Salida
+            // IN A,(n): A aparece en la mitad alta del bus de direcciones
+            0xDB => {
+                let n = self.leer_instruccion(1);
+                let puerto = (self.reg.a as u16) << 8 | n as u16;
+                self.reg.a = self.leer_puerto(puerto);
+                self.memptr = puerto.wrapping_add(1);
+            }
+
+            // OUT (n),A
+            0xD3 => {
+                let n = self.leer_instruccion(1);
+                let puerto = (self.reg.a as u16) << 8 | n as u16;
+                self.escribir_puerto(puerto, self.reg.a);
+                self.memptr = (self.reg.a as u16) << 8 | (n.wrapping_add(1) as u16);
             }
 
             _ => {
@@ -4330,10 +4899,10 @@ impl CPU {
         if self.debug.opcode {
             print!("{:#06X}\t{}\nSP : {:#06X}\tS : {}\tZ : {}\tH : {}\tP : {}\tN : {}\tC : {}\nB\
              : {:#04X}\tC : {:#04X}\tD : {:#04X}\tE : {:#04X}\tH : {:#04X}\tL : {:#04X}\tA : {:#04X}\t(SP)\
-              : {:#06X}\n", pc, self.dasm(pc).0, self.reg.sp, self.reg.flags.s as i32, self.reg.flags.z as i32,
+              : {:#06X}\tWZ : {:#06X}\n", pc, self.dasm_anotado(pc), self.reg.sp, self.reg.flags.s as i32, self.reg.flags.z as i32,
                    self.reg.flags.h as i32, self.reg.flags.p as i32, self.reg.flags.n as i32,
                    self.reg.flags.c as i32, self.reg.b, self.reg.c, self.reg.d, self.reg.e, self.reg.h,
-                   self.reg.l, self.reg.a, self.bus.read_word(self.reg.sp))
+                   self.reg.l, self.reg.a, self.bus.read_word(self.reg.sp), self.memptr)
         }
 
         match opcode {
@@ -4342,9 +4911,9 @@ impl CPU {
             | 0xF8 | 0xF0 | 0xE8 | 0xE0 | 0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF
             | 0x76 | 0x18 | 0x38 | 0x30 | 0x28 | 0x20 | 0x10 => {}
             0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E | 0xC6 | 0xCE | 0xD6 | 0xDE
-            | 0xE6 | 0xF6 | 0xEE | 0xFE | 0xDB | 0xD3 => self.reg.pc += 2,
-            0x32 | 0x01 | 0x11 | 0x21 | 0x31 | 0x2A | 0x22 | 0x3A => self.reg.pc += 3,
-            _ => self.reg.pc += 1,
+            | 0xE6 | 0xF6 | 0xEE | 0xFE | 0xDB | 0xD3 => self.reg.pc = self.reg.pc.wrapping_add(2),
+            0x32 | 0x01 | 0x11 | 0x21 | 0x31 | 0x2A | 0x22 | 0x3A => self.reg.pc = self.reg.pc.wrapping_add(3),
+            _ => self.reg.pc = self.reg.pc.wrapping_add(1),
         }
 
         cycles
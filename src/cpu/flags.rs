@@ -1,9 +1,10 @@
+#[derive(Debug, Clone, PartialEq)]
 pub struct Flags {
     pub s: bool,  // signo                : bit 7
     pub z: bool,  // cero                 : bit 6
-    pub b5: bool, // no usado
+    pub b5: bool, // no documentado (Y) : bit 5
     pub h: bool,  // acarreo (carry)      : bit 4
-    pub b3: bool, // no usado
+    pub b3: bool, // no documentado (X) : bit 3
     pub p: bool,  // parity / overflow    : bit 2
     pub n: bool,  // substract            : bit 1
     pub c: bool,  // carry                : bit 0
@@ -29,6 +30,13 @@ impl Flags {
         }
     }
 
+    /// Copia los bits 3 y 5 de `valor` en los flags no documentados. En casi todas las
+    /// instrucciones `valor` es el resultado.
+    pub fn set_xy(&mut self, valor: u8) {
+        self.b3 = (valor & 0x08) != 0;
+        self.b5 = (valor & 0x20) != 0;
+    }
+
     /// Convierte flags a un byte.
     pub fn to_byte(&self) -> u8 {
         let s = if self.s { 1 << 7 } else { 0 };
@@ -0,0 +1,204 @@
+use std::fmt;
+
+/// Bytes por registro al escribir un fichero Intel HEX
+const BYTES_POR_REGISTRO: usize = 16;
+
+/// Bloque de bytes consecutivos de un fichero Intel HEX
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segmento {
+    pub direccion: u16,
+    pub datos: Vec<u8>,
+}
+
+/// Error de lectura de un fichero Intel HEX con la línea (empezando en 1) donde se produjo
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorHex {
+    pub linea: usize,
+    pub mensaje: String,
+}
+
+impl fmt::Display for ErrorHex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "línea {}: {}", self.linea, self.mensaje)
+    }
+}
+
+impl std::error::Error for ErrorHex {}
+
+/// Lee un fichero Intel HEX y devuelve sus datos agrupados en segmentos contiguos, en el
+/// orden en que aparecen. Se comprueba el checksum de cada registro.
+///
+/// Registros admitidos: 00 (datos), 01 (fin), 02 y 04 (dirección extendida, que debe
+/// dejar los datos dentro de los 64 KiB del Z80) y 03/05 (dirección de arranque, se ignoran).
+pub fn leer_hex(texto: &str) -> Result<Vec<Segmento>, ErrorHex> {
+    let mut segmentos: Vec<Segmento> = Vec::new();
+    let mut base: u32 = 0;
+    for (n, linea) in texto.lines().enumerate() {
+        let error = |mensaje: String| ErrorHex {
+            linea: n + 1,
+            mensaje,
+        };
+        let linea = linea.trim();
+        if linea.is_empty() {
+            continue;
+        }
+        let hex = linea
+            .strip_prefix(':')
+            .ok_or_else(|| error(String::from("el registro no empieza por ':'")))?;
+        if !hex.is_ascii() {
+            return Err(error(String::from("carácter no hexadecimal")));
+        }
+        if hex.len() % 2 != 0 || hex.len() < 10 {
+            return Err(error(String::from("longitud de registro no válida")));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| error(String::from("carácter no hexadecimal")))?;
+
+        let longitud = bytes[0] as usize;
+        if bytes.len() != longitud + 5 {
+            return Err(error(format!(
+                "el registro declara {} bytes y contiene {}",
+                longitud,
+                bytes.len() - 5
+            )));
+        }
+        let checksum = bytes.iter().fold(0u8, |suma, b| suma.wrapping_add(*b));
+        if checksum != 0 {
+            let esperado = bytes[..bytes.len() - 1]
+                .iter()
+                .fold(0u8, |suma, b| suma.wrapping_add(*b))
+                .wrapping_neg();
+            return Err(error(format!(
+                "checksum incorrecto: {:02X}, se esperaba {:02X}",
+                bytes[bytes.len() - 1],
+                esperado
+            )));
+        }
+
+        let offset = (bytes[1] as u32) << 8 | bytes[2] as u32;
+        let datos = &bytes[4..4 + longitud];
+        match bytes[3] {
+            0x00 => {
+                let direccion = base + offset;
+                if direccion + longitud as u32 > 0x10000 {
+                    return Err(error(format!(
+                        "datos fuera del espacio del Z80 en {:#06X}",
+                        direccion
+                    )));
+                }
+                let direccion = direccion as u16;
+                match segmentos.last_mut() {
+                    Some(s) if s.direccion as u32 + s.datos.len() as u32 == direccion as u32 => {
+                        s.datos.extend_from_slice(datos)
+                    }
+                    _ => segmentos.push(Segmento {
+                        direccion,
+                        datos: datos.to_vec(),
+                    }),
+                }
+            }
+            0x01 => break,
+            tipo @ (0x02 | 0x04) => {
+                if longitud != 2 {
+                    return Err(error(format!(
+                        "el registro de tipo {:02X} debe tener 2 bytes y tiene {}",
+                        tipo, longitud
+                    )));
+                }
+                let valor = (datos[0] as u32) << 8 | datos[1] as u32;
+                base = if tipo == 0x02 { valor << 4 } else { valor << 16 };
+            }
+            0x03 | 0x05 => {}
+            tipo => return Err(error(format!("tipo de registro no válido: {:02X}", tipo))),
+        }
+    }
+    Ok(segmentos)
+}
+
+/// Escribe datos situados a partir de `direccion` en formato Intel HEX, en registros de
+/// 16 bytes terminados con el registro de fin.
+pub fn escribir_hex(direccion: u16, datos: &[u8]) -> String {
+    let mut texto = String::new();
+    for (i, bloque) in datos.chunks(BYTES_POR_REGISTRO).enumerate() {
+        let dir = direccion.wrapping_add((i * BYTES_POR_REGISTRO) as u16);
+        texto.push_str(&registro(dir, 0x00, bloque));
+    }
+    texto.push_str(&registro(0x0000, 0x01, &[]));
+    texto
+}
+
+fn registro(direccion: u16, tipo: u8, datos: &[u8]) -> String {
+    let mut bytes = vec![datos.len() as u8, (direccion >> 8) as u8, direccion as u8, tipo];
+    bytes.extend_from_slice(datos);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |suma, b| suma.wrapping_add(*b))
+        .wrapping_neg();
+    bytes.push(checksum);
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(":{}\n", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::bus::{Bus, Memoria};
+
+    #[test]
+    fn leer_registros_dispersos() {
+        let texto = std::fs::read_to_string("PROGRAMAS/hex/im2.z80.hex").unwrap();
+        let segmentos = leer_hex(&texto).unwrap();
+        let direcciones: Vec<u16> = segmentos.iter().map(|s| s.direccion).collect();
+        assert_eq!(direcciones, vec![0x0000, 0x0038, 0x0050, 0x0102, 0x0106]);
+        assert_eq!(segmentos[1].datos, vec![0x57, 0xC9]);
+    }
+
+    #[test]
+    fn registros_contiguos_en_un_segmento() {
+        let texto = escribir_hex(0x8000, &[0xAA; 40]);
+        assert_eq!(texto.lines().count(), 4);
+        assert_eq!(
+            leer_hex(&texto).unwrap(),
+            vec![Segmento {
+                direccion: 0x8000,
+                datos: vec![0xAA; 40]
+            }]
+        );
+    }
+
+    #[test]
+    fn errores() {
+        let error = leer_hex(":020000000102FB\n:0200380057C9A7\n").unwrap_err();
+        assert_eq!(error.linea, 2);
+        assert!(error.mensaje.contains("checksum"));
+        assert!(leer_hex("0200380057C9A6").is_err());
+        assert!(leer_hex(":0300380057C9A6").is_err());
+        assert!(leer_hex(":020000040001F9\n:0200380057C9A6").is_err());
+        // Un carácter no ASCII es un error, no un pánico
+        let error = leer_hex(":02003800ñ7C9A6").unwrap_err();
+        assert!(error.mensaje.contains("no hexadecimal"));
+        let error = leer_hex(":0100000400FB").unwrap_err();
+        assert!(error.mensaje.contains("debe tener 2 bytes"), "{}", error.mensaje);
+    }
+
+    #[test]
+    fn cargar_y_volcar_bus() {
+        let mut bus = Bus::new(0xFFFF);
+        assert_eq!(bus.load_hex("PROGRAMAS/hex/im2.z80.hex").unwrap(), 26);
+        assert_eq!(bus.leer_byte(0x0038), 0x57);
+        assert_eq!(bus.leer_byte(0x0107), 0xC9);
+        assert_eq!(
+            bus.dump_hex(0x0050, 8),
+            ":08005000ED5EFBB8C25300C9CC\n:00000001FF\n"
+        );
+
+        // En una memoria que no llega a 0x0100 es un error, no un pánico, y no se carga nada
+        let mut pequena = Bus::new(0x00FF);
+        let error = pequena.load_hex("PROGRAMAS/hex/im2.z80.hex").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(pequena.leer_byte(0x0038), 0x00);
+    }
+}
@@ -0,0 +1,187 @@
+/// Dispositivo registrado en la cadena de prioridad
+struct Fuente {
+    prioridad: u8,
+    vector: u8,
+    pendiente: bool,
+    en_servicio: bool,
+}
+
+/// Cadena de prioridad de interrupciones (daisy chain) de los periféricos Z80.
+///
+/// Cada dispositivo tiene una entrada IEI y una salida IEO: mientras atiende su interrupción
+/// (desde el reconocimiento hasta el RETI) baja IEO y los que vienen detrás no pueden
+/// interrumpir, aunque los de delante sí. Al reconocer la interrupción el dispositivo de
+/// más prioridad con petición pone su vector en el bus para el modo 2. El RETI lo observa
+/// el propio dispositivo en el bus, así que aquí lo comunica la CPU al ejecutarlo.
+///
+/// La prioridad la da la posición en la cadena: 0 es el dispositivo más cercano a la CPU.
+pub struct CadenaInterrupciones {
+    fuentes: Vec<Fuente>,
+    // Identificadores ordenados por prioridad
+    orden: Vec<usize>,
+}
+
+impl Default for CadenaInterrupciones {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CadenaInterrupciones {
+    /// Crea una cadena sin dispositivos
+    pub fn new() -> CadenaInterrupciones {
+        CadenaInterrupciones {
+            fuentes: Vec::new(),
+            orden: Vec::new(),
+        }
+    }
+
+    /// Registra un dispositivo y devuelve su identificador. Con la misma prioridad va
+    /// antes el que se registró primero.
+    /// Ejemplo:
+    ///    let mut c = CPU::new(0xFFFF);
+    ///    let ctc = c.interrupciones.registrar(0, 0x10);
+    ///    c.interrupciones.solicitar(ctc);
+    pub fn registrar(&mut self, prioridad: u8, vector: u8) -> usize {
+        let id = self.fuentes.len();
+        self.fuentes.push(Fuente {
+            prioridad,
+            vector,
+            pendiente: false,
+            en_servicio: false,
+        });
+        let posicion = self.orden.partition_point(|&i| self.fuentes[i].prioridad <= prioridad);
+        self.orden.insert(posicion, id);
+        id
+    }
+
+    /// Cambia el vector que pone el dispositivo en el bus, como cuando el programa lo
+    /// escribe en un CTC o un PIO
+    pub fn set_vector(&mut self, id: usize, vector: u8) {
+        self.fuentes[id].vector = vector;
+    }
+
+    /// El dispositivo pide una interrupción. Queda pendiente hasta que se reconoce.
+    pub fn solicitar(&mut self, id: usize) {
+        self.fuentes[id].pendiente = true;
+    }
+
+    /// El dispositivo retira su petición sin que se haya reconocido
+    pub fn retirar(&mut self, id: usize) {
+        self.fuentes[id].pendiente = false;
+    }
+
+    /// Indica si el dispositivo está atendiendo su interrupción (IEO baja)
+    pub fn en_servicio(&self, id: usize) -> bool {
+        self.fuentes[id].en_servicio
+    }
+
+    /// Dispositivo que interrumpe ahora: el primero de la cadena con petición, siempre que
+    /// ni él ni ninguno de delante esté en servicio
+    fn solicitante(&self) -> Option<usize> {
+        for &id in &self.orden {
+            let fuente = &self.fuentes[id];
+            if fuente.en_servicio {
+                return None;
+            }
+            if fuente.pendiente {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Vector del dispositivo que tiene activa /INT, si lo hay
+    pub fn peticion(&self) -> Option<u8> {
+        self.solicitante().map(|id| self.fuentes[id].vector)
+    }
+
+    /// Ciclo de reconocimiento: el dispositivo que interrumpe pasa a servicio y devuelve
+    /// el vector que pone en el bus de datos
+    pub fn reconocer(&mut self) -> Option<u8> {
+        let id = self.solicitante()?;
+        let fuente = &mut self.fuentes[id];
+        fuente.pendiente = false;
+        fuente.en_servicio = true;
+        Some(fuente.vector)
+    }
+
+    /// RETI: termina el servicio del dispositivo de más prioridad que lo tenía
+    pub fn reti(&mut self) {
+        if let Some(&id) = self.orden.iter().find(|&&i| self.fuentes[i].en_servicio) {
+            self.fuentes[id].en_servicio = false;
+        }
+    }
+
+    /// /RESET: se borran las peticiones y los servicios en curso
+    pub fn reiniciar(&mut self) {
+        for fuente in &mut self.fuentes {
+            fuente.pendiente = false;
+            fuente.en_servicio = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prioridad_y_reti() {
+        let mut cadena = CadenaInterrupciones::new();
+        let sio = cadena.registrar(2, 0x20);
+        let ctc = cadena.registrar(0, 0x10);
+        let pio = cadena.registrar(1, 0x18);
+        assert_eq!(cadena.peticion(), None);
+
+        // Las dos peticiones se conservan y se atiende primero la de más prioridad
+        cadena.solicitar(sio);
+        cadena.solicitar(pio);
+        assert_eq!(cadena.reconocer(), Some(0x18));
+        assert!(cadena.en_servicio(pio));
+        // El SIO está detrás del PIO en servicio
+        assert_eq!(cadena.peticion(), None);
+
+        // El CTC está delante y puede anidar
+        cadena.solicitar(ctc);
+        assert_eq!(cadena.reconocer(), Some(0x10));
+        cadena.reti();
+        assert!(!cadena.en_servicio(ctc));
+        assert!(cadena.en_servicio(pio));
+        assert_eq!(cadena.peticion(), None);
+
+        cadena.reti();
+        cadena.set_vector(sio, 0x28);
+        assert_eq!(cadena.reconocer(), Some(0x28));
+        assert_eq!(cadena.reconocer(), None);
+    }
+
+    #[test]
+    fn en_servicio_se_bloquea() {
+        let mut cadena = CadenaInterrupciones::new();
+        let a = cadena.registrar(0, 0x10);
+        cadena.solicitar(a);
+        assert_eq!(cadena.reconocer(), Some(0x10));
+        // Una nueva petición de A espera al RETI de su propia rutina
+        cadena.solicitar(a);
+        assert_eq!(cadena.peticion(), None);
+        assert_eq!(cadena.reconocer(), None);
+        cadena.reti();
+        assert_eq!(cadena.reconocer(), Some(0x10));
+    }
+
+    #[test]
+    fn retirar_y_reiniciar() {
+        let mut cadena = CadenaInterrupciones::new();
+        let a = cadena.registrar(0, 0x00);
+        let b = cadena.registrar(0, 0x02);
+        cadena.solicitar(a);
+        cadena.solicitar(b);
+        cadena.retirar(a);
+        assert_eq!(cadena.peticion(), Some(0x02));
+        cadena.reconocer();
+        cadena.reiniciar();
+        assert!(!cadena.en_servicio(b));
+        assert_eq!(cadena.peticion(), None);
+    }
+}
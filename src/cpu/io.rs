@@ -0,0 +1,175 @@
+/// Dispositivo conectado al espacio de E/S del Z80.
+///
+/// El Z80 pone en el bus de direcciones los 16 bits completos del puerto: el byte bajo es
+/// el operando `n` o el registro C, y el byte alto es A (`IN A,(n)` / `OUT (n),A`) o B
+/// (instrucciones con `(C)` y de bloque). Cada dispositivo recibe esa dirección completa.
+pub trait DispositivoIO {
+    /// Lee un byte del puerto.
+    fn leer(&mut self, puerto: u16) -> u8;
+
+    /// Escribe un byte en el puerto.
+    fn escribir(&mut self, puerto: u16, dato: u8);
+}
+
+/// Dispositivo registrado junto a la decodificación de direcciones que lo selecciona.
+struct Conexion {
+    mascara: u16,
+    valor: u16,
+    dispositivo: Box<dyn DispositivoIO>,
+}
+
+/// Acceso al espacio de E/S anotado por `Puertos`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccesoIO {
+    pub puerto: u16,
+    pub dato: u8,
+    pub escritura: bool,
+}
+
+/// La estructura Puertos aloja los dispositivos del espacio de E/S.
+///
+/// Un dispositivo queda seleccionado cuando `puerto & mascara == valor`, lo que permite
+/// modelar tanto la decodificación completa de 16 bits como la parcial (solo A0-A7, o
+/// incluso una sola línea de dirección) que usan la mayoría de las máquinas reales.
+pub struct Puertos {
+    conexiones: Vec<Conexion>,
+    anotar: bool,
+    accesos: Vec<AccesoIO>,
+}
+
+impl Default for Puertos {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Puertos {
+    /// Crea un espacio de E/S vacío. Las lecturas sin dispositivo devuelven 0xFF (bus flotante).
+    pub fn new() -> Puertos {
+        Puertos {
+            conexiones: Vec::new(),
+            anotar: false,
+            accesos: Vec::new(),
+        }
+    }
+
+    /// Registra un dispositivo que responde cuando `puerto & mascara == valor`.
+    /// Ejemplo:
+    ///    let mut c = CPU::new(0xFFFF);
+    ///    c.io.registrar(0x00FF, 0x00FE, Box::new(ula));    // Puertos pares del Spectrum
+    pub fn registrar(&mut self, mascara: u16, valor: u16, dispositivo: Box<dyn DispositivoIO>) {
+        self.conexiones.push(Conexion {
+            mascara,
+            valor: valor & mascara,
+            dispositivo,
+        });
+    }
+
+    /// Registra un dispositivo en un puerto de 8 bits, ignorando el byte alto de la dirección.
+    pub fn registrar_puerto(&mut self, puerto: u8, dispositivo: Box<dyn DispositivoIO>) {
+        self.registrar(0x00FF, puerto as u16, dispositivo);
+    }
+
+    /// Activa o desactiva la anotación de los accesos, que se recogen con `tomar_accesos`
+    pub fn anotar_accesos(&mut self, activar: bool) {
+        self.anotar = activar;
+        self.accesos.clear();
+    }
+
+    /// Devuelve los accesos anotados desde la última llamada y los borra
+    pub fn tomar_accesos(&mut self) -> Vec<AccesoIO> {
+        std::mem::take(&mut self.accesos)
+    }
+
+    /// Lee un byte del primer dispositivo que decodifica la dirección.
+    pub fn leer(&mut self, puerto: u16) -> u8 {
+        let dato = match self
+            .conexiones
+            .iter_mut()
+            .find(|c| puerto & c.mascara == c.valor)
+        {
+            Some(c) => c.dispositivo.leer(puerto),
+            None => 0xFF,
+        };
+        if self.anotar {
+            self.accesos.push(AccesoIO { puerto, dato, escritura: false });
+        }
+        dato
+    }
+
+    /// Escribe un byte en todos los dispositivos que decodifican la dirección.
+    pub fn escribir(&mut self, puerto: u16, dato: u8) {
+        if self.anotar {
+            self.accesos.push(AccesoIO { puerto, dato, escritura: true });
+        }
+        for c in self
+            .conexiones
+            .iter_mut()
+            .filter(|c| puerto & c.mascara == c.valor)
+        {
+            c.dispositivo.escribir(puerto, dato);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Latch {
+        dato: u8,
+        escrituras: Rc<RefCell<Vec<(u16, u8)>>>,
+    }
+
+    impl DispositivoIO for Latch {
+        fn leer(&mut self, _puerto: u16) -> u8 {
+            self.dato
+        }
+
+        fn escribir(&mut self, puerto: u16, dato: u8) {
+            self.escrituras.borrow_mut().push((puerto, dato));
+        }
+    }
+
+    #[test]
+    fn puerto_sin_dispositivo() {
+        let mut p = Puertos::new();
+        assert_eq!(p.leer(0x1234), 0xFF);
+    }
+
+    #[test]
+    fn decodificacion_parcial() {
+        let escrituras = Rc::new(RefCell::new(Vec::new()));
+        let mut p = Puertos::new();
+        p.registrar_puerto(
+            0x07,
+            Box::new(Latch {
+                dato: 0x5A,
+                escrituras: escrituras.clone(),
+            }),
+        );
+        assert_eq!(p.leer(0xAB07), 0x5A);
+        assert_eq!(p.leer(0xAB08), 0xFF);
+        p.escribir(0xCD07, 0x11);
+        assert_eq!(*escrituras.borrow(), vec![(0xCD07, 0x11)]);
+    }
+
+    #[test]
+    fn decodificacion_completa() {
+        let escrituras = Rc::new(RefCell::new(Vec::new()));
+        let mut p = Puertos::new();
+        p.registrar(
+            0xFFFF,
+            0x7FFD,
+            Box::new(Latch {
+                dato: 0x00,
+                escrituras: escrituras.clone(),
+            }),
+        );
+        p.escribir(0x7FFD, 0x10);
+        p.escribir(0xFFFD, 0x20);
+        assert_eq!(*escrituras.borrow(), vec![(0x7FFD, 0x10)]);
+    }
+}
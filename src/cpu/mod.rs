@@ -5,4 +5,11 @@ pub mod registers;
 pub mod ciclos;
 pub mod flags;
 pub mod dasm;
+pub mod asm;
+pub mod hex;
+pub mod io;
+pub mod interrupciones;
+pub mod traza;
+pub mod pines;
+#[cfg(test)]
 mod test;
\ No newline at end of file
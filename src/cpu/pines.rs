@@ -0,0 +1,905 @@
+use std::collections::VecDeque;
+
+use crate::cpu::bus::{Bus, Memoria};
+use crate::cpu::cpu::CPU;
+use crate::cpu::dasm::desensamblar;
+use crate::cpu::io::AccesoIO;
+use crate::cpu::traza::{Acceso, BusTraza, TipoAcceso};
+
+/// Estado de los pines del Z80 durante un estado T. Las señales de control valen true
+/// cuando están activas (pin a nivel bajo).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pines {
+    pub direccion: u16,
+    pub dato: u8,
+    pub mreq: bool,
+    pub iorq: bool,
+    pub rd: bool,
+    pub wr: bool,
+    pub m1: bool,
+    pub rfsh: bool,
+    pub halt: bool,
+    pub busack: bool,
+    /// Entrada /RESET
+    pub reset: bool,
+}
+
+impl Pines {
+    /// Registro de 11 bytes con el formato de `enviar_datos_ciclo` en el firmware: ciclo
+    /// (big endian), reloj, dirección (little endian), dato y los niveles de /RD, /WR,
+    /// /MREQ, /RFSH y /RESET. /IORQ, /M1, /HALT y /BUSACK no se capturan.
+    pub fn registro_captura(&self, ciclo: u16, reloj: u8) -> [u8; 11] {
+        let nivel = |activa: bool| if activa { 0 } else { 1 };
+        let [ciclo_h, ciclo_l] = ciclo.to_be_bytes();
+        let [dir_l, dir_h] = self.direccion.to_le_bytes();
+        [
+            ciclo_h,
+            ciclo_l,
+            reloj,
+            dir_l,
+            dir_h,
+            self.dato,
+            nivel(self.rd),
+            nivel(self.wr),
+            nivel(self.mreq),
+            nivel(self.rfsh),
+            nivel(self.reset),
+        ]
+    }
+}
+
+/// Tipo de ciclo de máquina
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TipoCicloM {
+    /// Búsqueda de código de operación con su refresco (M1)
+    Fetch,
+    LecturaMemoria,
+    EscrituraMemoria,
+    LecturaIO,
+    EscrituraIO,
+    /// Reconocimiento de interrupción: M1 con /IORQ en lugar de /MREQ
+    ReconocimientoInt,
+    /// Estados T internos, sin actividad en el bus
+    Interno,
+    /// Bus cedido con /BUSACK. Dura un estado T.
+    BusLiberado,
+    /// /RESET activa. Dura un estado T.
+    Reset,
+}
+
+impl TipoCicloM {
+    /// Índice del estado tras el que se muestrea /WAIT: T2 en los accesos a memoria y el
+    /// TW automático en los de E/S y en el reconocimiento de interrupción
+    fn muestreo_wait(self) -> Option<usize> {
+        match self {
+            TipoCicloM::Fetch | TipoCicloM::LecturaMemoria | TipoCicloM::EscrituraMemoria => Some(1),
+            TipoCicloM::LecturaIO | TipoCicloM::EscrituraIO => Some(2),
+            TipoCicloM::ReconocimientoInt => Some(3),
+            TipoCicloM::Interno | TipoCicloM::BusLiberado | TipoCicloM::Reset => None,
+        }
+    }
+}
+
+/// Ciclo de máquina con el estado de los pines en cada uno de sus estados T
+#[derive(Debug, Clone, PartialEq)]
+pub struct CicloM {
+    pub tipo: TipoCicloM,
+    pub direccion: u16,
+    pub dato: u8,
+    pub estados: Vec<Pines>,
+}
+
+impl CicloM {
+    /// Ciclo con sus estados T según los diagramas de tiempos del Z80. `refresco` es la
+    /// dirección (I, R) que sale en la segunda mitad de un M1; `longitud` es el número de
+    /// estados de un ciclo interno o, en un ciclo de bus más largo que el normal (el M1 de
+    /// 5 estados de PUSH, la lectura de 4 de CALL...), el total con los estados internos
+    /// que le siguen sin actividad en el bus. 0 es la duración normal.
+    fn nuevo(tipo: TipoCicloM, direccion: u16, dato: u8, refresco: u16, halt: bool, longitud: usize) -> CicloM {
+        let base = Pines {
+            direccion,
+            dato,
+            halt,
+            ..Pines::default()
+        };
+        let refrescar = Pines {
+            direccion: refresco,
+            dato: 0xFF,
+            mreq: true,
+            rfsh: true,
+            halt,
+            ..Pines::default()
+        };
+        let mut estados = match tipo {
+            TipoCicloM::Fetch => {
+                let busqueda = Pines { m1: true, mreq: true, rd: true, ..base };
+                vec![busqueda, busqueda, refrescar, refrescar]
+            }
+            TipoCicloM::LecturaMemoria => vec![Pines { mreq: true, rd: true, ..base }; 3],
+            TipoCicloM::EscrituraMemoria => {
+                let escritura = Pines { mreq: true, wr: true, ..base };
+                vec![Pines { mreq: true, ..base }, escritura, escritura]
+            }
+            TipoCicloM::LecturaIO | TipoCicloM::EscrituraIO => {
+                let activo = Pines {
+                    iorq: true,
+                    rd: tipo == TipoCicloM::LecturaIO,
+                    wr: tipo == TipoCicloM::EscrituraIO,
+                    ..base
+                };
+                // T1, T2, TW automático y T3
+                vec![base, activo, activo, activo]
+            }
+            TipoCicloM::ReconocimientoInt => {
+                let m1 = Pines { m1: true, ..base };
+                let iorq = Pines { m1: true, iorq: true, ..base };
+                // T1, T2, dos TW automáticos y el refresco
+                vec![m1, m1, iorq, iorq, refrescar, refrescar]
+            }
+            TipoCicloM::Interno => vec![base; longitud],
+            TipoCicloM::BusLiberado => vec![Pines { busack: true, halt, ..Pines::default() }],
+            TipoCicloM::Reset => vec![Pines { reset: true, ..Pines::default() }],
+        };
+        if estados.len() < longitud {
+            estados.resize(longitud, base);
+        }
+        CicloM {
+            tipo,
+            direccion,
+            dato,
+            estados,
+        }
+    }
+}
+
+/// Estado T devuelto por `CpuPorCiclos::paso_t`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EstadoT {
+    pub tipo: TipoCicloM,
+    /// Número del estado dentro de su ciclo de máquina, empezando en 1. Un estado de
+    /// espera repite el número del estado tras el que se inserta.
+    pub t: usize,
+    /// Estado de espera (TW) insertado por /WAIT
+    pub espera: bool,
+    pub pines: Pines,
+}
+
+/// CPU que avanza de estado T en estado T o de ciclo de máquina en ciclo de máquina.
+///
+/// Solo los pines avanzan estado a estado. El emulador sigue ejecutando instrucciones
+/// completas: al pedir el primer estado de una instrucción esta se ejecuta entera y sus
+/// accesos (los de `BusTraza` y los de E/S) se reparten en los ciclos de máquina que
+/// marca para esa instrucción la tabla de `temporizacion`, con los estados internos en
+/// su sitio. Si aun así faltan estados T hasta los de `ciclos.rs` se añaden como un ciclo
+/// interno al final. Las instrucciones de bloque (LDIR, OTIR...) son una instrucción por
+/// repetición.
+///
+/// Por eso los registros de `cpu` solo son válidos en los límites de instrucción
+/// (`en_limite_de_instruccion`): en cualquier estado intermedio ya tienen los valores
+/// del final de la instrucción en curso.
+///
+/// Los pines de entrada de `cpu` se respetan a nivel de estado T: /WAIT añade estados de
+/// espera en el punto de muestreo de cada ciclo, y /BUSREQ y /RESET se atienden entre
+/// ciclos de máquina.
+/// Ejemplo:
+///    let mut z80 = CpuPorCiclos::new(CPU::con_bus(BusTraza::new(Bus::new(0xFFFF))));
+///    let m = z80.paso_m();
+///    let t = z80.paso_t();
+pub struct CpuPorCiclos<B: Memoria = Bus> {
+    pub cpu: CPU<BusTraza<B>>,
+    /// Estados T transcurridos
+    pub estados_t: u64,
+    pendientes: VecDeque<CicloM>,
+    /// Ciclo a medio recorrer con `paso_t` y su siguiente estado
+    actual: Option<(CicloM, usize)>,
+}
+
+impl<B: Memoria> CpuPorCiclos<B> {
+    pub fn new(mut cpu: CPU<BusTraza<B>>) -> CpuPorCiclos<B> {
+        cpu.io.anotar_accesos(true);
+        CpuPorCiclos {
+            cpu,
+            estados_t: 0,
+            pendientes: VecDeque::new(),
+            actual: None,
+        }
+    }
+
+    /// Reinicia el Z80 como `CPU::reiniciar` y abandona la instrucción a medias
+    pub fn reiniciar(&mut self) {
+        self.cpu.reiniciar();
+        self.cpu.bus.tomar_accesos();
+        self.cpu.io.tomar_accesos();
+        self.pendientes.clear();
+        self.actual = None;
+    }
+
+    /// Indica si se ha terminado la última instrucción empezada. Solo entonces los
+    /// registros de `cpu` corresponden a los pines.
+    pub fn en_limite_de_instruccion(&self) -> bool {
+        self.actual.is_none() && self.pendientes.is_empty()
+    }
+
+    /// Avanza un estado T
+    pub fn paso_t(&mut self) -> EstadoT {
+        self.estados_t += 1;
+        let (ciclo, i) = match self.actual.take() {
+            Some(actual) => actual,
+            None => (self.siguiente_ciclo(), 0),
+        };
+        let tipo = ciclo.tipo;
+        if i > 0 && tipo.muestreo_wait() == Some(i - 1) && self.cpu.wait() {
+            let pines = ciclo.estados[i - 1];
+            self.actual = Some((ciclo, i));
+            return EstadoT { tipo, t: i, espera: true, pines };
+        }
+        let pines = ciclo.estados[i];
+        if i + 1 < ciclo.estados.len() {
+            self.actual = Some((ciclo, i + 1));
+        }
+        EstadoT { tipo, t: i + 1, espera: false, pines }
+    }
+
+    /// Avanza un ciclo de máquina. Si `paso_t` dejó uno a medias, devuelve los estados que
+    /// le quedan. Con /WAIT activa el ciclo se corta en el primer estado de espera y la
+    /// siguiente llamada lo continúa.
+    pub fn paso_m(&mut self) -> CicloM {
+        let meta = |actual: &Option<(CicloM, usize)>| actual.as_ref().map(|(c, _)| (c.direccion, c.dato));
+        let a_medias = meta(&self.actual);
+        let primero = self.paso_t();
+        let (direccion, dato) = a_medias
+            .or_else(|| meta(&self.actual))
+            .unwrap_or((primero.pines.direccion, primero.pines.dato));
+        let mut estados = vec![primero.pines];
+        let mut espera = primero.espera;
+        while self.actual.is_some() && !espera {
+            let estado = self.paso_t();
+            estados.push(estado.pines);
+            espera = estado.espera;
+        }
+        CicloM {
+            tipo: primero.tipo,
+            direccion,
+            dato,
+            estados,
+        }
+    }
+
+    /// Siguiente ciclo de máquina. Entre ciclos el Z80 atiende /RESET y /BUSREQ.
+    fn siguiente_ciclo(&mut self) -> CicloM {
+        let halt = self.cpu.halt;
+        if self.cpu.en_reset() {
+            // La instrucción a medias se abandona
+            self.pendientes.clear();
+            return CicloM::nuevo(TipoCicloM::Reset, 0, 0xFF, 0, false, 0);
+        }
+        if self.cpu.ceder_bus() {
+            return CicloM::nuevo(TipoCicloM::BusLiberado, 0, 0xFF, 0, halt, 0);
+        }
+        while self.pendientes.is_empty() {
+            self.ejecutar_instruccion();
+        }
+        self.pendientes.pop_front().unwrap()
+    }
+
+    /// Ejecuta una instrucción y deja sus ciclos de máquina en `pendientes`
+    fn ejecutar_instruccion(&mut self) {
+        let halt = self.cpu.halt;
+        let pc = self.cpu.reg.pc;
+        let (i, r) = (self.cpu.reg.i, self.cpu.reg.r);
+        let refresco = (i as u16) << 8 | r as u16;
+        let nmi = self.cpu.nmi_pendiente();
+        let int = self.cpu.interrupcion_aceptada();
+        let im = self.cpu.im();
+        let bytes: Vec<u8> = (0..4).map(|i| self.cpu.bus.interno.leer_byte(pc.wrapping_add(i))).collect();
+
+        self.cpu.bus.tomar_accesos();
+        self.cpu.io.tomar_accesos();
+        // /WAIT solo alarga los ciclos, no impide ejecutar la instrucción
+        let wait = self.cpu.wait();
+        self.cpu.set_wait(false);
+        let estados = self.cpu.execute() as usize;
+        self.cpu.set_wait(wait);
+        let accesos = self.cpu.bus.tomar_accesos();
+        let io = self.cpu.io.tomar_accesos();
+
+        let nuevo = |tipo, direccion, dato, longitud| CicloM::nuevo(tipo, direccion, dato, refresco, halt, longitud);
+        let restantes = |ciclos: &Vec<CicloM>| estados.saturating_sub(ciclos.iter().map(|c| c.estados.len()).sum());
+        let mut ciclos = Vec::new();
+        if nmi {
+            // M1 que se descarta, PC a la pila y la instrucción de 0x0066
+            ciclos.push(nuevo(TipoCicloM::Fetch, pc, bytes[0], 5));
+            let (pila, resto) = accesos.split_at(2.min(accesos.len()));
+            ciclos.extend(ciclos_pila(pila, &nuevo));
+            let bytes: Vec<u8> = (0..4).map(|i| self.cpu.bus.interno.leer_byte(0x0066 + i)).collect();
+            ciclos.extend(ciclos_instruccion(0x0066, &bytes, restantes(&ciclos), resto, &io, &nuevo));
+        } else if let Some(instruccion) = int {
+            // En modos 1 y 2 el reconocimiento dura un estado más para decrementar SP
+            let longitud = if im == 0 { 0 } else { 7 };
+            ciclos.push(nuevo(TipoCicloM::ReconocimientoInt, pc, instruccion[0], longitud));
+            if im == 2 {
+                // PC a la pila, la dirección de la tabla de vectores y la primera instrucción
+                // de la rutina
+                let (pila, resto) = accesos.split_at(2.min(accesos.len()));
+                ciclos.extend(ciclos_pila(pila, &nuevo));
+                let (vector, resto) = resto.split_at(2.min(resto.len()));
+                ciclos.extend(vector.iter().map(|a| nuevo(TipoCicloM::LecturaMemoria, a.direccion, a.dato, 0)));
+                let tabla = (i as u16) << 8 | instruccion[0] as u16;
+                let rutina = self.cpu.bus.interno.read_word(tabla);
+                let bytes: Vec<u8> = (0..4).map(|i| self.cpu.bus.interno.leer_byte(rutina.wrapping_add(i))).collect();
+                ciclos.extend(ciclos_instruccion(rutina, &bytes, restantes(&ciclos), resto, &io, &nuevo));
+            } else {
+                // En modo 0 los operandos se leen del bus de datos sin que avance PC
+                if im == 0 {
+                    let operandos = instruccion[1..].iter();
+                    ciclos.extend(operandos.map(|&b| nuevo(TipoCicloM::LecturaMemoria, pc, b, 0)));
+                }
+                ciclos.extend(ciclos_datos(&accesos, &io, &nuevo));
+            }
+        } else if halt {
+            // Parado en HALT el Z80 sigue haciendo búsquedas M1 que ejecuta como NOP
+            ciclos.push(nuevo(TipoCicloM::Fetch, pc, bytes[0], 0));
+        } else {
+            ciclos.extend(ciclos_instruccion(pc, &bytes, estados, &accesos, &io, &nuevo));
+        }
+
+        // R se incrementa tras cada M1, así que cada refresco lleva un valor distinto
+        let m1 = ciclos
+            .iter_mut()
+            .filter(|c| matches!(c.tipo, TipoCicloM::Fetch | TipoCicloM::ReconocimientoInt));
+        for (n, ciclo) in m1.enumerate() {
+            let r = (r & 0x80) | (r.wrapping_add(n as u8) & 0x7F);
+            for p in ciclo.estados.iter_mut().filter(|p| p.rfsh) {
+                p.direccion = (i as u16) << 8 | r as u16;
+            }
+        }
+
+        let en_bus: usize = ciclos.iter().map(|c| c.estados.len()).sum();
+        if estados > en_bus {
+            let ultima = ciclos.last().map(|c| c.direccion).unwrap_or(pc);
+            ciclos.push(CicloM::nuevo(TipoCicloM::Interno, ultima, 0xFF, refresco, halt, estados - en_bus));
+        }
+        self.pendientes.extend(ciclos);
+    }
+}
+
+/// Ciclos de una instrucción normal según su fila de `temporizacion`: las búsquedas M1
+/// y las lecturas de operandos toman los bytes de la instrucción, y las lecturas, E/S y
+/// escrituras de datos toman los accesos en el orden en que los hizo el emulador. Entre
+/// las variantes de la fila se elige la que dura `estados`. Si los accesos no cuadran con
+/// la tabla se reparten como en `ciclos_datos`.
+fn ciclos_instruccion(
+    pc: u16,
+    bytes: &[u8],
+    estados: usize,
+    accesos: &[Acceso],
+    io: &[AccesoIO],
+    nuevo: &dyn Fn(TipoCicloM, u16, u8, usize) -> CicloM,
+) -> Vec<CicloM> {
+    let longitud = desensamblar(bytes, pc).bytes.len();
+    // Las búsquedas y lecturas de los bytes de la instrucción salen de `bytes`
+    let de_la_instruccion = |a: &&Acceso| {
+        a.tipo != TipoAcceso::Escritura && a.direccion.wrapping_sub(pc) < longitud as u16
+    };
+    let datos: Vec<Acceso> = accesos.iter().filter(|a| !de_la_instruccion(a)).copied().collect();
+
+    let variantes = temporizacion(bytes, longitud);
+    let duracion = |patron: &str| -> usize { patron.split(' ').map(|paso| paso[1..].parse::<usize>().unwrap()).sum() };
+    let patron = variantes.iter().find(|p| duracion(p) == estados).unwrap_or(&variantes[0]);
+
+    let mut codigo = (0..longitud).map(|i| (pc.wrapping_add(i as u16), bytes[i]));
+    let mut lecturas = datos.iter().filter(|a| a.tipo != TipoAcceso::Escritura);
+    let mut escrituras = datos.iter().filter(|a| a.tipo == TipoAcceso::Escritura);
+    let mut es = io.iter();
+    let mut ciclos: Vec<CicloM> = Vec::new();
+    for paso in patron.split(' ') {
+        let n: usize = paso[1..].parse().unwrap();
+        let memoria = |tipo, a: &Acceso| nuevo(tipo, a.direccion, a.dato, n);
+        let ciclo = match &paso[..1] {
+            "F" => codigo.next().map(|(d, b)| nuevo(TipoCicloM::Fetch, d, b, n)),
+            "R" => codigo
+                .next()
+                .map(|(d, b)| nuevo(TipoCicloM::LecturaMemoria, d, b, n))
+                .or_else(|| lecturas.next().map(|a| memoria(TipoCicloM::LecturaMemoria, a))),
+            "W" => escrituras.next().map(|a| memoria(TipoCicloM::EscrituraMemoria, a)),
+            "I" => es.next().filter(|a| !a.escritura).map(|a| nuevo(TipoCicloM::LecturaIO, a.puerto, a.dato, n)),
+            "O" => es.next().filter(|a| a.escritura).map(|a| nuevo(TipoCicloM::EscrituraIO, a.puerto, a.dato, n)),
+            _ => {
+                // Durante los estados internos el bus se queda con la última dirección
+                let ultima = ciclos.last().map(|c| c.direccion).unwrap_or(pc);
+                Some(nuevo(TipoCicloM::Interno, ultima, 0xFF, n))
+            }
+        };
+        match ciclo {
+            Some(ciclo) => ciclos.push(ciclo),
+            None => return ciclos_en_orden_fijo(pc, bytes, longitud, &datos, io, nuevo),
+        }
+    }
+    if codigo.next().is_some() || lecturas.next().is_some() || escrituras.next().is_some() || es.next().is_some() {
+        return ciclos_en_orden_fijo(pc, bytes, longitud, &datos, io, nuevo);
+    }
+    ciclos
+}
+
+/// Reparto sin tabla: una búsqueda M1 por código de operación (dos con prefijo), una
+/// lectura por operando y luego los accesos a datos
+fn ciclos_en_orden_fijo(
+    pc: u16,
+    bytes: &[u8],
+    longitud: usize,
+    datos: &[Acceso],
+    io: &[AccesoIO],
+    nuevo: &dyn Fn(TipoCicloM, u16, u8, usize) -> CicloM,
+) -> Vec<CicloM> {
+    let busquedas = match (bytes[0], longitud) {
+        (0xCB | 0xED, _) => 2,
+        (0xDD | 0xFD, 1) => 1,
+        (0xDD | 0xFD, _) => 2,
+        _ => 1,
+    };
+    let mut ciclos: Vec<CicloM> = (0..longitud)
+        .map(|i| {
+            let tipo = if i < busquedas { TipoCicloM::Fetch } else { TipoCicloM::LecturaMemoria };
+            nuevo(tipo, pc.wrapping_add(i as u16), bytes[i], 0)
+        })
+        .collect();
+    ciclos.extend(ciclos_datos(datos, io, nuevo));
+    ciclos
+}
+
+/// Ciclos de máquina de cada instrucción según los diagramas de tiempos del manual del
+/// Z80. Cada paso es el tipo de ciclo y sus estados T: F búsqueda M1, R lectura de
+/// memoria, W escritura en memoria, I lectura de E/S, O escritura de E/S y X estados
+/// internos. Un ciclo de bus más largo que el normal lleva detrás sus estados internos.
+/// Las instrucciones que duran distinto según se salte o se repita tienen una variante
+/// por duración.
+fn temporizacion(bytes: &[u8], longitud: usize) -> &'static [&'static str] {
+    let op = bytes[0];
+    match op {
+        0xCB => {
+            let cb = bytes[1];
+            match (cb >> 6, cb & 7) {
+                (1, 6) => &["F4 F4 R4"],
+                (_, 6) => &["F4 F4 R4 W3"],
+                _ => &["F4 F4"],
+            }
+        }
+        0xED => temporizacion_ed(bytes[1]),
+        0xDD | 0xFD if longitud == 1 => &["F4"],
+        0xDD | 0xFD => temporizacion_indice(bytes[1], bytes[3]),
+        _ => temporizacion_base(op),
+    }
+}
+
+fn temporizacion_base(op: u8) -> &'static [&'static str] {
+    let (x, y, z) = (op >> 6, (op >> 3) & 7, op & 7);
+    match (x, z) {
+        (0, 0) => match y {
+            0 | 1 => &["F4"],
+            // DJNZ
+            2 => &["F5 R3", "F5 R3 X5"],
+            3 => &["F4 R3 X5"],
+            _ => &["F4 R3", "F4 R3 X5"],
+        },
+        (0, 1) if y & 1 == 0 => &["F4 R3 R3"],
+        (0, 1) => &["F4 X4 X3"],
+        (0, 2) => match y {
+            0 | 2 => &["F4 W3"],
+            1 | 3 => &["F4 R3"],
+            4 => &["F4 R3 R3 W3 W3"],
+            5 => &["F4 R3 R3 R3 R3"],
+            6 => &["F4 R3 R3 W3"],
+            _ => &["F4 R3 R3 R3"],
+        },
+        (0, 3) => &["F6"],
+        (0, 4 | 5) if y == 6 => &["F4 R4 W3"],
+        (0, 6) if y == 6 => &["F4 R3 W3"],
+        (0, 6) => &["F4 R3"],
+        (1, _) if op == 0x76 => &["F4"],
+        (1, 6) | (2, 6) => &["F4 R3"],
+        (1, _) if y == 6 => &["F4 W3"],
+        (3, 0) => &["F5", "F5 R3 R3"],
+        (3, 1) => match op {
+            0xD9 | 0xE9 => &["F4"],
+            0xF9 => &["F6"],
+            // POP y RET
+            _ => &["F4 R3 R3"],
+        },
+        (3, 2) => &["F4 R3 R3"],
+        (3, 3) => match op {
+            0xC3 => &["F4 R3 R3"],
+            0xD3 => &["F4 R3 O4"],
+            0xDB => &["F4 R3 I4"],
+            0xE3 => &["F4 R3 R4 W3 W5"],
+            _ => &["F4"],
+        },
+        (3, 4) => &["F4 R3 R3", "F4 R3 R4 W3 W3"],
+        (3, 5) if op == 0xCD => &["F4 R3 R4 W3 W3"],
+        // PUSH y RST
+        (3, 5) | (3, 7) => &["F5 W3 W3"],
+        (3, 6) => &["F4 R3"],
+        _ => &["F4"],
+    }
+}
+
+fn temporizacion_ed(op: u8) -> &'static [&'static str] {
+    let (x, y, z) = (op >> 6, (op >> 3) & 7, op & 7);
+    match (x, z) {
+        (1, 0) => &["F4 F4 I4"],
+        (1, 1) => &["F4 F4 O4"],
+        (1, 2) => &["F4 F4 X4 X3"],
+        (1, 3) if y & 1 == 0 => &["F4 F4 R3 R3 W3 W3"],
+        (1, 3) => &["F4 F4 R3 R3 R3 R3"],
+        // RETN y RETI
+        (1, 5) => &["F4 F4 R3 R3"],
+        (1, 7) => match y {
+            0..=3 => &["F4 F5"],
+            // RRD y RLD
+            4 | 5 => &["F4 F4 R3 X4 W3"],
+            _ => &["F4 F4"],
+        },
+        // Bloques: la variante larga es la de una repetición
+        (2, 0) if y >= 4 => &["F4 F4 R3 W5", "F4 F4 R3 W5 X5"],
+        (2, 1) if y >= 4 => &["F4 F4 R3 X5", "F4 F4 R3 X5 X5"],
+        (2, 2) if y >= 4 => &["F4 F5 I4 W3", "F4 F5 I4 W3 X5"],
+        (2, 3) if y >= 4 => &["F4 F5 R3 O4", "F4 F5 R3 O4 X5"],
+        _ => &["F4 F4"],
+    }
+}
+
+/// Instrucciones con IX o IY. `cb` es el código de operación de DD CB d op.
+fn temporizacion_indice(op: u8, cb: u8) -> &'static [&'static str] {
+    let (x, y, z) = (op >> 6, (op >> 3) & 7, op & 7);
+    match op {
+        0xCB if cb >> 6 == 1 => &["F4 F4 R3 R5 R4"],
+        0xCB => &["F4 F4 R3 R5 R4 W3"],
+        0x21 => &["F4 F4 R3 R3"],
+        0x22 => &["F4 F4 R3 R3 W3 W3"],
+        0x2A => &["F4 F4 R3 R3 R3 R3"],
+        0x23 | 0x2B | 0xF9 => &["F4 F6"],
+        0x26 | 0x2E => &["F4 F4 R3"],
+        0x34 | 0x35 => &["F4 F4 R3 X5 R4 W3"],
+        0x36 => &["F4 F4 R3 R5 W3"],
+        0xE1 => &["F4 F4 R3 R3"],
+        0xE3 => &["F4 F4 R3 R4 W3 W5"],
+        0xE5 => &["F4 F5 W3 W3"],
+        _ if x == 0 && z == 1 => &["F4 F4 X4 X3"],
+        _ if x == 1 && y == 6 => &["F4 F4 R3 X5 W3"],
+        _ if (x == 1 || x == 2) && z == 6 => &["F4 F4 R3 X5 R3"],
+        _ => &["F4 F4"],
+    }
+}
+
+/// Escrituras de PC en la pila. El Z80 escribe primero el byte alto, en SP - 1.
+fn ciclos_pila(pila: &[Acceso], nuevo: &dyn Fn(TipoCicloM, u16, u8, usize) -> CicloM) -> Vec<CicloM> {
+    let mut pila = pila.to_vec();
+    pila.sort_by_key(|a| std::cmp::Reverse(a.direccion));
+    pila.iter().map(|a| nuevo(TipoCicloM::EscrituraMemoria, a.direccion, a.dato, 0)).collect()
+}
+
+/// Ciclos de acceso a datos con las lecturas antes que la E/S y esta antes que las
+/// escrituras. Es el orden de los reconocimientos de interrupción en modos 0 y 1.
+fn ciclos_datos(
+    accesos: &[Acceso],
+    io: &[AccesoIO],
+    nuevo: &dyn Fn(TipoCicloM, u16, u8, usize) -> CicloM,
+) -> Vec<CicloM> {
+    let lecturas = accesos
+        .iter()
+        .filter(|a| a.tipo != TipoAcceso::Escritura)
+        .map(|a| nuevo(TipoCicloM::LecturaMemoria, a.direccion, a.dato, 0));
+    let es = io.iter().map(|a| {
+        let tipo = if a.escritura { TipoCicloM::EscrituraIO } else { TipoCicloM::LecturaIO };
+        nuevo(tipo, a.puerto, a.dato, 0)
+    });
+    let escrituras = accesos
+        .iter()
+        .filter(|a| a.tipo == TipoAcceso::Escritura)
+        .map(|a| nuevo(TipoCicloM::EscrituraMemoria, a.direccion, a.dato, 0));
+    lecturas.chain(es).chain(escrituras).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captura::RegistroCiclo;
+
+    fn z80(programa: &[u8]) -> CpuPorCiclos {
+        let mut cpu = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+        cpu.bus.cargar(0x0000, programa);
+        CpuPorCiclos::new(cpu)
+    }
+
+    #[test]
+    fn ciclos_de_maquina() {
+        use TipoCicloM::*;
+        // LD HL,0x9000 ; LD (HL),A ; OUT (0x10),A ; INC BC ; HALT
+        let mut z = z80(&[0x21, 0x00, 0x90, 0x77, 0xD3, 0x10, 0x03, 0x76]);
+        z.cpu.reg.a = 0x5A;
+        let mut ciclos = Vec::new();
+        let mut limites = Vec::new();
+        for _ in 0..10 {
+            let m = z.paso_m();
+            ciclos.push((m.tipo, m.direccion, m.dato, m.estados.len()));
+            limites.push(z.en_limite_de_instruccion());
+        }
+        assert_eq!(
+            ciclos,
+            vec![
+                (Fetch, 0x0000, 0x21, 4),
+                (LecturaMemoria, 0x0001, 0x00, 3),
+                (LecturaMemoria, 0x0002, 0x90, 3),
+                (Fetch, 0x0003, 0x77, 4),
+                (EscrituraMemoria, 0x9000, 0x5A, 3),
+                (Fetch, 0x0004, 0xD3, 4),
+                (LecturaMemoria, 0x0005, 0x10, 3),
+                (EscrituraIO, 0x5A10, 0x5A, 4),
+                (Fetch, 0x0006, 0x03, 6),
+                (Fetch, 0x0007, 0x76, 4),
+            ]
+        );
+        assert_eq!(z.estados_t, 10 + 7 + 11 + 6 + 4);
+        assert_eq!(
+            limites,
+            vec![false, false, true, false, true, false, false, true, true, true]
+        );
+
+        // Parado en HALT sigue buscando con /HALT activa
+        let m = z.paso_m();
+        assert_eq!(m.tipo, Fetch);
+        assert!(m.estados.iter().all(|p| p.halt));
+    }
+
+    #[test]
+    fn ldir_por_repeticiones() {
+        use TipoCicloM::*;
+        // LDIR con BC = 2: cada repetición lee y escribe su byte
+        let mut z = z80(&[0xED, 0xB0]);
+        z.cpu.reg.set_hl(0x1000);
+        z.cpu.reg.set_de(0x2000);
+        z.cpu.reg.set_bc(0x0002);
+        let mut ciclos = Vec::new();
+        while z.cpu.reg.get_bc() != 0 || !z.en_limite_de_instruccion() {
+            let m = z.paso_m();
+            ciclos.push((m.tipo, m.direccion, m.estados.len()));
+        }
+        assert_eq!(
+            ciclos,
+            vec![
+                (Fetch, 0x0000, 4),
+                (Fetch, 0x0001, 4),
+                (LecturaMemoria, 0x1000, 3),
+                (EscrituraMemoria, 0x2000, 5),
+                (Interno, 0x2000, 5),
+                (Fetch, 0x0000, 4),
+                (Fetch, 0x0001, 4),
+                (LecturaMemoria, 0x1001, 3),
+                (EscrituraMemoria, 0x2001, 5),
+            ]
+        );
+        assert_eq!(z.cpu.reg.pc, 0x0002);
+    }
+
+    #[test]
+    fn temporizacion_documentada() {
+        use TipoCicloM::*;
+        // LD SP,0x8000 ; PUSH BC ; CALL 0x0010 ; ... 0x0010: EX (SP),HL ;
+        // LD A,(IX+2) ; LD (IX+3),0x55
+        let mut z = z80(&[0x31, 0x00, 0x80, 0xC5, 0xCD, 0x10, 0x00]);
+        z.cpu.bus.cargar(0x0010, &[0xE3, 0xDD, 0x7E, 0x02, 0xDD, 0x36, 0x03, 0x55]);
+        z.cpu.reg.set_bc(0x1234);
+        z.cpu.reg.set_hl(0xABCD);
+        z.cpu.reg.set_ix(0x9000);
+        let instruccion = |z: &mut CpuPorCiclos| {
+            let mut ciclos = Vec::new();
+            loop {
+                let m = z.paso_m();
+                ciclos.push((m.tipo, m.direccion, m.estados.len()));
+                if z.en_limite_de_instruccion() {
+                    return ciclos;
+                }
+            }
+        };
+        instruccion(&mut z);
+
+        // PUSH: M1 de 5 estados y el byte alto primero, en SP - 1
+        assert_eq!(
+            instruccion(&mut z),
+            vec![(Fetch, 0x0003, 5), (EscrituraMemoria, 0x7FFF, 3), (EscrituraMemoria, 0x7FFE, 3)]
+        );
+        // CALL: el estado que decrementa SP va tras la lectura del byte alto
+        assert_eq!(
+            instruccion(&mut z),
+            vec![
+                (Fetch, 0x0004, 4),
+                (LecturaMemoria, 0x0005, 3),
+                (LecturaMemoria, 0x0006, 4),
+                (EscrituraMemoria, 0x7FFD, 3),
+                (EscrituraMemoria, 0x7FFC, 3),
+            ]
+        );
+        // EX (SP),HL: lee bajo y alto, escribe alto y bajo
+        assert_eq!(
+            instruccion(&mut z),
+            vec![
+                (Fetch, 0x0010, 4),
+                (LecturaMemoria, 0x7FFC, 3),
+                (LecturaMemoria, 0x7FFD, 4),
+                (EscrituraMemoria, 0x7FFD, 3),
+                (EscrituraMemoria, 0x7FFC, 5),
+            ]
+        );
+        // LD A,(IX+d): 5 estados para sumar el desplazamiento antes de leer
+        assert_eq!(
+            instruccion(&mut z),
+            vec![
+                (Fetch, 0x0011, 4),
+                (Fetch, 0x0012, 4),
+                (LecturaMemoria, 0x0013, 3),
+                (Interno, 0x0013, 5),
+                (LecturaMemoria, 0x9002, 3),
+            ]
+        );
+        // LD (IX+d),n: la suma se solapa con la lectura de n
+        assert_eq!(
+            instruccion(&mut z),
+            vec![
+                (Fetch, 0x0014, 4),
+                (Fetch, 0x0015, 4),
+                (LecturaMemoria, 0x0016, 3),
+                (LecturaMemoria, 0x0017, 5),
+                (EscrituraMemoria, 0x9003, 3),
+            ]
+        );
+        assert_eq!(z.estados_t, 10 + 11 + 17 + 19 + 19 + 19);
+    }
+
+    #[test]
+    fn estados_de_un_ciclo_alargado() {
+        // PUSH BC: el quinto estado del M1 y los de la escritura
+        let mut z = z80(&[0xC5]);
+        z.cpu.reg.sp = 0x8000;
+        z.cpu.reg.set_bc(0x1234);
+        let m1 = z.paso_m();
+        assert!(m1.estados[2].rfsh && m1.estados[3].rfsh);
+        let t5 = m1.estados[4];
+        assert!(!t5.mreq && !t5.rd && !t5.rfsh && !t5.m1);
+
+        let escritura = z.paso_m();
+        assert_eq!((escritura.direccion, escritura.dato), (0x7FFF, 0x12));
+        assert!(escritura.estados[0].mreq && !escritura.estados[0].wr);
+        assert!(escritura.estados[1..].iter().all(|p| p.mreq && p.wr));
+
+        // /WAIT se muestrea en T2 aunque el ciclo dure más
+        let mut z = z80(&[0xCD, 0x10, 0x00]);
+        z.cpu.reg.sp = 0x8000;
+        z.paso_m();
+        z.paso_m();
+        z.cpu.set_wait(true);
+        let t1 = z.paso_t();
+        let t2 = z.paso_t();
+        let tw = z.paso_t();
+        assert_eq!((t1.tipo, t1.pines.direccion), (TipoCicloM::LecturaMemoria, 0x0002));
+        assert!(!t2.espera && tw.espera);
+        z.cpu.set_wait(false);
+        let resto = z.paso_m();
+        assert_eq!(resto.estados.len(), 2);
+        assert!(resto.estados[0].rd && !resto.estados[1].rd);
+    }
+
+    #[test]
+    fn estados_t_y_captura() {
+        // LD (IX+1),A con I = 0x3F y R = 0x10
+        let mut z = z80(&[0xDD, 0x77, 0x01]);
+        z.cpu.reg.i = 0x3F;
+        z.cpu.reg.r = 0x10;
+        z.cpu.reg.a = 0x42;
+        z.cpu.reg.set_ix(0x8000);
+
+        let t1 = z.paso_t();
+        assert_eq!((t1.tipo, t1.t), (TipoCicloM::Fetch, 1));
+        assert!(t1.pines.m1 && t1.pines.mreq && t1.pines.rd && !t1.pines.rfsh);
+        z.paso_t();
+        let t3 = z.paso_t();
+        assert_eq!(t3.pines.direccion, 0x3F10);
+        assert!(t3.pines.rfsh && t3.pines.mreq && !t3.pines.m1);
+        // Lo que queda del primer M1
+        assert_eq!(z.paso_m().estados.len(), 1);
+
+        let mut estados = Vec::new();
+        while z.estados_t < 19 {
+            estados.push(z.paso_t());
+        }
+        let tipos: Vec<TipoCicloM> = estados.iter().filter(|e| e.t == 1).map(|e| e.tipo).collect();
+        use TipoCicloM::*;
+        assert_eq!(tipos, vec![Fetch, LecturaMemoria, Interno, EscrituraMemoria]);
+        // El segundo M1 refresca con R ya incrementado
+        assert_eq!(estados[2].pines.direccion, 0x3F11);
+        assert_eq!(z.cpu.reg.r, 0x12);
+
+        // El registro de captura se lee igual que los del firmware
+        let escritura = estados.iter().find(|e| e.tipo == EscrituraMemoria && e.t == 2).unwrap();
+        let r = RegistroCiclo::decodificar(&escritura.pines.registro_captura(7, 1)).unwrap();
+        assert_eq!((r.ciclo, r.direccion, r.dato), (7, 0x8001, 0x42));
+        assert_eq!((r.rd, r.wr, r.mreq, r.rfsh, r.reset), (1, 0, 0, 1, 1));
+    }
+
+    #[test]
+    fn reconocimiento_im2() {
+        use TipoCicloM::*;
+        // IM 2 ; EI ; NOP ; tabla en 0x2010 con la rutina en 0x3000: LD A,(0x9000)
+        let mut z = z80(&[0xED, 0x5E, 0xFB, 0x00]);
+        z.cpu.bus.cargar(0x2010, &[0x00, 0x30]);
+        z.cpu.bus.cargar(0x3000, &[0x3A, 0x00, 0x90]);
+        z.cpu.reg.i = 0x20;
+        z.cpu.reg.sp = 0x8000;
+        for _ in 0..4 {
+            z.paso_m();
+        }
+        z.cpu.int_requerimiento(0x10);
+        let r = z.cpu.reg.r;
+        let mut ciclos = Vec::new();
+        for _ in 0..9 {
+            ciclos.push(z.paso_m());
+        }
+        let resumen: Vec<(TipoCicloM, u16)> = ciclos.iter().map(|c| (c.tipo, c.direccion)).collect();
+        assert_eq!(
+            resumen,
+            vec![
+                (ReconocimientoInt, 0x0004),
+                (EscrituraMemoria, 0x7FFF),
+                (EscrituraMemoria, 0x7FFE),
+                (LecturaMemoria, 0x2010),
+                (LecturaMemoria, 0x2011),
+                (Fetch, 0x3000),
+                (LecturaMemoria, 0x3001),
+                (LecturaMemoria, 0x3002),
+                (LecturaMemoria, 0x9000),
+            ]
+        );
+        // El M1 de la rutina refresca con R ya incrementado por el reconocimiento
+        let refresco = ciclos[5].estados.iter().find(|p| p.rfsh).unwrap();
+        assert!(ciclos[5].estados[0].m1);
+        assert_eq!(refresco.direccion, 0x2000 | u16::from((r + 1) & 0x7F));
+    }
+
+    #[test]
+    fn pines_de_entrada() {
+        // LD A,(0x8000) ; NOP
+        let mut z = z80(&[0x3A, 0x00, 0x80, 0x00]);
+
+        // /WAIT alarga el M1 tras T2 con estados de espera
+        z.cpu.set_wait(true);
+        let t1 = z.paso_t();
+        let t2 = z.paso_t();
+        let tw = z.paso_t();
+        assert!(!t1.espera && !t2.espera);
+        assert!(tw.espera);
+        assert_eq!((tw.t, tw.pines), (2, t2.pines));
+        assert!(z.paso_t().espera);
+        z.cpu.set_wait(false);
+        let t3 = z.paso_t();
+        assert_eq!((t3.t, t3.espera), (3, false));
+        z.paso_m();
+
+        // /BUSREQ se atiende al acabar el ciclo: el bus queda cedido con /BUSACK
+        z.cpu.set_busreq(true);
+        let m = z.paso_m();
+        assert_eq!(m.tipo, TipoCicloM::BusLiberado);
+        assert!(m.estados[0].busack && !m.estados[0].mreq);
+        assert!(z.cpu.busack());
+        z.cpu.set_busreq(false);
+        let m = z.paso_m();
+        assert_eq!((m.tipo, m.direccion), (TipoCicloM::LecturaMemoria, 0x0001));
+        assert!(!z.cpu.busack());
+
+        // /RESET abandona la instrucción en curso y al soltarla se empieza en 0x0000
+        z.cpu.set_reset(true);
+        let m = z.paso_m();
+        assert_eq!(m.tipo, TipoCicloM::Reset);
+        assert_eq!(RegistroCiclo::decodificar(&m.estados[0].registro_captura(0, 0)).unwrap().reset, 0);
+        z.cpu.set_reset(false);
+        let m = z.paso_m();
+        assert_eq!((m.tipo, m.direccion, m.dato), (TipoCicloM::Fetch, 0x0000, 0x3A));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::cpu::flags::Flags;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Registros {
     pub a: u8,
     pub b: u8,
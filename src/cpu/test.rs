@@ -1,4 +1,8 @@
-use crate::cpu::cpu::CPU;
+use crate::cpu::bus::Memoria;
+use crate::cpu::cpu::{EstadoEncendido, CPU};
+use crate::cpu::io::DispositivoIO;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 // carry flag
 const CF: u8 = 1 << 0;
@@ -13,13 +17,13 @@ const VF: u8 = 1 << 2;
 const PF: u8 = 1 << 2;
 
 // undocumented 'X' flag
-// const XF: u8 = 1 << 3;
+const XF: u8 = 1 << 3;
 
 // half carry flag
 const HF: u8 = 1 << 4;
 
 // undocumented 'Y' flag
-// const YF: u8 = 1 << 5;
+const YF: u8 = 1 << 5;
 
 // zero flag
 const ZF: u8 = 1 << 6;
@@ -355,12 +359,12 @@ fn add_r_asm() {
     assert_eq!(c.flags(), 0); // LD A,0x0F
     assert_eq!(c.execute(), 4);
     assert_eq!(0x1E, c.reg.a);
-    assert_eq!(c.flags(), HF); // ADD A,A
+    assert_eq!(c.flags(), HF | XF); // ADD A,A
     assert_eq!(c.execute(), 7);
     assert_eq!(0xE0, c.reg.b); // LD B,0xE0
     assert_eq!(c.execute(), 4);
     assert_eq!(0xFE, c.reg.a);
-    assert_eq!(c.flags(), SF); // ADD A,B
+    assert_eq!(c.flags(), SF | YF | XF); // ADD A,B
     assert_eq!(c.execute(), 7);
     assert_eq!(0x81, c.reg.a); // LD A,0x81
     assert_eq!(c.execute(), 7);
@@ -387,10 +391,10 @@ fn add_r_asm() {
     assert_eq!(0x33, c.reg.l); // LD L,0x33
     assert_eq!(c.execute(), 4);
     assert_eq!(0xF3, c.reg.a);
-    assert_eq!(c.flags(), SF); // ADD A,L
+    assert_eq!(c.flags(), SF | YF); // ADD A,L
     assert_eq!(c.execute(), 7);
     assert_eq!(0x37, c.reg.a);
-    assert_eq!(c.flags(), CF); // ADD A,0x44
+    assert_eq!(c.flags(), YF | CF); // ADD A,0x44
 }
 
 #[test]
@@ -413,10 +417,10 @@ fn add_i_hl_ix_iy_asm() {
     assert_eq!(c.flags(), 0); // ADD A,(HL)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xA2, c.reg.a);
-    assert_eq!(c.flags(), SF | VF); // ADD A,(IX+1)
+    assert_eq!(c.flags(), SF | YF | VF); // ADD A,(IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x23, c.reg.a);
-    assert_eq!(c.flags(), VF | CF); // ADD A,(IY-1)
+    assert_eq!(c.flags(), YF | VF | CF); // ADD A,(IY-1)
 }
 
 #[test]
@@ -428,12 +432,12 @@ fn add_ixh_ixl_asm() {
     assert_eq!(c.flags(), 0); // LD A,0x0F
     assert_eq!(c.execute(), 4);
     assert_eq!(0x1E, c.reg.a);
-    assert_eq!(c.flags(), HF); // ADD A,A
+    assert_eq!(c.flags(), HF | XF); // ADD A,A
     assert_eq!(c.execute(), 14);
     assert_eq!(0xE080, c.reg.get_ix(),); // LD  IX,0xE080
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFE, c.reg.a);
-    assert_eq!(c.flags(), SF); // ADD A,IXH
+    assert_eq!(c.flags(), SF | YF | XF); // ADD A,IXH
     assert_eq!(c.execute(), 7);
     assert_eq!(0x81, c.reg.a); // LD  A,0x81
     assert_eq!(c.execute(), 8);
@@ -450,12 +454,12 @@ fn add_a_iyh_iyl_asm() {
     assert_eq!(c.flags(), 0); // LD A,0x0F
     assert_eq!(c.execute(), 4);
     assert_eq!(0x1E, c.reg.a);
-    assert_eq!(c.flags(), HF); // ADD A,A
+    assert_eq!(c.flags(), HF | XF); // ADD A,A
     assert_eq!(c.execute(), 14);
     assert_eq!(0xE080, c.reg.get_iy()); // LD  IY,0xE080
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFE, c.reg.a);
-    assert_eq!(c.flags(), SF); // ADD A,IYH
+    assert_eq!(c.flags(), SF | YF | XF); // ADD A,IYH
     assert_eq!(c.execute(), 7);
     assert_eq!(0x81, c.reg.a); // LD  A,0x81
     assert_eq!(c.execute(), 8);
@@ -479,7 +483,7 @@ fn adc_a_ixh_ixl_asm() {
     assert_eq!(c.flags(), 0); // ADC A,IXH
     assert_eq!(c.execute(), 8);
     assert_eq!(0xA2, c.reg.a);
-    assert_eq!(c.flags(), SF | VF); // ADC A,IXL
+    assert_eq!(c.flags(), SF | YF | VF); // ADC A,IXL
 }
 
 #[test]
@@ -498,7 +502,7 @@ fn adc_a_iyh_iyl_asm() {
     assert_eq!(c.flags(), 0); // ADC A,IYH
     assert_eq!(c.execute(), 8);
     assert_eq!(0xA2, c.reg.a);
-    assert_eq!(c.flags(), SF | VF); // ADC A,IYL
+    assert_eq!(c.flags(), SF | YF | VF); // ADC A,IYL
 }
 
 #[test]
@@ -527,13 +531,13 @@ fn adc_r_asm() {
     assert_eq!(c.flags(), 0); // ADC A,B
     assert_eq!(c.execute(), 4);
     assert_eq!(0xA2, c.reg.a);
-    assert_eq!(c.flags(), SF | VF); // ADC A,C
+    assert_eq!(c.flags(), SF | YF | VF); // ADC A,C
     assert_eq!(c.execute(), 4);
     assert_eq!(0x23, c.reg.a);
-    assert_eq!(c.flags(), VF | CF); // ADC A,D
+    assert_eq!(c.flags(), YF | VF | CF); // ADC A,D
     assert_eq!(c.execute(), 4);
     assert_eq!(0x65, c.reg.a);
-    assert_eq!(c.flags(), 0); // ADC A,E
+    assert_eq!(c.flags(), YF); // ADC A,E
     assert_eq!(c.execute(), 4);
     assert_eq!(0xC6, c.reg.a);
     assert_eq!(c.flags(), SF | VF); // ADC A,H
@@ -542,7 +546,7 @@ fn adc_r_asm() {
     assert_eq!(c.flags(), VF | CF); // ADC A,L
     assert_eq!(c.execute(), 7);
     assert_eq!(0x49, c.reg.a);
-    assert_eq!(c.flags(), 0); // ADC A,0x01
+    assert_eq!(c.flags(), XF); // ADC A,0x01
     assert_eq!(c.execute(), 7);
     assert_eq!(0x0F, c.reg.a); // LD A,0x0F
     assert_eq!(c.execute(), 7);
@@ -573,13 +577,13 @@ fn adc_i_hl_ix_iy_asm() {
     assert_eq!(c.flags(), 0); // ADD A,(HL)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xA2, c.reg.a);
-    assert_eq!(c.flags(), SF | VF); // ADC A,(IX+1)
+    assert_eq!(c.flags(), SF | YF | VF); // ADC A,(IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x23, c.reg.a);
-    assert_eq!(c.flags(), VF | CF); // ADC A,(IY-1)
+    assert_eq!(c.flags(), YF | VF | CF); // ADC A,(IY-1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x26, c.reg.a);
-    assert_eq!(c.flags(), 0); // ADC A,(IX+3)
+    assert_eq!(c.flags(), YF); // ADC A,(IX+3)
 }
 
 #[test]
@@ -605,25 +609,25 @@ fn sub_r_asm() {
     assert_eq!(c.flags(), ZF | NF); // SUB A,A
     assert_eq!(c.execute(), 4);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SUB A,B
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SUB A,B
     assert_eq!(c.execute(), 4);
     assert_eq!(0x07, c.reg.a);
     assert_eq!(c.flags(), NF); // SUB A,C
     assert_eq!(c.execute(), 4);
     assert_eq!(0xF8, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SUB A,D
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SUB A,D
     assert_eq!(c.execute(), 4);
     assert_eq!(0x7F, c.reg.a);
-    assert_eq!(c.flags(), HF | VF | NF); // SUB A,E
+    assert_eq!(c.flags(), YF | HF | XF | VF | NF); // SUB A,E
     assert_eq!(c.execute(), 4);
     assert_eq!(0xBF, c.reg.a);
-    assert_eq!(c.flags(), SF | VF | NF | CF); // SUB A,H
+    assert_eq!(c.flags(), SF | YF | XF | VF | NF | CF); // SUB A,H
     assert_eq!(c.execute(), 4);
     assert_eq!(0x00, c.reg.a);
     assert_eq!(c.flags(), ZF | NF); // SUB A,L
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SUB A,0x01
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SUB A,0x01
     assert_eq!(c.execute(), 7);
     assert_eq!(0x01, c.reg.a);
     assert_eq!(c.flags(), NF); // SUB A,0xFE
@@ -642,7 +646,7 @@ fn sub_ixh_ixl_asm() {
     assert_eq!(c.flags(), ZF | NF); // SUB A,A
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SUB A,IXH
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SUB A,IXH
     assert_eq!(c.execute(), 8);
     assert_eq!(0x07, c.reg.a);
     assert_eq!(c.flags(), NF); // SUB A,IXL
@@ -661,7 +665,7 @@ fn sub_iyh_iyl_asm() {
     assert_eq!(c.flags(), ZF | NF); // SUB A,A
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SUB A,IXH
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SUB A,IXH
     assert_eq!(c.execute(), 8);
     assert_eq!(0x07, c.reg.a);
     assert_eq!(c.flags(), NF); // SUB A,IXL
@@ -696,16 +700,16 @@ fn cp_r_asm() {
     assert_eq!(c.flags(), NF); // CP C
     assert_eq!(c.execute(), 4);
     assert_eq!(0x04, c.reg.a);
-    assert_eq!(c.flags(), HF | NF | CF); // CP D
+    assert_eq!(c.flags(), YF | HF | XF | NF | CF); // CP D
     assert_eq!(c.execute(), 4);
     assert_eq!(0x04, c.reg.a);
-    assert_eq!(c.flags(), HF | NF | CF); // CP E
+    assert_eq!(c.flags(), YF | HF | XF | NF | CF); // CP E
     assert_eq!(c.execute(), 4);
     assert_eq!(0x04, c.reg.a);
     assert_eq!(c.flags(), SF | VF | NF | CF); // CP H
     assert_eq!(c.execute(), 4);
     assert_eq!(0x04, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // CP L
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // CP L
     assert_eq!(c.execute(), 7);
     assert_eq!(0x04, c.reg.a);
     assert_eq!(c.flags(), ZF | NF); // CP 0x04
@@ -728,13 +732,13 @@ fn sub_i_hl_ix_iy_asm() {
     assert_eq!(0x00, c.reg.a); // LD A,0x00
     assert_eq!(c.execute(), 7);
     assert_eq!(0xBF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SUB A,(HL)
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SUB A,(HL)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x5E, c.reg.a);
-    assert_eq!(c.flags(), VF | NF); // SUB A,(IX+1)
+    assert_eq!(c.flags(), XF | VF | NF); // SUB A,(IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xFD, c.reg.a);
-    assert_eq!(c.flags(), SF | NF | CF); // SUB A,(IY-2)
+    assert_eq!(c.flags(), SF | YF | XF | NF | CF); // SUB A,(IY-2)
 }
 
 #[test]
@@ -757,10 +761,10 @@ fn cp_i_hl_ix_iy_asm() {
     assert_eq!(c.flags(), ZF | NF); // CP (HL)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x41, c.reg.a);
-    assert_eq!(c.flags(), SF | NF | CF); // CP (IX+1)
+    assert_eq!(c.flags(), SF | YF | NF | CF); // CP (IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x41, c.reg.a);
-    assert_eq!(c.flags(), HF | NF); // CP (IY-1)
+    assert_eq!(c.flags(), YF | HF | NF); // CP (IY-1)
 }
 
 #[test]
@@ -782,28 +786,28 @@ fn sbc_r_asm() {
     assert_eq!(c.flags(), ZF | NF); // SUB A,A
     assert_eq!(c.execute(), 4);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SBC A,B (0x00 - 0x01)
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SBC A,B (0x00 - 0x01)
     assert_eq!(c.execute(), 4);
     assert_eq!(0x06, c.reg.a);
     assert_eq!(c.flags(), NF); // SBC A,C (0xFF - 0xF8 - carry)
     assert_eq!(c.execute(), 4);
     assert_eq!(0xF7, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SBC A,D (0x06 - 0x0F)
+    assert_eq!(c.flags(), SF | YF | HF | NF | CF); // SBC A,D (0x06 - 0x0F)
     assert_eq!(c.execute(), 4);
     assert_eq!(0x7D, c.reg.a);
-    assert_eq!(c.flags(), HF | VF | NF); // SBC A,E (0xF7 - 0x79)
+    assert_eq!(c.flags(), YF | HF | XF | VF | NF); // SBC A,E (0xF7 - 0x79)
     assert_eq!(c.execute(), 4);
     assert_eq!(0xBD, c.reg.a);
-    assert_eq!(c.flags(), SF | VF | NF | CF); // SBC A,H (0x7D - 0xC0)
+    assert_eq!(c.flags(), SF | YF | XF | VF | NF | CF); // SBC A,H (0x7D - 0xC0)
     assert_eq!(c.execute(), 4);
     assert_eq!(0xFD, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SBC A,L (0xBD - 0xBF - carry ) should set HF
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SBC A,L (0xBD - 0xBF - carry ) should set HF
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFB, c.reg.a);
-    assert_eq!(c.flags(), SF | NF); // SBC A,0x01
+    assert_eq!(c.flags(), SF | YF | XF | NF); // SBC A,0x01
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFD, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SBC A,0xFE
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SBC A,0xFE
 }
 
 #[test]
@@ -817,7 +821,7 @@ fn sbc_ixyh_ixyl_asm() {
     assert_eq!(c.flags(), ZF | NF); // SUB A,A
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SBC A,IXH
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SBC A,IXH
     assert_eq!(c.execute(), 8);
     assert_eq!(0x06, c.reg.a);
     assert_eq!(c.flags(), NF); // SBC A,IXL
@@ -828,7 +832,7 @@ fn sbc_ixyh_ixyl_asm() {
     assert_eq!(c.flags(), ZF | NF); // SUB A,A
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // SBC A,IYH
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // SBC A,IYH
     assert_eq!(c.execute(), 8);
     assert_eq!(0x06, c.reg.a);
     assert_eq!(c.flags(), NF); // SBC A,IYL
@@ -851,13 +855,13 @@ fn sbc_i_hl_ix_iy_asm() {
     assert_eq!(0x00, c.reg.a);
     assert_eq!(c.execute(), 7);
     assert_eq!(0xBF, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF | CF);
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF);
     assert_eq!(c.execute(), 19);
     assert_eq!(0x5D, c.reg.a);
-    assert_eq!(c.flags(), VF | NF);
+    assert_eq!(c.flags(), XF | VF | NF);
     assert_eq!(c.execute(), 19);
     assert_eq!(0xFC, c.reg.a);
-    assert_eq!(c.flags(), SF | NF | CF);
+    assert_eq!(c.flags(), SF | YF | XF | NF | CF);
 }
 
 #[test]
@@ -881,19 +885,19 @@ fn or_r_asm() {
     assert_eq!(c.flags(), 0); // OR D
     assert_eq!(c.execute(), 4);
     assert_eq!(0x0F, c.reg.a);
-    assert_eq!(c.flags(), PF); // OR E
+    assert_eq!(c.flags(), XF | PF); // OR E
     assert_eq!(c.execute(), 4);
     assert_eq!(0x1F, c.reg.a);
-    assert_eq!(c.flags(), 0); // OR H
+    assert_eq!(c.flags(), XF); // OR H
     assert_eq!(c.execute(), 4);
     assert_eq!(0x3F, c.reg.a);
-    assert_eq!(c.flags(), PF); // OR L
+    assert_eq!(c.flags(), YF | XF | PF); // OR L
     assert_eq!(c.execute(), 7);
     assert_eq!(0x7F, c.reg.a);
-    assert_eq!(c.flags(), 0); // OR 0x40
+    assert_eq!(c.flags(), YF | XF); // OR 0x40
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0x80
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0x80
 }
 
 #[test]
@@ -917,19 +921,19 @@ fn xor_r_asm() {
     assert_eq!(c.flags(), PF); // XOR D
     assert_eq!(c.execute(), 4);
     assert_eq!(0x0A, c.reg.a);
-    assert_eq!(c.flags(), PF); // XOR E
+    assert_eq!(c.flags(), XF | PF); // XOR E
     assert_eq!(c.execute(), 4);
     assert_eq!(0x15, c.reg.a);
     assert_eq!(c.flags(), 0); // XOR H
     assert_eq!(c.execute(), 4);
     assert_eq!(0x2A, c.reg.a);
-    assert_eq!(c.flags(), 0); // XOR L
+    assert_eq!(c.flags(), YF | XF); // XOR L
     assert_eq!(c.execute(), 7);
     assert_eq!(0x55, c.reg.a);
     assert_eq!(c.flags(), PF); // XOR 0x7F
     assert_eq!(c.execute(), 7);
     assert_eq!(0xAA, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // XOR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // XOR 0xFF
 }
 
 #[test]
@@ -947,13 +951,13 @@ fn or_xor_i_hl_ix_iy_asm() {
     assert_eq!(c.flags(), PF); // OR (HL)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x63, c.reg.a);
-    assert_eq!(c.flags(), PF); // OR (IX+1)
+    assert_eq!(c.flags(), YF | PF); // OR (IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xE7, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR (IY-1)
+    assert_eq!(c.flags(), SF | YF | PF); // OR (IY-1)
     assert_eq!(c.execute(), 7);
     assert_eq!(0xA6, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // XOR (HL)
+    assert_eq!(c.flags(), SF | YF | PF); // XOR (HL)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xC4, c.reg.a);
     assert_eq!(c.flags(), SF); // XOR (IX+1)
@@ -974,46 +978,46 @@ fn and_r_asm() {
     assert_eq!(c.flags(), HF); // AND B
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0xFF
     assert_eq!(c.execute(), 4);
     assert_eq!(0x03, c.reg.a);
     assert_eq!(c.flags(), HF | PF); // AND C
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0xFF
     assert_eq!(c.execute(), 4);
     assert_eq!(0x04, c.reg.a);
     assert_eq!(c.flags(), HF); // AND D
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0xFF
     assert_eq!(c.execute(), 4);
     assert_eq!(0x08, c.reg.a);
-    assert_eq!(c.flags(), HF); // AND E
+    assert_eq!(c.flags(), HF | XF); // AND E
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0xFF
     assert_eq!(c.execute(), 4);
     assert_eq!(0x10, c.reg.a);
     assert_eq!(c.flags(), HF); // AND H
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0xFF
     assert_eq!(c.execute(), 4);
     assert_eq!(0x20, c.reg.a);
-    assert_eq!(c.flags(), HF); // AND L
+    assert_eq!(c.flags(), YF | HF); // AND L
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0xFF
     assert_eq!(c.execute(), 7);
     assert_eq!(0x40, c.reg.a);
     assert_eq!(c.flags(), HF); // AND 0x40
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF | PF); // OR 0xFF
+    assert_eq!(c.flags(), SF | YF | XF | PF); // OR 0xFF
     assert_eq!(c.execute(), 7);
     assert_eq!(0xAA, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | PF); // AND 0xAA
+    assert_eq!(c.flags(), SF | YF | HF | XF | PF); // AND 0xAA
 }
 
 #[test]
@@ -1028,13 +1032,13 @@ fn and_i_hl_ix_iy_asm() {
     }
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFE, c.reg.a);
-    assert_eq!(c.flags(), SF | HF); // AND (HL)
+    assert_eq!(c.flags(), SF | YF | HF | XF); // AND (HL)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xAA, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | PF); // AND (IX+1)
+    assert_eq!(c.flags(), SF | YF | HF | XF | PF); // AND (IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x88, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | PF); // AND (IY-1)
+    assert_eq!(c.flags(), SF | HF | XF | PF); // AND (IY-1)
 }
 
 #[test]
@@ -1055,19 +1059,19 @@ fn inc_dec_r_asm() {
     assert_eq!(c.flags(), ZF | HF); // INC B
     assert_eq!(c.execute(), 4);
     assert_eq!(0xFF, c.reg.b);
-    assert_eq!(c.flags(), SF | HF | NF); // DEC B
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF); // DEC B
     assert_eq!(c.execute(), 4);
     assert_eq!(0x10, c.reg.c);
     assert_eq!(c.flags(), HF); // INC C
     assert_eq!(c.execute(), 4);
     assert_eq!(0x0F, c.reg.c);
-    assert_eq!(c.flags(), HF | NF); // DEC C
+    assert_eq!(c.flags(), HF | XF | NF); // DEC C
     assert_eq!(c.execute(), 4);
     assert_eq!(0x0F, c.reg.d);
-    assert_eq!(c.flags(), 0); // INC D
+    assert_eq!(c.flags(), XF); // INC D
     assert_eq!(c.execute(), 4);
     assert_eq!(0x0E, c.reg.d);
-    assert_eq!(c.flags(), NF); // DEC D
+    assert_eq!(c.flags(), XF | NF); // DEC D
     assert_eq!(c.execute(), 7);
     assert_eq!(0x00, c.reg.a);
     assert_eq!(c.flags(), SF | HF | NF | CF); // CP 0x01   set carry flag (should be preserved)
@@ -1076,19 +1080,19 @@ fn inc_dec_r_asm() {
     assert_eq!(c.flags(), SF | HF | VF | CF); // INC E
     assert_eq!(c.execute(), 4);
     assert_eq!(0x7F, c.reg.e);
-    assert_eq!(c.flags(), HF | VF | NF | CF); // DEC E
+    assert_eq!(c.flags(), YF | HF | XF | VF | NF | CF); // DEC E
     assert_eq!(c.execute(), 4);
     assert_eq!(0x3F, c.reg.h);
-    assert_eq!(c.flags(), CF); // INC H
+    assert_eq!(c.flags(), YF | XF | CF); // INC H
     assert_eq!(c.execute(), 4);
     assert_eq!(0x3E, c.reg.h);
-    assert_eq!(c.flags(), NF | CF); // DEC H
+    assert_eq!(c.flags(), YF | XF | NF | CF); // DEC H
     assert_eq!(c.execute(), 4);
     assert_eq!(0x24, c.reg.l);
-    assert_eq!(c.flags(), CF); // INC L
+    assert_eq!(c.flags(), YF | CF); // INC L
     assert_eq!(c.execute(), 4);
     assert_eq!(0x23, c.reg.l);
-    assert_eq!(c.flags(), NF | CF); // DEC L
+    assert_eq!(c.flags(), YF | NF | CF); // DEC L
 }
 
 #[test]
@@ -1103,7 +1107,7 @@ fn inc_dec_i_hl_ix_iy_asm() {
     }
     assert_eq!(c.execute(), 11);
     assert_eq!(0xFF, c.bus.leer_byte(0x1000));
-    assert_eq!(c.flags(), SF | HF | NF); // DEC (HL)
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF); // DEC (HL)
     assert_eq!(c.execute(), 11);
     assert_eq!(0x00, c.bus.leer_byte(0x1000));
     assert_eq!(c.flags(), ZF | HF); // INC (HL)
@@ -1112,13 +1116,13 @@ fn inc_dec_i_hl_ix_iy_asm() {
     assert_eq!(c.flags(), HF); // INC (IX+1)
     assert_eq!(c.execute(), 23);
     assert_eq!(0x3F, c.bus.leer_byte(0x1001));
-    assert_eq!(c.flags(), HF | NF); // DEC (IX+1)
+    assert_eq!(c.flags(), YF | HF | XF | NF); // DEC (IX+1)
     assert_eq!(c.execute(), 23);
     assert_eq!(0x80, c.bus.leer_byte(0x1002));
     assert_eq!(c.flags(), SF | HF | VF); // INC (IY-1)
     assert_eq!(c.execute(), 23);
     assert_eq!(0x7F, c.bus.leer_byte(0x1002));
-    assert_eq!(c.flags(), HF | PF | NF); // DEC (IY-1)
+    assert_eq!(c.flags(), YF | HF | XF | PF | NF); // DEC (IY-1)
 }
 
 #[test]
@@ -1334,13 +1338,13 @@ fn daa_asm() {
     assert_eq!(0x27, c.reg.b); // LD B,0x27
     assert_eq!(c.execute(), 4);
     assert_eq!(0x3C, c.reg.a);
-    assert_eq!(c.flags(), 0); // ADD A,B
+    assert_eq!(c.flags(), YF | XF); // ADD A,B
     assert_eq!(c.execute(), 4);
     assert_eq!(0x42, c.reg.a);
     assert_eq!(c.flags(), HF | PF); // DAA
     assert_eq!(c.execute(), 4);
     assert_eq!(0x1B, c.reg.a);
-    assert_eq!(c.flags(), HF | NF); // SUB B
+    assert_eq!(c.flags(), HF | XF | NF); // SUB B
     assert_eq!(c.execute(), 4);
     assert_eq!(0x15, c.reg.a);
     assert_eq!(c.flags(), NF); // DAA
@@ -1352,13 +1356,13 @@ fn daa_asm() {
     assert_eq!(c.flags(), NF); // LD B,0x15
     assert_eq!(c.execute(), 4);
     assert_eq!(0xA5, c.reg.a);
-    assert_eq!(c.flags(), SF); // ADD A,B
+    assert_eq!(c.flags(), SF | YF); // ADD A,B
     assert_eq!(c.execute(), 4);
     assert_eq!(0x05, c.reg.a);
     assert_eq!(c.flags(), PF | CF); // DAA
     assert_eq!(c.execute(), 4);
     assert_eq!(0xF0, c.reg.a);
-    assert_eq!(c.flags(), SF | NF | CF); // SUB B
+    assert_eq!(c.flags(), SF | YF | NF | CF); // SUB B
     assert_eq!(c.execute(), 4);
     assert_eq!(0x90, c.reg.a);
     assert_eq!(c.flags(), SF | PF | NF | CF); // DAA
@@ -1373,19 +1377,19 @@ fn cpl_asm() {
     assert_eq!(c.flags(), ZF | NF); // SUB A
     assert_eq!(c.execute(), 4);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), ZF | HF | NF); // CPL
+    assert_eq!(c.flags(), ZF | YF | HF | XF | NF); // CPL
     assert_eq!(c.execute(), 4);
     assert_eq!(0x00, c.reg.a);
     assert_eq!(c.flags(), ZF | HF | NF); // CPL
     assert_eq!(c.execute(), 7);
     assert_eq!(0xAA, c.reg.a);
-    assert_eq!(c.flags(), SF); // ADD A,0xAA
+    assert_eq!(c.flags(), SF | YF | XF); // ADD A,0xAA
     assert_eq!(c.execute(), 4);
     assert_eq!(0x55, c.reg.a);
     assert_eq!(c.flags(), SF | HF | NF); // CPL
     assert_eq!(c.execute(), 4);
     assert_eq!(0xAA, c.reg.a);
-    assert_eq!(c.flags(), SF | HF | NF); // CPL
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF); // CPL
 }
 
 #[test]
@@ -1403,13 +1407,13 @@ fn ccf_scf_asm() {
     assert_eq!(c.flags(), ZF | HF); // CCF
     assert_eq!(c.execute(), 7);
     assert_eq!(0x34, c.reg.a);
-    assert_eq!(c.flags(), HF | NF | CF); // SUB 0xCC
+    assert_eq!(c.flags(), YF | HF | NF | CF); // SUB 0xCC
     assert_eq!(c.execute(), 4);
     assert_eq!(0x34, c.reg.a);
-    assert_eq!(c.flags(), HF); // CCF
+    assert_eq!(c.flags(), YF | HF); // CCF
     assert_eq!(c.execute(), 4);
     assert_eq!(0x34, c.reg.a);
-    assert_eq!(c.flags(), CF); // SCF
+    assert_eq!(c.flags(), YF | CF); // SCF
 }
 
 #[test]
@@ -1593,7 +1597,7 @@ fn jp_cc_nn_asm() {
     assert_eq!(0x021D, c.reg.pc);
     assert_eq!(c.execute(), 7);
     assert_eq!(0xFF, c.reg.a);
-    assert_eq!(c.flags(), SF);
+    assert_eq!(c.flags(), SF | YF | XF);
     assert_eq!(c.execute(), 10);
     assert_eq!(0x0222, c.reg.pc);
     assert_eq!(c.execute(), 10);
@@ -1652,13 +1656,13 @@ fn ldi_asm() {
     assert_eq!(0x2002, c.reg.get_de());
     assert_eq!(0x0001, c.reg.get_bc());
     assert_eq!(0x02, c.bus.leer_byte(0x2001));
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | PF);
     assert_eq!(c.execute(), 16);
     assert_eq!(0x1003, c.reg.get_hl());
     assert_eq!(0x2003, c.reg.get_de());
     assert_eq!(0x0000, c.reg.get_bc());
     assert_eq!(0x03, c.bus.leer_byte(0x2002));
-    assert_eq!(c.flags(), 0);
+    assert_eq!(c.flags(), YF);
 }
 
 #[test]
@@ -1671,12 +1675,12 @@ fn ldir_asm() {
     for _ in 0..3 {
         c.execute();
     }
-    c.execute();
+    ejecutar_bloque(&mut c);
     assert_eq!(0x1003, c.reg.get_hl());
     assert_eq!(0x2003, c.reg.get_de());
     assert_eq!(0x0000, c.reg.get_bc());
     assert_eq!(0x03, c.bus.leer_byte(0x2002));
-    assert_eq!(c.flags(), 0);
+    assert_eq!(c.flags(), YF);
     c.execute();
     assert_eq!(0x33, c.reg.a);
 }
@@ -1696,13 +1700,13 @@ fn ldd_asm() {
     assert_eq!(0x2001, c.reg.get_de());
     assert_eq!(0x0002, c.reg.get_bc());
     assert_eq!(0x03, c.bus.leer_byte(0x2002));
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | PF);
     assert_eq!(c.execute(), 16);
     assert_eq!(0x1000, c.reg.get_hl());
     assert_eq!(0x2000, c.reg.get_de());
     assert_eq!(0x0001, c.reg.get_bc());
     assert_eq!(0x02, c.bus.leer_byte(0x2001));
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | PF);
     assert_eq!(c.execute(), 16);
     assert_eq!(0x0FFF, c.reg.get_hl());
     assert_eq!(0x1FFF, c.reg.get_de());
@@ -1721,7 +1725,7 @@ fn lddr_asm() {
     for _ in 0..3 {
         c.execute();
     }
-    c.execute();
+    ejecutar_bloque(&mut c);
     assert_eq!(0x0FFF, c.reg.get_hl());
     assert_eq!(0x1FFF, c.reg.get_de());
     assert_eq!(0x0000, c.reg.get_bc());
@@ -1745,7 +1749,7 @@ fn cpi_asm() {
     assert_eq!(c.execute(), 16);
     assert_eq!(0x1001, c.reg.get_hl());
     assert_eq!(0x0003, c.reg.get_bc());
-    assert_eq!(c.flags(), PF | NF);
+    assert_eq!(c.flags(), YF | PF | NF);
     let f = c.flags() | CF;
     c.reg.flags.set_from_byte(f);
     assert_eq!(c.execute(), 16);
@@ -1759,7 +1763,7 @@ fn cpi_asm() {
     assert_eq!(c.execute(), 16);
     assert_eq!(0x1004, c.reg.get_hl());
     assert_eq!(0x0000, c.reg.get_bc());
-    assert_eq!(c.flags(), SF | HF | NF | CF);
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF);
 }
 
 #[test]
@@ -1774,15 +1778,15 @@ fn cpir_asm() {
         c.execute();
     }
 
-    c.execute();
+    ejecutar_bloque(&mut c);
     assert_eq!(0x1003, c.reg.get_hl());
     assert_eq!(0x0001, c.reg.get_bc());
     assert_eq!(c.flags(), ZF | PF | NF);
 
-    c.execute();
+    ejecutar_bloque(&mut c);
     assert_eq!(0x1004, c.reg.get_hl());
     assert_eq!(0x0000, c.reg.get_bc());
-    assert_eq!(c.flags(), SF | HF | NF);
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF);
 }
 
 #[test]
@@ -1799,7 +1803,7 @@ fn cpd_asm() {
     assert_eq!(c.execute(), 16);
     assert_eq!(0x1002, c.reg.get_hl());
     assert_eq!(0x0003, c.reg.get_bc());
-    assert_eq!(c.flags(), SF | HF | PF | NF);
+    assert_eq!(c.flags(), SF | YF | HF | XF | PF | NF);
     assert_eq!(c.execute(), 16);
     assert_eq!(0x1001, c.reg.get_hl());
     assert_eq!(0x0002, c.reg.get_bc());
@@ -1811,7 +1815,7 @@ fn cpd_asm() {
     assert_eq!(c.execute(), 16);
     assert_eq!(0x0FFF, c.reg.get_hl());
     assert_eq!(0x0000, c.reg.get_bc());
-    assert_eq!(c.flags(), NF);
+    assert_eq!(c.flags(), YF | NF);
 }
 
 #[test]
@@ -1881,7 +1885,7 @@ fn add_adc_sbc_16_asm() {
     assert_eq!(c.flags(), SF | HF | PF);
     assert_eq!(c.execute(), 15);
     assert_eq!(0x7FFF, c.reg.get_hl());
-    assert_eq!(c.flags(), NF | HF | PF);
+    assert_eq!(c.flags(), YF | HF | XF | PF | NF);
 }
 
 #[test]
@@ -1933,8 +1937,9 @@ fn ld_a_ir_asm() {
     assert_eq!(0x00, c.reg.a);
     assert_eq!(c.flags(), ZF | NF);
     assert_eq!(c.execute(), 9);
-    assert_eq!(0x34, c.reg.a);
-    assert_eq!(c.flags(), PF);
+    // R avanza en cada M1: seis desde el principio del programa
+    assert_eq!(0x3A, c.reg.a);
+    assert_eq!(c.flags(), YF | XF | PF);
 }
 
 #[test]
@@ -1964,10 +1969,10 @@ fn rlc_rl_rrc_rr_r_asm() {
     assert_eq!(c.flags(), CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.b);
-    assert_eq!(c.flags(), SF | PF | CF);
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.b);
-    assert_eq!(c.flags(), SF | PF | CF);
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x06, c.reg.c);
     assert_eq!(c.flags(), PF);
@@ -1976,28 +1981,28 @@ fn rlc_rl_rrc_rr_r_asm() {
     assert_eq!(c.flags(), PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFD, c.reg.d);
-    assert_eq!(c.flags(), SF | CF);
+    assert_eq!(c.flags(), SF | YF | XF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFE, c.reg.d);
-    assert_eq!(c.flags(), SF | CF);
+    assert_eq!(c.flags(), SF | YF | XF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x88, c.reg.e);
-    assert_eq!(c.flags(), SF | PF | CF);
+    assert_eq!(c.flags(), SF | XF | PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x11, c.reg.e);
     assert_eq!(c.flags(), PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x7E, c.reg.h);
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | XF | PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x3F, c.reg.h);
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | XF | PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xE0, c.reg.l);
-    assert_eq!(c.flags(), SF);
+    assert_eq!(c.flags(), SF | YF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x70, c.reg.l);
-    assert_eq!(c.flags(), 0);
+    assert_eq!(c.flags(), YF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x00, c.reg.a);
     assert_eq!(c.flags(), ZF | PF | CF);
@@ -2006,10 +2011,10 @@ fn rlc_rl_rrc_rr_r_asm() {
     assert_eq!(c.flags(), 0);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x7F, c.reg.b);
-    assert_eq!(c.flags(), CF);
+    assert_eq!(c.flags(), YF | XF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.b);
-    assert_eq!(c.flags(), SF | PF);
+    assert_eq!(c.flags(), SF | YF | XF | PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x06, c.reg.c);
     assert_eq!(c.flags(), PF);
@@ -2018,28 +2023,28 @@ fn rlc_rl_rrc_rr_r_asm() {
     assert_eq!(c.flags(), PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFC, c.reg.d);
-    assert_eq!(c.flags(), SF | PF | CF);
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFE, c.reg.d);
-    assert_eq!(c.flags(), SF);
+    assert_eq!(c.flags(), SF | YF | XF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x08, c.reg.e);
-    assert_eq!(c.flags(), CF);
+    assert_eq!(c.flags(), XF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x11, c.reg.e);
     assert_eq!(c.flags(), PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x7E, c.reg.h);
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | XF | PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x3F, c.reg.h);
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | XF | PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xE0, c.reg.l);
-    assert_eq!(c.flags(), SF);
+    assert_eq!(c.flags(), SF | YF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x70, c.reg.l);
-    assert_eq!(c.flags(), 0);
+    assert_eq!(c.flags(), YF);
 }
 
 #[test]
@@ -2066,17 +2071,17 @@ fn rrc_rlc_rr_rl_i_hl_ix_iy_asm() {
     assert_eq!(0x01, c.reg.a); // LD A,(HL)
     assert_eq!(c.execute(), 23);
     assert_eq!(0xFF, c.bus.leer_byte(0x1001));
-    assert_eq!(c.flags(), SF | PF | CF); // RRC (IX+1)
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF); // RRC (IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xFF, c.reg.a); // LD A,(IX+1)
     assert_eq!(c.execute(), 23);
     assert_eq!(0xFF, c.bus.leer_byte(0x1001));
-    assert_eq!(c.flags(), SF | PF | CF); // RLC (IX+1)
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF); // RLC (IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xFF, c.reg.a); // LD A,(IX+1)
     assert_eq!(c.execute(), 23);
     assert_eq!(0x88, c.bus.leer_byte(0x1002));
-    assert_eq!(c.flags(), SF | PF | CF); // RRC (IY-1)
+    assert_eq!(c.flags(), SF | XF | PF | CF); // RRC (IY-1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x88, c.reg.a); // LD A,(IY-1)
     assert_eq!(c.execute(), 23);
@@ -2096,17 +2101,17 @@ fn rrc_rlc_rr_rl_i_hl_ix_iy_asm() {
     assert_eq!(0x01, c.reg.a); // LD A,(HL)
     assert_eq!(c.execute(), 23);
     assert_eq!(0xFF, c.bus.leer_byte(0x1001));
-    assert_eq!(c.flags(), SF | PF | CF); // RR (IX+1)
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF); // RR (IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xFF, c.reg.a); // LD A,(IX+1)
     assert_eq!(c.execute(), 23);
     assert_eq!(0xFF, c.bus.leer_byte(0x1001));
-    assert_eq!(c.flags(), SF | PF | CF); // RL (IX+1)
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF); // RL (IX+1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0xFF, c.reg.a); // LD A,(IX+1)
     assert_eq!(c.execute(), 23);
     assert_eq!(0x23, c.bus.leer_byte(0x1002));
-    assert_eq!(c.flags(), 0); // RL (IY-1)
+    assert_eq!(c.flags(), YF); // RL (IY-1)
     assert_eq!(c.execute(), 19);
     assert_eq!(0x23, c.reg.a); // LD A,(IY-1)
     assert_eq!(c.execute(), 23);
@@ -2134,13 +2139,13 @@ fn sla_r_asm() {
     assert_eq!(c.flags(), CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFC, c.reg.d);
-    assert_eq!(c.flags(), SF | PF | CF);
+    assert_eq!(c.flags(), SF | YF | XF | PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFE, c.reg.e);
-    assert_eq!(c.flags(), SF);
+    assert_eq!(c.flags(), SF | YF | XF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x22, c.reg.h);
-    assert_eq!(c.flags(), PF);
+    assert_eq!(c.flags(), YF | PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x00, c.reg.l);
     assert_eq!(c.flags(), ZF | PF);
@@ -2164,13 +2169,13 @@ fn sra_r_asm() {
     assert_eq!(c.flags(), SF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0xFF, c.reg.d);
-    assert_eq!(c.flags(), SF | PF);
+    assert_eq!(c.flags(), SF | YF | XF | PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x3F, c.reg.e);
-    assert_eq!(c.flags(), PF | CF);
+    assert_eq!(c.flags(), YF | XF | PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x08, c.reg.h);
-    assert_eq!(c.flags(), CF);
+    assert_eq!(c.flags(), XF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x00, c.reg.l);
     assert_eq!(c.flags(), ZF | PF);
@@ -2194,13 +2199,13 @@ fn srl_r_asm() {
     assert_eq!(c.flags(), PF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x7F, c.reg.d);
-    assert_eq!(c.flags(), 0);
+    assert_eq!(c.flags(), YF | XF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x3F, c.reg.e);
-    assert_eq!(c.flags(), PF | CF);
+    assert_eq!(c.flags(), YF | XF | PF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x08, c.reg.h);
-    assert_eq!(c.flags(), CF);
+    assert_eq!(c.flags(), XF | CF);
     assert_eq!(c.execute(), 8);
     assert_eq!(0x00, c.reg.l);
     assert_eq!(c.flags(), ZF | PF);
@@ -2312,11 +2317,11 @@ fn rld_rrd_asm() {
     assert_eq!(c.execute(), 18);
     assert_eq!(0xF0, c.reg.a);
     assert_eq!(0x0E, c.bus.leer_byte(0x1000));
-    assert_eq!(c.flags(), SF | PF);
+    assert_eq!(c.flags(), SF | YF | PF);
     assert_eq!(c.execute(), 18);
     assert_eq!(0xFE, c.reg.a);
     assert_eq!(0x00, c.bus.leer_byte(0x1000));
-    assert_eq!(c.flags(), SF);
+    assert_eq!(c.flags(), SF | YF | XF);
     assert_eq!(c.execute(), 7);
     assert_eq!(0x00, c.reg.a);
     assert_eq!(c.execute(), 7);
@@ -2751,7 +2756,8 @@ fn ld_a_r() {
     c.reg.r = 0x56;
     assert_eq!(c.execute(), 9);
     assert_eq!(c.reg.pc, 0x0002);
-    assert_eq!(c.reg.a, 0x56);
+    // Valor de R tras los dos M1 de la instrucción
+    assert_eq!(c.reg.a, 0x58);
 }
 
 #[test]
@@ -3203,6 +3209,16 @@ fn ldi() {
     assert_eq!(c.reg.get_bc(), 0x06);
 }
 
+// Ejecuta todas las repeticiones de LDIR, CPIR, INIR... y devuelve los ciclos consumidos
+fn ejecutar_bloque(c: &mut CPU) -> u32 {
+    let pc = c.reg.pc;
+    let mut ciclos = c.execute();
+    while c.reg.pc == pc {
+        ciclos += c.execute();
+    }
+    ciclos
+}
+
 #[test]
 fn ldir() {
     let mut c = CPU::new(0xFFFF);
@@ -3217,7 +3233,7 @@ fn ldir() {
     c.bus.escribir_byte(0x2223, 0x59);
     c.bus.escribir_byte(0x1113, 0xA5);
     c.bus.escribir_byte(0x2224, 0xC5);
-    assert_eq!(c.execute(), 21);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 21 + 16);
     assert_eq!(c.reg.pc, 2);
     assert_eq!(c.reg.get_hl(), 0x1114);
     assert_eq!(c.bus.leer_byte(0x1111), 0x88);
@@ -3263,7 +3279,7 @@ fn lddr() {
     c.bus.escribir_byte(0x2224, 0x59);
     c.bus.escribir_byte(0x1114, 0xA5);
     c.bus.escribir_byte(0x2225, 0xC5);
-    assert_eq!(c.execute(), 21);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 21 + 16);
     assert_eq!(c.reg.pc, 2);
     assert_eq!(c.reg.get_hl(), 0x1111);
     assert_eq!(c.bus.leer_byte(0x1112), 0x88);
@@ -3304,7 +3320,7 @@ fn cpir() {
     c.bus.escribir_byte(0x1111, 0x52);
     c.bus.escribir_byte(0x1112, 0x00);
     c.bus.escribir_byte(0x1113, 0xF3);
-    assert_eq!(c.execute(), 21);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 21 + 16);
     assert_eq!(c.reg.pc, 2);
     assert_eq!(c.reg.get_hl(), 0x1114);
     assert_eq!(c.reg.get_bc(), 4);
@@ -3340,7 +3356,7 @@ fn cpdr() {
     c.bus.escribir_byte(0x1116, 0xF3);
     c.bus.escribir_byte(0x1117, 0x00);
     c.bus.escribir_byte(0x1118, 0x52);
-    assert_eq!(c.execute(), 21);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 21 + 16);
     assert_eq!(c.reg.pc, 2);
     assert_eq!(c.reg.get_hl(), 0x1115);
     assert_eq!(c.reg.get_bc(), 4);
@@ -4013,7 +4029,7 @@ fn neg_asm() {
     assert_eq!(c.reg.a, 0x01); // LD A,0x01
     assert_eq!(c.execute(), 8);
     assert_eq!(c.reg.a, 0xFF);
-    assert_eq!(c.flags(), SF | HF | NF | CF); // NEG
+    assert_eq!(c.flags(), SF | YF | HF | XF | NF | CF); // NEG
     assert_eq!(c.execute(), 7);
     assert_eq!(c.reg.a, 0x00);
     assert_eq!(c.flags(), ZF | HF | CF); // ADD A,0x01
@@ -4921,11 +4937,11 @@ fn rst() {
 #[test]
 fn debug_unkn() {
     let mut c = CPU::new(0xFFFF);
-    c.bus.escribir_byte(0x0000, 0xDD);
+    c.bus.escribir_byte(0x0000, 0xED);
     c.bus.escribir_byte(0x0001, 0x00);
     c.debug.unknw_instr = true;
     assert_eq!(c.execute(), 0xFF);
-    assert_eq!(c.debug.string, String::from("0xDD00"));
+    assert_eq!(c.debug.string, String::from("0xED00"));
 }
 
 // Si esta prueba se repite indefinidamente, las interrupciones no funcionan.
@@ -4962,21 +4978,25 @@ fn int_im1() {
     }
 }
 
-// if this test loops forever, mode 1 interrupts are not working
 #[test]
 fn int_im2() {
     let mut c = CPU::new(0xFFFF);
-    c.bus.load_bin("PROGRAMAS/bin/int_im2.bin", 0).unwrap();
+    // El .hex lleva la tabla de vectores en 0x0102 (-> 0x0106); int_im2.bin no la tiene
+    c.bus.load_hex("PROGRAMAS/hex/im2.z80.hex").unwrap();
     for _ in 0..9 {
         c.execute();
     }
     c.int_requerimiento(0x02);
-    loop {
+    // Sin interrupciones en modo 2 el bucle no termina nunca
+    for _ in 0..1000 {
         c.execute();
         if c.reg.pc == 0x0000 {
             break;
         }
     }
+    assert_eq!(c.reg.pc, 0x0000);
+    assert_eq!(c.reg.b, 0x0F);
+    assert_eq!(c.im(), 2);
 }
 
 #[test]
@@ -5038,4 +5058,853 @@ fn dasm_cb() {
     c.bus.escribir_byte(0x0277, 0x48);
     assert_eq!(c.dasm(0x274), (String::from("CB00          RLC B"), 2));
     assert_eq!(c.dasm(0x276), (String::from("CB48          BIT 1,B"), 2));
-}
\ No newline at end of file
+}
+
+#[test]
+fn dasm_ed() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.cargar(0x0100, &[0xED, 0x4B, 0x34, 0x12]); // LD BC,($1234)
+    c.bus.cargar(0x0104, &[0xED, 0xB0]); // LDIR
+    c.bus.cargar(0x0106, &[0xED, 0x71]); // OUT (C),0
+    c.bus.cargar(0x0108, &[0xED, 0x00]); // Sin definir
+    assert_eq!(c.dasm(0x100), (String::from("ED 4B 34 12   LD BC,($1234)"), 4));
+    assert_eq!(c.dasm(0x104), (String::from("ED B0         LDIR"), 2));
+    assert_eq!(c.dasm(0x106), (String::from("ED 71         OUT (C),0"), 2));
+    assert_eq!(c.dasm(0x108), (String::from("ED 00         DB $ED,$00"), 2));
+}
+
+#[test]
+fn dasm_dd_fd() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.cargar(0x0100, &[0xDD, 0x21, 0x00, 0x80]); // LD IX,$8000
+    c.bus.cargar(0x0104, &[0xFD, 0x36, 0xFE, 0x55]); // LD (IY-$02),$55
+    c.bus.cargar(0x0108, &[0xDD, 0x7E, 0x05]); // LD A,(IX+$05)
+    c.bus.cargar(0x010B, &[0xFD, 0x65]); // LD IYH,IYL
+    c.bus.cargar(0x010D, &[0xDD, 0x00]); // El prefijo no afecta a NOP
+    assert_eq!(c.dasm(0x100), (String::from("DD 21 00 80   LD IX,$8000"), 4));
+    assert_eq!(c.dasm(0x104), (String::from("FD 36 FE 55   LD (IY-$02),$55"), 4));
+    assert_eq!(c.dasm(0x108), (String::from("DD 7E 05      LD A,(IX+$05)"), 3));
+    assert_eq!(c.dasm(0x10B), (String::from("FD 65         LD IYH,IYL"), 2));
+    assert_eq!(c.dasm(0x10D), (String::from("DD            DB $DD"), 1));
+}
+
+#[test]
+fn dasm_ddcb_fdcb() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.cargar(0x0100, &[0xDD, 0xCB, 0x05, 0x06]); // RLC (IX+$05)
+    c.bus.cargar(0x0104, &[0xFD, 0xCB, 0xFF, 0x7E]); // BIT 7,(IY-$01)
+    c.bus.cargar(0x0108, &[0xDD, 0xCB, 0x02, 0x80]); // RES 0,(IX+$02),B
+    c.bus.cargar(0x010C, &[0xFD, 0xCB, 0x03, 0x47]); // BIT 0,(IY+$03)
+    c.bus.cargar(0x0110, &[0xDD, 0xCB, 0x04, 0x3F]); // SRL (IX+$04),A
+    assert_eq!(c.dasm(0x100), (String::from("DD CB 05 06   RLC (IX+$05)"), 4));
+    assert_eq!(c.dasm(0x104), (String::from("FD CB FF 7E   BIT 7,(IY-$01)"), 4));
+    assert_eq!(c.dasm(0x108), (String::from("DD CB 02 80   RES 0,(IX+$02),B"), 4));
+    assert_eq!(c.dasm(0x10C), (String::from("FD CB 03 47   BIT 0,(IY+$03)"), 4));
+    assert_eq!(c.dasm(0x110), (String::from("DD CB 04 3F   SRL (IX+$04),A"), 4));
+}
+
+#[test]
+fn dasm_independiente_de_registros() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.cargar(0x0100, &[0x46]); // LD B,(HL)
+    c.bus.cargar(0x0101, &[0xDD, 0x77, 0xFE]); // LD (IX-$02),A
+    c.reg.set_hl(0x4000);
+    let antes = c.dasm(0x100);
+    c.reg.set_hl(0x5000);
+    assert_eq!(c.dasm(0x100), antes);
+    assert_eq!(antes, (String::from("46            LD B,(HL)"), 1));
+
+    // La anotación sí usa el estado actual de la CPU
+    c.reg.set_ix(0x8001);
+    assert_eq!(c.dasm_anotado(0x100), "46            LD B,(HL)  ; (HL)=$5000");
+    assert_eq!(c.dasm_anotado(0x101), "DD 77 FE      LD (IX-$02),A  ; (IX-$02)=$7FFF");
+}
+
+#[test]
+fn dasm_tamano_instrucciones() {
+    // Recorre un programa con todos los prefijos sumando los tamaños devueltos
+    let programa = [
+        0x3E, 0x01, // LD A,$01
+        0xD3, 0xFE, // OUT ($FE),A
+        0xED, 0x43, 0x00, 0x90, // LD ($9000),BC
+        0xDD, 0x34, 0x01, // INC (IX+$01)
+        0xFD, 0xCB, 0x02, 0xC6, // SET 0,(IY+$02)
+        0xDD, 0xE9, // JP (IX)
+        0x76, // HALT
+    ];
+    let mut c = CPU::new(0xFFFF);
+    c.bus.cargar(0, &programa);
+    let mut pc = 0u16;
+    let mut instrucciones = Vec::new();
+    while (pc as usize) < programa.len() {
+        let (texto, size) = c.dasm(pc);
+        instrucciones.push(texto[14..].to_string());
+        pc += size as u16;
+    }
+    assert_eq!(pc as usize, programa.len());
+    assert_eq!(
+        instrucciones,
+        vec![
+            "LD A,$01",
+            "OUT ($FE),A",
+            "LD ($9000),BC",
+            "INC (IX+$01)",
+            "SET 0,(IY+$02)",
+            "JP (IX)",
+            "HALT"
+        ]
+    );
+}
+
+// Dispositivo de E/S de prueba: devuelve bytes de una cola y anota las escrituras
+struct PuertoPrueba {
+    entrada: Vec<u8>,
+    lecturas: Rc<RefCell<Vec<u16>>>,
+    escrituras: Rc<RefCell<Vec<(u16, u8)>>>,
+}
+
+impl DispositivoIO for PuertoPrueba {
+    fn leer(&mut self, puerto: u16) -> u8 {
+        self.lecturas.borrow_mut().push(puerto);
+        if self.entrada.is_empty() {
+            0x00
+        } else {
+            self.entrada.remove(0)
+        }
+    }
+
+    fn escribir(&mut self, puerto: u16, dato: u8) {
+        self.escrituras.borrow_mut().push((puerto, dato));
+    }
+}
+
+type Accesos = (Rc<RefCell<Vec<u16>>>, Rc<RefCell<Vec<(u16, u8)>>>);
+
+fn conectar_puerto(c: &mut CPU, puerto: u8, entrada: &[u8]) -> Accesos {
+    let lecturas = Rc::new(RefCell::new(Vec::new()));
+    let escrituras = Rc::new(RefCell::new(Vec::new()));
+    c.io.registrar_puerto(
+        puerto,
+        Box::new(PuertoPrueba {
+            entrada: entrada.to_vec(),
+            lecturas: lecturas.clone(),
+            escrituras: escrituras.clone(),
+        }),
+    );
+    (lecturas, escrituras)
+}
+
+#[test]
+fn in_a_asm() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.load_bin("PROGRAMAS/bin/in_a.bin", 0).unwrap();
+    let (lecturas, _) = conectar_puerto(&mut c, 0x07, &[0x00, 0x12, 0xDE]);
+    loop {
+        c.execute();
+        if c.reg.pc == 0x0000 {
+            break;
+        }
+    }
+    assert_eq!(c.reg.a, 0xDE);
+    assert_eq!(lecturas.borrow().len(), 3);
+    // El primer IN pone en la parte alta del bus el valor que tenía A
+    assert_eq!(lecturas.borrow()[0], 0x0007);
+    assert_eq!(lecturas.borrow()[1], 0x0007);
+    assert_eq!(lecturas.borrow()[2], 0x1207);
+}
+
+#[test]
+fn out_a_asm() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.load_bin("PROGRAMAS/bin/out_a.bin", 0).unwrap();
+    let (_, escrituras) = conectar_puerto(&mut c, 0x07, &[]);
+    loop {
+        c.execute();
+        if c.reg.pc == 0x0000 {
+            break;
+        }
+    }
+    assert_eq!(*escrituras.borrow(), vec![(0xBB07, 0xBB)]);
+}
+
+#[test]
+fn in_a_n() {
+    let mut c = CPU::new(0xFFFF);
+    conectar_puerto(&mut c, 0x01, &[0x7B]);
+    c.reg.a = 0x23;
+    c.reg.flags.set_from_byte(0xFF);
+    c.bus.escribir_byte(0x0000, 0xDB);
+    c.bus.escribir_byte(0x0001, 0x01);
+    assert_eq!(c.execute(), 11);
+    assert_eq!(c.reg.a, 0x7B);
+    assert_eq!(c.reg.pc, 0x0002);
+    // IN A,(n) no afecta a los flags
+    assert_eq!(c.flags(), 0xFF);
+}
+
+#[test]
+fn in_r_c() {
+    let mut c = CPU::new(0xFFFF);
+    conectar_puerto(&mut c, 0x07, &[0x7B, 0x00]);
+    c.reg.set_bc(0x1007);
+    c.reg.flags.c = true;
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0x50);
+    assert_eq!(c.execute(), 12);
+    assert_eq!(c.reg.d, 0x7B);
+    assert_eq!(c.flags() & (SF | ZF | HF | PF | NF | CF), PF | CF);
+    // IN (C) solo afecta a los flags
+    c.bus.escribir_byte(0x0002, 0xED);
+    c.bus.escribir_byte(0x0003, 0x70);
+    assert_eq!(c.execute(), 12);
+    assert_eq!(c.flags() & (SF | ZF | HF | PF | NF | CF), ZF | PF | CF);
+}
+
+#[test]
+fn out_c_r() {
+    let mut c = CPU::new(0xFFFF);
+    let (_, escrituras) = conectar_puerto(&mut c, 0x07, &[]);
+    c.reg.set_bc(0x1007);
+    c.reg.h = 0x5A;
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0x61);
+    c.bus.escribir_byte(0x0002, 0xED);
+    c.bus.escribir_byte(0x0003, 0x71);
+    assert_eq!(c.execute(), 12);
+    assert_eq!(c.execute(), 12);
+    assert_eq!(*escrituras.borrow(), vec![(0x1007, 0x5A), (0x1007, 0x00)]);
+}
+
+#[test]
+fn ini() {
+    let mut c = CPU::new(0xFFFF);
+    let (lecturas, _) = conectar_puerto(&mut c, 0x07, &[0x7B]);
+    c.reg.set_bc(0x1007);
+    c.reg.set_hl(0x1000);
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0xA2);
+    assert_eq!(c.execute(), 16);
+    assert_eq!(c.bus.leer_byte(0x1000), 0x7B);
+    assert_eq!(c.reg.get_hl(), 0x1001);
+    assert_eq!(c.reg.b, 0x0F);
+    // El puerto se direcciona con B antes del decremento
+    assert_eq!(*lecturas.borrow(), vec![0x1007]);
+    // k = 0x7B + 0x08 = 0x83: sin acarreo, P = paridad(3 ^ 0x0F)
+    assert_eq!(c.flags() & (SF | ZF | HF | PF | NF | CF), PF);
+}
+
+#[test]
+fn inir() {
+    let mut c = CPU::new(0xFFFF);
+    conectar_puerto(&mut c, 0x07, &[0x01, 0x02, 0x83]);
+    c.reg.set_bc(0x0307);
+    c.reg.set_hl(0x1000);
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0xB2);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 21 + 16);
+    assert_eq!(c.bus.read_mem_slice(0x1000, 0x1002), vec![0x01, 0x02, 0x83]);
+    assert_eq!(c.reg.get_hl(), 0x1003);
+    assert_eq!(c.reg.b, 0x00);
+    assert_eq!(c.reg.pc, 0x0002);
+    assert!(c.reg.flags.z);
+    assert!(c.reg.flags.n);
+}
+
+#[test]
+fn indr() {
+    let mut c = CPU::new(0xFFFF);
+    conectar_puerto(&mut c, 0x07, &[0x01, 0x02]);
+    c.reg.set_bc(0x0207);
+    c.reg.set_hl(0x1001);
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0xBA);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 16);
+    assert_eq!(c.bus.read_mem_slice(0x1000, 0x1001), vec![0x02, 0x01]);
+    assert_eq!(c.reg.get_hl(), 0x0FFF);
+    assert!(c.reg.flags.z);
+}
+
+#[test]
+fn outi() {
+    let mut c = CPU::new(0xFFFF);
+    let (_, escrituras) = conectar_puerto(&mut c, 0x07, &[]);
+    c.reg.set_bc(0x1007);
+    c.reg.set_hl(0x10FF);
+    c.bus.escribir_byte(0x10FF, 0x59);
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0xA3);
+    assert_eq!(c.execute(), 16);
+    // El puerto se direcciona con B ya decrementado
+    assert_eq!(*escrituras.borrow(), vec![(0x0F07, 0x59)]);
+    assert_eq!(c.reg.get_hl(), 0x1100);
+    // k = 0x59 + L(0x00): sin acarreo
+    assert_eq!(c.flags() & (SF | ZF | HF | NF | CF), 0);
+}
+
+#[test]
+fn otir() {
+    let mut c = CPU::new(0xFFFF);
+    let (_, escrituras) = conectar_puerto(&mut c, 0x07, &[]);
+    c.reg.set_bc(0x0307);
+    c.reg.set_hl(0x1000);
+    c.bus.escribir_byte(0x1000, 0x11);
+    c.bus.escribir_byte(0x1001, 0x22);
+    c.bus.escribir_byte(0x1002, 0xF3);
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0xB3);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 21 + 16);
+    assert_eq!(
+        *escrituras.borrow(),
+        vec![(0x0207, 0x11), (0x0107, 0x22), (0x0007, 0xF3)]
+    );
+    assert_eq!(c.reg.b, 0x00);
+    assert!(c.reg.flags.z);
+    // k = 0xF3 + 0x03 = 0xF6: sin acarreo, N copia el bit 7 del dato
+    assert!(!c.reg.flags.c);
+    assert!(c.reg.flags.n);
+}
+
+#[test]
+fn otdr() {
+    let mut c = CPU::new(0xFFFF);
+    let (_, escrituras) = conectar_puerto(&mut c, 0x07, &[]);
+    c.reg.set_bc(0x0207);
+    c.reg.set_hl(0x1001);
+    c.bus.escribir_byte(0x1000, 0xF0);
+    c.bus.escribir_byte(0x1001, 0x22);
+    c.bus.escribir_byte(0x0000, 0xED);
+    c.bus.escribir_byte(0x0001, 0xBB);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 16);
+    assert_eq!(*escrituras.borrow(), vec![(0x0107, 0x22), (0x0007, 0xF0)]);
+    assert_eq!(c.reg.get_hl(), 0x0FFF);
+    // k = 0xF0 + 0xFF (L tras el decremento) > 0xFF: H y C activos
+    assert!(c.reg.flags.c);
+    assert!(c.reg.flags.h);
+}
+
+#[test]
+fn pin_wait() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.escribir_byte(0x0000, 0x3C); // INC A
+    c.set_wait(true);
+    assert_eq!(c.execute(), 1);
+    assert_eq!(c.reg.pc, 0x0000);
+    c.set_wait(false);
+    assert_eq!(c.execute(), 4);
+    assert_eq!(c.reg.a, 0x01);
+}
+
+#[test]
+fn pin_busreq() {
+    let mut c = CPU::new(0xFFFF);
+    c.bus.escribir_byte(0x0000, 0x3C); // INC A
+    c.set_busreq(true);
+    assert!(!c.busack());
+    c.execute();
+    assert!(c.busack());
+    assert_eq!(c.reg.pc, 0x0000);
+    c.set_busreq(false);
+    c.execute();
+    assert!(!c.busack());
+    assert_eq!(c.reg.pc, 0x0001);
+}
+
+#[test]
+fn pin_reset() {
+    let mut c = CPU::new(0xFFFF);
+    // EI ; IM 2 ; HALT
+    c.bus.cargar(0x0100, &[0xFB, 0xED, 0x5E, 0x76]);
+    c.reg.pc = 0x0100;
+    c.reg.i = 0x12;
+    c.reg.set_hl(0x1234);
+    c.reg.sp = 0x8000;
+    for _ in 0..3 {
+        c.execute();
+    }
+    assert!(c.iff1());
+    assert_eq!(c.im(), 2);
+
+    c.set_reset(true);
+    assert!(c.en_reset());
+    assert_eq!(c.execute(), 1);
+    assert_eq!((c.reg.pc, c.reg.i, c.reg.r), (0, 0, 0));
+    assert!(!c.iff1() && !c.iff2());
+    assert_eq!(c.im(), 0);
+    // El resto de registros y la memoria no cambian
+    assert_eq!(c.reg.get_hl(), 0x1234);
+    assert_eq!(c.reg.sp, 0x8000);
+    assert_eq!(c.bus.leer_byte(0x0100), 0xFB);
+
+    c.set_reset(false);
+    c.execute();
+    assert_eq!(c.reg.pc, 0x0001);
+}
+
+#[test]
+fn reset_y_encendido() {
+    let mut c = CPU::new(0xFFFF);
+    c.encender(EstadoEncendido::NMOS);
+    assert_eq!(c.reg.get_af(), 0xFFFF);
+    assert_eq!(c.reg.sp, 0xFFFF);
+    assert_eq!((c.reg.get_bc(), c.reg.get_hl(), c.alt.get_af()), (0, 0, 0));
+    assert_eq!(c.reg.pc, 0x0000);
+
+    // LD BC,0x1234 ; HALT
+    c.bus.cargar(0x0000, &[0x01, 0x34, 0x12, 0x76]);
+    c.execute();
+    c.execute();
+    assert!(c.halt);
+    c.reiniciar();
+    assert!(!c.halt);
+    assert_eq!(c.reg.pc, 0x0000);
+    assert_eq!(c.reg.get_bc(), 0x1234);
+    assert_eq!(c.bus.leer_byte(0x0001), 0x34);
+    // A diferencia de set_reset, el Z80 sigue ejecutando
+    assert_eq!(c.execute(), 10);
+}
+
+#[test]
+fn registro_r() {
+    let mut c = CPU::new(0xFFFF);
+    // NOP ; LD B,(IX+1) ; SET 0,(IY+2) ; BIT 0,A ; NEG ; EI ; LD A,R
+    c.bus.cargar(
+        0x0000,
+        &[0x00, 0xDD, 0x46, 0x01, 0xFD, 0xCB, 0x02, 0xC6, 0xCB, 0x47, 0xED, 0x44, 0xFB, 0xED, 0x5F],
+    );
+    c.reg.r = 0xFD;
+    c.execute();
+    assert_eq!(c.reg.r, 0xFE);
+    c.execute();
+    assert_eq!(c.reg.r, 0x80);
+    // Con prefijo doble el cuarto byte no es un M1
+    c.execute();
+    assert_eq!(c.reg.r, 0x82);
+    c.execute();
+    c.execute();
+    assert_eq!(c.reg.r, 0x86);
+
+    // LD A,R lee R con sus dos M1 y copia IFF2 en P/V
+    c.execute();
+    c.execute();
+    assert_eq!(c.reg.a, 0x89);
+    assert!(c.reg.flags.s);
+    assert!(c.reg.flags.p);
+}
+
+#[test]
+fn registro_r_en_instrucciones_de_bloque() {
+    let mut c = CPU::new(0xFFFF);
+    // LDIR con BC = 3 ; INIR con B = 2
+    c.bus.cargar(0x0000, &[0xED, 0xB0, 0xED, 0xB2]);
+    c.reg.set_hl(0x1000);
+    c.reg.set_de(0x2000);
+    c.reg.set_bc(0x0003);
+    c.reg.r = 0x7E;
+    // Cada repetición son dos M1
+    assert_eq!(c.execute(), 21);
+    assert_eq!((c.reg.pc, c.reg.r), (0x0000, 0x00));
+    assert_eq!(c.execute(), 21);
+    assert_eq!(c.execute(), 16);
+    assert_eq!((c.reg.pc, c.reg.r), (0x0002, 0x04));
+    c.reg.set_bc(0x0207);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 16);
+    assert_eq!(c.reg.r, 0x08);
+}
+
+#[test]
+fn nmi_entre_repeticiones() {
+    let mut c = CPU::new(0xFFFF);
+    // LDIR con BC = 4; la NMI llega tras la primera repetición
+    c.bus.cargar(0x0000, &[0xED, 0xB0]);
+    c.bus.escribir_byte(0x0066, 0xED);
+    c.bus.escribir_byte(0x0067, 0x45);
+    c.reg.set_hl(0x1000);
+    c.reg.set_de(0x2000);
+    c.reg.set_bc(0x0004);
+    c.reg.sp = 0x8000;
+    c.execute();
+    c.nmi_requerimiento();
+    // Se apila la dirección del propio LDIR y RETN vuelve a él
+    c.execute();
+    assert_eq!(c.bus.read_word(0x7FFE), 0x0000);
+    assert_eq!(c.reg.pc, 0x0000);
+    assert_eq!(c.reg.get_bc(), 0x0003);
+    assert_eq!(ejecutar_bloque(&mut c), 21 + 21 + 16);
+    assert_eq!(c.reg.get_bc(), 0x0000);
+}
+
+#[test]
+fn flags_xy() {
+    let mut c = CPU::new(0xFFFF);
+    // CP 0x28 ; BIT 7,A ; ADD HL,BC ; LDI ; CPI ; BIT 0,(IX+1)
+    c.bus.cargar(
+        0x0000,
+        &[0xFE, 0x28, 0xCB, 0x7F, 0x09, 0xED, 0xA0, 0xED, 0xA1, 0xDD, 0xCB, 0x01, 0x46],
+    );
+    c.reg.a = 0x80;
+    c.execute();
+    // CP toma X e Y del operando
+    assert_eq!(c.flags(), YF | HF | XF | VF | NF);
+    c.execute();
+    // BIT 7 a uno: S activo, P/V igual a Z, X e Y del registro
+    assert_eq!(c.flags(), SF | HF);
+
+    c.reg.set_hl(0x1000);
+    c.reg.set_bc(0x1800);
+    c.execute();
+    // ADD HL: X e Y del byte alto del resultado; S no cambia
+    assert_eq!(c.reg.get_hl(), 0x2800);
+    assert_eq!(c.flags(), SF | YF | XF);
+
+    // LDI: A + (HL) = 0x80 + 0x0A = 0x8A, X del bit 3 e Y del bit 1
+    c.reg.set_hl(0x9000);
+    c.reg.set_de(0xA000);
+    c.bus.escribir_byte(0x9000, 0x0A);
+    c.execute();
+    assert_eq!(c.flags() & (YF | XF | HF | PF | NF), YF | XF | PF);
+
+    // CPI: A - (HL) - H = 0x80 - 0x76 - 1 = 0x09
+    c.bus.escribir_byte(0x9001, 0x76);
+    c.execute();
+    assert_eq!(c.flags() & (YF | XF | NF), XF | NF);
+
+    // BIT n,(IX+d): X e Y del byte alto de la dirección
+    c.reg.set_ix(0x27FF);
+    c.bus.escribir_byte(0x2800, 0x01);
+    c.execute();
+    assert_eq!(c.flags() & (ZF | YF | XF | PF), YF | XF);
+}
+
+#[test]
+fn memptr() {
+    let mut c = CPU::new(0xFFFF);
+    // LD A,(0x1234) ; LD (0x12FF),A ; ADD HL,BC ; BIT 0,(HL) ; JP Z,0x4000 ; EX (SP),HL
+    c.bus.cargar(
+        0x0000,
+        &[0x3A, 0x34, 0x12, 0x32, 0xFF, 0x12, 0x09, 0xCB, 0x46, 0xCA, 0x00, 0x40, 0xE3],
+    );
+    c.bus.escribir_byte(0x1234, 0x56);
+    c.execute();
+    assert_eq!(c.memptr, 0x1235);
+    c.execute();
+    assert_eq!(c.memptr, 0x5600);
+
+    c.reg.set_hl(0x27FF);
+    c.execute();
+    assert_eq!(c.memptr, 0x2800);
+    // BIT n,(HL) toma X e Y de MEMPTR
+    c.bus.escribir_byte(0x27FF, 0x01);
+    c.execute();
+    assert_eq!(c.flags() & (ZF | YF | XF), YF | XF);
+    // JP cc carga MEMPTR aunque no salte
+    c.execute();
+    assert_eq!(c.reg.pc, 0x000C);
+    assert_eq!(c.memptr, 0x4000);
+    c.reg.sp = 0x8000;
+    c.bus.write_word(0x8000, 0xBEEF);
+    c.execute();
+    assert_eq!(c.memptr, 0xBEEF);
+
+    // IN A,(n) ; LD B,(IX-2) ; CPIR
+    c.bus.cargar(0x0100, &[0xDB, 0xFE, 0xDD, 0x46, 0xFE, 0xED, 0xB1]);
+    c.reg.pc = 0x0100;
+    c.reg.a = 0x7F;
+    c.execute();
+    assert_eq!(c.memptr, 0x7FFF);
+    c.reg.set_ix(0x9000);
+    c.execute();
+    assert_eq!(c.memptr, 0x8FFE);
+    // CPIR que encuentra el byte en la segunda vuelta
+    c.reg.a = 0x22;
+    c.reg.set_hl(0xA000);
+    c.reg.set_bc(0x0010);
+    c.bus.cargar(0xA000, &[0x11, 0x22]);
+    ejecutar_bloque(&mut c);
+    assert_eq!(c.reg.get_hl(), 0xA002);
+    assert_eq!(c.memptr, 0x0107);
+}
+
+#[test]
+fn ei_retrasa_int() {
+    let mut c = CPU::new(0xFFFF);
+    // IM 1 ; EI ; EI ; INC A ; INC A
+    c.bus.cargar(0x0000, &[0xED, 0x56, 0xFB, 0xFB, 0x3C, 0x3C]);
+    c.reg.sp = 0x8000;
+    // Con las interrupciones desactivadas la petición queda pendiente
+    c.int_requerimiento(0xFF);
+    c.execute();
+    c.execute();
+    // Ni tras el primer EI ni tras el segundo
+    c.execute();
+    assert_eq!(c.reg.pc, 0x0004);
+    c.execute();
+    assert_eq!((c.reg.pc, c.reg.a), (0x0005, 0x01));
+    c.execute();
+    assert_eq!(c.reg.pc, 0x0038);
+    assert_eq!(c.bus.read_word(0x7FFE), 0x0005);
+    assert!(!c.iff1() && !c.iff2());
+    // La petición se retiró al reconocerla
+    c.bus.escribir_byte(0x0038, 0xFB);
+    c.execute();
+    c.execute();
+    assert_eq!(c.reg.pc, 0x003A);
+}
+
+#[test]
+fn prefijos_sin_interrupcion() {
+    let mut c = CPU::new(0xFFFF);
+    // EI ; NOP ; DD FD 21 34 12 (LD IY,0x1234 con un DD sin efecto delante)
+    c.bus.cargar(0x0000, &[0xFB, 0x00, 0xDD, 0xFD, 0x21, 0x34, 0x12]);
+    c.reg.sp = 0x8000;
+    c.execute();
+    c.execute();
+    // El DD se ejecuta como un NOP, pero no se aceptan interrupciones hasta acabar LD IY
+    assert_eq!(c.execute(), 4);
+    assert_eq!(c.reg.pc, 0x0003);
+    c.int_requerimiento(0xFF);
+    c.nmi_requerimiento();
+    assert_eq!(c.execute(), 14);
+    assert_eq!(c.reg.get_iy(), 0x1234);
+    assert_eq!(c.reg.pc, 0x0007);
+    // Primero la NMI
+    c.execute();
+    assert_eq!(c.bus.read_word(0x7FFE), 0x0007);
+    assert!(!c.iff1() && c.iff2());
+}
+
+#[test]
+fn salto_relativo_no_tomado_lee_desplazamiento() {
+    use crate::cpu::bus::Bus;
+    use crate::cpu::traza::{Acceso, BusTraza, TipoAcceso};
+    let mut c = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+    // JR NZ,$+4 con Z activo ; DJNZ $-2 con B = 1
+    c.bus.cargar(0x0000, &[0x20, 0x02, 0x10, 0xFE]);
+    c.reg.flags.z = true;
+    c.reg.b = 1;
+    assert_eq!(c.execute(), 7);
+    assert_eq!(c.execute(), 8);
+    assert_eq!(c.reg.pc, 0x0004);
+    let lecturas: Vec<Acceso> =
+        c.bus.tomar_accesos().into_iter().filter(|a| a.tipo == TipoAcceso::Lectura).collect();
+    assert_eq!(
+        lecturas,
+        vec![
+            Acceso { tipo: TipoAcceso::Lectura, direccion: 0x0001, dato: 0x02 },
+            Acceso { tipo: TipoAcceso::Lectura, direccion: 0x0003, dato: 0xFE },
+        ]
+    );
+}
+
+#[test]
+fn orden_de_escritura_en_la_pila() {
+    use crate::cpu::bus::Bus;
+    use crate::cpu::traza::{BusTraza, TipoAcceso};
+    let mut c = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+    // PUSH BC ; CALL 0x0010 ; en 0x0010: EX (SP),HL ; RST 0x08
+    c.bus.cargar(0x0000, &[0xC5, 0xCD, 0x10, 0x00]);
+    c.bus.cargar(0x0010, &[0xE3, 0xCF]);
+    c.reg.sp = 0x8000;
+    c.reg.set_bc(0x1234);
+    c.reg.set_hl(0xABCD);
+    for _ in 0..4 {
+        c.execute();
+    }
+    // Como en el Z80: el byte alto primero, en la dirección más alta
+    let escrituras: Vec<(u16, u8)> = c
+        .bus
+        .tomar_accesos()
+        .iter()
+        .filter(|a| a.tipo == TipoAcceso::Escritura)
+        .map(|a| (a.direccion, a.dato))
+        .collect();
+    assert_eq!(
+        escrituras,
+        vec![
+            (0x7FFF, 0x12),
+            (0x7FFE, 0x34),
+            (0x7FFD, 0x00),
+            (0x7FFC, 0x04),
+            (0x7FFD, 0xAB),
+            (0x7FFC, 0xCD),
+            (0x7FFB, 0x00),
+            (0x7FFA, 0x12),
+        ]
+    );
+    assert_eq!(c.reg.get_hl(), 0x0004);
+    assert_eq!(c.reg.sp, 0x7FFA);
+}
+
+#[test]
+fn prefijo_sin_efecto() {
+    use crate::cpu::bus::Bus;
+    use crate::cpu::traza::{BusTraza, TipoAcceso};
+    let mut c = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+    // DD NOP ; FD LD B,A ; DD INC BC
+    c.bus.cargar(0x0000, &[0xDD, 0x00, 0xFD, 0x47, 0xDD, 0x03]);
+    c.reg.a = 0x5A;
+    // Cada prefijo es un M1 de 4 estados T que sube R; luego se ejecuta la instrucción normal
+    let ciclos: Vec<u32> = (0..6).map(|_| c.execute()).collect();
+    assert_eq!(ciclos, vec![4, 4, 4, 4, 4, 6]);
+    assert_eq!(c.reg.r, 6);
+    assert_eq!(c.reg.get_bc(), 0x5A01);
+    assert_eq!(c.reg.pc, 0x0006);
+    // Cada byte se busca una sola vez
+    let fetches: Vec<u16> = c
+        .bus
+        .tomar_accesos()
+        .iter()
+        .filter(|a| a.tipo == TipoAcceso::Fetch)
+        .map(|a| a.direccion)
+        .collect();
+    assert_eq!(fetches, vec![0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn int_im0_instruccion() {
+    let mut c = CPU::new(0xFFFF);
+    // EI ; NOP ; NOP
+    c.bus.cargar(0x1000, &[0xFB, 0x00, 0x00]);
+    c.reg.pc = 0x1000;
+    c.reg.sp = 0x8000;
+    c.execute();
+    // El dispositivo pone CALL 0x2345 en el bus
+    c.int_requerimiento_instruccion(&[0xCD, 0x45, 0x23]);
+    c.execute();
+    assert_eq!(c.execute(), 17 + 2);
+    assert_eq!(c.reg.pc, 0x2345);
+    assert_eq!(c.reg.sp, 0x7FFE);
+    assert_eq!(c.bus.read_word(0x7FFE), 0x1002);
+
+    // RST 08 con PC en 0x0000
+    let mut c = CPU::new(0xFFFF);
+    c.bus.cargar(0x0000, &[0xFB, 0x00]);
+    c.reg.sp = 0x8000;
+    c.int_requerimiento(0xCF);
+    c.execute();
+    c.execute();
+    assert_eq!(c.execute(), 11 + 2);
+    assert_eq!(c.reg.pc, 0x0008);
+    assert_eq!(c.bus.read_word(0x7FFE), 0x0002);
+}
+
+#[test]
+fn ciclos_reconocimiento() {
+    let mut c = CPU::new(0xFFFF);
+    // IM 1 ; EI ; NOP ... 0x0038: IM 2 ; EI ; NOP
+    c.bus.cargar(0x0000, &[0xED, 0x56, 0xFB, 0x00]);
+    c.bus.cargar(0x0038, &[0xED, 0x5E, 0xFB, 0x00]);
+    c.reg.sp = 0x8000;
+    c.reg.i = 0x20;
+    c.bus.write_word(0x2010, 0x3000);
+    c.bus.escribir_byte(0x3000, 0x3C);
+    c.bus.escribir_byte(0x0066, 0x3C);
+    c.execute();
+    c.execute();
+    c.execute();
+    c.int_requerimiento(0x00);
+    assert_eq!(c.execute(), 13);
+    assert_eq!(c.reg.pc, 0x0038);
+
+    // En modo 2 se ejecuta además la primera instrucción de la rutina (INC A)
+    c.execute();
+    c.execute();
+    c.execute();
+    c.int_requerimiento(0x10);
+    assert_eq!(c.execute(), 19 + 4);
+    assert_eq!(c.reg.pc, 0x3001);
+
+    c.nmi_requerimiento();
+    assert_eq!(c.execute(), 11 + 4);
+    assert_eq!(c.reg.pc, 0x0067);
+}
+
+#[test]
+fn halt_sale_con_interrupcion() {
+    let mut c = CPU::new(0xFFFF);
+    // IM 1 ; EI ; HALT ; HALT
+    c.bus.cargar(0x0100, &[0xED, 0x56, 0xFB, 0x76, 0x76]);
+    c.bus.escribir_byte(0x0038, 0x3C);
+    c.bus.escribir_byte(0x0066, 0x3C);
+    c.reg.pc = 0x0100;
+    c.reg.sp = 0x8000;
+    c.reg.r = 0x80;
+    for _ in 0..3 {
+        c.execute();
+    }
+    assert!(c.halt());
+    // Parada, PC se queda en el HALT y cada NOP interno incrementa R
+    assert_eq!(c.execute(), 4);
+    assert_eq!(c.execute(), 4);
+    assert_eq!(c.reg.pc, 0x0103);
+    assert_eq!(c.reg.r, 0x86);
+
+    // /INT: se apila la dirección siguiente al HALT
+    c.int_requerimiento(0xFF);
+    assert_eq!(c.execute(), 13);
+    assert!(!c.halt());
+    assert_eq!(c.reg.pc, 0x0038);
+    assert_eq!(c.bus.read_word(0x7FFE), 0x0104);
+
+    // NMI desde el segundo HALT, con las interrupciones desactivadas
+    c.reg.pc = 0x0104;
+    c.execute();
+    assert!(c.halt());
+    c.nmi_requerimiento();
+    c.execute();
+    assert!(!c.halt());
+    assert_eq!(c.reg.pc, 0x0067);
+    assert_eq!(c.bus.read_word(0x7FFC), 0x0105);
+}
+
+#[test]
+fn reti_restaura_iff1() {
+    let mut c = CPU::new(0xFFFF);
+    // EI ; NOP ; NOP y RETI en la rutina de NMI
+    c.bus.cargar(0x0000, &[0xFB, 0x00, 0x00]);
+    c.bus.cargar(0x0066, &[0xED, 0x4D]);
+    c.reg.sp = 0x8000;
+    c.execute();
+    c.execute();
+    // La NMI borra IFF1 y la misma llamada ejecuta el RETI de 0x0066
+    c.nmi_requerimiento();
+    c.execute();
+    assert_eq!(c.reg.pc, 0x0002);
+    assert!(c.iff1() && c.iff2());
+}
+
+#[test]
+fn cadena_interrupciones_im2() {
+    let mut c = CPU::new(0xFFFF);
+    // IM 2 ; EI ; NOP ; NOP
+    c.bus.cargar(0x0000, &[0xED, 0x5E, 0xFB, 0x00, 0x00]);
+    // Rutina del CTC: RETI. Rutina del PIO: EI ; NOP ; RETI
+    c.bus.cargar(0x3000, &[0xED, 0x4D]);
+    c.bus.cargar(0x3100, &[0xFB, 0x00, 0xED, 0x4D]);
+    c.bus.write_word(0x2010, 0x3000);
+    c.bus.write_word(0x2018, 0x3100);
+    c.reg.i = 0x20;
+    c.reg.sp = 0x8000;
+    let ctc = c.interrupciones.registrar(0, 0x10);
+    let pio = c.interrupciones.registrar(1, 0x18);
+    for _ in 0..3 {
+        c.execute();
+    }
+
+    // Reconocimiento del PIO, que ejecuta EI
+    c.interrupciones.solicitar(pio);
+    c.execute();
+    assert_eq!(c.reg.pc, 0x3101);
+    assert!(c.interrupciones.en_servicio(pio));
+
+    // El CTC tiene más prioridad y anida su interrupción
+    c.interrupciones.solicitar(ctc);
+    c.execute();
+    c.execute();
+    assert_eq!(c.reg.pc, 0x3102);
+    assert!(!c.interrupciones.en_servicio(ctc));
+    assert!(c.interrupciones.en_servicio(pio));
+
+    c.execute();
+    assert_eq!(c.reg.pc, 0x0004);
+    assert!(!c.interrupciones.en_servicio(pio));
+}
@@ -0,0 +1,110 @@
+use crate::cpu::bus::{Bus, Memoria};
+
+/// Tipo de acceso a memoria visto desde el bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TipoAcceso {
+    /// Búsqueda de código de operación (ciclo M1)
+    Fetch,
+    Lectura,
+    Escritura,
+}
+
+/// Acceso a memoria registrado por `BusTraza`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Acceso {
+    pub tipo: TipoAcceso,
+    pub direccion: u16,
+    pub dato: u8,
+}
+
+/// Mapa de memoria que anota cada acceso que hace la CPU sobre otro mapa de memoria.
+///
+/// Los accesos quedan en el orden en que los pide el emulador, que no siempre es el del
+/// Z80 real (las instrucciones con prefijo, por ejemplo, leen los operandos de golpe).
+/// Ejemplo:
+///    let mut c = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+///    c.execute();
+///    let accesos = c.bus.tomar_accesos();
+pub struct BusTraza<B: Memoria = Bus> {
+    pub interno: B,
+    accesos: Vec<Acceso>,
+}
+
+impl<B: Memoria> BusTraza<B> {
+    pub fn new(interno: B) -> BusTraza<B> {
+        BusTraza {
+            interno,
+            accesos: Vec::new(),
+        }
+    }
+
+    /// Devuelve los accesos registrados desde la última llamada y los borra
+    pub fn tomar_accesos(&mut self) -> Vec<Acceso> {
+        std::mem::take(&mut self.accesos)
+    }
+
+    fn anotar(&mut self, tipo: TipoAcceso, direccion: u16, dato: u8) {
+        self.accesos.push(Acceso {
+            tipo,
+            direccion,
+            dato,
+        });
+    }
+}
+
+impl<B: Memoria> Memoria for BusTraza<B> {
+    // Las consultas sin efectos (desensamblador, volcados) no son accesos de la CPU
+    fn leer_byte(&self, direccion: u16) -> u8 {
+        self.interno.leer_byte(direccion)
+    }
+
+    fn tamano(&self) -> usize {
+        self.interno.tamano()
+    }
+
+    fn leer(&mut self, direccion: u16) -> u8 {
+        let dato = self.interno.leer(direccion);
+        self.anotar(TipoAcceso::Lectura, direccion, dato);
+        dato
+    }
+
+    fn escribir_byte(&mut self, direccion: u16, data: u8) {
+        self.anotar(TipoAcceso::Escritura, direccion, data);
+        self.interno.escribir_byte(direccion, data);
+    }
+
+    fn fetch(&mut self, direccion: u16) -> u8 {
+        let dato = self.interno.fetch(direccion);
+        self.anotar(TipoAcceso::Fetch, direccion, dato);
+        dato
+    }
+
+    // La carga no es un acceso de la CPU: no se anota
+    fn cargar(&mut self, direccion: u16, datos: &[u8]) {
+        self.interno.cargar(direccion, datos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::cpu::CPU;
+
+    #[test]
+    fn accesos_de_una_instruccion() {
+        let mut c = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+        // LD (0x8000),A
+        c.bus.cargar(0x0000, &[0x32, 0x00, 0x80]);
+        c.reg.a = 0x5A;
+        c.execute();
+        let accesos = c.bus.tomar_accesos();
+        assert_eq!(accesos[0], Acceso { tipo: TipoAcceso::Fetch, direccion: 0x0000, dato: 0x32 });
+        assert_eq!(
+            accesos.last(),
+            Some(&Acceso { tipo: TipoAcceso::Escritura, direccion: 0x8000, dato: 0x5A })
+        );
+        assert!(accesos[1..accesos.len() - 1].iter().all(|a| a.tipo == TipoAcceso::Lectura));
+        assert_eq!(c.bus.interno.leer_byte(0x8000), 0x5A);
+        assert!(c.bus.tomar_accesos().is_empty());
+    }
+}
@@ -14,6 +14,15 @@
 //  ... (añadir más datos según sea necesario) ...
 
 mod serial;
+mod protocolo;
+mod captura;
+mod vcd;
+mod traza_captura;
+mod comparacion;
+mod transporte;
+mod placa_simulada;
+mod placa_virtual;
+mod cli;
 
 mod cpu;
 mod tests;
@@ -33,9 +42,6 @@ enum SystemState {
     StateHalted,
 }
 
-const ACK_SUCCESS: u8 = 0xDD;
-const ACK_ERROR: u8 = 0x00;
-
 const CMD_ECHO: u8 = 0x01;
 const CMD_SEND_BYTE: u8 = 0x02;
 const CMD_GET_STATUS: u8 = 0x03;
@@ -56,8 +62,35 @@ const BAUD_RATE: u32 = 115_200;
 const SERIAL_TIMEOUT: Duration = Duration::from_millis(500);
 
 fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let opciones = match cli::analizar(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::AYUDA);
+            std::process::exit(2);
+        }
+    };
+    if opciones.comando == Some(cli::Comando::Ayuda) {
+        println!("{}", cli::AYUDA);
+        return Ok(());
+    }
+    // El visor de capturas no necesita la placa
+    match &opciones.comando {
+        Some(cli::Comando::View { fichero, filtro, csv }) => return cli::ver_captura(fichero, filtro, csv.as_deref()),
+        Some(cli::Comando::Trace { fichero }) => return cli::trazar_captura(fichero),
+        Some(cli::Comando::Lockstep { programa, direccion, captura: Some(captura) }) => {
+            let registros = cli::leer_registros_captura(captura)?;
+            return cli::comparar_con_emulador(programa, *direccion, &registros);
+        }
+        _ => {}
+    }
+    // La placa virtual abre su propio pseudo-terminal
+    if opciones.comando == Some(cli::Comando::Virtual) {
+        return placa_virtual::servir_en_pty();
+    }
+
     let mut serial = Serial::new();
-    let builder = serialport::new(SERIAL_PORT_NAME, BAUD_RATE)
+    let builder = serialport::new(&opciones.puerto, opciones.baudios)
         .data_bits(DataBits::Eight)
         .flow_control(FlowControl::None)
         .parity(Parity::None)
@@ -67,7 +100,12 @@ fn main() -> io::Result<()> {
     match builder.open() {
         Ok(mut port) => {
             println!("Puerto serie {} abierto a {} bps con timeout de {} ms.",
-                     SERIAL_PORT_NAME, BAUD_RATE, SERIAL_TIMEOUT.as_millis());
+                     opciones.puerto, opciones.baudios, SERIAL_TIMEOUT.as_millis());
+
+            // Modo no interactivo: un solo subcomando
+            if let Some(comando) = &opciones.comando {
+                return cli::ejecutar(&mut serial, &mut port, comando);
+            }
 
             loop {
                 println!("\n--- Menú ---");
@@ -83,6 +121,7 @@ fn main() -> io::Result<()> {
                 println!("a. Quitar WAIT");
                 println!("b. Poner WAIT");
                 println!("c. Ensamblar Z80");
+                println!("d. Leer registros Z80");
 
                 println!("z. Salir");
                 print!("Selecciona una opción: ");
@@ -105,6 +144,7 @@ fn main() -> io::Result<()> {
                     "a" => serial.quitar_wait(&mut port)?,
                     "b" => serial.poner_wait(&mut port)?,
                     "c" => serial.ensamblar_z80(&mut port)?,
+                    "d" => serial.mostrar_registros(&mut port)?,
 
                     "z" => break,
                     _ => println!("Opción inválida."),
@@ -115,7 +155,7 @@ fn main() -> io::Result<()> {
             Ok(())
         }
         Err(e) => {
-            eprintln!("Error al abrir el puerto serie '{}': {}", SERIAL_PORT_NAME, e);
+            eprintln!("Error al abrir el puerto serie '{}': {}", opciones.puerto, e);
             Err(io::Error::new(io::ErrorKind::Other, e))
         }
     }
@@ -0,0 +1,417 @@
+// Placa simulada en el propio proceso.
+//
+// Implementa los comandos del firmware del Giga R1 sobre los 64 KiB de memoria de un
+// `CPU` emulado, para poder probar `Serial` sin hardware. El Z80 avanza estado T a
+// estado T con `CpuPorCiclos`, así que la captura lleva los mismos registros que la
+// placa real. Mientras nadie llame a `paso` el Z80 no se mueve y la placa es una simple
+// memoria; `placa_virtual` la pone a ejecutar detrás de un pseudo-terminal. Permite
+// además estropear peticiones y respuestas para comprobar los reintentos del protocolo.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use crate::cpu::bus::{Bus, Memoria};
+use crate::cpu::cpu::{EstadoEncendido, CPU};
+use crate::cpu::pines::CpuPorCiclos;
+use crate::cpu::traza::BusTraza;
+use crate::protocolo::{
+    self, Peticion, Receptor, VolcadoRegistros, ACK_SUCCESS, ESTADO_HALTED, ESTADO_IDLE,
+    ESTADO_RUNNING, NAK_COMANDO, NAK_LONGITUD,
+};
+use crate::transporte::Transporte;
+
+pub struct PlacaSimulada {
+    pub z80: CpuPorCiclos,
+    pub estado: u8,
+    pub direccion_ejecucion: u16,
+    pub capturando: bool,
+    /// Contador de registros de captura, como la variable `ciclo` del firmware
+    pub ciclo: u16,
+    /// Comandos recibidos correctamente, en orden
+    pub comandos: Vec<u8>,
+    receptor: Receptor,
+    salida: VecDeque<u8>,
+    timeout: Duration,
+    peticiones_perdidas: usize,
+    peticiones_corruptas: usize,
+    respuestas_corruptas: usize,
+}
+
+impl Default for PlacaSimulada {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PlacaSimulada {
+    pub fn new() -> PlacaSimulada {
+        // Como en el firmware, /WAIT está activa al arrancar. El Z80 de la placa es NMOS.
+        let mut cpu = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+        cpu.encender(EstadoEncendido::NMOS);
+        cpu.set_wait(true);
+        PlacaSimulada {
+            z80: CpuPorCiclos::new(cpu),
+            estado: ESTADO_IDLE,
+            direccion_ejecucion: 0,
+            capturando: false,
+            ciclo: 0,
+            comandos: Vec::new(),
+            receptor: Receptor::new(),
+            salida: VecDeque::new(),
+            timeout: Duration::from_millis(500),
+            peticiones_perdidas: 0,
+            peticiones_corruptas: 0,
+            respuestas_corruptas: 0,
+        }
+    }
+
+    /// Indica si la placa tiene /WAIT activa
+    pub fn wait(&self) -> bool {
+        self.z80.cpu.wait()
+    }
+
+    /// Lee `longitud` bytes de la memoria del Z80 a partir de `direccion`. Como en el Z80,
+    /// la dirección pasa de 0xFFFF a 0x0000.
+    pub fn memoria(&self, direccion: u16, longitud: usize) -> Vec<u8> {
+        (0..longitud)
+            .map(|i| self.z80.cpu.bus.interno.leer_byte(direccion.wrapping_add(i as u16)))
+            .collect()
+    }
+
+    /// Ejecuta una instrucción si el Z80 está en marcha y sin WAIT. Durante una captura
+    /// envía un registro por cada estado T, como `enviar_datos_ciclo` en el firmware, con
+    /// los pines de cada ciclo de máquina: búsquedas M1, refrescos, lecturas, escrituras,
+    /// E/S y estados internos. Devuelve los ciclos de reloj consumidos (0 si el Z80 está
+    /// parado).
+    pub fn paso(&mut self) -> u32 {
+        if self.estado != ESTADO_RUNNING || self.wait() {
+            return 0;
+        }
+        let mut ciclos = 0;
+        loop {
+            let estado = self.z80.paso_t();
+            if self.capturando {
+                let registro = estado.pines.registro_captura(self.ciclo, 1);
+                self.responder(crate::CMD_START_CAPTURE, ACK_SUCCESS, &registro);
+            }
+            self.ciclo = self.ciclo.wrapping_add(1);
+            ciclos += 1;
+            if self.z80.en_limite_de_instruccion() {
+                break;
+            }
+        }
+        if self.z80.cpu.halt {
+            self.estado = ESTADO_HALTED;
+        }
+        ciclos
+    }
+
+    /// Activa /RESET en el Z80: PC, I y R a cero; la memoria y el resto de registros
+    /// no cambian
+    fn reset_z80(&mut self) {
+        self.z80.reiniciar();
+        self.ciclo = 0;
+    }
+
+    /// Las próximas `n` peticiones se pierden por completo, como si se cortara la línea
+    pub fn perder_peticiones(&mut self, n: usize) {
+        self.peticiones_perdidas = n;
+    }
+
+    /// Las próximas `n` peticiones llegan con un byte cambiado
+    pub fn corromper_peticiones(&mut self, n: usize) {
+        self.peticiones_corruptas = n;
+    }
+
+    /// Las próximas `n` respuestas salen con un byte cambiado
+    pub fn corromper_respuestas(&mut self, n: usize) {
+        self.respuestas_corruptas = n;
+    }
+
+    fn responder(&mut self, comando: u8, estado: u8, datos: &[u8]) {
+        let mut trama = protocolo::codificar_respuesta(comando, estado, datos);
+        if self.respuestas_corruptas > 0 {
+            self.respuestas_corruptas -= 1;
+            let ultimo = trama.len() - 1;
+            trama[ultimo] ^= 0x01;
+        }
+        self.salida.extend(trama);
+    }
+
+    /// Ejecuta una petición igual que `ejecutar_comando` en el firmware
+    fn ejecutar(&mut self, peticion: Peticion) {
+        let Peticion { comando, datos } = peticion;
+        self.comandos.push(comando);
+        match comando {
+            crate::CMD_ECHO => self.responder(comando, ACK_SUCCESS, &datos),
+            crate::CMD_SEND_BYTE => self.responder(comando, ACK_SUCCESS, &[]),
+            crate::CMD_GET_STATUS => self.responder(comando, ACK_SUCCESS, &[self.estado]),
+            crate::CMD_READ_Z80_MEMORY => {
+                if datos.len() != 4 {
+                    return self.responder(comando, NAK_LONGITUD, &[]);
+                }
+                let inicio = (datos[0] as usize) << 8 | datos[1] as usize;
+                let longitud = (datos[2] as usize) << 8 | datos[3] as usize;
+                if inicio + longitud > 0x10000 || longitud > protocolo::BLOQUE_MEMORIA {
+                    return self.responder(comando, NAK_LONGITUD, &[]);
+                }
+                let bloque: Vec<u8> = (inicio..inicio + longitud)
+                    .map(|d| self.z80.cpu.bus.interno.leer_byte(d as u16))
+                    .collect();
+                self.responder(comando, ACK_SUCCESS, &bloque);
+            }
+            crate::CMD_WRITE_Z80_MEMORY => {
+                if datos.len() < 3 {
+                    return self.responder(comando, NAK_LONGITUD, &[]);
+                }
+                let inicio = (datos[0] as usize) << 8 | datos[1] as usize;
+                let bloque = &datos[2..];
+                if inicio + bloque.len() > 0x10000 {
+                    return self.responder(comando, NAK_LONGITUD, &[]);
+                }
+                self.z80.cpu.bus.cargar(inicio as u16, bloque);
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            crate::CMD_RUN_Z80 => {
+                if datos.len() != 2 {
+                    return self.responder(comando, NAK_LONGITUD, &[]);
+                }
+                self.direccion_ejecucion = (datos[0] as u16) << 8 | datos[1] as u16;
+                self.z80.cpu.reg.pc = self.direccion_ejecucion;
+                self.z80.cpu.halt = false;
+                self.estado = ESTADO_RUNNING;
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            crate::CMD_RESET_Z80 => {
+                self.reset_z80();
+                self.z80.cpu.bus.interno.clear_mem_slice(0x0000, 0xFFFF);
+                self.estado = ESTADO_IDLE;
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            crate::CMD_RESET_SIN_MODIFICAR_Z80 => {
+                self.reset_z80();
+                self.direccion_ejecucion = 0;
+                self.estado = ESTADO_RUNNING;
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            // Sin rutina inyectada: los registros se leen directamente del emulador
+            crate::CMD_GET_REGISTERS => {
+                let datos = VolcadoRegistros::desde_cpu(&self.z80.cpu).codificar();
+                self.responder(comando, ACK_SUCCESS, &datos);
+            }
+            crate::CMD_START_CAPTURE => {
+                self.capturando = true;
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            crate::CMD_STOP_CAPTURE => {
+                self.capturando = false;
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            crate::CMD_QUITAR_WAIT => {
+                self.z80.cpu.set_wait(false);
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            crate::CMD_PONER_WAIT => {
+                self.z80.cpu.set_wait(true);
+                self.responder(comando, ACK_SUCCESS, &[]);
+            }
+            _ => self.responder(comando, NAK_COMANDO, &[]),
+        }
+    }
+}
+
+impl Write for PlacaSimulada {
+    /// Cada llamada lleva una petición completa de `Serial::enviar_comando`, que es donde
+    /// se aplican los fallos programados.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.peticiones_perdidas > 0 {
+            self.peticiones_perdidas -= 1;
+            return Ok(buf.len());
+        }
+        if self.peticiones_corruptas > 0 && !buf.is_empty() {
+            self.peticiones_corruptas -= 1;
+            let mut copia = buf.to_vec();
+            let ultimo = copia.len() - 1;
+            copia[ultimo] ^= 0x01;
+            self.receptor.recibir(&copia);
+        } else {
+            self.receptor.recibir(buf);
+        }
+        while let Some(peticion) = self.receptor.siguiente() {
+            match peticion {
+                Ok(p) => self.ejecutar(p),
+                Err((comando, error)) => self.responder(comando, protocolo::nak(&error), &[]),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for PlacaSimulada {
+    /// Sin respuestas pendientes se devuelve `TimedOut` enseguida, como haría el puerto
+    /// serie al agotar su timeout, para que las pruebas no tengan que esperar.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.salida.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "placa simulada sin datos"));
+        }
+        let n = buf.len().min(self.salida.len());
+        for (destino, byte) in buf.iter_mut().zip(self.salida.drain(..n)) {
+            *destino = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl Transporte for PlacaSimulada {
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn limpiar_entrada(&mut self) -> io::Result<()> {
+        self.salida.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captura::RegistroCiclo;
+    use crate::cli::{ejecutar, Comando};
+    use crate::traza_captura::{ciclos_de_bus, TipoCiclo};
+    use crate::serial::Serial;
+
+    #[test]
+    fn escribir_y_leer_memoria() {
+        let mut placa = PlacaSimulada::new();
+        let mut serial = Serial::new();
+        let datos: Vec<u8> = (0..600u32).map(|i| i as u8).collect();
+
+        serial.escribir_memoria_z80(&mut placa, 0x8000, &datos).unwrap();
+        assert_eq!(placa.memoria(0x8000, 600), datos);
+        // 600 bytes van en tres tramas
+        assert_eq!(placa.comandos, vec![crate::CMD_WRITE_Z80_MEMORY; 3]);
+
+        assert_eq!(serial.leer_memoria(&mut placa, 0x8000, 600).unwrap(), datos);
+        assert!(serial.leer_memoria(&mut placa, 0xFFF0, 0x20).is_err());
+    }
+
+    #[test]
+    fn comandos_de_control() {
+        let mut placa = PlacaSimulada::new();
+        let mut serial = Serial::new();
+
+        serial.comprobar_echo(&mut placa).unwrap();
+        assert_eq!(serial.leer_status(&mut placa).unwrap(), ESTADO_IDLE);
+        serial.ejecutar_z80(&mut placa, 0x1234).unwrap();
+        assert_eq!(placa.direccion_ejecucion, 0x1234);
+        assert_eq!(serial.leer_status(&mut placa).unwrap(), ESTADO_RUNNING);
+
+        serial.quitar_wait(&mut placa).unwrap();
+        assert!(!placa.wait());
+        ejecutar(&mut serial, &mut placa, &Comando::Wait(true)).unwrap();
+        assert!(placa.wait());
+
+        placa.z80.cpu.bus.cargar(0x10, &[0x55]);
+        ejecutar(&mut serial, &mut placa, &Comando::Reset { conservar_memoria: true }).unwrap();
+        assert_eq!(placa.memoria(0x10, 1), vec![0x55]);
+        assert!(placa.memoria(0x10, 0).is_empty());
+        // Pasa de 0xFFFF a 0x0000
+        assert_eq!(placa.memoria(0xFFFF, 0x12)[0x11], 0x55);
+        ejecutar(&mut serial, &mut placa, &Comando::Reset { conservar_memoria: false }).unwrap();
+        assert_eq!(placa.memoria(0x10, 1), vec![0x00]);
+        assert_eq!(placa.estado, ESTADO_IDLE);
+    }
+
+    #[test]
+    fn subir_fichero_hex() {
+        let mut placa = PlacaSimulada::new();
+        let mut serial = Serial::new();
+        serial.subir_fichero(&mut placa, "PROGRAMAS/hex/im2.z80.hex", 0).unwrap();
+        assert_eq!(placa.memoria(0x0038, 2), vec![0x57, 0xC9]);
+        assert_eq!(placa.memoria(0x0107, 1), vec![0xC9]);
+    }
+
+    #[test]
+    fn reintentos() {
+        let mut placa = PlacaSimulada::new();
+        let mut serial = Serial::new();
+
+        // Petición perdida, petición con el CRC mal (NAK_CRC) y respuesta con el CRC mal
+        placa.perder_peticiones(1);
+        assert_eq!(serial.leer_status(&mut placa).unwrap(), ESTADO_IDLE);
+        placa.corromper_peticiones(1);
+        serial.escribir_memoria_z80(&mut placa, 0x0000, &[1, 2, 3]).unwrap();
+        assert_eq!(placa.memoria(0x0000, 3), vec![1, 2, 3]);
+        placa.corromper_respuestas(2);
+        assert_eq!(serial.leer_memoria(&mut placa, 0x0000, 3).unwrap(), vec![1, 2, 3]);
+
+        // Sin respuesta válida tras todos los reintentos se devuelve el error
+        placa.corromper_respuestas(10);
+        let error = serial.leer_status(&mut placa).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ejecucion_y_captura() {
+        let mut placa = PlacaSimulada::new();
+        let mut serial = Serial::new();
+        // LD A,0x42 ; LD (0x9000),A ; HALT
+        serial.escribir_memoria_z80(&mut placa, 0x0100, &[0x3E, 0x42, 0x32, 0x00, 0x90, 0x76]).unwrap();
+        serial.ejecutar_z80(&mut placa, 0x0100).unwrap();
+
+        // Con WAIT puesto el Z80 no avanza
+        assert_eq!(placa.paso(), 0);
+        serial.quitar_wait(&mut placa).unwrap();
+        serial.transaccion(&mut placa, crate::CMD_START_CAPTURE, &[], Duration::from_secs(1)).unwrap();
+        while placa.paso() > 0 {}
+        assert_eq!(serial.leer_status(&mut placa).unwrap(), ESTADO_HALTED);
+        assert_eq!(placa.memoria(0x9000, 1), vec![0x42]);
+        let registros = serial.leer_registros(&mut placa).unwrap();
+        assert_eq!(registros.reg.a, 0x42);
+        assert_eq!(registros.reg.pc, placa.z80.cpu.reg.pc);
+        assert_eq!(registros.im, Some(0));
+
+        // Los registros quedan en cola delante de la respuesta al status
+        let mut registros = Vec::new();
+        serial.ejecutar_z80(&mut placa, 0x0100).unwrap();
+        placa.paso();
+        while let Ok(r) = serial.leer_respuesta(&mut placa, Duration::from_millis(10)) {
+            registros.push(r.datos);
+        }
+        // LD A,n: M1 de 4 estados (búsqueda y refresco) y lectura del operando en 3
+        assert_eq!(registros.len(), 7);
+        assert_eq!(registros[0][3..6], [0x00, 0x01, 0x3E]);
+        // /RD, /WR, /MREQ y /RFSH
+        assert_eq!(registros[0][6..10], [0, 1, 0, 1]);
+        assert_eq!(registros[2][6..10], [1, 1, 0, 0]);
+        assert_eq!(registros[4][3..6], [0x01, 0x01, 0x42]);
+        assert_eq!(registros[4][6..10], [0, 1, 0, 1]);
+        let decodificados: Vec<RegistroCiclo> =
+            registros.iter().map(|r| RegistroCiclo::decodificar(r).unwrap()).collect();
+        let ciclos = ciclos_de_bus(&decodificados);
+        let tipos: Vec<TipoCiclo> = ciclos.iter().map(|c| c.tipo).collect();
+        assert_eq!(tipos, vec![TipoCiclo::Fetch, TipoCiclo::Refresco, TipoCiclo::Lectura]);
+    }
+
+    #[test]
+    fn comando_no_soportado() {
+        let mut placa = PlacaSimulada::new();
+        let mut serial = Serial::new();
+        let error = serial
+            .transaccion(&mut placa, 0x7F, &[], Duration::from_secs(1))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert_eq!(placa.comandos, vec![0x7F]);
+    }
+}
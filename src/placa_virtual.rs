@@ -0,0 +1,111 @@
+// Placa virtual: sirve el protocolo del firmware en un pseudo-terminal con el emulador
+// detrás, para usar el cliente sin el Giga R1.
+//
+//   terminal 1:  main virtual                      -> "Placa virtual en /dev/pts/N"
+//   terminal 2:  main --port /dev/pts/N upload prog.bin
+//                main --port /dev/pts/N wait off
+//                main --port /dev/pts/N run
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serialport::{SerialPort, TTYPort};
+
+use crate::placa_simulada::PlacaSimulada;
+use crate::protocolo::ESTADO_RUNNING;
+use crate::transporte::Transporte;
+
+/// Instrucciones que ejecuta el Z80 entre dos consultas del puerto
+const INSTRUCCIONES_POR_VUELTA: usize = 1000;
+
+/// Espera de cada lectura del puerto con el Z80 parado
+const ESPERA_PARADO: Duration = Duration::from_millis(50);
+
+/// Atiende las peticiones que llegan por `puerto` con `placa` y ejecuta el Z80 mientras
+/// está en marcha. Termina cuando el otro extremo cierra el puerto.
+pub fn servir(placa: &mut PlacaSimulada, puerto: &mut dyn Transporte) -> io::Result<()> {
+    let mut buffer = [0u8; 512];
+    loop {
+        let en_marcha = placa.estado == ESTADO_RUNNING && !placa.wait();
+        puerto.set_timeout(if en_marcha { Duration::ZERO } else { ESPERA_PARADO })?;
+        match puerto.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                placa.write_all(&buffer[..n])?;
+            }
+            Err(ref e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {}
+            Err(e) => return Err(e),
+        }
+
+        for _ in 0..INSTRUCCIONES_POR_VUELTA {
+            if placa.paso() == 0 {
+                break;
+            }
+        }
+
+        // Respuestas y registros de captura pendientes
+        loop {
+            match placa.read(&mut buffer) {
+                Ok(n) => puerto.write_all(&buffer[..n])?,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) => return Err(e),
+            }
+        }
+        puerto.flush()?;
+    }
+}
+
+/// Crea un pseudo-terminal y sirve en él una placa virtual nueva. El cliente se conecta
+/// al dispositivo esclavo que se muestra por pantalla.
+pub fn servir_en_pty() -> io::Result<()> {
+    let (maestro, esclavo) = TTYPort::pair().map_err(io::Error::from)?;
+    let nombre = esclavo.name().unwrap_or_default();
+    println!("Placa virtual en {}. Ctrl+C para terminar.", nombre);
+
+    // El esclavo se mantiene abierto para que el maestro no dé error mientras no haya
+    // ningún cliente conectado
+    let _esclavo = esclavo;
+    let mut maestro: Box<dyn SerialPort> = Box::new(maestro);
+    servir(&mut PlacaSimulada::new(), &mut maestro)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocolo::ESTADO_HALTED;
+    use crate::serial::Serial;
+    use std::thread;
+
+    #[test]
+    fn cliente_contra_placa_virtual() {
+        let (maestro, esclavo) = TTYPort::pair().unwrap();
+        let servidor = thread::spawn(move || {
+            let mut maestro: Box<dyn SerialPort> = Box::new(maestro);
+            let _ = servir(&mut PlacaSimulada::new(), &mut maestro);
+        });
+
+        let mut port: Box<dyn SerialPort> = Box::new(esclavo);
+        let mut serial = Serial::new();
+        serial.comprobar_echo(&mut port).unwrap();
+
+        // LD HL,0x9000 ; LD (HL),0x99 ; INC (HL) ; HALT
+        let programa = [0x21, 0x00, 0x90, 0x36, 0x99, 0x34, 0x76];
+        serial.escribir_memoria_z80(&mut port, 0x0000, &programa).unwrap();
+        serial.quitar_wait(&mut port).unwrap();
+        serial.ejecutar_z80(&mut port, 0x0000).unwrap();
+
+        let mut status = serial.leer_status(&mut port).unwrap();
+        for _ in 0..100 {
+            if status == ESTADO_HALTED {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            status = serial.leer_status(&mut port).unwrap();
+        }
+        assert_eq!(status, ESTADO_HALTED);
+        assert_eq!(serial.leer_memoria(&mut port, 0x9000, 1).unwrap(), vec![0x9A]);
+
+        drop(port);
+        servidor.join().unwrap();
+    }
+}
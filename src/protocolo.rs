@@ -0,0 +1,760 @@
+// Formato de las tramas entre el PC y el Arduino (versión 1)
+//
+// Petición (PC -> placa):
+//   SYNC | VERSION | CMD | LEN_H | LEN_L | DATOS[LEN] | CRC_H | CRC_L
+//
+// Respuesta (placa -> PC):
+//   SYNC | VERSION | CMD | ESTADO | LEN_H | LEN_L | DATOS[LEN] | CRC_H | CRC_L
+//
+// El CRC es CRC-16/CCITT-FALSE (polinomio 0x1021, valor inicial 0xFFFF) de todos los
+// bytes entre SYNC y el propio CRC. La placa contesta a cada petición con una respuesta
+// del mismo CMD. Durante una captura envía además una respuesta CMD_START_CAPTURE por
+// cada ciclo, con los 11 bytes del registro como datos.
+//
+// La respuesta a CMD_GET_REGISTERS lleva LONGITUD_REGISTROS bytes: AF BC DE HL IX IY SP PC
+// AF' BC' DE' HL' (palabras en big endian), seguidos de I, R, IFF1, IFF2 e IM. La placa
+// real no puede leer IM: lo deduce de las instrucciones IM que sirve en ciclos M1 y de los
+// reset, y manda IM_DESCONOCIDO mientras no ha visto ninguno. MEMPTR (WZ) no va en la
+// respuesta: solo se conoce en el emulador.
+
+use std::fmt;
+
+use crate::cpu::bus::Memoria;
+use crate::cpu::cpu::CPU;
+use crate::cpu::registers::Registros;
+
+/// Byte de sincronismo que abre cada trama
+pub const SYNC: u8 = 0xA5;
+
+/// Versión del formato de trama
+pub const VERSION: u8 = 0x01;
+
+/// Máximo de bytes de datos en una trama
+pub const MAX_DATOS: usize = 260;
+
+/// Máximo de bytes de memoria Z80 por trama en lecturas y escrituras
+pub const BLOQUE_MEMORIA: usize = 256;
+
+// Códigos de estado de las respuestas
+pub const ACK_SUCCESS: u8 = 0xDD;
+pub const ACK_ERROR: u8 = 0x00;
+/// La petición llegó con el CRC mal: se puede repetir
+pub const NAK_CRC: u8 = 0x01;
+/// Longitud o rango de direcciones no válido
+pub const NAK_LONGITUD: u8 = 0x02;
+/// Comando desconocido o no implementado
+pub const NAK_COMANDO: u8 = 0x03;
+/// Versión de trama distinta de la del firmware
+pub const NAK_VERSION: u8 = 0x04;
+
+// Estados de la placa que devuelve CMD_GET_STATUS (SystemState en config.h)
+pub const ESTADO_IDLE: u8 = 0;
+pub const ESTADO_RUNNING: u8 = 1;
+pub const ESTADO_HALTED: u8 = 2;
+
+/// Bytes de datos de la respuesta a CMD_GET_REGISTERS
+pub const LONGITUD_REGISTROS: usize = 29;
+
+/// Valor del byte IM cuando la placa no conoce el modo de interrupción
+pub const IM_DESCONOCIDO: u8 = 0xFF;
+
+/// Respuesta decodificada de la placa
+#[derive(Debug, Clone, PartialEq)]
+pub struct Respuesta {
+    pub comando: u8,
+    pub estado: u8,
+    pub datos: Vec<u8>,
+}
+
+/// Petición decodificada del PC
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peticion {
+    pub comando: u8,
+    pub datos: Vec<u8>,
+}
+
+/// Error al decodificar una trama
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorTrama {
+    Version(u8),
+    Longitud(usize),
+    Crc { recibido: u16, calculado: u16 },
+}
+
+impl fmt::Display for ErrorTrama {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorTrama::Version(v) => write!(f, "versión de trama {} no soportada", v),
+            ErrorTrama::Longitud(l) => write!(f, "longitud de trama {} no válida", l),
+            ErrorTrama::Crc { recibido, calculado } => write!(
+                f,
+                "CRC incorrecto: recibido {:04X}, calculado {:04X}",
+                recibido, calculado
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ErrorTrama {}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(datos: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &b in datos {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Construye una trama de petición
+pub fn codificar_peticion(comando: u8, datos: &[u8]) -> Vec<u8> {
+    let mut trama = vec![SYNC, VERSION, comando];
+    trama.extend_from_slice(&(datos.len() as u16).to_be_bytes());
+    cerrar_trama(trama, datos)
+}
+
+/// Construye una trama de respuesta
+pub fn codificar_respuesta(comando: u8, estado: u8, datos: &[u8]) -> Vec<u8> {
+    let mut trama = vec![SYNC, VERSION, comando, estado];
+    trama.extend_from_slice(&(datos.len() as u16).to_be_bytes());
+    cerrar_trama(trama, datos)
+}
+
+fn cerrar_trama(mut trama: Vec<u8>, datos: &[u8]) -> Vec<u8> {
+    trama.extend_from_slice(datos);
+    let crc = crc16(&trama[1..]);
+    trama.extend_from_slice(&crc.to_be_bytes());
+    trama
+}
+
+/// Longitud de datos de una respuesta a partir de su cabecera (los 5 bytes tras SYNC)
+pub fn longitud_respuesta(cabecera: &[u8; 5]) -> Result<usize, ErrorTrama> {
+    if cabecera[0] != VERSION {
+        return Err(ErrorTrama::Version(cabecera[0]));
+    }
+    let longitud = (cabecera[3] as usize) << 8 | cabecera[4] as usize;
+    if longitud > MAX_DATOS {
+        return Err(ErrorTrama::Longitud(longitud));
+    }
+    Ok(longitud)
+}
+
+/// Decodifica una respuesta a partir de su cabecera y del resto de la trama (datos y CRC)
+pub fn decodificar_respuesta(cabecera: &[u8; 5], resto: &[u8]) -> Result<Respuesta, ErrorTrama> {
+    let longitud = longitud_respuesta(cabecera)?;
+    if resto.len() != longitud + 2 {
+        return Err(ErrorTrama::Longitud(resto.len()));
+    }
+    let mut cubierto = cabecera.to_vec();
+    cubierto.extend_from_slice(&resto[..longitud]);
+    let calculado = crc16(&cubierto);
+    let recibido = (resto[longitud] as u16) << 8 | resto[longitud + 1] as u16;
+    if recibido != calculado {
+        return Err(ErrorTrama::Crc {
+            recibido,
+            calculado,
+        });
+    }
+    Ok(Respuesta {
+        comando: cabecera[1],
+        estado: cabecera[2],
+        datos: resto[..longitud].to_vec(),
+    })
+}
+
+/// Registros del Z80 devueltos por CMD_GET_REGISTERS
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolcadoRegistros {
+    pub reg: Registros,
+    /// Registros alternativos (solo AF', BC', DE' y HL')
+    pub alt: Registros,
+    pub iff1: bool,
+    pub iff2: bool,
+    /// None si la placa no conoce el modo de interrupción
+    pub im: Option<u8>,
+    /// Registro interno MEMPTR. None en los volcados de la placa.
+    pub wz: Option<u16>,
+}
+
+impl VolcadoRegistros {
+    /// Toma los registros de la CPU emulada
+    pub fn desde_cpu<B: Memoria>(cpu: &CPU<B>) -> VolcadoRegistros {
+        VolcadoRegistros {
+            reg: cpu.reg.clone(),
+            alt: cpu.alt.clone(),
+            iff1: cpu.iff1(),
+            iff2: cpu.iff2(),
+            im: Some(cpu.im()),
+            wz: Some(cpu.memptr),
+        }
+    }
+
+    /// Datos de la respuesta a CMD_GET_REGISTERS
+    pub fn codificar(&self) -> Vec<u8> {
+        let palabras = [
+            self.reg.get_af(),
+            self.reg.get_bc(),
+            self.reg.get_de(),
+            self.reg.get_hl(),
+            self.reg.get_ix(),
+            self.reg.get_iy(),
+            self.reg.sp,
+            self.reg.pc,
+            self.alt.get_af(),
+            self.alt.get_bc(),
+            self.alt.get_de(),
+            self.alt.get_hl(),
+        ];
+        let mut datos: Vec<u8> = palabras.iter().flat_map(|p| p.to_be_bytes()).collect();
+        datos.extend_from_slice(&[
+            self.reg.i,
+            self.reg.r,
+            self.iff1 as u8,
+            self.iff2 as u8,
+            self.im.unwrap_or(IM_DESCONOCIDO),
+        ]);
+        datos
+    }
+
+    /// Decodifica los datos de la respuesta a CMD_GET_REGISTERS
+    pub fn decodificar(datos: &[u8]) -> Result<VolcadoRegistros, ErrorTrama> {
+        if datos.len() != LONGITUD_REGISTROS {
+            return Err(ErrorTrama::Longitud(datos.len()));
+        }
+        let palabra = |i: usize| (datos[2 * i] as u16) << 8 | datos[2 * i + 1] as u16;
+        let mut reg = Registros::new();
+        let mut alt = Registros::new();
+        reg.set_af(palabra(0));
+        reg.set_bc(palabra(1));
+        reg.set_de(palabra(2));
+        reg.set_hl(palabra(3));
+        reg.set_ix(palabra(4));
+        reg.set_iy(palabra(5));
+        reg.sp = palabra(6);
+        reg.pc = palabra(7);
+        alt.set_af(palabra(8));
+        alt.set_bc(palabra(9));
+        alt.set_de(palabra(10));
+        alt.set_hl(palabra(11));
+        reg.i = datos[24];
+        reg.r = datos[25];
+        Ok(VolcadoRegistros {
+            reg,
+            alt,
+            iff1: datos[26] != 0,
+            iff2: datos[27] != 0,
+            im: (datos[28] != IM_DESCONOCIDO).then_some(datos[28]),
+            wz: None,
+        })
+    }
+}
+
+impl fmt::Display for VolcadoRegistros {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = &self.reg;
+        let a = &self.alt;
+        let flags: String = "SZ5H3PNC"
+            .chars()
+            .enumerate()
+            .map(|(i, c)| if r.flags.to_byte() & (0x80 >> i) != 0 { c } else { '-' })
+            .collect();
+        writeln!(f, "AF  {:04X}   AF' {:04X}   IX {:04X}   PC {:04X}", r.get_af(), a.get_af(), r.get_ix(), r.pc)?;
+        writeln!(f, "BC  {:04X}   BC' {:04X}   IY {:04X}   SP {:04X}", r.get_bc(), a.get_bc(), r.get_iy(), r.sp)?;
+        let wz = self.wz.map_or(String::from("????"), |wz| format!("{:04X}", wz));
+        writeln!(f, "DE  {:04X}   DE' {:04X}   I  {:02X}     R  {:02X}   WZ {}", r.get_de(), a.get_de(), r.i, r.r, wz)?;
+        writeln!(f, "HL  {:04X}   HL' {:04X}   F  {}", r.get_hl(), a.get_hl(), flags)?;
+        let im = self.im.map_or(String::from("?"), |im| im.to_string());
+        write!(f, "IFF1 {}   IFF2 {}   IM {}", self.iff1 as u8, self.iff2 as u8, im)
+    }
+}
+
+/// Reconstruye las peticiones a partir de los bytes que van llegando, igual que hace el
+/// firmware: descarta lo que no empieza por SYNC y comprueba el CRC de cada trama.
+#[derive(Debug, Default)]
+pub struct Receptor {
+    buffer: Vec<u8>,
+}
+
+impl Receptor {
+    pub fn new() -> Receptor {
+        Receptor { buffer: Vec::new() }
+    }
+
+    /// Añade bytes recibidos
+    pub fn recibir(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Devuelve la siguiente petición completa, o None si aún no ha llegado entera.
+    /// Una trama incorrecta se consume y se devuelve su comando junto al error, para que
+    /// se pueda contestar con el NAK que corresponda.
+    pub fn siguiente(&mut self) -> Option<Result<Peticion, (u8, ErrorTrama)>> {
+        let inicio = self.buffer.iter().position(|&b| b == SYNC).unwrap_or(self.buffer.len());
+        self.buffer.drain(..inicio);
+        if self.buffer.len() < 5 {
+            return None;
+        }
+        let comando = self.buffer[2];
+        let longitud = (self.buffer[3] as usize) << 8 | self.buffer[4] as usize;
+        if longitud > MAX_DATOS {
+            // Se descarta solo el SYNC para volver a sincronizar con lo que venga después
+            self.buffer.remove(0);
+            return Some(Err((comando, ErrorTrama::Longitud(longitud))));
+        }
+        if self.buffer.len() < 5 + longitud + 2 {
+            return None;
+        }
+        let calculado = crc16(&self.buffer[1..5 + longitud]);
+        let recibido = (self.buffer[5 + longitud] as u16) << 8 | self.buffer[6 + longitud] as u16;
+        if recibido != calculado {
+            // Si se perdió un byte, la trama se ha comido el principio de la siguiente:
+            // igual que con la longitud, se descarta solo el SYNC
+            self.buffer.remove(0);
+            return Some(Err((comando, ErrorTrama::Crc { recibido, calculado })));
+        }
+        let trama: Vec<u8> = self.buffer.drain(..5 + longitud + 2).collect();
+        if trama[1] != VERSION {
+            return Some(Err((comando, ErrorTrama::Version(trama[1]))));
+        }
+        Some(Ok(Peticion {
+            comando,
+            datos: trama[5..5 + longitud].to_vec(),
+        }))
+    }
+}
+
+/// Código de estado con el que se contesta a una trama incorrecta
+pub fn nak(error: &ErrorTrama) -> u8 {
+    match error {
+        ErrorTrama::Version(_) => NAK_VERSION,
+        ErrorTrama::Longitud(_) => NAK_LONGITUD,
+        ErrorTrama::Crc { .. } => NAK_CRC,
+    }
+}
+
+/// Nombre de un código de estado para los mensajes de error
+pub fn nombre_estado(estado: u8) -> &'static str {
+    match estado {
+        ACK_SUCCESS => "ACK",
+        ACK_ERROR => "error",
+        NAK_CRC => "CRC incorrecto",
+        NAK_LONGITUD => "longitud o rango no válido",
+        NAK_COMANDO => "comando no soportado",
+        NAK_VERSION => "versión de protocolo distinta",
+        _ => "estado desconocido",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_ccitt_false() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn peticion() {
+        let trama = codificar_peticion(0x04, &[0x12, 0x34, 0xAA]);
+        assert_eq!(&trama[..6], &[SYNC, VERSION, 0x04, 0x00, 0x03, 0x12]);
+        assert_eq!(trama.len(), 5 + 3 + 2);
+        let crc = crc16(&trama[1..8]);
+        assert_eq!(&trama[8..], &crc.to_be_bytes());
+    }
+
+    #[test]
+    fn respuesta_ida_y_vuelta() {
+        let trama = codificar_respuesta(0x06, ACK_SUCCESS, &[1, 2, 3]);
+        assert_eq!(trama[0], SYNC);
+        let cabecera: [u8; 5] = trama[1..6].try_into().unwrap();
+        assert_eq!(longitud_respuesta(&cabecera), Ok(3));
+        assert_eq!(
+            decodificar_respuesta(&cabecera, &trama[6..]),
+            Ok(Respuesta {
+                comando: 0x06,
+                estado: ACK_SUCCESS,
+                datos: vec![1, 2, 3]
+            })
+        );
+    }
+
+    #[test]
+    fn receptor() {
+        let mut r = Receptor::new();
+        let mut bytes = vec![0x00, 0x13];
+        bytes.extend(codificar_peticion(0x01, &[0xAA]));
+        let mut mala = codificar_peticion(0x03, &[]);
+        mala[5] ^= 0xFF;
+        bytes.extend(mala);
+        bytes.extend(codificar_peticion(0x05, &[0x12, 0x34]));
+
+        // Los bytes llegan en trozos arbitrarios
+        r.recibir(&bytes[..6]);
+        assert_eq!(r.siguiente(), None);
+        r.recibir(&bytes[6..]);
+        assert_eq!(
+            r.siguiente(),
+            Some(Ok(Peticion {
+                comando: 0x01,
+                datos: vec![0xAA]
+            }))
+        );
+        assert!(matches!(r.siguiente(), Some(Err((0x03, ErrorTrama::Crc { .. })))));
+        assert_eq!(
+            r.siguiente(),
+            Some(Ok(Peticion {
+                comando: 0x05,
+                datos: vec![0x12, 0x34]
+            }))
+        );
+        assert_eq!(r.siguiente(), None);
+    }
+
+    #[test]
+    fn receptor_byte_perdido() {
+        // A la trama le falta un byte de datos y el reintento llega justo detrás
+        let mut r = Receptor::new();
+        let mut bytes = codificar_peticion(0x04, &[0x80, 0x00, 0x11, 0x22]);
+        bytes.remove(7);
+        bytes.extend(codificar_peticion(0x04, &[0x80, 0x00, 0x11, 0x22]));
+        r.recibir(&bytes);
+
+        assert!(matches!(r.siguiente(), Some(Err((0x04, ErrorTrama::Crc { .. })))));
+        let mut peticiones = Vec::new();
+        while let Some(p) = r.siguiente() {
+            peticiones.extend(p.ok());
+        }
+        assert_eq!(
+            peticiones,
+            vec![Peticion {
+                comando: 0x04,
+                datos: vec![0x80, 0x00, 0x11, 0x22]
+            }]
+        );
+    }
+
+    #[test]
+    fn registros_ida_y_vuelta() {
+        let mut c = CPU::new(0xFFFF);
+        c.reg.set_af(0x12D7);
+        c.reg.set_hl(0xBEEF);
+        c.reg.set_iy(0x4000);
+        c.reg.sp = 0xFFF0;
+        c.reg.pc = 0x0123;
+        c.reg.r = 0x85;
+        c.alt.set_de(0xCAFE);
+        c.memptr = 0x1235;
+        let volcado = VolcadoRegistros::desde_cpu(&c);
+        assert!(volcado.to_string().contains("R  85   WZ 1235"));
+        let datos = volcado.codificar();
+        assert_eq!(datos.len(), LONGITUD_REGISTROS);
+        assert_eq!(&datos[..2], &[0x12, 0xD7]);
+        // WZ no viaja en la trama
+        assert_eq!(VolcadoRegistros::decodificar(&datos), Ok(VolcadoRegistros { wz: None, ..volcado }));
+
+        let mut datos = datos;
+        datos[28] = IM_DESCONOCIDO;
+        let volcado = VolcadoRegistros::decodificar(&datos).unwrap();
+        assert_eq!(volcado.im, None);
+        let texto = volcado.to_string();
+        assert!(texto.contains("AF  12D7"));
+        assert!(texto.contains("DE' CAFE"));
+        assert!(texto.contains("F  SZ-H-PNC"));
+        assert!(texto.contains("WZ ????"));
+        assert!(texto.ends_with("IM ?"));
+        assert!(VolcadoRegistros::decodificar(&datos[1..]).is_err());
+    }
+
+    // Valor de una constante `const uint8_t` de config.h del firmware
+    fn constante_firmware(nombre: &str) -> u8 {
+        let config = std::fs::read_to_string("ARDUINO_GIGA_R1/include/config.h").unwrap();
+        let prefijo = format!("const uint8_t {} = ", nombre);
+        let linea = config.lines().find_map(|l| l.strip_prefix(&prefijo)).unwrap();
+        linea.split(';').next().unwrap().parse().unwrap()
+    }
+
+    // Modo de interrupción que deduce anotar_modo_interrupcion en el firmware a partir de
+    // los códigos servidos en ciclos M1
+    fn modo_interrupcion_firmware(codigos: &[u8]) -> u8 {
+        let mut modo = IM_DESCONOCIDO;
+        let mut anterior_ed = false;
+        for &codigo in codigos {
+            if anterior_ed {
+                modo = match codigo {
+                    0x46 | 0x4E | 0x66 | 0x6E => 0,
+                    0x56 | 0x76 => 1,
+                    0x5E | 0x7E => 2,
+                    _ => modo,
+                };
+            }
+            anterior_ed = codigo == 0xED;
+        }
+        modo
+    }
+
+    #[test]
+    fn rutina_de_volcado_del_firmware() {
+        use crate::cpu::bus::Bus;
+        use crate::cpu::traza::{BusTraza, TipoAcceso};
+
+        // La rutina que inyecta el firmware debe dejar los registros en su buffer y volver
+        // a la instrucción interrumpida con todo intacto
+        let fuente = std::fs::read_to_string("ARDUINO_GIGA_R1/z80/volcado_registros.asm").unwrap();
+        let rutina = crate::cpu::asm::ensamblar(&fuente).unwrap();
+        assert_eq!(rutina.origen, 0xFF00);
+
+        let mut c = CPU::con_bus(BusTraza::new(Bus::new(0xFFFF)));
+        c.bus.cargar(rutina.origen, &rutina.binario);
+        c.reg.set_af(0x1234);
+        c.reg.set_bc(0x5678);
+        c.reg.set_de(0x9ABC);
+        c.reg.set_hl(0xDEF0);
+        c.reg.set_ix(0x1111);
+        c.reg.set_iy(0x2222);
+        c.reg.i = 0x3F;
+        c.alt.set_af(0x3344);
+        c.alt.set_bc(0x5566);
+        c.alt.set_de(0x7788);
+        c.alt.set_hl(0x99AA);
+        c.reg.sp = 0xE000;
+        // R con el bit 7 a uno y a punto de dar la vuelta en los 7 bits bajos
+        c.reg.r = 0xF0;
+        // IM 2 antes de la instrucción interrumpida
+        c.bus.cargar(0x01FE, &[0xED, 0x5E]);
+        c.reg.pc = 0x01FE;
+        c.execute();
+        let antes = c.reg.clone();
+        let alternativos = c.alt.clone();
+
+        // CALL inyectado en la búsqueda del código de la instrucción en 0x0200
+        c.bus.cargar(0x0200, &[0xCD, 0x00, 0xFF]);
+        c.execute();
+        while c.reg.pc != 0x0200 {
+            c.execute();
+        }
+
+        let palabra = |d: u16| c.bus.read_word(0xFF80 + d);
+        assert_eq!(palabra(0x00), 0x1234);
+        assert_eq!(palabra(0x02), 0x5678);
+        assert_eq!(palabra(0x04), 0x9ABC);
+        assert_eq!(palabra(0x06), 0xDEF0);
+        assert_eq!(palabra(0x08), 0x1111);
+        assert_eq!(palabra(0x0A), 0x2222);
+        assert_eq!(palabra(0x0C).wrapping_add(2), 0xE000);
+        assert_eq!(palabra(0x0E).wrapping_sub(3), 0x0200);
+        assert_eq!(palabra(0x10), 0x3344);
+        assert_eq!(palabra(0x12), 0x5566);
+        assert_eq!(palabra(0x14), 0x7788);
+        assert_eq!(palabra(0x16), 0x99AA);
+        assert_eq!(c.bus.leer_byte(0xFF98), 0x3F);
+        // R es el único registro que cambia
+        assert_eq!(c.reg, Registros { r: c.reg.r, ..antes });
+        assert_eq!(c.alt, alternativos);
+
+        // enviar_volcado corrige R con los ciclos M1 de la rutina hasta LD A,R
+        let r = c.bus.leer_byte(0xFF99);
+        let correccion = constante_firmware("CORRECCION_R");
+        assert_eq!((r & 0x80) | (r.wrapping_sub(correccion) & 0x7F), antes.r);
+
+        // e informa del último IM servido en un M1
+        let codigos: Vec<u8> = c
+            .bus
+            .tomar_accesos()
+            .iter()
+            .filter(|a| a.tipo == TipoAcceso::Fetch)
+            .map(|a| a.dato)
+            .collect();
+        assert_eq!(modo_interrupcion_firmware(&codigos), 2);
+        assert_eq!(modo_interrupcion_firmware(&codigos), c.im());
+    }
+
+    // Estados de `servir_lectura` en el firmware
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum EstadoVolcado {
+        Inactivo,
+        EsperaM1,
+        OperandoL,
+        OperandoH,
+        EnRutina,
+        Restaurar,
+    }
+
+    // Memoria de la placa real: sirve las lecturas del Z80 como `servir_lectura` y sigue
+    // los M1 como `anotar_m1`. `pedir_tras` simula un CMD_GET_REGISTERS que llega justo
+    // después de servir el M1 de esa dirección; `parado` es el pin /HALT.
+    struct PlacaConVolcado {
+        memoria: crate::cpu::bus::Bus,
+        rutina: crate::cpu::asm::Programa,
+        estado: EstadoVolcado,
+        m1_anterior_prefijo: bool,
+        parado: bool,
+        zona_guardada: Vec<u8>,
+        pedir_tras: Option<u16>,
+        // Buffer de la rutina al buscar su RET final, lo que envía `enviar_volcado`
+        volcado: Option<Vec<u8>>,
+    }
+
+    const TAMANO_ZONA_VOLCADO: u16 = 0x9B;
+    const BUFFER_VOLCADO: u16 = 0xFF80;
+
+    impl PlacaConVolcado {
+        fn iniciar_volcado(&mut self) {
+            let zona = self.rutina.origen;
+            self.zona_guardada = (0..TAMANO_ZONA_VOLCADO).map(|i| self.memoria.leer_byte(zona + i)).collect();
+            self.memoria.cargar(zona, &self.rutina.binario);
+            self.estado = EstadoVolcado::EsperaM1;
+        }
+
+        fn servir(&mut self, dir: u16, m1: bool) -> u8 {
+            use EstadoVolcado::*;
+            let zona = self.rutina.origen;
+            let fin = zona + self.rutina.binario.len() as u16 - 1;
+            let en_zona = (zona..zona + TAMANO_ZONA_VOLCADO).contains(&dir);
+            let inyectado = match self.estado {
+                EsperaM1 if m1 && !en_zona && !self.m1_anterior_prefijo && !self.parado => {
+                    self.estado = OperandoL;
+                    Some(0xCD)
+                }
+                OperandoL => {
+                    self.estado = OperandoH;
+                    Some(zona as u8)
+                }
+                OperandoH => {
+                    self.estado = EnRutina;
+                    Some((zona >> 8) as u8)
+                }
+                EnRutina if m1 && dir == fin => {
+                    let buffer = (0..0x1B).map(|i| self.memoria.leer_byte(BUFFER_VOLCADO + i)).collect();
+                    self.volcado = Some(buffer);
+                    self.estado = Restaurar;
+                    None
+                }
+                Restaurar if m1 && !en_zona => {
+                    let guardada = std::mem::take(&mut self.zona_guardada);
+                    self.memoria.cargar(zona, &guardada);
+                    self.estado = Inactivo;
+                    None
+                }
+                _ => None,
+            };
+            let dato = inyectado.unwrap_or_else(|| self.memoria.leer_byte(dir));
+            if m1 {
+                self.m1_anterior_prefijo = matches!(dato, 0xCB | 0xDD | 0xED | 0xFD);
+                if self.pedir_tras == Some(dir) && self.estado == Inactivo {
+                    self.pedir_tras = None;
+                    self.iniciar_volcado();
+                }
+            }
+            dato
+        }
+    }
+
+    impl Memoria for PlacaConVolcado {
+        fn leer_byte(&self, direccion: u16) -> u8 {
+            self.memoria.leer_byte(direccion)
+        }
+
+        fn escribir_byte(&mut self, direccion: u16, data: u8) {
+            self.memoria.escribir_byte(direccion, data);
+        }
+
+        fn leer(&mut self, direccion: u16) -> u8 {
+            self.servir(direccion, false)
+        }
+
+        fn fetch(&mut self, direccion: u16) -> u8 {
+            self.servir(direccion, true)
+        }
+    }
+
+    #[test]
+    fn volcado_pedido_a_mitad_de_instruccion() {
+        use crate::cpu::bus::Bus;
+
+        let fuente = std::fs::read_to_string("ARDUINO_GIGA_R1/z80/volcado_registros.asm").unwrap();
+        let rutina = crate::cpu::asm::ensamblar(&fuente).unwrap();
+        // LD SP,0xE000 ; LD IX,0x3000 ; LD HL,0x1000 ; LD DE,0x2000 ; LD BC,4 ; LDIR ;
+        // LD (IX+1),0x77 ; HALT
+        let programa = [
+            0x31, 0x00, 0xE0, 0xDD, 0x21, 0x00, 0x30, 0x21, 0x00, 0x10, 0x11, 0x00, 0x20, 0x01, 0x04,
+            0x00, 0xED, 0xB0, 0xDD, 0x36, 0x01, 0x77, 0x76,
+        ];
+        // Petición tras el M1 de un prefijo, instrucción en la que se inyecta el CALL y BC
+        // en ese momento. El M1 que sigue al prefijo es de la misma instrucción.
+        for (prefijo, inyectado, bc) in [(0x0003, 0x0007, 0x0000), (0x0010, 0x0010, 0x0003), (0x0012, 0x0016, 0x0000)] {
+            let placa = PlacaConVolcado {
+                memoria: Bus::new(0xFFFF),
+                rutina: rutina.clone(),
+                estado: EstadoVolcado::Inactivo,
+                m1_anterior_prefijo: false,
+                parado: false,
+                zona_guardada: Vec::new(),
+                pedir_tras: Some(prefijo),
+                volcado: None,
+            };
+            let mut c = CPU::con_bus(placa);
+            c.bus.cargar(0x0000, &programa);
+            c.bus.cargar(0x1000, &[1, 2, 3, 4]);
+            for _ in 0..100 {
+                if c.halt() {
+                    break;
+                }
+                c.execute();
+            }
+            assert!(c.halt(), "petición tras 0x{:04X}", prefijo);
+
+            // El programa no nota el volcado
+            assert_eq!((0..4).map(|i| c.bus.leer_byte(0x2000 + i)).collect::<Vec<u8>>(), [1, 2, 3, 4]);
+            assert_eq!(c.bus.leer_byte(0x3001), 0x77);
+            assert_eq!((c.reg.pc, c.reg.sp), (0x0016, 0xE000));
+
+            let volcado = c.bus.volcado.take().unwrap();
+            let palabra = |d: usize| u16::from_le_bytes([volcado[d], volcado[d + 1]]);
+            assert_eq!(palabra(0x0E).wrapping_sub(3), inyectado);
+            assert_eq!(palabra(0x02), bc);
+            assert_eq!(palabra(0x0C).wrapping_add(2), 0xE000);
+
+            // y la zona queda como estaba
+            assert_eq!(c.bus.estado, EstadoVolcado::Inactivo);
+            assert!((0..TAMANO_ZONA_VOLCADO).all(|i| c.bus.leer_byte(rutina.origen + i) == 0));
+
+            // Parado en HALT no se inyecta nada: el Z80 no ejecutaría el CALL y el volcado
+            // queda a la espera hasta que lo abandone comprobar_volcado
+            c.bus.parado = c.halt();
+            c.bus.iniciar_volcado();
+            // `execute` no hace los M1 de HALT; en la placa llegan con la dirección siguiente
+            for _ in 0..10 {
+                assert_eq!(c.bus.fetch(0x0017), 0x00);
+            }
+            assert_eq!(c.bus.estado, EstadoVolcado::EsperaM1);
+        }
+    }
+
+    #[test]
+    fn respuesta_corrupta() {
+        let mut trama = codificar_respuesta(0x06, ACK_SUCCESS, &[1, 2, 3]);
+        trama[7] ^= 0x10;
+        let cabecera: [u8; 5] = trama[1..6].try_into().unwrap();
+        assert!(matches!(
+            decodificar_respuesta(&cabecera, &trama[6..]),
+            Err(ErrorTrama::Crc { .. })
+        ));
+        assert_eq!(
+            longitud_respuesta(&[0x02, 0x06, ACK_SUCCESS, 0, 0]),
+            Err(ErrorTrama::Version(0x02))
+        );
+        assert_eq!(
+            longitud_respuesta(&[VERSION, 0x06, ACK_SUCCESS, 0xFF, 0xFF]),
+            Err(ErrorTrama::Longitud(0xFFFF))
+        );
+    }
+}
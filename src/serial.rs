@@ -1,12 +1,16 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::{io, thread};
-use std::io::{Read, Write};
+use std::io::{BufWriter, Read, Write};
 use std::io::ErrorKind::TimedOut;
 use std::path::Path;
-use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
-use serialport::SerialPort;
 use termion::async_stdin;
+use crate::captura::{Cabecera, DestinoCaptura, EscritorCaptura, RegistroCiclo, LONGITUD_REGISTRO};
+use crate::vcd::EscritorVcd;
+use crate::cpu::asm::ensamblar;
+use crate::cpu::hex::leer_hex;
+use crate::protocolo::{self, Respuesta, VolcadoRegistros};
+use crate::transporte::Transporte;
 
 // Estados del sistema
 enum SystemState {
@@ -15,9 +19,6 @@ enum SystemState {
     StateHalted,
 }
 
-const ACK_SUCCESS: u8 = 0xDD;
-const ACK_ERROR: u8 = 0x00;
-
 const CMD_ECHO: u8 = 0x01;
 const CMD_SEND_BYTE: u8 = 0x02;
 const CMD_GET_STATUS: u8 = 0x03;
@@ -37,6 +38,15 @@ const SERIAL_PORT_NAME: &str = "/dev/ttyUSB0";
 const BAUD_RATE: u32 = 115_200;
 const SERIAL_TIMEOUT: Duration = Duration::from_millis(500);
 
+/// Fichero de captura de la opción del menú y del subcomando capture sin -o
+pub const CAPTURA_POR_DEFECTO: &str = "z80_captura.cap";
+
+/// Tiempo máximo de espera de la respuesta a una petición
+const TIMEOUT_RESPUESTA: Duration = Duration::from_secs(2);
+
+/// Veces que se repite una petición sin respuesta válida
+const REINTENTOS: usize = 3;
+
 pub struct Serial {}
 
 impl Serial {
@@ -49,7 +59,7 @@ impl Serial {
     /// donde se escribirá el programa.
     ///
     /// # Parámetros
-    /// - `port`: Referencia mutable al puerto serie
+    /// - `port`: Transporte hacia la placa
     /// - `bin_file_path`: Ruta al archivo binario a enviar
     ///
     /// # Retorno
@@ -59,10 +69,8 @@ impl Serial {
     ///
     /// # Notas
     /// - La dirección de inicio se introduce en formato hexadecimal
-    /// - Se envía primero un comando con la dirección y longitud
-    /// - Luego se envían los datos del programa
-    /// - Se espera un breve tiempo después de enviar los datos
-    fn enviar_bin(&mut self, port: &mut Box<dyn SerialPort>, bin_file_path: &str) -> io::Result<()> {
+    /// - La escritura real la hace `escribir_memoria_z80`
+    fn enviar_bin(&mut self, port: &mut dyn Transporte, bin_file_path: &str) -> io::Result<()> {
         // Abrir el archivo binario
         let path = Path::new(bin_file_path);
         let mut file = match File::open(path) {
@@ -88,33 +96,90 @@ impl Serial {
                 io::stdin().read_line(&mut start_addr_str)?;
                 let start_address = u16::from_str_radix(start_addr_str.trim(), 16)
                     .unwrap_or(0x0000);
-                let length_to_write: u16 = program_data.len() as u16;
-
-                // Convertir la dirección a número hexadecimal
-                let start_address_bytes = start_address.to_be_bytes();
-                let length_to_write_bytes = length_to_write.to_be_bytes();
-
-                // Preparar los datos del comando
-                let mut data_to_send: Vec<u8> = Vec::new();
-                data_to_send.extend_from_slice(&start_address_bytes);
-                data_to_send.extend_from_slice(&length_to_write_bytes);
-
-                // Enviar el comando de escritura de memoria
-                self.enviar_comando(port, crate::CMD_WRITE_Z80_MEMORY, &data_to_send)?;
+                self.escribir_memoria_z80(port, start_address, &program_data)?;
+            }
+            Err(e) => eprintln!("Error al leer el fichero binario: {}", e),
+        }
+        Ok(())
+    }
 
-                // Enviar los datos del programa
-                println!("Enviando datos del programa ({} bytes)...", length_to_write);
-                port.write_all(&program_data)?;
-                port.flush()?;
+    /// Envía un fichero Intel HEX a la memoria del Z80.
+    ///
+    /// Cada registro lleva su propia dirección, así que no se pide la dirección de inicio.
+    /// Los registros contiguos se agrupan y se envía un CMD_WRITE_Z80_MEMORY por segmento.
+    ///
+    /// # Parámetros
+    /// - `port`: Transporte hacia la placa
+    /// - `hex_file_path`: Ruta al fichero .hex a enviar
+    ///
+    /// # Retorno
+    /// - `io::Result<()>`:
+    ///   - `Ok(())` si la operación fue exitosa
+    ///   - `Err(io::Error)` si ocurrió un error durante la operación
+    ///
+    /// # Notas
+    /// - Si el fichero no se puede leer o tiene un checksum incorrecto no se envía nada
+    ///   y se devuelve el error
+    fn enviar_hex(&mut self, port: &mut dyn Transporte, hex_file_path: &str) -> io::Result<()> {
+        let texto = fs::read_to_string(hex_file_path)?;
+        let segmentos = leer_hex(&texto)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        println!("Leídos {} segmentos del fichero HEX '{}'.", segmentos.len(), hex_file_path);
+        for segmento in &segmentos {
+            self.escribir_memoria_z80(port, segmento.direccion, &segmento.datos)?;
+        }
+        Ok(())
+    }
 
-                // Mostrar mensaje de confirmación
-                println!("Datos del programa enviados.");
+    /// Envía un fichero a la memoria del Z80 sin preguntar nada.
+    ///
+    /// # Parámetros
+    /// - `port`: Transporte hacia la placa
+    /// - `file_path`: Ruta al fichero .bin o .hex
+    /// - `direccion`: Dirección de carga de los .bin (los .hex llevan la suya)
+    ///
+    /// # Retorno
+    /// - `io::Result<()>`:
+    ///   - `Ok(())` si la operación fue exitosa
+    ///   - `Err(io::Error)` si no se pudo leer el fichero o falló el envío
+    pub fn subir_fichero(&mut self, port: &mut dyn Transporte, file_path: &str, direccion: u16) -> io::Result<()> {
+        if file_path.ends_with(".hex") {
+            return self.enviar_hex(port, file_path);
+        }
+        let program_data = fs::read(file_path)?;
+        println!("Leídos {} bytes del fichero binario '{}'.", program_data.len(), file_path);
+        self.escribir_memoria_z80(port, direccion, &program_data)
+    }
 
-                // Esperar un breve tiempo para asegurar que los datos se procesen
-                thread::sleep(Duration::from_millis(100));
-            }
-            Err(e) => eprintln!("Error al leer el fichero binario: {}", e),
+    /// Escribe un bloque de datos en la memoria del Z80 a partir de `direccion`.
+    ///
+    /// # Parámetros
+    /// - `port`: Transporte hacia la placa
+    /// - `direccion`: Dirección de inicio en la memoria del Z80
+    /// - `datos`: Bytes a escribir
+    ///
+    /// # Retorno
+    /// - `io::Result<()>`:
+    ///   - `Ok(())` si la operación fue exitosa
+    ///   - `Err(io::Error)` si ocurrió un error durante la escritura
+    ///
+    /// # Notas
+    /// - Los datos se envían en tramas de hasta `protocolo::BLOQUE_MEMORIA` bytes, cada una
+    ///   con la dirección de sus datos delante, y se espera el ACK de cada una
+    pub fn escribir_memoria_z80(&mut self, port: &mut dyn Transporte, direccion: u16, datos: &[u8]) -> io::Result<()> {
+        if direccion as usize + datos.len() > 0x10000 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "{} bytes en 0x{:04X} se salen de la memoria del Z80", datos.len(), direccion)));
+        }
+        println!("Enviando datos del programa ({} bytes en 0x{:04X})...", datos.len(), direccion);
+        for (i, bloque) in datos.chunks(protocolo::BLOQUE_MEMORIA).enumerate() {
+            let dir = direccion.wrapping_add((i * protocolo::BLOQUE_MEMORIA) as u16);
+            let mut trama = dir.to_be_bytes().to_vec();
+            trama.extend_from_slice(bloque);
+            self.transaccion(port, crate::CMD_WRITE_Z80_MEMORY, &trama, TIMEOUT_RESPUESTA)?;
         }
+        println!("Datos del programa enviados.");
         Ok(())
     }
 
@@ -122,10 +187,11 @@ impl Serial {
     ///
     /// Esta función permite ensamblar un archivo de código fuente Z80 y,
     /// opcionalmente, cargar el binario resultante en la memoria del emulador.
-    /// Utiliza el ensamblador z80asm para generar el archivo binario.
+    /// Utiliza el ensamblador integrado (cpu::asm) para generar el archivo binario
+    /// y muestra la tabla de símbolos.
     ///
     /// # Parámetros
-    /// - `port`: Referencia mutable al puerto serie
+    /// - `port`: Transporte hacia la placa
     ///
     /// # Retorno
     /// - `io::Result<()>`:
@@ -136,7 +202,7 @@ impl Serial {
     /// - El archivo fuente debe estar en el directorio "PROGRAMAS"
     /// - Se genera un archivo .bin con el mismo nombre base
     /// - Se puede elegir cargar automáticamente el binario en la memoria
-    pub fn ensamblar_z80(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn ensamblar_z80(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         // Mostrar mensaje inicial
         println!("Ensamblar código Z80...");
 
@@ -156,50 +222,52 @@ impl Serial {
         // Mostrar mensaje de inicio del ensamblado
         println!("Ensamblando '{}'...", asm_file_path);
 
-        // Ejecutar el ensamblador z80asm
-        let output = Command::new("z80asm")
-            .arg(&asm_file_path)     // Archivo fuente
-            .arg("-o")               // Opción para especificar el archivo de salida
-            .arg(&bin_file_path)     // Archivo binario de salida
-            .stdout(Stdio::piped())  // Capturar la salida estándar
-            .stderr(Stdio::piped())  // Capturar la salida de error
-            .spawn()?;                             // Iniciar el proceso
-
-        // Esperar a que termine el ensamblador y obtener el resultado
-        let output_result = output.wait_with_output()?;
-
-        // Verificar si el ensamblado fue exitoso
-        if output_result.status.success() {
-            println!("Ensamblado exitoso. Fichero binario guardado en '{}'.", bin_file_path);
-
-            // Preguntar si se desea cargar el binario en la memoria
-            println!("¿Escribir automáticamente el fichero ensamblado a la memoria Z80? (s/n)");
-            io::stdout().flush()?;
-            let mut respuesta = String::new();
-            io::stdin().read_line(&mut respuesta)?;
-
-            // Si la respuesta es 's', cargar el binario en la memoria
-            if respuesta.trim().to_lowercase() == "s" {
-                self.enviar_bin(port, &bin_file_path)?;
+        // Ensamblar con el ensamblador integrado
+        let fuente = match fs::read_to_string(&asm_file_path) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Error al abrir el fichero fuente '{}': {}", asm_file_path, e);
+                return Ok(());
+            }
+        };
+
+        match ensamblar(&fuente) {
+            Ok(programa) => {
+                fs::write(&bin_file_path, &programa.binario)?;
+                println!("Ensamblado exitoso. {} bytes desde 0x{:04X} guardados en '{}'.",
+                         programa.binario.len(), programa.origen, bin_file_path);
+
+                // Mostrar la tabla de símbolos
+                for (simbolo, valor) in &programa.simbolos {
+                    println!("  {:<16} 0x{:04X}", simbolo, valor);
+                }
+
+                // Preguntar si se desea cargar el binario en la memoria
+                println!("¿Escribir automáticamente el fichero ensamblado a la memoria Z80? (s/n)");
+                io::stdout().flush()?;
+                let mut respuesta = String::new();
+                io::stdin().read_line(&mut respuesta)?;
+
+                // Si la respuesta es 's', cargar el binario en la memoria
+                if respuesta.trim().to_lowercase() == "s" {
+                    self.enviar_bin(port, &bin_file_path)?;
+                }
+            }
+            Err(e) => {
+                // Si hubo error, mostrar la línea y el motivo
+                eprintln!("Error al ensamblar '{}': {}", asm_file_path, e);
             }
-        } else {
-            // Si hubo error, mostrar el mensaje de error del ensamblador
-            eprintln!("Error al ensamblar '{}':", asm_file_path);
-            eprintln!("{}", String::from_utf8_lossy(&output_result.stderr));
         }
 
         Ok(())
     }
 
-    /// Envía un comando y sus datos asociados al puerto serie.
-    ///
-    /// Esta función envía un comando de un byte seguido de hasta 4 bytes de datos
-    /// al puerto serie. El comando y los datos se envían en un solo buffer.
+    /// Envía una trama de petición con un comando y sus datos.
     ///
     /// # Parámetros
-    /// - `port`: Referencia mutable al puerto serie
+    /// - `port`: Transporte hacia la placa
     /// - `comando`: Byte que representa el comando a enviar
-    /// - `datos`: Slice de bytes con los datos a enviar (máximo 4 bytes)
+    /// - `datos`: Datos de la trama (como mucho `protocolo::MAX_DATOS` bytes)
     ///
     /// # Retorno
     /// - `io::Result<()>`:
@@ -207,25 +275,89 @@ impl Serial {
     ///   - `Err(io::Error)` si ocurrió un error durante la escritura
     ///
     /// # Notas
-    /// - Si hay más de 4 bytes en datos, solo se envían los primeros 4
-    /// - Los bytes restantes se rellenan con 0
-    /// - Se muestra un mensaje con el comando y los datos enviados en hexadecimal
-    pub fn enviar_comando(&mut self, port: &mut Box<dyn SerialPort>, comando: u8, datos: &[u8]) -> io::Result<()> {
-        let mut buffer = Vec::new();
-        buffer.push(comando);
-        let mut data_buffer = [0u8; 4];
-        for (i, &byte) in datos.iter().enumerate().take(4) {
-            data_buffer[i] = byte;
+    /// - El formato de la trama está descrito en `protocolo.rs`
+    /// - Solo envía: para esperar la respuesta se usa `transaccion`
+    pub fn enviar_comando(&mut self, port: &mut dyn Transporte, comando: u8, datos: &[u8]) -> io::Result<()> {
+        if datos.len() > protocolo::MAX_DATOS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "demasiados datos para una trama: {} bytes", datos.len())));
         }
-        //dbg_hex!(&data_buffer);
-        buffer.extend_from_slice(&data_buffer);
-        port.write_all(&buffer)?;
+        port.write_all(&protocolo::codificar_peticion(comando, datos))?;
         port.flush()?;
-        let data_hex: Vec<String> = data_buffer.iter().map(|b| format!("{:02X}", b)).collect();
-        println!("Enviado comando: 0x{:02X} con datos: [{}]", comando, data_hex.join(", "));
         Ok(())
     }
 
+    /// Lee la siguiente trama de respuesta completa, descartando lo que llegue antes del
+    /// byte de sincronismo.
+    ///
+    /// # Errores
+    /// - `TimedOut`: Si no llega una trama completa en `timeout`
+    /// - `InvalidData`: Si la trama tiene una versión, una longitud o un CRC incorrectos
+    pub fn leer_respuesta(&mut self, port: &mut dyn Transporte, timeout: Duration) -> io::Result<Respuesta> {
+        let limite = Instant::now() + timeout;
+        let restante = || limite.saturating_duration_since(Instant::now());
+
+        let mut byte = [0u8; 1];
+        loop {
+            self.leer_bytes_con_timeout(port, &mut byte, restante())?;
+            if byte[0] == protocolo::SYNC {
+                break;
+            }
+        }
+        let mut cabecera = [0u8; 5];
+        self.leer_bytes_con_timeout(port, &mut cabecera, restante())?;
+        let longitud = protocolo::longitud_respuesta(&cabecera)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut resto = vec![0u8; longitud + 2];
+        self.leer_bytes_con_timeout(port, &mut resto, restante())?;
+        protocolo::decodificar_respuesta(&cabecera, &resto)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Envía un comando y espera su respuesta. Devuelve los datos de la respuesta.
+    ///
+    /// Si la respuesta no llega a tiempo, llega con el CRC mal o la placa contesta NAK_CRC,
+    /// se repite la petición hasta `REINTENTOS` veces. Las respuestas de otro comando
+    /// (por ejemplo registros de una captura que aún llegan) se descartan.
+    ///
+    /// # Errores
+    /// - `TimedOut` / `InvalidData`: Si tras los reintentos no hay una respuesta válida
+    /// - `Other`: Si la placa contesta con un estado de error
+    pub fn transaccion(&mut self, port: &mut dyn Transporte, comando: u8, datos: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+        let mut ultimo_error = io::Error::new(TimedOut, "sin respuesta");
+        for intento in 0..=REINTENTOS {
+            if intento > 0 {
+                eprintln!("Reintentando comando 0x{:02X} ({}/{}): {}", comando, intento, REINTENTOS, ultimo_error);
+                // Lo que quede en el buffer pertenece a la petición fallida
+                let _ = port.limpiar_entrada();
+            }
+            self.enviar_comando(port, comando, datos)?;
+            let limite = Instant::now() + timeout;
+            let respuesta = loop {
+                match self.leer_respuesta(port, limite.saturating_duration_since(Instant::now())) {
+                    Ok(r) if r.comando == comando => break Ok(r),
+                    Ok(_) => continue,
+                    Err(e) => break Err(e),
+                }
+            };
+            match respuesta {
+                Ok(r) if r.estado == protocolo::ACK_SUCCESS => return Ok(r.datos),
+                Ok(r) if r.estado == protocolo::NAK_CRC => {
+                    ultimo_error = io::Error::new(io::ErrorKind::InvalidData, "la placa recibió la petición con el CRC mal");
+                }
+                Ok(r) => {
+                    return Err(io::Error::other(format!(
+                        "la placa rechazó el comando 0x{:02X}: {} (0x{:02X})",
+                        comando, protocolo::nombre_estado(r.estado), r.estado)));
+                }
+                Err(e) if matches!(e.kind(), TimedOut | io::ErrorKind::InvalidData) => ultimo_error = e,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(ultimo_error.kind(), format!(
+            "comando 0x{:02X} sin respuesta válida tras {} reintentos: {}", comando, REINTENTOS, ultimo_error)))
+    }
+
     /// Lee bytes del puerto serie con un tiempo máximo de espera.
     ///
     /// Esta función intenta leer una cantidad específica de bytes del puerto serie
@@ -233,7 +365,7 @@ impl Serial {
     /// en el tiempo especificado, retorna un error de timeout.
     ///
     /// # Parámetros
-    /// - `port`: Referencia mutable al puerto serie
+    /// - `port`: Transporte hacia la placa
     /// - `buffer`: Buffer donde se almacenarán los bytes leídos
     /// - `timeout`: Duración máxima de espera para la lectura
     ///
@@ -246,95 +378,101 @@ impl Serial {
     /// - `UnexpectedEof`: Si la conexión se cierra antes de leer todos los bytes
     /// - `TimedOut`: Si no se pueden leer todos los bytes en el tiempo especificado
     /// - Otros errores de I/O que puedan ocurrir durante la lectura
-    pub fn leer_bytes_con_timeout(&mut self, port: &mut Box<dyn SerialPort>, buffer: &mut [u8], timeout: Duration) -> io::Result<()> {
+    pub fn leer_bytes_con_timeout(&mut self, port: &mut dyn Transporte, buffer: &mut [u8], timeout: Duration) -> io::Result<()> {
         // Registrar el momento de inicio para controlar el timeout
         let start = Instant::now();
         let buffer_len = buffer.len();
         let mut bytes_read = 0;
-
-        // Intentar leer bytes hasta completar el buffer o alcanzar el timeout
-        while bytes_read < buffer_len && start.elapsed() < timeout {
-            match port.read(&mut buffer[bytes_read..]) {
-                Ok(0) => { // Si se leen 0 bytes
-                    return if bytes_read == buffer_len {
-                        Ok(()) // Si ya teníamos todos los bytes, retornar éxito
-                    } else {
-                        // Si faltaban bytes, retornar error de conexión cerrada
-                        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Conexión serie cerrada prematuramente"))
-                    };
-                }
-                // Si se leyeron bytes correctamente
-                Ok(n) => {
-                    bytes_read += n;
-                }
-                // Si el puerto está bloqueado temporalmente
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                    // Esperar un poco y volver a intentar
-                    thread::sleep(Duration::from_millis(5));
-                    continue;
+        // Cada lectura del transporte se limita a lo que queda de timeout
+        let timeout_original = port.timeout();
+
+        let resultado = (|| {
+            // Intentar leer bytes hasta completar el buffer o alcanzar el timeout
+            while bytes_read < buffer_len && start.elapsed() < timeout {
+                port.set_timeout(timeout.saturating_sub(start.elapsed()).min(timeout_original))?;
+                match port.read(&mut buffer[bytes_read..]) {
+                    Ok(0) => { // Si se leen 0 bytes
+                        return if bytes_read == buffer_len {
+                            Ok(()) // Si ya teníamos todos los bytes, retornar éxito
+                        } else {
+                            // Si faltaban bytes, retornar error de conexión cerrada
+                            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Conexión serie cerrada prematuramente"))
+                        };
+                    }
+                    // Si se leyeron bytes correctamente
+                    Ok(n) => {
+                        bytes_read += n;
+                    }
+                    // Si el puerto está bloqueado temporalmente
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        // Esperar un poco y volver a intentar
+                        thread::sleep(Duration::from_millis(5));
+                        continue;
+                    }
+                    // Para cualquier otro error, retornarlo
+                    Err(e) => return Err(e),
                 }
-                // Para cualquier otro error, retornarlo
-                Err(e) => return Err(e),
             }
-        }
 
-        // Verificar si se leyeron todos los bytes
-        if bytes_read == buffer_len {
-            Ok(())
-        } else {
-            // Si no se leyeron todos los bytes, retornar error de timeout
-            Err(io::Error::new(TimedOut, "Timeout al leer la respuesta completa por serie"))
-        }
+            // Verificar si se leyeron todos los bytes
+            if bytes_read == buffer_len {
+                Ok(())
+            } else {
+                // Si no se leyeron todos los bytes, retornar error de timeout
+                Err(io::Error::new(TimedOut, "Timeout al leer la respuesta completa por serie"))
+            }
+        })();
+
+        port.set_timeout(timeout_original)?;
+        resultado
     }
 
-    pub fn quitar_wait(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn quitar_wait(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Quitando WAIT...");
-        self.enviar_comando(port, crate::CMD_QUITAR_WAIT, &[0x00, 0x00, 0x00, 0x00])?;
-        thread::sleep(Duration::from_millis(50));
+        self.transaccion(port, crate::CMD_QUITAR_WAIT, &[], TIMEOUT_RESPUESTA)?;
         println!("Comando Quitar Wait enviado.");
         Ok(())
     }
-    pub fn poner_wait(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn poner_wait(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Poniendo WAIT...");
-        self.enviar_comando(port, crate::CMD_PONER_WAIT, &[0x00, 0x00, 0x00, 0x00])?;
-        thread::sleep(Duration::from_millis(50));
+        self.transaccion(port, crate::CMD_PONER_WAIT, &[], TIMEOUT_RESPUESTA)?;
         println!("Comando Poner Wait enviado.");
         Ok(())
     }
-    pub fn capture_z80_data(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
-        println!("Iniciando captura de datos del Z80. Presiona cualquier tecla para detener.");
-
-        self.enviar_comando(port, crate::CMD_START_CAPTURE, &[0x00, 0x00, 0x00, 0x00])?;
+    pub fn capture_z80_data(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
+        self.capturar(port, CAPTURA_POR_DEFECTO)
+    }
 
-        let mut log_file = File::create("z80_log.txt")?;
+    /// Captura los ciclos del Z80 en `fichero` hasta que se pulse una tecla.
+    ///
+    /// Cada ciclo llega en una trama CMD_START_CAPTURE con 11 bytes de datos, que se
+    /// guardan sin formatear en un fichero de captura (ver `captura`), o en VCD si
+    /// `fichero` acaba en .vcd. Las tramas con el CRC mal se descartan avisando, sin
+    /// desalinear el resto de la captura.
+    pub fn capturar(&mut self, port: &mut dyn Transporte, fichero: &str) -> io::Result<()> {
+        println!("Iniciando captura de datos del Z80 en '{}'. Presiona cualquier tecla para detener.", fichero);
+
+        self.transaccion(port, crate::CMD_START_CAPTURE, &[], TIMEOUT_RESPUESTA)?;
+
+        let salida = BufWriter::new(File::create(fichero)?);
+        let mut escritor: Box<dyn DestinoCaptura> = if fichero.ends_with(".vcd") {
+            Box::new(EscritorVcd::new(salida, &Cabecera::nueva())?)
+        } else {
+            Box::new(EscritorCaptura::new(salida, &Cabecera::nueva())?)
+        };
         let mut stdin = async_stdin().bytes();
-        let mut buffer = [0u8; 11]; // Ahora esperamos 11 bytes
+        let mut descartados = 0;
 
         loop {
-            match port.read_exact(&mut buffer) { // Usamos read_exact para asegurar la lectura de n bytes
-                Ok(_) => {
-                    let ciclo = (buffer[0] as u16) << 8 | buffer[1] as u16;
-                    let reloj = buffer[2];
-                    let direccion = (buffer[3] as u16) << 8 | buffer[4] as u16;
-                    let datos = buffer[5];
-                    let rd_pin = buffer[6];
-                    let wr_pin = buffer[7];
-                    let mreq_pin = buffer[8];
-                    let rfsh_pin = buffer[9];
-                    let reset_pin = buffer[10];
-                    //let halt_pin = buffer[11];
-
-                    let log_entry = format!(
-                        "CICLO: {:04X} - RELOJ: {:02X} - DIRECCION: {:04X} - DATOS: {:02X} - RD_PIN: {:02X} -\
-                     WR_PIN: {:02X} - MREQ_PIN:{:02X} - RFSH_PIN:{:02X} - RESET_PIN:{:02X} \n",
-                        ciclo, reloj, direccion, datos, rd_pin, wr_pin, mreq_pin, rfsh_pin, reset_pin
-                    );
-                    log_file.write_all(log_entry.as_bytes())?;
-                    log_file.flush()?;
+            match self.leer_respuesta(port, SERIAL_TIMEOUT) {
+                Ok(r) if r.comando == crate::CMD_START_CAPTURE && r.datos.len() == LONGITUD_REGISTRO => {
+                    escritor.registrar_trama(&r.datos)?;
                 }
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                    thread::sleep(Duration::from_millis(10));
-                    continue;
+                Ok(_) => {}
+                Err(ref e) if e.kind() == TimedOut => {}
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                    descartados += 1;
+                    eprintln!("Registro de captura descartado: {}", e);
                 }
                 Err(e) => {
                     eprintln!("Error al leer datos del Z80: {}", e);
@@ -344,99 +482,145 @@ impl Serial {
 
             if let Some(Ok(_)) = stdin.next() {
                 println!("Captura de datos detenida.");
-                self.enviar_comando(port, crate::CMD_STOP_CAPTURE, &[0x00, 0x00, 0x00, 0x00])?;
+                self.transaccion(port, crate::CMD_STOP_CAPTURE, &[], TIMEOUT_RESPUESTA)?;
                 break;
             }
         }
+        println!("{} ciclos guardados en '{}'.", escritor.registros(), fichero);
+        escritor.cerrar()?;
+        if descartados > 0 {
+            eprintln!("Se descartaron {} registros con errores.", descartados);
+        }
 
         Ok(())
     }
 
-    pub fn run_z80(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    /// Ejecuta el programa cargado desde `direccion` capturando el bus, sin intervención
+    /// del usuario. La captura termina al recibir `limite` registros o cuando la placa
+    /// deja de enviarlos (el Z80 se ha parado).
+    pub fn capturar_ejecucion(&mut self, port: &mut dyn Transporte, direccion: u16, limite: usize) -> io::Result<Vec<RegistroCiclo>> {
+        self.transaccion(port, crate::CMD_START_CAPTURE, &[], TIMEOUT_RESPUESTA)?;
+        self.ejecutar_z80(port, direccion)?;
+        self.quitar_wait(port)?;
+
+        let mut registros = Vec::new();
+        while registros.len() < limite {
+            match self.leer_respuesta(port, SERIAL_TIMEOUT) {
+                Ok(r) if r.comando == crate::CMD_START_CAPTURE => registros.extend(RegistroCiclo::decodificar(&r.datos)),
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => eprintln!("Registro de captura descartado: {}", e),
+                Err(ref e) if e.kind() == TimedOut => break,
+                Err(e) => return Err(e),
+            }
+        }
+        self.transaccion(port, crate::CMD_STOP_CAPTURE, &[], TIMEOUT_RESPUESTA)?;
+        Ok(registros)
+    }
+
+    pub fn run_z80(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Ejecutar código Z80...");
         print!("Introduce la dirección de inicio (hex): ");
         io::stdout().flush()?;
         let mut start_addr_str = String::new();
         io::stdin().read_line(&mut start_addr_str)?;
         let start_address = u16::from_str_radix(start_addr_str.trim(), 16).unwrap_or(0x0000);
+        self.ejecutar_z80(port, start_address)
+    }
 
-        let comando = crate::CMD_RUN_Z80;
-        let start_address_bytes = start_address.to_be_bytes();
-        let data_to_send = &[
-            start_address_bytes[0],
-            start_address_bytes[1],
-            0x00,
-            0x00,
-        ];
-
-        self.enviar_comando(port, comando, data_to_send)?;
-        thread::sleep(Duration::from_millis(50));
-        println!("Comando RUN enviado. Z80 en ejecución (se espera).");
+    /// Ejecuta el código del Z80 a partir de `start_address`.
+    pub fn ejecutar_z80(&mut self, port: &mut dyn Transporte, start_address: u16) -> io::Result<()> {
+        self.transaccion(port, crate::CMD_RUN_Z80, &start_address.to_be_bytes(), TIMEOUT_RESPUESTA)?;
+        println!("Comando RUN aceptado. Z80 en ejecución.");
         Ok(())
     }
 
-    pub fn reset_z80(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn reset_z80(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Reseteando el Z80...");
-        self.enviar_comando(port, crate::CMD_RESET_Z80, &[0x00, 0x00, 0x00, 0x00])?;
-        thread::sleep(Duration::from_millis(50));
+        self.transaccion(port, crate::CMD_RESET_Z80, &[], TIMEOUT_RESPUESTA)?;
         println!("Comando RESET enviado. Z80 reseteado.");
         Ok(())
     }
 
-    pub fn reset_z80_sin_modificar_memoria(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn reset_z80_sin_modificar_memoria(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Reseteando el Z80...");
-        self.enviar_comando(port, crate::CMD_RESET_SIN_MODIFICAR_Z80, &[0x00, 0x00, 0x00, 0x00])?;
-        thread::sleep(Duration::from_millis(50));
+        self.transaccion(port, crate::CMD_RESET_SIN_MODIFICAR_Z80, &[], TIMEOUT_RESPUESTA)?;
         println!("Comando RESET SIN MODIFICAR MEMORIA enviado. Z80 reseteado.");
         Ok(())
     }
 
-    pub fn test_cmd_echo(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn test_cmd_echo(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
+        if let Err(e) = self.comprobar_echo(port) {
+            eprintln!("{}", e);
+        }
+        Ok(())
+    }
+
+    /// Envía CMD_ECHO y comprueba que la placa devuelve el mismo byte.
+    /// Devuelve error si no hay respuesta o si el byte no coincide.
+    pub fn comprobar_echo(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Probando CMD_ECHO...");
         let echo_data: u8 = 0xAA;
-        self.enviar_comando(port, crate::CMD_ECHO, &[0x00, 0x00, 0x00, echo_data])?;
-        let mut echo_response = [0u8; 1];
-        if let Err(e) = self.leer_bytes_con_timeout(port, &mut echo_response, Duration::from_secs(2)) {
-            eprintln!("Error al leer la respuesta ECHO: {}", e);
-        } else {
-            println!("Respuesta ECHO (byte sin procesar): 0x{:02X}", echo_response[0]);
-            if echo_response[0] == echo_data {
-                println!("Prueba CMD_ECHO exitosa!");
-            } else {
-                println!("Error en CMD_ECHO: Se esperaba 0x{:02X}, se recibió 0x{:02X}", echo_data, echo_response[0]);
-            }
+        let echo_response = match self.transaccion(port, crate::CMD_ECHO, &[echo_data], TIMEOUT_RESPUESTA) {
+            Ok(r) => r,
+            Err(e) => return Err(io::Error::new(e.kind(), format!("Error al leer la respuesta ECHO: {}", e))),
+        };
+        println!("Respuesta ECHO: {:02X?}", echo_response);
+        if echo_response != [echo_data] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "Error en CMD_ECHO: Se esperaba [{:02X}], se recibió {:02X?}", echo_data, echo_response)));
         }
+        println!("Prueba CMD_ECHO exitosa!");
         Ok(())
     }
 
-    pub fn enviar_byte(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn enviar_byte(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Probando CMD_SEND_BYTE...");
         let send_data: u8 = 0xBB;
-        self.enviar_comando(port, crate::CMD_SEND_BYTE, &[0x00, 0x00, 0x00, send_data])?;
-        thread::sleep(Duration::from_millis(50));
+        self.transaccion(port, crate::CMD_SEND_BYTE, &[send_data], TIMEOUT_RESPUESTA)?;
         println!("Comando SEND_BYTE enviado.");
         Ok(())
     }
 
-    pub fn obtener_status(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn obtener_status(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         println!("Probando CMD_GET_STATUS...");
-        self.enviar_comando(port, crate::CMD_GET_STATUS, &[0x00, 0x00, 0x00, 0x00])?;
-        let mut status_response = [0u8; 1];
-        if let Err(e) = self.leer_bytes_con_timeout(port, &mut status_response, Duration::from_secs(2)) {
-            eprintln!("Error al leer el status: {}", e);
-        } else {
-            println!("Status: 0x{:02X}", status_response[0]);
-            match status_response[0] {
-                0 => println!("Status: Idle"),
-                1 => println!("Status: Running"),
-                2 => println!("Status: Wait"),
-                3 => println!("Status: Halted"),
-                _ => println!("Status: Error"),
+        match self.leer_status(port) {
+            Ok(status) => {
+                println!("Status: 0x{:02X}", status);
+                println!("Status: {}", nombre_status(status));
             }
+            Err(e) => eprintln!("Error al leer el status: {}", e),
         }
         Ok(())
     }
 
+    /// Pide el estado a la placa y devuelve el byte recibido.
+    pub fn leer_status(&mut self, port: &mut dyn Transporte) -> io::Result<u8> {
+        match self.transaccion(port, crate::CMD_GET_STATUS, &[], TIMEOUT_RESPUESTA)?[..] {
+            [status] => Ok(status),
+            ref otro => Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "respuesta de status de {} bytes", otro.len()))),
+        }
+    }
+
+    pub fn mostrar_registros(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
+        println!("Leyendo registros del Z80...");
+        match self.leer_registros(port) {
+            Ok(registros) => println!("{}", registros),
+            Err(e) => eprintln!("Error al leer los registros: {}", e),
+        }
+        Ok(())
+    }
+
+    /// Pide a la placa los registros del Z80 con CMD_GET_REGISTERS.
+    ///
+    /// # Notas
+    /// - La placa real necesita el Z80 en marcha y sin WAIT: inyecta una rutina que vuelca
+    ///   los registros en memoria en la siguiente búsqueda de instrucción
+    pub fn leer_registros(&mut self, port: &mut dyn Transporte) -> io::Result<VolcadoRegistros> {
+        let datos = self.transaccion(port, crate::CMD_GET_REGISTERS, &[], TIMEOUT_RESPUESTA)?;
+        VolcadoRegistros::decodificar(&datos).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
     /// Lee un bloque de memoria del Z80 a través del puerto serie.
     ///
     /// Esta función permite leer un bloque de memoria del Z80 especificando
@@ -444,7 +628,7 @@ impl Serial {
     /// en formato hexadecimal, 16 bytes por línea.
     ///
     /// # Parámetros
-    /// - `port`: Referencia mutable al puerto serie
+    /// - `port`: Transporte hacia la placa
     ///
     /// # Retorno
     /// - `io::Result<()>`: Ok(()) si la operación fue exitosa, o un error si falló
@@ -454,7 +638,7 @@ impl Serial {
     /// - La longitud se introduce en formato decimal
     /// - Se usa un timeout de 10 segundos para la lectura
     /// - Los datos se muestran en formato hexadecimal, 16 bytes por línea
-    pub fn leer_memoria_z80(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    pub fn leer_memoria_z80(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         // Mostrar mensaje inicial
         println!("LEYENDO MEMORIA...");
 
@@ -478,51 +662,50 @@ impl Serial {
         let mut longitud_str = String::new();
         io::stdin().read_line(&mut longitud_str)?;
         // Convertir la entrada a número decimal, usar 16 si hay error
-        let longitud_a_leer = longitud_str.trim().parse::<u16>().unwrap_or(16);
-
-        // Convertir la entrada a número hexadecimal, usar 0x0000 si hay error
-        let direc_inicio_bytes = direc_inicio.to_be_bytes();
-        let longitud_a_leer_bytes = longitud_a_leer.to_be_bytes();
-
-        // Preparar los datos a enviar al Z80
-        let data_to_send = &[
-            direc_inicio_bytes[0],    // Byte alto de la dirección
-            direc_inicio_bytes[1],    // Byte bajo de la dirección
-            longitud_a_leer_bytes[0], // Byte alto de la longitud
-            longitud_a_leer_bytes[1], // Byte bajo de la longitud
-        ];
-
-        // Enviar el comando de lectura de memoria al Z80
-        self.enviar_comando(port, crate::CMD_READ_Z80_MEMORY, data_to_send)?;
-
-        // Crear un buffer para almacenar los datos leídos
-        let mut buffer_memoria = vec![0u8; longitud_a_leer as usize];
+        let longitud_a_leer = longitud_str.trim().parse::<usize>().unwrap_or(16);
 
-        // Intentar leer los datos con un timeout de 10 segundos
-        if let Err(e) = self.leer_bytes_con_timeout(port, &mut buffer_memoria, Duration::from_secs(10)) {
+        match self.leer_memoria(port, direc_inicio, longitud_a_leer) {
             // Si hay error, mostrar mensaje
-            eprintln!("Error al leer la memoria del Z80: {}", e);
-        } else {
-            // Si la lectura fue exitosa, mostrar los datos
-            println!("Memoria Z80 leída desde 0x{:04X} ({} bytes):", direc_inicio, longitud_a_leer);
-
-            // Mostrar los bytes en formato hexadecimal, 16 por línea
-            for (i, byte) in buffer_memoria.iter().enumerate() {
-                print!("{:02X} ", byte);
-                if (i + 1) % 16 == 0 {
-                    println!();
-                }
+            Err(e) => eprintln!("Error al leer la memoria del Z80: {}", e),
+            Ok(buffer_memoria) => {
+                // Si la lectura fue exitosa, mostrar los datos
+                println!("Memoria Z80 leída desde 0x{:04X} ({} bytes):", direc_inicio, longitud_a_leer);
+                mostrar_hex(&buffer_memoria);
             }
-            println!();
         }
         Ok(())
     }
 
-    /// Escribe código Z80 en la memoria del Arduino desde un archivo.
-    /// binario. El archivo debe estar en el directorio "PROGRAMAS" y se especifica sin la ruta.
+    /// Lee `longitud_a_leer` bytes de la memoria del Z80 a partir de `direc_inicio`.
+    ///
+    /// # Notas
+    /// - Se piden bloques de hasta `protocolo::BLOQUE_MEMORIA` bytes, cada uno en su trama
+    pub fn leer_memoria(&mut self, port: &mut dyn Transporte, direc_inicio: u16, longitud_a_leer: usize) -> io::Result<Vec<u8>> {
+        if direc_inicio as usize + longitud_a_leer > 0x10000 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "{} bytes en 0x{:04X} se salen de la memoria del Z80", longitud_a_leer, direc_inicio)));
+        }
+        let mut buffer_memoria = Vec::with_capacity(longitud_a_leer);
+        while buffer_memoria.len() < longitud_a_leer {
+            let direccion = direc_inicio.wrapping_add(buffer_memoria.len() as u16);
+            let longitud = (longitud_a_leer - buffer_memoria.len()).min(protocolo::BLOQUE_MEMORIA) as u16;
+            let mut peticion = direccion.to_be_bytes().to_vec();
+            peticion.extend_from_slice(&longitud.to_be_bytes());
+            let bloque = self.transaccion(port, crate::CMD_READ_Z80_MEMORY, &peticion, TIMEOUT_RESPUESTA)?;
+            if bloque.len() != longitud as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                    "se pidieron {} bytes en 0x{:04X} y llegaron {}", longitud, direccion, bloque.len())));
+            }
+            buffer_memoria.extend_from_slice(&bloque);
+        }
+        Ok(buffer_memoria)
+    }
+
+    /// Escribe código Z80 en la memoria del Arduino desde un archivo binario o Intel HEX.
+    /// El archivo debe estar en el directorio "PROGRAMAS" y se especifica sin la ruta.
     ///
     /// # Parámetros
-    /// - `port`: Referencia mutable al puerto serie
+    /// - `port`: Transporte hacia la placa
     ///
     /// # Retorno
     /// - `io::Result<()>`:
@@ -532,8 +715,9 @@ impl Serial {
     /// # Notas
     /// - El archivo debe estar en el directorio "PROGRAMAS"
     /// - Se solicita el nombre del archivo sin la ruta
-    /// - La función delega la escritura real a la función `enviar_bin`
-    pub fn escribir_fichero_z80_memoria(&mut self, port: &mut Box<dyn SerialPort>) -> io::Result<()> {
+    /// - Los .bin se buscan en PROGRAMAS/bin y se envían con `enviar_bin`
+    /// - Los .hex se buscan en PROGRAMAS/hex y se envían con `enviar_hex`
+    pub fn escribir_fichero_z80_memoria(&mut self, port: &mut dyn Transporte) -> io::Result<()> {
         // Mostrar mensaje inicial
         println!("Escribir código Z80 desde fichero...");
 
@@ -546,10 +730,40 @@ impl Serial {
         // Limpiar el nombre del archivo de espacios y saltos de línea
         let file_name = file_name.trim();
 
+        // Los ficheros Intel HEX llevan sus propias direcciones
+        if file_name.ends_with(".hex") {
+            let file_path = format!("PROGRAMAS/hex/{}", file_name);
+            if let Err(e) = self.enviar_hex(port, &file_path) {
+                eprintln!("Error al enviar el fichero HEX '{}': {}", file_path, e);
+            }
+            return Ok(());
+        }
+
         // Construir la ruta completa del archivo
         let file_path = format!("PROGRAMAS/bin/{}", file_name);
 
         // Enviar el contenido del archivo al Z80
         self.enviar_bin(port, &file_path)
     }
-}
\ No newline at end of file
+}
+
+/// Nombre del estado devuelto por CMD_GET_STATUS
+pub fn nombre_status(status: u8) -> &'static str {
+    match status {
+        protocolo::ESTADO_IDLE => "Idle",
+        protocolo::ESTADO_RUNNING => "Running",
+        protocolo::ESTADO_HALTED => "Halted",
+        _ => "Error",
+    }
+}
+
+/// Muestra bytes en formato hexadecimal, 16 por línea
+pub fn mostrar_hex(datos: &[u8]) {
+    for (i, byte) in datos.iter().enumerate() {
+        print!("{:02X} ", byte);
+        if (i + 1) % 16 == 0 {
+            println!();
+        }
+    }
+    println!();
+}
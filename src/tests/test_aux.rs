@@ -1,3 +1,4 @@
+use crate::cpu::bus::Memoria;
 use crate::cpu::cpu::CPU;
 
 pub enum Valores {
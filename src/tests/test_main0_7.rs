@@ -3,6 +3,8 @@ use crate::tests::test_aux::{ejecutar, prueba_flags};
 
 #[test]
 fn nop() {
+    use crate::cpu::bus::Memoria;
+
     // -------------+----+---+------+---------+------------+
     //  Mnemonico   |Clck|Siz|SZHPNC| OP-Code |Descripcion |
     // -------------+----+---+------+---------+------------+
@@ -19,6 +21,8 @@ fn nop() {
 
 #[test]
 fn ld_bc_nn() {
+    use crate::cpu::bus::Memoria;
+
     // -------------+----+---+------+----------+
     //  Mnemonico   |Clck|Siz|SZHPNC| OP-Code  |
     // -------------+----+---+------+----------+
@@ -37,6 +41,8 @@ fn ld_bc_nn() {
 
 #[test]
 fn ld_0bc0_a() {
+    use crate::cpu::bus::Memoria;
+
     // -------------+----+---+------+---------+
     //  Mnemonico   |Clck|Siz|SZHPNC| OP-Code |
     // -------------+----+---+------+---------+
@@ -193,6 +199,8 @@ fn add_hl_bc() {
 
 #[test]
 fn ld_a_0bc0() {
+    use crate::cpu::bus::Memoria;
+
     // Flags:  - no afectado    A afectado    0 reset    1 set    ? desconocido    P Parity    V overflow
     // -------------+----+---+------+---------+
     // Mnemonico    |Clck|Siz|SZHPNC| OP-Code |
@@ -0,0 +1,34 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serialport::{ClearBuffer, SerialPort};
+
+/// Canal de bytes con la placa.
+///
+/// `Serial` solo necesita leer y escribir bytes con un tiempo máximo de espera, así que
+/// puede hablar igual con el puerto serie real que con una placa simulada.
+pub trait Transporte: Read + Write {
+    /// Tiempo máximo que puede bloquearse una lectura
+    fn timeout(&self) -> Duration;
+
+    /// Cambia el tiempo máximo de una lectura. Una lectura sin datos en ese tiempo devuelve
+    /// un error `TimedOut`.
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Descarta los bytes recibidos que aún no se han leído
+    fn limpiar_entrada(&mut self) -> io::Result<()>;
+}
+
+impl Transporte for Box<dyn SerialPort> {
+    fn timeout(&self) -> Duration {
+        SerialPort::timeout(self.as_ref())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        SerialPort::set_timeout(self.as_mut(), timeout).map_err(io::Error::from)
+    }
+
+    fn limpiar_entrada(&mut self) -> io::Result<()> {
+        self.clear(ClearBuffer::Input).map_err(io::Error::from)
+    }
+}
@@ -0,0 +1,302 @@
+// Reconstrucción de las instrucciones ejecutadas a partir de una captura del bus.
+//
+// Primero se agrupan los registros en ciclos de bus (un ciclo de memoria dura varios
+// registros) y luego se reparten los ciclos entre instrucciones, desensamblando los bytes
+// buscados con `cpu::dasm`.
+
+use std::fmt;
+
+use crate::captura::{RegistroCiclo, Senal};
+use crate::cpu::dasm::{desensamblar, Instruccion};
+
+/// Tipo de ciclo de bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TipoCiclo {
+    /// Búsqueda de código de operación (M1)
+    Fetch,
+    Lectura,
+    Escritura,
+    /// Refresco de memoria que sigue a cada M1. La dirección lleva I y R.
+    Refresco,
+}
+
+/// Ciclo de bus reconstruido
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CicloBus {
+    pub tipo: TipoCiclo,
+    pub direccion: u16,
+    pub dato: u8,
+    /// Contador del firmware en el primer registro del ciclo
+    pub ciclo: u16,
+}
+
+impl fmt::Display for CicloBus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tipo = match self.tipo {
+            TipoCiclo::Fetch => "M1",
+            TipoCiclo::Lectura => "RD",
+            TipoCiclo::Escritura => "WR",
+            TipoCiclo::Refresco => "RF",
+        };
+        write!(f, "{:04X}  {} {:04X} {:02X}", self.ciclo, tipo, self.direccion, self.dato)
+    }
+}
+
+/// Instrucción ejecutada con los ciclos de bus que le pertenecen, búsquedas incluidas
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstruccionEjecutada {
+    pub instruccion: Instruccion,
+    pub accesos: Vec<CicloBus>,
+}
+
+impl fmt::Display for InstruccionEjecutada {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X}  {}", self.instruccion.direccion, self.instruccion)?;
+        for acceso in &self.accesos {
+            write!(f, "\n        {}", acceso)?;
+        }
+        Ok(())
+    }
+}
+
+/// Agrupa los registros en ciclos de bus. Los registros seguidos con /MREQ activa y las
+/// mismas señales y dirección son un solo ciclo; en las lecturas vale el último dato,
+/// que es el que el Z80 toma al final del ciclo. Un ciclo de lectura seguido de un
+/// refresco es una búsqueda M1.
+pub fn ciclos_de_bus(registros: &[RegistroCiclo]) -> Vec<CicloBus> {
+    let mut ciclos: Vec<CicloBus> = Vec::new();
+    let mut anterior: Option<(TipoCiclo, u16)> = None;
+    for r in registros {
+        let tipo = if !r.activa(Senal::Mreq) {
+            None
+        } else if r.activa(Senal::Rfsh) {
+            Some(TipoCiclo::Refresco)
+        } else if r.activa(Senal::Wr) {
+            Some(TipoCiclo::Escritura)
+        } else if r.activa(Senal::Rd) {
+            Some(TipoCiclo::Lectura)
+        } else {
+            None
+        };
+        let Some(tipo) = tipo else {
+            anterior = None;
+            continue;
+        };
+
+        if anterior == Some((tipo, r.direccion)) {
+            if tipo == TipoCiclo::Lectura {
+                ciclos.last_mut().unwrap().dato = r.dato;
+            }
+            continue;
+        }
+        if let Some(c) = ciclos
+            .last_mut()
+            .filter(|c| tipo == TipoCiclo::Refresco && c.tipo == TipoCiclo::Lectura)
+        {
+            c.tipo = TipoCiclo::Fetch;
+        }
+        ciclos.push(CicloBus {
+            tipo,
+            direccion: r.direccion,
+            dato: r.dato,
+            ciclo: r.ciclo,
+        });
+        anterior = Some((tipo, r.direccion));
+    }
+    ciclos
+}
+
+/// Bytes que ocupa la instrucción que empieza por `bytes`. Basta con los dos primeros
+/// para saberlo.
+fn longitud(bytes: &[u8]) -> usize {
+    if bytes.len() == 1 && matches!(bytes[0], 0xCB | 0xDD | 0xED | 0xFD) {
+        return 2;
+    }
+    let mut relleno = [0u8; 4];
+    for (r, b) in relleno.iter_mut().zip(bytes) {
+        *r = *b;
+    }
+    desensamblar(&relleno, 0).bytes.len()
+}
+
+/// Instrucción a medio reconstruir
+struct Pendiente {
+    direccion: u16,
+    bytes: Vec<u8>,
+    accesos: Vec<CicloBus>,
+    /// Lecturas de datos desde que se completaron los bytes de la instrucción
+    lecturas: usize,
+}
+
+impl Pendiente {
+    fn completa(&self) -> bool {
+        self.bytes.len() >= longitud(&self.bytes)
+    }
+
+    fn instruccion(&self) -> Instruccion {
+        desensamblar(&self.bytes, self.direccion)
+    }
+
+    fn terminar(self) -> InstruccionEjecutada {
+        InstruccionEjecutada {
+            instruccion: self.instruccion(),
+            accesos: self.accesos,
+        }
+    }
+
+    /// Sin ciclos de refresco no se sabe qué lecturas son M1. Se supone que la siguiente
+    /// instrucción empieza en la dirección a la que sigue el programa; si no se conoce
+    /// (RET, JP (HL), HALT) empieza en la primera lectura tras las de la pila.
+    fn sigue_en(&self, direccion: u16) -> bool {
+        let instr = self.instruccion();
+        if direccion == instr.siguiente() || Some(direccion) == instr.destino {
+            return true;
+        }
+        match instr.mnemonico.as_str() {
+            "RET" | "RETI" | "RETN" => self.lecturas >= 2,
+            "JP" => instr.destino.is_none(),
+            "HALT" => true,
+            _ => false,
+        }
+    }
+}
+
+/// Reparte los ciclos de bus entre las instrucciones ejecutadas.
+///
+/// Con ciclos de refresco en la captura cada M1 empieza una instrucción (o continúa una
+/// con prefijo). Sin ellos las búsquedas se deducen siguiendo el flujo del programa, que
+/// puede equivocarse si una instrucción lee datos justo de la dirección siguiente.
+pub fn decodificar_instrucciones(ciclos: &[CicloBus]) -> Vec<InstruccionEjecutada> {
+    let con_refresco = ciclos.iter().any(|c| c.tipo == TipoCiclo::Refresco);
+    let mut instrucciones = Vec::new();
+    let mut actual: Option<Pendiente> = None;
+
+    for c in ciclos {
+        match c.tipo {
+            TipoCiclo::Refresco | TipoCiclo::Escritura => {
+                if let Some(p) = actual.as_mut() {
+                    p.accesos.push(*c);
+                }
+            }
+            TipoCiclo::Fetch | TipoCiclo::Lectura => {
+                if let Some(p) = actual.as_mut() {
+                    // Prefijos, operandos y desplazamientos de la instrucción en curso
+                    let siguiente_byte = p.direccion.wrapping_add(p.bytes.len() as u16);
+                    if !p.completa() && c.direccion == siguiente_byte {
+                        p.bytes.push(c.dato);
+                        p.accesos.push(*c);
+                        continue;
+                    }
+                }
+                let empieza = match &actual {
+                    None => !con_refresco || c.tipo == TipoCiclo::Fetch,
+                    Some(_) if con_refresco => c.tipo == TipoCiclo::Fetch,
+                    Some(p) => p.completa() && p.sigue_en(c.direccion),
+                };
+                if empieza {
+                    instrucciones.extend(actual.take().map(Pendiente::terminar));
+                    actual = Some(Pendiente {
+                        direccion: c.direccion,
+                        bytes: vec![c.dato],
+                        accesos: vec![*c],
+                        lecturas: 0,
+                    });
+                } else if let Some(p) = actual.as_mut() {
+                    p.accesos.push(*c);
+                    p.lecturas += 1;
+                }
+            }
+        }
+    }
+    instrucciones.extend(actual.map(Pendiente::terminar));
+    instrucciones
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registro(ciclo: u16, direccion: u16, dato: u8, senales: &str) -> RegistroCiclo {
+        let nivel = |s: &str| if senales.contains(s) { 0 } else { 1 };
+        RegistroCiclo {
+            ciclo,
+            reloj: (ciclo & 1) as u8,
+            direccion,
+            dato,
+            rd: nivel("rd"),
+            wr: nivel("wr"),
+            mreq: nivel("mreq"),
+            rfsh: nivel("rfsh"),
+            reset: 1,
+        }
+    }
+
+    #[test]
+    fn con_refresco() {
+        // LD (IX+2),A con A = 0x77, IX = 0x8000, y NOP. Cada ciclo de memoria dura varios
+        // registros y el dato de una lectura no es válido hasta el final.
+        let registros = [
+            registro(0, 0x0000, 0xFF, "mreq rd"),
+            registro(1, 0x0000, 0xDD, "mreq rd"),
+            registro(2, 0x1000, 0x00, "mreq rfsh"),
+            registro(3, 0x0001, 0x77, "mreq rd"),
+            registro(4, 0x1001, 0x00, "mreq rfsh"),
+            registro(5, 0x0002, 0x02, "mreq rd"),
+            registro(6, 0x0002, 0x02, ""),
+            registro(7, 0x8002, 0x77, "mreq wr"),
+            registro(8, 0x8002, 0x77, "mreq wr"),
+            registro(9, 0x0003, 0x00, "mreq rd"),
+            registro(10, 0x1002, 0x00, "mreq rfsh"),
+        ];
+        let ciclos = ciclos_de_bus(&registros);
+        let tipos: Vec<TipoCiclo> = ciclos.iter().map(|c| c.tipo).collect();
+        use TipoCiclo::*;
+        assert_eq!(tipos, vec![Fetch, Refresco, Fetch, Refresco, Lectura, Escritura, Fetch, Refresco]);
+        assert_eq!(ciclos[0].dato, 0xDD);
+
+        let instrucciones = decodificar_instrucciones(&ciclos);
+        assert_eq!(instrucciones.len(), 2);
+        assert_eq!(instrucciones[0].instruccion.texto(), "LD (IX+$02),A");
+        assert_eq!(instrucciones[0].accesos.len(), 6);
+        assert_eq!(instrucciones[0].accesos[5].direccion, 0x8002);
+        assert_eq!(instrucciones[1].instruccion.texto(), "NOP");
+        assert_eq!(
+            instrucciones[0].to_string().lines().next(),
+            Some("0000  DD 77 02      LD (IX+$02),A")
+        );
+    }
+
+    #[test]
+    fn sin_refresco() {
+        // LD HL,0x9000 ; LD A,(HL) ; CALL 0x0010 ... 0x0010: RET ; HALT
+        let accesos: [(u16, u8, &str); 12] = [
+            (0x0000, 0x21, "rd"),
+            (0x0001, 0x00, "rd"),
+            (0x0002, 0x90, "rd"),
+            (0x0003, 0x7E, "rd"),
+            (0x9000, 0x55, "rd"),
+            (0x0004, 0xCD, "rd"),
+            (0x0005, 0x10, "rd"),
+            (0x0006, 0x00, "rd"),
+            (0xFFFF, 0x00, "wr"),
+            (0xFFFE, 0x07, "wr"),
+            (0x0010, 0xC9, "rd"),
+            (0xFFFE, 0x07, "rd"),
+        ];
+        let mut registros: Vec<RegistroCiclo> = accesos
+            .iter()
+            .enumerate()
+            .map(|(i, &(dir, dato, s))| registro(i as u16, dir, dato, &format!("mreq {}", s)))
+            .collect();
+        registros.push(registro(12, 0xFFFF, 0x00, "mreq rd"));
+        registros.push(registro(13, 0x0007, 0x76, "mreq rd"));
+
+        let instrucciones = decodificar_instrucciones(&ciclos_de_bus(&registros));
+        let textos: Vec<String> = instrucciones.iter().map(|i| i.instruccion.texto()).collect();
+        assert_eq!(textos, vec!["LD HL,$9000", "LD A,(HL)", "CALL $0010", "RET", "HALT"]);
+        assert_eq!(instrucciones[1].accesos[1].direccion, 0x9000);
+        assert_eq!(instrucciones[2].accesos.len(), 5);
+        assert_eq!(instrucciones[3].accesos.len(), 3);
+        assert_eq!(instrucciones[4].instruccion.direccion, 0x0007);
+    }
+}
@@ -0,0 +1,177 @@
+// Exportación de capturas a Value Change Dump para verlas en GTKWave.
+//
+// Cada unidad de tiempo es un ciclo del contador del firmware, así que los huecos en el
+// contador (ciclos que no se enviaron) aparecen como huecos en el tiempo. Los buses van
+// como vectores y las señales de control como escalares, con el nivel del pin.
+
+use std::io::{self, Write};
+
+use crate::captura::{Cabecera, DestinoCaptura, RegistroCiclo};
+
+/// Señales del volcado: identificador, ancho en bits y nombre
+const SENALES: [(&str, u8, &str); 8] = [
+    ("c", 1, "CLK"),
+    ("a", 16, "A"),
+    ("d", 8, "D"),
+    ("r", 1, "RD_n"),
+    ("w", 1, "WR_n"),
+    ("m", 1, "MREQ_n"),
+    ("f", 1, "RFSH_n"),
+    ("s", 1, "RESET_n"),
+];
+
+/// Convierte registros de captura en un fichero VCD, escribiendo solo los cambios
+pub struct EscritorVcd<W: Write> {
+    salida: W,
+    tiempo: u64,
+    anterior: Option<RegistroCiclo>,
+    pub registros: usize,
+}
+
+impl<W: Write> EscritorVcd<W> {
+    /// Escribe las definiciones del volcado. La fecha y la versión salen de la cabecera
+    /// de la captura.
+    pub fn new(mut salida: W, cabecera: &Cabecera) -> io::Result<EscritorVcd<W>> {
+        writeln!(salida, "$date {} $end", cabecera.marca_tiempo)?;
+        writeln!(salida, "$version P_Z80 captura, firmware v{} $end", cabecera.version_firmware)?;
+        writeln!(salida, "$comment una unidad de tiempo por ciclo de captura $end")?;
+        writeln!(salida, "$timescale 1 us $end")?;
+        writeln!(salida, "$scope module z80 $end")?;
+        for (id, ancho, nombre) in SENALES {
+            if ancho == 1 {
+                writeln!(salida, "$var wire 1 {} {} $end", id, nombre)?;
+            } else {
+                writeln!(salida, "$var wire {} {} {} [{}:0] $end", ancho, id, nombre, ancho - 1)?;
+            }
+        }
+        writeln!(salida, "$upscope $end")?;
+        writeln!(salida, "$enddefinitions $end")?;
+        Ok(EscritorVcd {
+            salida,
+            tiempo: 0,
+            anterior: None,
+            registros: 0,
+        })
+    }
+
+    pub fn registrar(&mut self, r: &RegistroCiclo) -> io::Result<()> {
+        if let Some(anterior) = &self.anterior {
+            self.tiempo += r.ciclo.wrapping_sub(anterior.ciclo).max(1) as u64;
+        }
+        let valores = valores_de(r);
+        let cambios: Vec<usize> = match &self.anterior {
+            Some(anterior) => {
+                let previos = valores_de(anterior);
+                (0..SENALES.len()).filter(|&i| valores[i] != previos[i]).collect()
+            }
+            None => (0..SENALES.len()).collect(),
+        };
+        if !cambios.is_empty() {
+            writeln!(self.salida, "#{}", self.tiempo)?;
+            if self.anterior.is_none() {
+                writeln!(self.salida, "$dumpvars")?;
+            }
+            for i in &cambios {
+                let (id, ancho, _) = SENALES[*i];
+                if ancho == 1 {
+                    writeln!(self.salida, "{}{}", valores[*i] & 1, id)?;
+                } else {
+                    writeln!(self.salida, "b{:0ancho$b} {}", valores[*i], id, ancho = ancho as usize)?;
+                }
+            }
+            if self.anterior.is_none() {
+                writeln!(self.salida, "$end")?;
+            }
+        }
+        self.anterior = Some(*r);
+        self.registros += 1;
+        Ok(())
+    }
+
+    pub fn terminar(mut self) -> io::Result<W> {
+        // Marca final para que se vea el último ciclo entero
+        writeln!(self.salida, "#{}", self.tiempo + 1)?;
+        self.salida.flush()?;
+        Ok(self.salida)
+    }
+}
+
+impl<W: Write> DestinoCaptura for EscritorVcd<W> {
+    fn registrar_trama(&mut self, datos: &[u8]) -> io::Result<()> {
+        match RegistroCiclo::decodificar(datos) {
+            Some(r) => self.registrar(&r),
+            None => Ok(()),
+        }
+    }
+
+    fn registros(&self) -> usize {
+        self.registros
+    }
+
+    fn cerrar(self: Box<Self>) -> io::Result<()> {
+        self.terminar().map(|_| ())
+    }
+}
+
+/// Valor de cada señal de `SENALES` en el registro
+fn valores_de(r: &RegistroCiclo) -> [u16; 8] {
+    [
+        r.reloj as u16,
+        r.direccion,
+        r.dato as u16,
+        r.rd as u16,
+        r.wr as u16,
+        r.mreq as u16,
+        r.rfsh as u16,
+        r.reset as u16,
+    ]
+}
+
+/// Convierte una captura ya leída en VCD
+pub fn escribir_vcd<'a>(
+    salida: &mut dyn Write,
+    cabecera: &Cabecera,
+    registros: impl IntoIterator<Item = &'a RegistroCiclo>,
+) -> io::Result<()> {
+    let mut escritor = EscritorVcd::new(salida, cabecera)?;
+    for r in registros {
+        escritor.registrar(r)?;
+    }
+    escritor.terminar()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registro(ciclo: u16, reloj: u8, direccion: u16, dato: u8, rd: u8) -> RegistroCiclo {
+        RegistroCiclo { ciclo, reloj, direccion, dato, rd, wr: 1, mreq: rd, rfsh: 1, reset: 1 }
+    }
+
+    #[test]
+    fn volcado_de_cambios() {
+        let cabecera = Cabecera { marca_tiempo: 1_700_000_000, ..Cabecera::nueva() };
+        let registros = [
+            registro(10, 0, 0x0000, 0x3E, 0),
+            registro(11, 1, 0x0000, 0x3E, 0),
+            // Ciclos 12 y 13 no llegaron
+            registro(14, 0, 0x0001, 0x3E, 1),
+        ];
+        let mut vcd = Vec::new();
+        escribir_vcd(&mut vcd, &cabecera, &registros).unwrap();
+        let vcd = String::from_utf8(vcd).unwrap();
+
+        assert!(vcd.starts_with("$date 1700000000 $end\n"));
+        assert!(vcd.contains("$var wire 16 a A [15:0] $end\n"));
+        assert!(vcd.contains("$var wire 1 r RD_n $end\n"));
+        let cambios = vcd.split("$enddefinitions $end\n").nth(1).unwrap();
+        assert_eq!(
+            cambios,
+            "#0\n$dumpvars\n0c\nb0000000000000000 a\nb00111110 d\n0r\n1w\n0m\n1f\n1s\n$end\n\
+             #1\n1c\n\
+             #4\n0c\nb0000000000000001 a\n1r\n1m\n\
+             #5\n"
+        );
+    }
+}